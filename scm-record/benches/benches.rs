@@ -29,6 +29,9 @@ fn bench_record(c: &mut Criterion) {
         sections: vec![Section::Changed {
           lines: [vec![before_line; 1000], vec![after_line; 1000]].concat(),
         }],
+        commit_assignments: Default::default(),
+        similarity: None,
+        unloaded: None,
         #[cfg(feature = "tree-sitter")]
         containers: None,
       }],
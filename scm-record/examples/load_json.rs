@@ -10,8 +10,8 @@ use scm_record::{
 
 #[cfg(feature = "serde")]
 fn load_state(path: impl AsRef<Path>) -> RecordState<'static> {
-  let json_file = std::fs::File::open(path).expect("opening JSON file");
-  serde_json::from_reader(json_file).expect("deserializing state")
+  let json = std::fs::read_to_string(path).expect("reading JSON file");
+  scm_record::interchange::from_json(&json).expect("deserializing state")
 }
 
 #[cfg(not(feature = "serde"))]
@@ -39,6 +39,7 @@ fn main() {
         let (selected, _unselected) = file.get_selected_contents();
 
         let SelectedChanges {
+          path: _,
           contents,
           file_mode,
         } = selected;
@@ -59,6 +60,11 @@ fn main() {
                 new_description: Some(description),
               } => format!("<binary description={description}>\n"),
               SelectedContents::Text { contents } => contents.clone(),
+              SelectedContents::Symlink { target } => format!("<symlink target={target}>\n"),
+              SelectedContents::Submodule { commit } => format!("<submodule commit={commit}>\n"),
+              SelectedContents::Incomplete { num_omitted_lines } => {
+                format!("<incomplete num_omitted_lines={num_omitted_lines:?}>\n")
+              }
             }
           );
         }
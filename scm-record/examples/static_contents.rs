@@ -15,6 +15,9 @@ fn main() {
       old_path: None,
       path: Cow::Borrowed(Path::new("foo/bar")),
       file_mode: FileMode::FILE_DEFAULT,
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       #[cfg(feature = "tree-sitter")]
       containers: None,
       sections: vec![
@@ -57,6 +60,9 @@ fn main() {
       old_path: None,
       path: Cow::Borrowed(Path::new("baz")),
       file_mode: FileMode::FILE_DEFAULT,
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       #[cfg(feature = "tree-sitter")]
       containers: None,
       sections: vec![
@@ -116,6 +122,7 @@ fn main() {
         let (selected, _unselected) = file.get_selected_contents();
 
         let SelectedChanges {
+          path: _,
           contents,
           file_mode,
         } = selected;
@@ -135,6 +142,11 @@ fn main() {
                 new_description: Some(description),
               } => format!("<binary description={description}>\n"),
               SelectedContents::Text { contents } => contents.clone(),
+              SelectedContents::Symlink { target } => format!("<symlink target={target}>\n"),
+              SelectedContents::Submodule { commit } => format!("<submodule commit={commit}>\n"),
+              SelectedContents::Incomplete { num_omitted_lines } => {
+                format!("<incomplete num_omitted_lines={num_omitted_lines:?}>\n")
+              }
               SelectedContents::Unchanged => "<unchanged\n>".to_string(),
             }
           );
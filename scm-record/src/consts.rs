@@ -1,8 +1,9 @@
 //! Special runtime variables.
 
 /// Upon launch, write a serialized version of the UI state to the file named
-/// [`DUMP_UI_STATE_FILENAME`] in the current directory. Only works if compiled
-/// with the `debug` feature.
+/// [`DUMP_UI_STATE_FILENAME`] in the current directory, in the format
+/// described by `crate::interchange`. Only works if compiled with the `debug`
+/// feature.
 pub const ENV_VAR_DUMP_UI_STATE: &str = "SCM_RECORD_DUMP_UI_STATE";
 
 /// The filename to write to for [`ENV_VAR_DUMP_UI_STATE`].
@@ -0,0 +1,242 @@
+//! Grouping of files by directory, for the sidebar toggled with `t` in the
+//! recorder.
+//!
+//! [`DirectoryTree::new`] builds a tree of the directories containing a list of
+//! [`File`]s, with the files themselves as leaves. Each node records every file
+//! below it, so that a whole directory can be selected at once.
+
+use std::path::Component;
+
+use crate::File;
+
+/// A directory or file in a [`DirectoryTree`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DirectoryNode {
+  /// The number of directories above this node.
+  pub depth: usize,
+
+  /// The path of the node relative to its parent. A directory whose only
+  /// child is another directory is combined with it, as in `src/legacy`.
+  pub name: String,
+
+  /// Whether this node is a directory, rather than a file.
+  pub is_directory: bool,
+
+  /// The index of the node's parent directory, if any.
+  pub parent_idx: Option<usize>,
+
+  /// The indices of the files at or below this node, in their original order.
+  pub file_idxs: Vec<usize>,
+}
+
+/// The directories containing a list of [`File`]s, and the files in them.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DirectoryTree {
+  nodes: Vec<DirectoryNode>,
+}
+
+/// A file along with the components of its path which haven't been grouped
+/// into directories yet.
+type Entry = (usize, Vec<String>);
+
+impl DirectoryTree {
+  /// Group `files` by the components of their paths. The children of each
+  /// directory are ordered by their first file.
+  pub fn new(files: &[File]) -> Self {
+    let entries = files
+      .iter()
+      .enumerate()
+      .map(|(file_idx, file)| {
+        let components = file
+          .path
+          .components()
+          .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            Component::Prefix(_)
+            | Component::RootDir
+            | Component::CurDir
+            | Component::ParentDir => None,
+          })
+          .collect();
+        (file_idx, components)
+      })
+      .collect();
+    let mut tree = Self::default();
+    tree.push_children(entries, 0, None);
+    tree
+  }
+
+  /// The nodes of the tree, with each directory followed by its children.
+  pub fn nodes(&self) -> &[DirectoryNode] {
+    &self.nodes
+  }
+
+  /// The index of the node for the file at `file_idx`.
+  pub fn file_node_idx(
+    &self,
+    file_idx: usize,
+  ) -> Option<usize> {
+    self
+      .nodes
+      .iter()
+      .position(|node| !node.is_directory && node.file_idxs == [file_idx])
+  }
+
+  fn push_children(
+    &mut self,
+    entries: Vec<Entry>,
+    depth: usize,
+    parent_idx: Option<usize>,
+  ) {
+    let mut children: Vec<Child> = Vec::new();
+    for (file_idx, mut components) in entries {
+      if components.len() <= 1 {
+        let name = components.pop().unwrap_or_default();
+        children.push(Child::File { name, file_idx });
+        continue;
+      }
+      let dir_name = components.remove(0);
+      let dir = children.iter_mut().find_map(|child| match child {
+        Child::Directory { name, entries } if *name == dir_name => Some(entries),
+        Child::Directory { .. } | Child::File { .. } => None,
+      });
+      match dir {
+        Some(entries) => entries.push((file_idx, components)),
+        None => children.push(Child::Directory {
+          name: dir_name,
+          entries: vec![(file_idx, components)],
+        }),
+      }
+    }
+
+    for child in children {
+      match child {
+        Child::File { name, file_idx } => {
+          self.nodes.push(DirectoryNode {
+            depth,
+            name,
+            is_directory: false,
+            parent_idx,
+            file_idxs: vec![file_idx],
+          });
+        }
+        Child::Directory {
+          mut name,
+          mut entries,
+        } => {
+          // Combine directories which only contain a single directory.
+          while let Some(dir_name) = single_directory(&entries) {
+            name.push('/');
+            name.push_str(&dir_name);
+            for (_, components) in &mut entries {
+              components.remove(0);
+            }
+          }
+          let mut file_idxs: Vec<usize> = entries.iter().map(|(file_idx, _)| *file_idx).collect();
+          file_idxs.sort_unstable();
+          let node_idx = self.nodes.len();
+          self.nodes.push(DirectoryNode {
+            depth,
+            name,
+            is_directory: true,
+            parent_idx,
+            file_idxs,
+          });
+          self.push_children(entries, depth + 1, Some(node_idx));
+        }
+      }
+    }
+  }
+}
+
+/// A child of a directory while the tree is being built.
+enum Child {
+  File { name: String, file_idx: usize },
+  Directory { name: String, entries: Vec<Entry> },
+}
+
+/// The name of the directory which contains every one of `entries`, if they're
+/// all in the same subdirectory.
+fn single_directory(entries: &[Entry]) -> Option<String> {
+  let (_, first_components) = entries.first()?;
+  let dir_name = match first_components.as_slice() {
+    [dir_name, _, ..] => dir_name,
+    [] | [_] => return None,
+  };
+  entries
+    .iter()
+    .all(|(_, components)| components.len() > 1 && components[0] == *dir_name)
+    .then(|| dir_name.clone())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::borrow::Cow;
+
+  use super::*;
+  use crate::FileMode;
+
+  fn file(path: &str) -> File<'static> {
+    File {
+      old_path: None,
+      path: Cow::Owned(path.into()),
+      file_mode: FileMode::FILE_DEFAULT,
+      sections: Vec::new(),
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
+      #[cfg(feature = "tree-sitter")]
+      containers: None,
+    }
+  }
+
+  fn describe(tree: &DirectoryTree) -> Vec<String> {
+    tree
+      .nodes()
+      .iter()
+      .map(|node| {
+        let DirectoryNode {
+          depth,
+          name,
+          is_directory,
+          parent_idx,
+          file_idxs,
+        } = node;
+        format!(
+          "{}{name}{} {file_idxs:?} parent={parent_idx:?}",
+          "  ".repeat(*depth),
+          if *is_directory { "/" } else { "" },
+        )
+      })
+      .collect()
+  }
+
+  #[test]
+  fn test_directory_tree() {
+    let files = [
+      file("README.md"),
+      file("src/legacy/foo.rs"),
+      file("src/legacy/bar.rs"),
+      file("src/lib.rs"),
+      file("tests/data/one/a.txt"),
+      file("src/legacy/old/baz.rs"),
+    ];
+    let tree = DirectoryTree::new(&files);
+    insta::assert_debug_snapshot!(describe(&tree), @r#"
+    [
+        "README.md [0] parent=None",
+        "src/ [1, 2, 3, 5] parent=None",
+        "  legacy/ [1, 2, 5] parent=Some(1)",
+        "    foo.rs [1] parent=Some(2)",
+        "    bar.rs [2] parent=Some(2)",
+        "    old/ [5] parent=Some(2)",
+        "      baz.rs [5] parent=Some(5)",
+        "  lib.rs [3] parent=Some(1)",
+        "tests/data/one/ [4] parent=None",
+        "  a.txt [4] parent=Some(8)",
+    ]
+    "#);
+    assert_eq!(tree.file_node_idx(2), Some(4));
+    assert_eq!(tree.file_node_idx(6), None);
+  }
+}
@@ -0,0 +1,252 @@
+//! Fuzzy matching of file paths and symbol names, for the picker opened with
+//! `Ctrl-p` in the recorder.
+//!
+//! A query matches a candidate if its characters appear in the candidate in
+//! order, not necessarily adjacent to each other. Matches are scored so that
+//! the characters of the query which are adjacent to each other, or at the
+//! start of a word or path component, rank higher, similarly to `fzf`.
+
+use crate::util::UsizeExt;
+
+/// The score for each matched character.
+const MATCH_SCORE: isize = 16;
+
+/// The additional score for a matched character directly after the previous
+/// matched character.
+const CONSECUTIVE_BONUS: isize = 8;
+
+/// The additional score for a matched character at the start of the
+/// candidate, or after a separator such as `/` or `_`.
+const BOUNDARY_BONUS: isize = 8;
+
+/// The additional score for a matched uppercase character after a lowercase
+/// one, as in `camelCase`.
+const CAMEL_CASE_BONUS: isize = 7;
+
+/// The penalty for each character skipped between two matched characters.
+const GAP_PENALTY: isize = 1;
+
+/// A match of a query in a candidate.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FuzzyMatch {
+  /// How well the query matches. Higher is better.
+  pub score: isize,
+
+  /// The byte offsets of the characters in the candidate which matched the
+  /// characters of the query.
+  pub indices: Vec<usize>,
+}
+
+/// Match `query` against `candidate`, returning `None` if the characters of
+/// the query don't all appear in order in the candidate. Whitespace in the
+/// query is ignored. The match is case-insensitive unless the query contains
+/// an uppercase letter.
+pub fn fuzzy_match(
+  query: &str,
+  candidate: &str,
+) -> Option<FuzzyMatch> {
+  let is_case_sensitive = query.chars().any(char::is_uppercase);
+  let normalize = |char: char| {
+    if is_case_sensitive {
+      char
+    } else {
+      char.to_lowercase().next().unwrap_or(char)
+    }
+  };
+  let query: Vec<char> = query
+    .chars()
+    .filter(|char| !char.is_whitespace())
+    .map(normalize)
+    .collect();
+  let candidate: Vec<(usize, char)> = candidate.char_indices().collect();
+  if query.is_empty() {
+    return Some(FuzzyMatch {
+      score: 0,
+      indices: Vec::new(),
+    });
+  }
+
+  let bonuses: Vec<isize> = candidate
+    .iter()
+    .enumerate()
+    .map(
+      |(idx, (_, char))| match idx.checked_sub(1).map(|idx| candidate[idx].1) {
+        None => BOUNDARY_BONUS,
+        Some(prev_char) if is_separator(prev_char) => BOUNDARY_BONUS,
+        Some(prev_char) if prev_char.is_lowercase() && char.is_uppercase() => CAMEL_CASE_BONUS,
+        Some(_) => 0,
+      },
+    )
+    .collect();
+
+  // `scores[query_idx][candidate_idx]` is the best score for matching the
+  // query up to `query_idx` with the character at `query_idx` matched at
+  // `candidate_idx`, along with the index at which the previous character of
+  // the query was matched.
+  let mut scores: Vec<Vec<Option<(isize, usize)>>> = vec![vec![None; candidate.len()]; query.len()];
+  for (query_idx, query_char) in query.iter().enumerate() {
+    // The best of `prev_scores[k] + k * GAP_PENALTY` over the indices `k`
+    // which are at least two characters before the current index, so that
+    // the penalty for the gap can be applied in constant time.
+    let mut best_gapped: Option<(isize, usize)> = None;
+    for (candidate_idx, (_, candidate_char)) in candidate.iter().enumerate() {
+      if query_idx > 0 && candidate_idx >= 2 {
+        let prev_idx = candidate_idx - 2;
+        if let Some((prev_score, _)) = scores[query_idx - 1][prev_idx] {
+          let score = prev_score + prev_idx.unwrap_isize() * GAP_PENALTY;
+          if best_gapped.is_none_or(|(best_score, _)| score > best_score) {
+            best_gapped = Some((score, prev_idx));
+          }
+        }
+      }
+      if normalize(*candidate_char) != *query_char {
+        continue;
+      }
+
+      let char_score = MATCH_SCORE + bonuses[candidate_idx];
+      scores[query_idx][candidate_idx] = if query_idx == 0 {
+        Some((char_score, candidate_idx))
+      } else {
+        let consecutive = candidate_idx
+          .checked_sub(1)
+          .and_then(|prev_idx| scores[query_idx - 1][prev_idx])
+          .map(|(prev_score, _)| {
+            (
+              prev_score + char_score + CONSECUTIVE_BONUS,
+              candidate_idx - 1,
+            )
+          });
+        let gapped = best_gapped.map(|(score, prev_idx)| {
+          (
+            score - (candidate_idx - 1).unwrap_isize() * GAP_PENALTY + char_score,
+            prev_idx,
+          )
+        });
+        match (consecutive, gapped) {
+          (Some(consecutive), Some(gapped)) if gapped.0 > consecutive.0 => Some(gapped),
+          (Some(consecutive), Some(_) | None) => Some(consecutive),
+          (None, gapped) => gapped,
+        }
+      };
+    }
+  }
+
+  let (mut candidate_idx, (score, _)) = scores[query.len() - 1]
+    .iter()
+    .enumerate()
+    .filter_map(|(candidate_idx, score)| score.map(|score| (candidate_idx, score)))
+    .max_by_key(|(candidate_idx, (score, _))| (*score, std::cmp::Reverse(*candidate_idx)))?;
+  let mut indices = vec![0; query.len()];
+  for query_idx in (0..query.len()).rev() {
+    indices[query_idx] = candidate[candidate_idx].0;
+    let (_, prev_idx) = scores[query_idx][candidate_idx]?;
+    candidate_idx = prev_idx;
+  }
+  Some(FuzzyMatch { score, indices })
+}
+
+fn is_separator(char: char) -> bool {
+  matches!(char, '/' | '\\' | '_' | '-' | '.' | ':' | ' ')
+}
+
+/// Match `query` against each of `candidates`, and return the indices of the
+/// candidates which match along with their matches, best first. Candidates
+/// with equal scores are ordered by length and then by their original order.
+/// If the query is empty, every candidate is returned in its original order.
+pub fn rank<'a>(
+  query: &str,
+  candidates: impl IntoIterator<Item = &'a str>,
+) -> Vec<(usize, FuzzyMatch)> {
+  if query.trim().is_empty() {
+    return candidates
+      .into_iter()
+      .enumerate()
+      .map(|(idx, _)| {
+        (
+          idx,
+          FuzzyMatch {
+            score: 0,
+            indices: Vec::new(),
+          },
+        )
+      })
+      .collect();
+  }
+
+  let mut matches: Vec<(usize, usize, FuzzyMatch)> = candidates
+    .into_iter()
+    .enumerate()
+    .filter_map(|(idx, candidate)| {
+      fuzzy_match(query, candidate).map(|fuzzy_match| (idx, candidate.len(), fuzzy_match))
+    })
+    .collect();
+  matches.sort_by_key(|(idx, len, fuzzy_match)| (std::cmp::Reverse(fuzzy_match.score), *len, *idx));
+  matches
+    .into_iter()
+    .map(|(idx, _, fuzzy_match)| (idx, fuzzy_match))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn matched_chars(
+    query: &str,
+    candidate: &str,
+  ) -> Option<String> {
+    fuzzy_match(query, candidate).map(|fuzzy_match| {
+      fuzzy_match
+        .indices
+        .iter()
+        .map(|idx| &candidate[*idx..][..1])
+        .collect()
+    })
+  }
+
+  #[test]
+  fn test_fuzzy_match() {
+    assert_eq!(matched_chars("fb", "foo/bar"), Some("fb".to_owned()));
+    assert_eq!(matched_chars("bf", "foo/bar"), None);
+    assert_eq!(matched_chars("", "foo/bar"), Some("".to_owned()));
+    assert_eq!(
+      matched_chars("foo bar", "foo/bar"),
+      Some("foobar".to_owned())
+    );
+
+    // The characters at the start of path components are preferred over
+    // earlier ones.
+    let fuzzy_match = fuzzy_match("ui", "src/build/ui.rs").unwrap();
+    assert_eq!(fuzzy_match.indices, vec![10, 11]);
+  }
+
+  #[test]
+  fn test_fuzzy_match_case() {
+    assert!(fuzzy_match("foo", "FooBar").is_some());
+    assert!(fuzzy_match("Foo", "foobar").is_none());
+    assert!(fuzzy_match("FB", "FooBar").is_some());
+  }
+
+  #[test]
+  fn test_rank() {
+    let candidates = [
+      "src/list/bin.rs",
+      "src/lib.rs",
+      "tests/test_lib.rs",
+      "README.md",
+    ];
+    let ranked: Vec<&str> = rank("lib", candidates)
+      .into_iter()
+      .map(|(idx, _)| candidates[idx])
+      .collect();
+    assert_eq!(
+      ranked,
+      vec!["src/lib.rs", "tests/test_lib.rs", "src/list/bin.rs"]
+    );
+    let unranked: Vec<usize> = rank("", candidates)
+      .into_iter()
+      .map(|(idx, _)| idx)
+      .collect();
+    assert_eq!(unranked, vec![0, 1, 2, 3]);
+  }
+}
@@ -24,17 +24,11 @@ impl RecordInput for CrosstermInput {
   }
 
   fn next_events(&mut self) -> Result<Vec<Event>, RecordError> {
-    // Ensure we block for at least one event.
-    let first_event = crossterm::event::read().map_err(RecordError::ReadInput)?;
-    let mut events = vec![first_event.into()];
-    // Some events, like scrolling, are generated more quickly than
-    // we can render the UI. In those cases, batch up all available
-    // events and process them before the next render.
-    while crossterm::event::poll(Duration::ZERO).map_err(RecordError::ReadInput)? {
-      let event = crossterm::event::read().map_err(RecordError::ReadInput)?;
-      events.push(event.into());
-    }
-    Ok(events)
+    read_crossterm_events(Event::from)
+  }
+
+  fn next_text_events(&mut self) -> Result<Vec<Event>, RecordError> {
+    read_crossterm_events(Event::from_text_input)
   }
 
   fn edit_commit_message(
@@ -45,6 +39,35 @@ impl RecordInput for CrosstermInput {
   }
 }
 
+fn read_crossterm_events(
+  convert: impl Fn(crossterm::event::Event) -> Event
+) -> Result<Vec<Event>, RecordError> {
+  // Ensure we block for at least one event.
+  let first_event = crossterm::event::read().map_err(RecordError::ReadInput)?;
+  let mut events = vec![convert(first_event)];
+  // Some events, like scrolling, are generated more quickly than
+  // we can render the UI. In those cases, batch up all available
+  // events and process them before the next render.
+  //
+  // Events which start or stop typing text end the batch, since the events
+  // after them need to be converted differently.
+  while !matches!(
+    events.last(),
+    Some(
+      Event::StartSearch { .. }
+        | Event::OpenPicker
+        | Event::TextBackspace
+        | Event::TextSubmit
+        | Event::TextCancel
+    )
+  ) && crossterm::event::poll(Duration::ZERO).map_err(RecordError::ReadInput)?
+  {
+    let event = crossterm::event::read().map_err(RecordError::ReadInput)?;
+    events.push(convert(event));
+  }
+  Ok(events)
+}
+
 /// Reads events from the provided sequence of events.
 pub struct TestingInput {
   /// The width of the virtual terminal in columns.
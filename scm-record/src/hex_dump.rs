@@ -0,0 +1,183 @@
+//! Hex dumps of the bytes which changed in a binary file.
+//!
+//! A [`Section::Binary`](crate::Section::Binary) can only be selected as a
+//! whole, but if its [`BinaryContents`](crate::BinaryContents) are provided,
+//! the old and new bytes are shown side by side so that the change can be
+//! reviewed before it's selected. Bytes are compared by offset, in rows of
+//! [`BYTES_PER_ROW`], and only the rows which differ are shown, along with
+//! [`NUM_CONTEXT_ROWS`] identical rows around them.
+
+/// The number of bytes shown in each row of the hex dump.
+pub const BYTES_PER_ROW: usize = 8;
+
+/// The number of identical rows shown before and after each differing row.
+pub const NUM_CONTEXT_ROWS: usize = 1;
+
+/// The maximum number of rows shown. Any further rows are summarized by a
+/// [`HexDumpLine::Truncated`].
+pub const MAX_ROWS: usize = 256;
+
+/// A line of a hex dump, as computed by [`hex_dump_lines`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HexDumpLine {
+  /// The row of bytes starting at `offset` in both the old and new contents.
+  /// Either side may have fewer than [`BYTES_PER_ROW`] bytes, or none, at
+  /// this offset.
+  Row {
+    /// The offset of the first byte of the row.
+    offset: usize,
+  },
+
+  /// Rows which are identical in the old and new contents, and aren't shown.
+  Omitted {
+    /// The number of rows omitted.
+    num_rows: usize,
+  },
+
+  /// The rest of the rows, which aren't shown because there are more than
+  /// [`MAX_ROWS`].
+  Truncated {
+    /// The number of rows not shown.
+    num_rows: usize,
+  },
+}
+
+/// Calculate which rows of `old` and `new` to show in a hex dump.
+pub fn hex_dump_lines(
+  old: &[u8],
+  new: &[u8],
+) -> Vec<HexDumpLine> {
+  let num_rows = old.len().max(new.len()).div_ceil(BYTES_PER_ROW);
+  let is_shown: Vec<bool> = {
+    let is_changed: Vec<bool> = (0..num_rows)
+      .map(|row_idx| {
+        let offset = row_idx * BYTES_PER_ROW;
+        row_bytes(old, offset) != row_bytes(new, offset)
+      })
+      .collect();
+    (0..num_rows)
+      .map(|row_idx| {
+        let start = row_idx.saturating_sub(NUM_CONTEXT_ROWS);
+        let end = (row_idx + NUM_CONTEXT_ROWS + 1).min(num_rows);
+        is_changed[start..end].iter().any(|is_changed| *is_changed)
+      })
+      .collect()
+  };
+
+  let mut lines = Vec::new();
+  let mut num_rows_shown = 0;
+  let mut num_omitted = 0;
+  for (row_idx, is_shown) in is_shown.iter().enumerate() {
+    if !is_shown {
+      num_omitted += 1;
+      continue;
+    }
+    if num_rows_shown == MAX_ROWS {
+      lines.push(HexDumpLine::Truncated {
+        num_rows: num_rows - row_idx,
+      });
+      return lines;
+    }
+    if num_omitted > 0 {
+      lines.push(HexDumpLine::Omitted {
+        num_rows: num_omitted,
+      });
+      num_omitted = 0;
+    }
+    lines.push(HexDumpLine::Row {
+      offset: row_idx * BYTES_PER_ROW,
+    });
+    num_rows_shown += 1;
+  }
+  if num_omitted > 0 {
+    lines.push(HexDumpLine::Omitted {
+      num_rows: num_omitted,
+    });
+  }
+  lines
+}
+
+/// The bytes of `bytes` in the row starting at `offset`, which may be fewer
+/// than [`BYTES_PER_ROW`] at the end of the contents.
+pub fn row_bytes(
+  bytes: &[u8],
+  offset: usize,
+) -> &[u8] {
+  let start = offset.min(bytes.len());
+  let end = (start + BYTES_PER_ROW).min(bytes.len());
+  &bytes[start..end]
+}
+
+/// The character to show for `byte` in the ASCII column of a hex dump.
+pub fn ascii_char(byte: u8) -> char {
+  if byte.is_ascii_graphic() || byte == b' ' {
+    char::from(byte)
+  } else {
+    '.'
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_hex_dump_lines() {
+    let old: Vec<u8> = (0..64).collect();
+    let mut new = old.clone();
+    new[30] = 0xff;
+    insta::assert_debug_snapshot!(hex_dump_lines(&old, &new), @r"
+    [
+        Omitted {
+            num_rows: 2,
+        },
+        Row {
+            offset: 16,
+        },
+        Row {
+            offset: 24,
+        },
+        Row {
+            offset: 32,
+        },
+        Omitted {
+            num_rows: 3,
+        },
+    ]
+    ");
+
+    // Rows past the end of the shorter side differ.
+    insta::assert_debug_snapshot!(hex_dump_lines(b"", b"abcdefghij"), @r"
+    [
+        Row {
+            offset: 0,
+        },
+        Row {
+            offset: 8,
+        },
+    ]
+    ");
+
+    assert_eq!(
+      hex_dump_lines(b"same", b"same"),
+      vec![HexDumpLine::Omitted { num_rows: 1 }]
+    );
+  }
+
+  #[test]
+  fn test_hex_dump_lines_truncated() {
+    let old = vec![0; BYTES_PER_ROW * (MAX_ROWS + 10)];
+    let new = vec![1; old.len()];
+    let lines = hex_dump_lines(&old, &new);
+    assert_eq!(lines.len(), MAX_ROWS + 1);
+    assert_eq!(lines.last(), Some(&HexDumpLine::Truncated { num_rows: 10 }));
+  }
+
+  #[test]
+  fn test_ascii_char() {
+    assert_eq!(ascii_char(b'a'), 'a');
+    assert_eq!(ascii_char(b' '), ' ');
+    assert_eq!(ascii_char(b'\n'), '.');
+    assert_eq!(ascii_char(0xff), '.');
+  }
+}
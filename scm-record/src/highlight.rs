@@ -0,0 +1,457 @@
+//! Syntax highlighting of the lines in a diff.
+//!
+//! [`SyntaxHighlights::detect`] reconstructs the old and new contents of each
+//! file from its sections, parses them with tree-sitter, and runs the
+//! highlights query for the file's [`SupportedLanguage`](crate::semantic::SupportedLanguage)
+//! over them. The runs of text between [`Section::Omitted`] lines are parsed
+//! separately, since a diff usually only contains parts of a file. Files in
+//! other languages, and every file when the `tree-sitter` feature is
+//! disabled, have no highlights.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::moved::LineLocation;
+use crate::File;
+#[cfg(feature = "tree-sitter")]
+use crate::{ChangeType, Section, SectionChangedLine};
+
+/// The kind of syntax in a part of a line, which determines its color.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum HighlightKind {
+  /// An attribute or annotation, like `#[derive(Debug)]`.
+  Attribute,
+
+  /// A comment.
+  Comment,
+
+  /// A constant, like `true` or `None`.
+  Constant,
+
+  /// The name of a function or macro.
+  Function,
+
+  /// A keyword, or a heading in Markdown.
+  Keyword,
+
+  /// A number.
+  Number,
+
+  /// The name of a field or property.
+  Property,
+
+  /// A string or character literal.
+  String,
+
+  /// The name of a type.
+  Type,
+}
+
+impl HighlightKind {
+  /// The kind of syntax captured by `name` in a highlights query, such as
+  /// `keyword` or `function.method`. Captures which aren't highlighted, like
+  /// `variable` and `punctuation.bracket`, return `None`.
+  pub fn from_capture_name(name: &str) -> Option<Self> {
+    match name {
+      "text.title" | "variable.builtin" => return Some(Self::Keyword),
+      "text.literal" | "text.uri" => return Some(Self::String),
+      _ => {}
+    }
+    let (category, _) = name.split_once('.').unwrap_or((name, ""));
+    match category {
+      "attribute" | "label" => Some(Self::Attribute),
+      "comment" => Some(Self::Comment),
+      "boolean" | "constant" => Some(Self::Constant),
+      "function" => Some(Self::Function),
+      "keyword" => Some(Self::Keyword),
+      "number" => Some(Self::Number),
+      "property" => Some(Self::Property),
+      "escape" | "string" => Some(Self::String),
+      "constructor" | "type" => Some(Self::Type),
+      _ => None,
+    }
+  }
+}
+
+/// A highlighted part of a line.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Highlight {
+  /// The byte range of the highlighted text in the line.
+  pub range: Range<usize>,
+
+  /// The kind of syntax in the range.
+  pub kind: HighlightKind,
+}
+
+/// The highlighted parts of the lines in a list of [`File`]s.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SyntaxHighlights {
+  files: HashMap<usize, HashMap<(usize, usize), Vec<Highlight>>>,
+}
+
+impl SyntaxHighlights {
+  /// Highlight the lines in `files` which are in a supported language.
+  /// Unchanged lines are highlighted as part of the new contents of their
+  /// file.
+  pub fn detect(files: &[File]) -> Self {
+    let mut syntax_highlights = Self::default();
+    for file_idx in 0..files.len() {
+      syntax_highlights.update_file(files, file_idx);
+    }
+    syntax_highlights
+  }
+
+  /// Highlight the lines in the file at `file_idx` again, after its sections
+  /// have changed.
+  pub fn update_file(
+    &mut self,
+    files: &[File],
+    file_idx: usize,
+  ) {
+    self.files.remove(&file_idx);
+    if let Some(lines) = files.get(file_idx).and_then(highlight_file) {
+      self.files.insert(file_idx, lines);
+    }
+  }
+
+  /// Whether the file at `file_idx` is in a supported language, so that its
+  /// lines are highlighted, even if some of them have no highlights.
+  pub fn is_highlighted(
+    &self,
+    file_idx: usize,
+  ) -> bool {
+    self.files.contains_key(&file_idx)
+  }
+
+  /// The highlights of the line at `location`, in order. The location may be
+  /// in a [`Section::Unchanged`] as well as a [`Section::Changed`].
+  pub fn line(
+    &self,
+    location: LineLocation,
+  ) -> &[Highlight] {
+    let LineLocation {
+      file_idx,
+      section_idx,
+      line_idx,
+    } = location;
+    self
+      .files
+      .get(&file_idx)
+      .and_then(|lines| lines.get(&(section_idx, line_idx)))
+      .map(Vec::as_slice)
+      .unwrap_or_default()
+  }
+}
+
+/// One version of a run of lines in a file which aren't interrupted by
+/// omitted lines.
+#[cfg(feature = "tree-sitter")]
+#[derive(Default)]
+struct Chunk {
+  source: String,
+
+  /// The section index and line index of each line to highlight, and the
+  /// byte range of the line in `source`.
+  lines: Vec<((usize, usize), Range<usize>)>,
+}
+
+#[cfg(feature = "tree-sitter")]
+impl Chunk {
+  fn push_line(
+    &mut self,
+    key: Option<(usize, usize)>,
+    line: &str,
+  ) {
+    let start = self.source.len();
+    self.source.push_str(line);
+    if let Some(key) = key {
+      self.lines.push((key, start..self.source.len()));
+    }
+  }
+}
+
+#[cfg(feature = "tree-sitter")]
+fn highlight_file(file: &File) -> Option<HashMap<(usize, usize), Vec<Highlight>>> {
+  use crate::semantic::{create_parser, SupportedLanguage};
+
+  let language = SupportedLanguage::from_path(&file.path)?;
+  let mut parser = create_parser(language).ok()?;
+  let query = match tree_sitter::Query::new(
+    &language.tree_sitter_language(),
+    language.highlights_query(),
+  ) {
+    Ok(query) => query,
+    Err(err) => {
+      tracing::warn!(language = language.name(), ?err, "Invalid highlights query");
+      return None;
+    }
+  };
+  let capture_kinds: Vec<Option<HighlightKind>> = query
+    .capture_names()
+    .iter()
+    .map(|name| HighlightKind::from_capture_name(name))
+    .collect();
+
+  let mut lines = HashMap::new();
+  for (old_chunk, new_chunk) in chunks(file) {
+    for chunk in [old_chunk, new_chunk] {
+      if chunk.lines.is_empty() {
+        continue;
+      }
+      // Tree-sitter recovers from syntax errors, so parts of a file still
+      // produce a tree, if not always the one for the whole file.
+      let tree = match parser.parse(&chunk.source, None) {
+        Some(tree) => tree,
+        None => continue,
+      };
+      let byte_kinds = highlight_bytes(&query, &capture_kinds, &tree, &chunk.source);
+      for (key, line_range) in chunk.lines {
+        lines.insert(key, line_highlights(&byte_kinds[line_range]));
+      }
+    }
+  }
+  Some(lines)
+}
+
+#[cfg(not(feature = "tree-sitter"))]
+fn highlight_file(_file: &File) -> Option<HashMap<(usize, usize), Vec<Highlight>>> {
+  None
+}
+
+/// The old and new versions of each run of lines in `file` which aren't
+/// interrupted by omitted lines.
+#[cfg(feature = "tree-sitter")]
+fn chunks(file: &File) -> Vec<(Chunk, Chunk)> {
+  let mut chunks = vec![(Chunk::default(), Chunk::default())];
+  for (section_idx, section) in file.sections.iter().enumerate() {
+    let (old_chunk, new_chunk) = chunks.last_mut().expect("chunks should not be empty");
+    match section {
+      Section::Unchanged { lines } => {
+        for (line_idx, line) in lines.iter().enumerate() {
+          old_chunk.push_line(None, line);
+          new_chunk.push_line(Some((section_idx, line_idx)), line);
+        }
+      }
+      Section::Changed { lines } => {
+        for (line_idx, line) in lines.iter().enumerate() {
+          let SectionChangedLine {
+            is_checked: _,
+            change_type,
+            line,
+          } = line;
+          let chunk = match change_type {
+            ChangeType::Removed => &mut *old_chunk,
+            ChangeType::Added => &mut *new_chunk,
+          };
+          chunk.push_line(Some((section_idx, line_idx)), line);
+        }
+      }
+      Section::Omitted { num_lines: _ } => {
+        chunks.push((Chunk::default(), Chunk::default()));
+      }
+      Section::FileMode { .. }
+      | Section::Rename { .. }
+      | Section::Binary { .. }
+      | Section::Symlink { .. }
+      | Section::Submodule { .. } => {}
+    }
+  }
+  chunks
+}
+
+/// The kind of syntax of each byte in `source`. Where captures overlap, the
+/// smaller node wins, and for the same node, the earlier pattern in the query
+/// wins, as with `tree-sitter highlight`.
+#[cfg(feature = "tree-sitter")]
+fn highlight_bytes(
+  query: &tree_sitter::Query,
+  capture_kinds: &[Option<HighlightKind>],
+  tree: &tree_sitter::Tree,
+  source: &str,
+) -> Vec<Option<HighlightKind>> {
+  use streaming_iterator::StreamingIterator;
+
+  let mut captures: Vec<(Range<usize>, usize, Option<HighlightKind>)> = Vec::new();
+  let mut cursor = tree_sitter::QueryCursor::new();
+  let mut matches = cursor.matches(query, tree.root_node(), source.as_bytes());
+  while let Some(query_match) = matches.next() {
+    for capture in query_match.captures {
+      let kind = capture_kinds
+        .get(usize::try_from(capture.index).unwrap_or(usize::MAX))
+        .copied()
+        .flatten();
+      captures.push((capture.node.byte_range(), query_match.pattern_index, kind));
+    }
+  }
+  captures.sort_by_key(|(range, pattern_idx, _)| {
+    (
+      std::cmp::Reverse(range.len()),
+      std::cmp::Reverse(*pattern_idx),
+    )
+  });
+
+  let mut byte_kinds = vec![None; source.len()];
+  for (range, _, kind) in captures {
+    let range = range.start.min(source.len())..range.end.min(source.len());
+    byte_kinds[range].fill(kind);
+  }
+  byte_kinds
+}
+
+#[cfg(feature = "tree-sitter")]
+fn line_highlights(byte_kinds: &[Option<HighlightKind>]) -> Vec<Highlight> {
+  let mut highlights: Vec<Highlight> = Vec::new();
+  for (byte_idx, kind) in byte_kinds.iter().enumerate() {
+    let kind = match kind {
+      Some(kind) => *kind,
+      None => continue,
+    };
+    match highlights.last_mut() {
+      Some(highlight) if highlight.kind == kind && highlight.range.end == byte_idx => {
+        highlight.range.end += 1;
+      }
+      Some(_) | None => highlights.push(Highlight {
+        range: byte_idx..byte_idx + 1,
+        kind,
+      }),
+    }
+  }
+  highlights
+}
+
+#[cfg(all(test, feature = "tree-sitter"))]
+mod tests {
+  use std::borrow::Cow;
+
+  use super::*;
+  use crate::semantic::SupportedLanguage;
+  use crate::FileMode;
+
+  fn describe(
+    files: &[File],
+    syntax_highlights: &SyntaxHighlights,
+  ) -> Vec<String> {
+    let mut result = Vec::new();
+    for (file_idx, file) in files.iter().enumerate() {
+      for (section_idx, section) in file.sections.iter().enumerate() {
+        let lines: Vec<&str> = match section {
+          Section::Unchanged { lines } => lines.iter().map(|line| line.as_ref()).collect(),
+          Section::Changed { lines } => lines.iter().map(|line| line.line.as_ref()).collect(),
+          Section::Omitted { .. }
+          | Section::FileMode { .. }
+          | Section::Rename { .. }
+          | Section::Binary { .. }
+          | Section::Symlink { .. }
+          | Section::Submodule { .. } => continue,
+        };
+        for (line_idx, line) in lines.into_iter().enumerate() {
+          let highlights = syntax_highlights
+            .line(LineLocation {
+              file_idx,
+              section_idx,
+              line_idx,
+            })
+            .iter()
+            .map(|Highlight { range, kind }| format!("{kind:?}({:?})", &line[range.clone()]))
+            .collect::<Vec<_>>();
+          result.push(format!("{line:?}: {}", highlights.join(" ")));
+        }
+      }
+    }
+    result
+  }
+
+  fn file<'a>(
+    path: &'a str,
+    sections: Vec<Section<'a>>,
+  ) -> File<'a> {
+    File {
+      old_path: None,
+      path: Cow::Borrowed(std::path::Path::new(path)),
+      file_mode: FileMode::FILE_DEFAULT,
+      sections,
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
+      containers: None,
+    }
+  }
+
+  fn changed_line(
+    change_type: ChangeType,
+    line: &str,
+  ) -> SectionChangedLine<'_> {
+    SectionChangedLine {
+      is_checked: false,
+      change_type,
+      line: Cow::Borrowed(line),
+    }
+  }
+
+  #[test]
+  fn test_syntax_highlights() {
+    let files = [
+      file(
+        "foo.rs",
+        vec![
+          Section::Unchanged {
+            lines: vec![Cow::Borrowed("/* a comment\n")],
+          },
+          Section::Omitted {
+            num_lines: Some(10),
+          },
+          Section::Unchanged {
+            lines: vec![Cow::Borrowed("fn foo() {\n")],
+          },
+          Section::Changed {
+            lines: vec![
+              changed_line(ChangeType::Removed, "    let x = 1;\n"),
+              changed_line(ChangeType::Added, "    let x = \"one\";\n"),
+            ],
+          },
+          Section::Unchanged {
+            lines: vec![Cow::Borrowed("}\n")],
+          },
+        ],
+      ),
+      file(
+        "foo.txt",
+        vec![Section::Changed {
+          lines: vec![changed_line(ChangeType::Added, "fn foo() {}\n")],
+        }],
+      ),
+    ];
+    let syntax_highlights = SyntaxHighlights::detect(&files);
+    insta::assert_debug_snapshot!(describe(&files, &syntax_highlights), @r#"
+    [
+        "\"/* a comment\\n\": Comment(\"/* a comment\")",
+        "\"fn foo() {\\n\": Keyword(\"fn\") Function(\"foo\")",
+        "\"    let x = 1;\\n\": Keyword(\"let\") Constant(\"1\")",
+        "\"    let x = \\\"one\\\";\\n\": Keyword(\"let\") String(\"\\\"one\\\"\")",
+        "\"}\\n\": ",
+        "\"fn foo() {}\\n\": ",
+    ]
+    "#);
+    assert!(syntax_highlights.is_highlighted(0));
+    assert!(!syntax_highlights.is_highlighted(1));
+  }
+
+  #[test]
+  fn test_highlights_queries_are_valid() {
+    for language in [
+      SupportedLanguage::Rust,
+      SupportedLanguage::Kotlin,
+      SupportedLanguage::Java,
+      SupportedLanguage::Hcl,
+      SupportedLanguage::Python,
+      SupportedLanguage::Markdown,
+      SupportedLanguage::Yaml,
+    ] {
+      let query = tree_sitter::Query::new(
+        &language.tree_sitter_language(),
+        language.highlights_query(),
+      );
+      assert!(query.is_ok(), "{}: {query:?}", language.name());
+    }
+  }
+}
@@ -0,0 +1,424 @@
+//! Word-level differences between removed and added lines.
+//!
+//! A [`Section::Changed`](crate::Section::Changed) only records which whole
+//! lines were removed and added, so a one-character edit in a long line is
+//! hard to spot. [`pair_changed_lines`] matches each removed line with the
+//! added line that most likely replaced it and finds the byte ranges of each
+//! line which actually differ, so they can be emphasized when rendering.
+
+use std::ops::Range;
+
+use crate::{ChangeType, SectionChangedLine};
+
+/// Pairs whose token tables would be larger than this aren't diffed, since
+/// the lines are probably not edits of each other anyway.
+const MAX_TOKEN_PAIRS: usize = 40_000;
+
+/// A removed line and the added line which replaced it, along with the parts
+/// of each line which changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChangedLinePair {
+  /// The index of the removed line in the section's lines.
+  pub removed_line_idx: usize,
+
+  /// The index of the added line in the section's lines.
+  pub added_line_idx: usize,
+
+  /// The byte ranges of the removed line which aren't in the added line.
+  pub removed_ranges: Vec<Range<usize>>,
+
+  /// The byte ranges of the added line which aren't in the removed line.
+  pub added_ranges: Vec<Range<usize>>,
+}
+
+/// The parts of two lines which differ, as computed by [`diff_words`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WordDiff {
+  /// The byte ranges of the old line which aren't in the new line.
+  pub old_ranges: Vec<Range<usize>>,
+
+  /// The byte ranges of the new line which aren't in the old line.
+  pub new_ranges: Vec<Range<usize>>,
+}
+
+/// Pair up the removed and added lines of a changed section.
+///
+/// Like `git diff --word-diff`, each run of removed lines followed by a run
+/// of added lines is paired positionally: the first removed line with the
+/// first added line, and so on. Pairs which have too little in common to be
+/// edits of each other are omitted, as are lines without a counterpart.
+pub fn pair_changed_lines(lines: &[SectionChangedLine]) -> Vec<ChangedLinePair> {
+  let mut result = Vec::new();
+  for (removed, added) in removed_added_runs(lines) {
+    for (removed_line_idx, added_line_idx) in removed.zip(added) {
+      if let Some(WordDiff {
+        old_ranges,
+        new_ranges,
+      }) = diff_words(&lines[removed_line_idx].line, &lines[added_line_idx].line)
+      {
+        result.push(ChangedLinePair {
+          removed_line_idx,
+          added_line_idx,
+          removed_ranges: old_ranges,
+          added_ranges: new_ranges,
+        });
+      }
+    }
+  }
+  result
+}
+
+/// Arrange the lines of a changed section into rows for showing the removed
+/// lines next to the added lines, as the indices of the removed line and the
+/// added line in each row.
+///
+/// Lines are paired positionally as in [`pair_changed_lines`], but regardless
+/// of how similar they are. A line without a counterpart is shown alone.
+pub fn align_changed_lines(lines: &[SectionChangedLine]) -> Vec<(Option<usize>, Option<usize>)> {
+  let mut result = Vec::new();
+  for (removed, added) in removed_added_runs(lines) {
+    let num_rows = removed.len().max(added.len());
+    for offset in 0..num_rows {
+      let removed_line_idx = removed.start + offset;
+      let added_line_idx = added.start + offset;
+      result.push((
+        removed
+          .contains(&removed_line_idx)
+          .then_some(removed_line_idx),
+        added.contains(&added_line_idx).then_some(added_line_idx),
+      ));
+    }
+  }
+  result
+}
+
+/// Split the lines of a changed section into runs of removed lines, each
+/// followed by a run of added lines. Either run may be empty.
+fn removed_added_runs(lines: &[SectionChangedLine]) -> Vec<(Range<usize>, Range<usize>)> {
+  let mut result = Vec::new();
+  let mut line_idx = 0;
+  while line_idx < lines.len() {
+    let removed_start = line_idx;
+    while line_idx < lines.len() && lines[line_idx].change_type == ChangeType::Removed {
+      line_idx += 1;
+    }
+    let added_start = line_idx;
+    while line_idx < lines.len() && lines[line_idx].change_type == ChangeType::Added {
+      line_idx += 1;
+    }
+    result.push((removed_start..added_start, added_start..line_idx));
+  }
+  result
+}
+
+/// Get the byte ranges to emphasize for each line of a changed section.
+///
+/// The result has one entry per line, which is empty for lines which weren't
+/// paired by [`pair_changed_lines`].
+pub fn inline_changes(lines: &[SectionChangedLine]) -> Vec<Vec<Range<usize>>> {
+  let mut result = vec![Vec::new(); lines.len()];
+  for pair in pair_changed_lines(lines) {
+    let ChangedLinePair {
+      removed_line_idx,
+      added_line_idx,
+      removed_ranges,
+      added_ranges,
+    } = pair;
+    result[removed_line_idx] = removed_ranges;
+    result[added_line_idx] = added_ranges;
+  }
+  result
+}
+
+/// Compare two lines word by word.
+///
+/// Returns `None` if less than half of the non-whitespace text is shared
+/// between the lines.
+pub fn diff_words(
+  old: &str,
+  new: &str,
+) -> Option<WordDiff> {
+  let old_tokens = tokenize(old);
+  let new_tokens = tokenize(new);
+  if old_tokens.len().saturating_mul(new_tokens.len()) > MAX_TOKEN_PAIRS {
+    return None;
+  }
+
+  // Compute the longest common subsequence of tokens. `lengths[i][j]` is the
+  // length of the LCS of `old_tokens[i..]` and `new_tokens[j..]`.
+  let mut lengths = vec![vec![0usize; new_tokens.len() + 1]; old_tokens.len() + 1];
+  for i in (0..old_tokens.len()).rev() {
+    for j in (0..new_tokens.len()).rev() {
+      lengths[i][j] = if old_tokens[i] == new_tokens[j] {
+        lengths[i + 1][j + 1] + 1
+      } else {
+        lengths[i + 1][j].max(lengths[i][j + 1])
+      };
+    }
+  }
+
+  let mut old_is_common = vec![false; old_tokens.len()];
+  let mut new_is_common = vec![false; new_tokens.len()];
+  let (mut i, mut j) = (0, 0);
+  while i < old_tokens.len() && j < new_tokens.len() {
+    if old_tokens[i] == new_tokens[j] {
+      old_is_common[i] = true;
+      new_is_common[j] = true;
+      i += 1;
+      j += 1;
+    } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+      i += 1;
+    } else {
+      j += 1;
+    }
+  }
+
+  let common_len: usize = old_tokens
+    .iter()
+    .zip(&old_is_common)
+    .filter(|(token, is_common)| **is_common && !is_whitespace(token))
+    .map(|(token, _)| token.len())
+    .sum();
+  let total_len = non_whitespace_len(old).max(non_whitespace_len(new));
+  if common_len * 2 < total_len {
+    return None;
+  }
+
+  Some(WordDiff {
+    old_ranges: changed_ranges(old, &old_tokens, &old_is_common),
+    new_ranges: changed_ranges(new, &new_tokens, &new_is_common),
+  })
+}
+
+/// Split a line into words, runs of whitespace, and individual punctuation
+/// characters.
+fn tokenize(line: &str) -> Vec<&str> {
+  #[derive(PartialEq)]
+  enum CharClass {
+    Word,
+    Whitespace,
+    Other,
+  }
+  let classify = |c: char| {
+    if c.is_alphanumeric() || c == '_' {
+      CharClass::Word
+    } else if c.is_whitespace() {
+      CharClass::Whitespace
+    } else {
+      CharClass::Other
+    }
+  };
+
+  let mut result = Vec::new();
+  let mut start = 0;
+  let mut prev_class = None;
+  for (idx, c) in line.char_indices() {
+    let class = classify(c);
+    let continues_token = match &prev_class {
+      Some(prev_class) => *prev_class == class && class != CharClass::Other,
+      None => true,
+    };
+    if !continues_token {
+      result.push(&line[start..idx]);
+      start = idx;
+    }
+    prev_class = Some(class);
+  }
+  if start < line.len() {
+    result.push(&line[start..]);
+  }
+  result
+}
+
+fn is_whitespace(token: &str) -> bool {
+  token.chars().all(char::is_whitespace)
+}
+
+fn non_whitespace_len(line: &str) -> usize {
+  line
+    .chars()
+    .filter(|c| !c.is_whitespace())
+    .map(char::len_utf8)
+    .sum()
+}
+
+/// Convert the tokens which aren't part of the common subsequence into byte
+/// ranges of `line`. Changed tokens separated only by whitespace are merged
+/// into one range so that a changed phrase is emphasized as a whole.
+fn changed_ranges(
+  line: &str,
+  tokens: &[&str],
+  is_common: &[bool],
+) -> Vec<Range<usize>> {
+  let mut result: Vec<Range<usize>> = Vec::new();
+  let mut offset = 0;
+  for (token, is_common) in tokens.iter().zip(is_common) {
+    let range = offset..offset + token.len();
+    offset = range.end;
+    if *is_common {
+      continue;
+    }
+    match result.last_mut() {
+      Some(last) if is_whitespace(&line[last.end..range.start]) => last.end = range.end,
+      _ => result.push(range),
+    }
+  }
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use std::borrow::Cow;
+
+  use super::*;
+
+  fn changed_line(
+    change_type: ChangeType,
+    line: &'static str,
+  ) -> SectionChangedLine<'static> {
+    SectionChangedLine {
+      is_checked: false,
+      change_type,
+      line: Cow::Borrowed(line),
+    }
+  }
+
+  fn show_ranges(
+    line: &str,
+    ranges: &[Range<usize>],
+  ) -> String {
+    ranges
+      .iter()
+      .map(|range| format!("[{}]", &line[range.clone()]))
+      .collect::<Vec<_>>()
+      .join(" ")
+  }
+
+  #[test]
+  fn test_tokenize() {
+    insta::assert_debug_snapshot!(tokenize("  let foo_bar = baz(1, 2);\n"), @r#"
+    [
+        "  ",
+        "let",
+        " ",
+        "foo_bar",
+        " ",
+        "=",
+        " ",
+        "baz",
+        "(",
+        "1",
+        ",",
+        " ",
+        "2",
+        ")",
+        ";",
+        "\n",
+    ]
+    "#);
+  }
+
+  #[test]
+  fn test_diff_words() {
+    let diff = |old: &str, new: &str| {
+      diff_words(old, new).map(
+        |WordDiff {
+           old_ranges,
+           new_ranges,
+         }| { (show_ranges(old, &old_ranges), show_ranges(new, &new_ranges)) },
+      )
+    };
+    insta::assert_debug_snapshot!(diff("let x = foo(1, 2);\n", "let x = foo(1, 3);\n"), @r#"
+    Some(
+        (
+            "[2]",
+            "[3]",
+        ),
+    )
+    "#);
+    insta::assert_debug_snapshot!(diff("the quick brown fox\n", "the slow red fox\n"), @"None");
+    insta::assert_debug_snapshot!(diff("same\n", "same"), @r#"
+    Some(
+        (
+            "[\n]",
+            "",
+        ),
+    )
+    "#);
+    insta::assert_debug_snapshot!(diff("abc\n", "abc\n"), @r#"
+    Some(
+        (
+            "",
+            "",
+        ),
+    )
+    "#);
+    insta::assert_debug_snapshot!(diff("completely different\n", "nothing alike here\n"), @"None");
+  }
+
+  #[test]
+  fn test_pair_changed_lines() {
+    let lines = [
+      changed_line(ChangeType::Removed, "foo(a);\n"),
+      changed_line(ChangeType::Removed, "bar(b);\n"),
+      changed_line(ChangeType::Removed, "baz(c);\n"),
+      changed_line(ChangeType::Added, "foo(x);\n"),
+      changed_line(ChangeType::Added, "bar(b, c);\n"),
+      changed_line(ChangeType::Added, "qux\n"),
+      changed_line(ChangeType::Added, "extra line\n"),
+      changed_line(ChangeType::Removed, "trailing removal\n"),
+    ];
+    let pairs = pair_changed_lines(&lines);
+    insta::assert_debug_snapshot!(pairs, @"
+    [
+        ChangedLinePair {
+            removed_line_idx: 0,
+            added_line_idx: 3,
+            removed_ranges: [
+                4..5,
+            ],
+            added_ranges: [
+                4..5,
+            ],
+        },
+        ChangedLinePair {
+            removed_line_idx: 1,
+            added_line_idx: 4,
+            removed_ranges: [],
+            added_ranges: [
+                5..8,
+            ],
+        },
+    ]
+    ");
+
+    let inline_changes = inline_changes(&lines);
+    assert_eq!(inline_changes.len(), lines.len());
+    assert_eq!(inline_changes[0], pairs[0].removed_ranges);
+    assert_eq!(inline_changes[3], pairs[0].added_ranges);
+    assert!(inline_changes[6].is_empty());
+    assert!(inline_changes[7].is_empty());
+  }
+
+  #[test]
+  fn test_align_changed_lines() {
+    let lines = [
+      changed_line(ChangeType::Added, "header\n"),
+      changed_line(ChangeType::Removed, "foo(a);\n"),
+      changed_line(ChangeType::Removed, "bar(b);\n"),
+      changed_line(ChangeType::Removed, "baz(c);\n"),
+      changed_line(ChangeType::Added, "foo(x);\n"),
+      changed_line(ChangeType::Removed, "trailing removal\n"),
+    ];
+    assert_eq!(
+      align_changed_lines(&lines),
+      vec![
+        (None, Some(0)),
+        (Some(1), Some(4)),
+        (Some(2), None),
+        (Some(3), None),
+        (Some(5), None),
+      ]
+    );
+  }
+}
@@ -0,0 +1,1231 @@
+//! A stable, versioned JSON format for [`RecordState`].
+//!
+//! The `serde` derives on [`RecordState`] and its fields mirror the Rust
+//! types, so their JSON representation changes whenever the types do. This
+//! module instead defines a documented wire format which only changes along
+//! with [`INTERCHANGE_FORMAT_VERSION`], so that front ends written in other
+//! languages can produce and consume recorder state. The format is described
+//! by the JSON Schema in [`JSON_SCHEMA`].
+//!
+//! Documents written by older versions are migrated when read by
+//! [`from_json`]. A document without a `version` field is assumed to have
+//! been written with the `serde` derives of scm-record 0.8, which is treated
+//! as version 0. Version 2 added file similarity and unloaded files, rename,
+//! symlink, and submodule sections, the contents of binary sections, and
+//! omitted sections with an unknown number of lines. Version 1 documents are
+//! read with their own types, so that they can't contain any of these, and
+//! then upgraded. The version 1 schema is still published in
+//! `schema/record-state.v1.schema.json`.
+//!
+//! Semantic containers aren't part of the format, since they are derived
+//! from the file contents and can be recalculated by the consumer.
+
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  BinaryContents, ChangeType, Commit, CommitAssignment, File, FileMode, FileStats, RecordError,
+  RecordState, Section, SectionChangedLine,
+};
+
+/// The current version of the interchange format, as written by [`to_json`].
+pub const INTERCHANGE_FORMAT_VERSION: u64 = 2;
+
+/// The JSON Schema describing the current version of the interchange format.
+pub const JSON_SCHEMA: &str = include_str!("../schema/record-state.v2.schema.json");
+
+/// Serialize `state` in the current version of the interchange format.
+pub fn to_json(state: &RecordState) -> Result<String, RecordError> {
+  serde_json::to_string_pretty(&WireState::from(state)).map_err(RecordError::SerializeJson)
+}
+
+/// Deserialize a [`RecordState`] written in any supported version of the
+/// interchange format, migrating it to the current version if necessary.
+pub fn from_json(json: &str) -> Result<RecordState<'static>, RecordError> {
+  let value: serde_json::Value =
+    serde_json::from_str(json).map_err(RecordError::DeserializeJson)?;
+  let version = match value.get("version") {
+    None => 0,
+    Some(version) => version.as_u64().ok_or_else(|| {
+      RecordError::Other(format!("invalid interchange format version: {version}"))
+    })?,
+  };
+  let wire_state = match version {
+    0 => serde_json::from_value::<legacy::State>(value)
+      .map_err(RecordError::DeserializeJson)?
+      .into(),
+    1 => serde_json::from_value::<v1::State>(value)
+      .map_err(RecordError::DeserializeJson)?
+      .into(),
+    INTERCHANGE_FORMAT_VERSION => {
+      serde_json::from_value::<WireState>(value).map_err(RecordError::DeserializeJson)?
+    }
+    version => return Err(RecordError::UnsupportedInterchangeVersion(version)),
+  };
+  wire_state.try_into()
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct WireState {
+  version: u64,
+  is_read_only: bool,
+  commits: Vec<WireCommit>,
+  files: Vec<WireFile>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct WireCommit {
+  message: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct WireFile {
+  old_path: Option<String>,
+  path: String,
+  /// The octal Unix file mode, or `None` if the file is absent.
+  file_mode: Option<String>,
+  sections: Vec<WireSection>,
+  #[serde(default)]
+  commit_assignments: Vec<WireCommitAssignment>,
+  #[serde(default)]
+  similarity: Option<u8>,
+  #[serde(default)]
+  unloaded: Option<WireFileStats>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct WireFileStats {
+  num_added: Option<usize>,
+  num_removed: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WireSection {
+  Unchanged {
+    lines: Vec<String>,
+  },
+  Omitted {
+    /// `None` if the number of lines isn't known.
+    num_lines: Option<usize>,
+  },
+  Changed {
+    lines: Vec<WireChangedLine>,
+  },
+  FileMode {
+    is_checked: bool,
+    mode: Option<String>,
+  },
+  Rename {
+    is_checked: bool,
+    is_copy: bool,
+  },
+  Binary {
+    is_checked: bool,
+    old_description: Option<String>,
+    new_description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    contents: Option<WireBinaryContents>,
+  },
+  Symlink {
+    is_checked: bool,
+    old_target: Option<String>,
+    new_target: Option<String>,
+  },
+  Submodule {
+    is_checked: bool,
+    old_commit: Option<String>,
+    new_commit: Option<String>,
+  },
+}
+
+/// Binary contents, as lowercase hexadecimal strings.
+#[derive(Debug, Deserialize, Serialize)]
+struct WireBinaryContents {
+  old: String,
+  new: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct WireChangedLine {
+  is_checked: bool,
+  change_type: WireChangeType,
+  line: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum WireChangeType {
+  Added,
+  Removed,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct WireCommitAssignment {
+  section_idx: usize,
+  line_idx: usize,
+  commit_idx: usize,
+}
+
+fn file_mode_to_wire(file_mode: FileMode) -> Option<String> {
+  match file_mode {
+    FileMode::Unix(mode) => Some(format!("{mode:o}")),
+    FileMode::Absent => None,
+  }
+}
+
+fn bytes_to_hex(bytes: &[u8]) -> String {
+  let mut result = String::with_capacity(bytes.len() * 2);
+  for byte in bytes {
+    write!(result, "{byte:02x}").unwrap();
+  }
+  result
+}
+
+fn bytes_from_hex(hex: &str) -> Result<Vec<u8>, RecordError> {
+  let invalid = || RecordError::Other(format!("invalid hexadecimal contents: {hex:?}"));
+  if hex.len() % 2 != 0 {
+    return Err(invalid());
+  }
+  (0..hex.len())
+    .step_by(2)
+    .map(|idx| {
+      hex
+        .get(idx..idx + 2)
+        .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        .ok_or_else(invalid)
+    })
+    .collect()
+}
+
+fn file_mode_from_wire(file_mode: Option<String>) -> Result<FileMode, RecordError> {
+  match file_mode {
+    None => Ok(FileMode::Absent),
+    Some(mode) => match usize::from_str_radix(&mode, 8) {
+      Ok(mode) => Ok(FileMode::Unix(mode)),
+      Err(_) => Err(RecordError::Other(format!("invalid file mode: {mode:?}"))),
+    },
+  }
+}
+
+impl From<&RecordState<'_>> for WireState {
+  fn from(state: &RecordState) -> Self {
+    let RecordState {
+      is_read_only,
+      commits,
+      files,
+    } = state;
+    Self {
+      version: INTERCHANGE_FORMAT_VERSION,
+      is_read_only: *is_read_only,
+      commits: commits
+        .iter()
+        .map(|Commit { message }| WireCommit {
+          message: message.clone(),
+        })
+        .collect(),
+      files: files.iter().map(WireFile::from).collect(),
+    }
+  }
+}
+
+impl From<&File<'_>> for WireFile {
+  fn from(file: &File) -> Self {
+    let File {
+      old_path,
+      path,
+      file_mode,
+      sections,
+      commit_assignments,
+      similarity,
+      unloaded,
+      #[cfg(feature = "tree-sitter")]
+        containers: _,
+    } = file;
+    Self {
+      old_path: old_path
+        .as_ref()
+        .map(|old_path| old_path.to_string_lossy().into_owned()),
+      path: path.to_string_lossy().into_owned(),
+      file_mode: file_mode_to_wire(*file_mode),
+      sections: sections.iter().map(WireSection::from).collect(),
+      commit_assignments: commit_assignments
+        .iter()
+        .map(
+          |CommitAssignment {
+             section_idx,
+             line_idx,
+             commit_idx,
+           }| WireCommitAssignment {
+            section_idx: *section_idx,
+            line_idx: *line_idx,
+            commit_idx: *commit_idx,
+          },
+        )
+        .collect(),
+      similarity: *similarity,
+      unloaded: unloaded.map(
+        |FileStats {
+           num_added,
+           num_removed,
+         }| WireFileStats {
+          num_added,
+          num_removed,
+        },
+      ),
+    }
+  }
+}
+
+impl From<&Section<'_>> for WireSection {
+  fn from(section: &Section) -> Self {
+    match section {
+      Section::Unchanged { lines } => WireSection::Unchanged {
+        lines: lines.iter().map(|line| line.clone().into_owned()).collect(),
+      },
+      Section::Omitted { num_lines } => WireSection::Omitted {
+        num_lines: *num_lines,
+      },
+      Section::Changed { lines } => WireSection::Changed {
+        lines: lines
+          .iter()
+          .map(
+            |SectionChangedLine {
+               is_checked,
+               change_type,
+               line,
+             }| WireChangedLine {
+              is_checked: *is_checked,
+              change_type: match change_type {
+                ChangeType::Added => WireChangeType::Added,
+                ChangeType::Removed => WireChangeType::Removed,
+              },
+              line: line.clone().into_owned(),
+            },
+          )
+          .collect(),
+      },
+      Section::FileMode { is_checked, mode } => WireSection::FileMode {
+        is_checked: *is_checked,
+        mode: file_mode_to_wire(*mode),
+      },
+      Section::Rename {
+        is_checked,
+        is_copy,
+      } => WireSection::Rename {
+        is_checked: *is_checked,
+        is_copy: *is_copy,
+      },
+      Section::Binary {
+        is_checked,
+        old_description,
+        new_description,
+        contents,
+      } => WireSection::Binary {
+        is_checked: *is_checked,
+        old_description: old_description
+          .as_ref()
+          .map(|description| description.clone().into_owned()),
+        new_description: new_description
+          .as_ref()
+          .map(|description| description.clone().into_owned()),
+        contents: contents
+          .as_ref()
+          .map(|BinaryContents { old, new }| WireBinaryContents {
+            old: bytes_to_hex(old),
+            new: bytes_to_hex(new),
+          }),
+      },
+      Section::Symlink {
+        is_checked,
+        old_target,
+        new_target,
+      } => WireSection::Symlink {
+        is_checked: *is_checked,
+        old_target: old_target
+          .as_ref()
+          .map(|target| target.clone().into_owned()),
+        new_target: new_target
+          .as_ref()
+          .map(|target| target.clone().into_owned()),
+      },
+      Section::Submodule {
+        is_checked,
+        old_commit,
+        new_commit,
+      } => WireSection::Submodule {
+        is_checked: *is_checked,
+        old_commit: old_commit
+          .as_ref()
+          .map(|commit| commit.clone().into_owned()),
+        new_commit: new_commit
+          .as_ref()
+          .map(|commit| commit.clone().into_owned()),
+      },
+    }
+  }
+}
+
+impl TryFrom<WireState> for RecordState<'static> {
+  type Error = RecordError;
+
+  fn try_from(wire_state: WireState) -> Result<Self, Self::Error> {
+    let WireState {
+      version: _,
+      is_read_only,
+      commits,
+      files,
+    } = wire_state;
+    Ok(RecordState {
+      is_read_only,
+      commits: commits
+        .into_iter()
+        .map(|WireCommit { message }| Commit { message })
+        .collect(),
+      files: files
+        .into_iter()
+        .map(File::try_from)
+        .collect::<Result<_, _>>()?,
+    })
+  }
+}
+
+impl TryFrom<WireFile> for File<'static> {
+  type Error = RecordError;
+
+  fn try_from(wire_file: WireFile) -> Result<Self, Self::Error> {
+    let WireFile {
+      old_path,
+      path,
+      file_mode,
+      sections,
+      commit_assignments,
+      similarity,
+      unloaded,
+    } = wire_file;
+    let mut commit_assignments: Vec<_> = commit_assignments
+      .into_iter()
+      .map(
+        |WireCommitAssignment {
+           section_idx,
+           line_idx,
+           commit_idx,
+         }| CommitAssignment {
+          section_idx,
+          line_idx,
+          commit_idx,
+        },
+      )
+      .collect();
+    commit_assignments.sort();
+    Ok(File {
+      old_path: old_path.map(|old_path| Cow::Owned(Path::new(&old_path).to_owned())),
+      path: Cow::Owned(Path::new(&path).to_owned()),
+      file_mode: file_mode_from_wire(file_mode)?,
+      sections: sections
+        .into_iter()
+        .map(Section::try_from)
+        .collect::<Result<_, _>>()?,
+      commit_assignments,
+      similarity,
+      unloaded: unloaded.map(
+        |WireFileStats {
+           num_added,
+           num_removed,
+         }| FileStats {
+          num_added,
+          num_removed,
+        },
+      ),
+      #[cfg(feature = "tree-sitter")]
+      containers: None,
+    })
+  }
+}
+
+impl TryFrom<WireSection> for Section<'static> {
+  type Error = RecordError;
+
+  fn try_from(wire_section: WireSection) -> Result<Self, Self::Error> {
+    Ok(match wire_section {
+      WireSection::Unchanged { lines } => Section::Unchanged {
+        lines: lines.into_iter().map(Cow::Owned).collect(),
+      },
+      WireSection::Omitted { num_lines } => Section::Omitted { num_lines },
+      WireSection::Changed { lines } => Section::Changed {
+        lines: lines
+          .into_iter()
+          .map(
+            |WireChangedLine {
+               is_checked,
+               change_type,
+               line,
+             }| SectionChangedLine {
+              is_checked,
+              change_type: match change_type {
+                WireChangeType::Added => ChangeType::Added,
+                WireChangeType::Removed => ChangeType::Removed,
+              },
+              line: Cow::Owned(line),
+            },
+          )
+          .collect(),
+      },
+      WireSection::FileMode { is_checked, mode } => Section::FileMode {
+        is_checked,
+        mode: file_mode_from_wire(mode)?,
+      },
+      WireSection::Rename {
+        is_checked,
+        is_copy,
+      } => Section::Rename {
+        is_checked,
+        is_copy,
+      },
+      WireSection::Binary {
+        is_checked,
+        old_description,
+        new_description,
+        contents,
+      } => Section::Binary {
+        is_checked,
+        old_description: old_description.map(Cow::Owned),
+        new_description: new_description.map(Cow::Owned),
+        contents: match contents {
+          Some(WireBinaryContents { old, new }) => Some(BinaryContents {
+            old: Cow::Owned(bytes_from_hex(&old)?),
+            new: Cow::Owned(bytes_from_hex(&new)?),
+          }),
+          None => None,
+        },
+      },
+      WireSection::Symlink {
+        is_checked,
+        old_target,
+        new_target,
+      } => Section::Symlink {
+        is_checked,
+        old_target: old_target.map(Cow::Owned),
+        new_target: new_target.map(Cow::Owned),
+      },
+      WireSection::Submodule {
+        is_checked,
+        old_commit,
+        new_commit,
+      } => Section::Submodule {
+        is_checked,
+        old_commit: old_commit.map(Cow::Owned),
+        new_commit: new_commit.map(Cow::Owned),
+      },
+    })
+  }
+}
+
+/// The unversioned format produced by the `serde` derives of scm-record 0.8,
+/// frozen here so that it can still be read after the Rust types change.
+mod legacy {
+  use serde::Deserialize;
+
+  use super::{
+    file_mode_to_wire, WireChangeType, WireChangedLine, WireCommit, WireCommitAssignment, WireFile,
+    WireSection, WireState,
+  };
+  use crate::FileMode;
+
+  #[derive(Deserialize)]
+  pub(super) struct State {
+    is_read_only: bool,
+    commits: Vec<Commit>,
+    files: Vec<File>,
+  }
+
+  #[derive(Deserialize)]
+  struct Commit {
+    message: Option<String>,
+  }
+
+  #[derive(Deserialize)]
+  struct File {
+    old_path: Option<String>,
+    path: String,
+    file_mode: LegacyFileMode,
+    sections: Vec<Section>,
+    #[serde(default)]
+    commit_assignments: Vec<CommitAssignment>,
+  }
+
+  #[derive(Clone, Copy, Deserialize)]
+  enum LegacyFileMode {
+    Unix(usize),
+    Absent,
+  }
+
+  #[derive(Deserialize)]
+  enum Section {
+    Unchanged {
+      lines: Vec<String>,
+    },
+    Omitted {
+      num_lines: usize,
+    },
+    Changed {
+      lines: Vec<ChangedLine>,
+    },
+    FileMode {
+      is_checked: bool,
+      mode: LegacyFileMode,
+    },
+    Binary {
+      is_checked: bool,
+      old_description: Option<String>,
+      new_description: Option<String>,
+    },
+  }
+
+  #[derive(Deserialize)]
+  struct ChangedLine {
+    is_checked: bool,
+    change_type: ChangeType,
+    line: String,
+  }
+
+  #[derive(Deserialize)]
+  enum ChangeType {
+    Added,
+    Removed,
+  }
+
+  #[derive(Deserialize)]
+  struct CommitAssignment {
+    section_idx: usize,
+    line_idx: usize,
+    commit_idx: usize,
+  }
+
+  fn migrate_file_mode(file_mode: LegacyFileMode) -> Option<String> {
+    file_mode_to_wire(match file_mode {
+      LegacyFileMode::Unix(mode) => FileMode::Unix(mode),
+      LegacyFileMode::Absent => FileMode::Absent,
+    })
+  }
+
+  impl From<State> for WireState {
+    fn from(state: State) -> Self {
+      let State {
+        is_read_only,
+        commits,
+        files,
+      } = state;
+      WireState {
+        version: super::INTERCHANGE_FORMAT_VERSION,
+        is_read_only,
+        commits: commits
+          .into_iter()
+          .map(|Commit { message }| WireCommit { message })
+          .collect(),
+        files: files.into_iter().map(WireFile::from).collect(),
+      }
+    }
+  }
+
+  impl From<File> for WireFile {
+    fn from(file: File) -> Self {
+      let File {
+        old_path,
+        path,
+        file_mode,
+        sections,
+        commit_assignments,
+      } = file;
+      WireFile {
+        old_path,
+        path,
+        file_mode: migrate_file_mode(file_mode),
+        sections: sections.into_iter().map(WireSection::from).collect(),
+        commit_assignments: commit_assignments
+          .into_iter()
+          .map(
+            |CommitAssignment {
+               section_idx,
+               line_idx,
+               commit_idx,
+             }| WireCommitAssignment {
+              section_idx,
+              line_idx,
+              commit_idx,
+            },
+          )
+          .collect(),
+        similarity: None,
+        unloaded: None,
+      }
+    }
+  }
+
+  impl From<Section> for WireSection {
+    fn from(section: Section) -> Self {
+      match section {
+        Section::Unchanged { lines } => WireSection::Unchanged { lines },
+        Section::Omitted { num_lines } => WireSection::Omitted {
+          num_lines: Some(num_lines),
+        },
+        Section::Changed { lines } => WireSection::Changed {
+          lines: lines
+            .into_iter()
+            .map(
+              |ChangedLine {
+                 is_checked,
+                 change_type,
+                 line,
+               }| WireChangedLine {
+                is_checked,
+                change_type: match change_type {
+                  ChangeType::Added => WireChangeType::Added,
+                  ChangeType::Removed => WireChangeType::Removed,
+                },
+                line,
+              },
+            )
+            .collect(),
+        },
+        Section::FileMode { is_checked, mode } => WireSection::FileMode {
+          is_checked,
+          mode: migrate_file_mode(mode),
+        },
+        Section::Binary {
+          is_checked,
+          old_description,
+          new_description,
+        } => WireSection::Binary {
+          is_checked,
+          old_description,
+          new_description,
+          contents: None,
+        },
+      }
+    }
+  }
+}
+
+/// Version 1 of the interchange format, which only has the fields and
+/// sections described by `schema/record-state.v1.schema.json`.
+mod v1 {
+  use serde::Deserialize;
+
+  use super::{
+    WireChangedLine, WireCommit, WireCommitAssignment, WireFile, WireSection, WireState,
+  };
+
+  #[derive(Deserialize)]
+  pub(super) struct State {
+    is_read_only: bool,
+    commits: Vec<WireCommit>,
+    files: Vec<File>,
+  }
+
+  #[derive(Deserialize)]
+  struct File {
+    old_path: Option<String>,
+    path: String,
+    file_mode: Option<String>,
+    sections: Vec<Section>,
+    #[serde(default)]
+    commit_assignments: Vec<WireCommitAssignment>,
+  }
+
+  #[derive(Deserialize)]
+  #[serde(tag = "type", rename_all = "snake_case")]
+  enum Section {
+    Unchanged {
+      lines: Vec<String>,
+    },
+    Omitted {
+      num_lines: usize,
+    },
+    Changed {
+      lines: Vec<WireChangedLine>,
+    },
+    FileMode {
+      is_checked: bool,
+      mode: Option<String>,
+    },
+    Binary {
+      is_checked: bool,
+      old_description: Option<String>,
+      new_description: Option<String>,
+    },
+  }
+
+  impl From<State> for WireState {
+    fn from(state: State) -> Self {
+      let State {
+        is_read_only,
+        commits,
+        files,
+      } = state;
+      WireState {
+        version: super::INTERCHANGE_FORMAT_VERSION,
+        is_read_only,
+        commits,
+        files: files.into_iter().map(WireFile::from).collect(),
+      }
+    }
+  }
+
+  impl From<File> for WireFile {
+    fn from(file: File) -> Self {
+      let File {
+        old_path,
+        path,
+        file_mode,
+        sections,
+        commit_assignments,
+      } = file;
+      WireFile {
+        old_path,
+        path,
+        file_mode,
+        sections: sections.into_iter().map(WireSection::from).collect(),
+        commit_assignments,
+        similarity: None,
+        unloaded: None,
+      }
+    }
+  }
+
+  impl From<Section> for WireSection {
+    fn from(section: Section) -> Self {
+      match section {
+        Section::Unchanged { lines } => WireSection::Unchanged { lines },
+        Section::Omitted { num_lines } => WireSection::Omitted {
+          num_lines: Some(num_lines),
+        },
+        Section::Changed { lines } => WireSection::Changed { lines },
+        Section::FileMode { is_checked, mode } => WireSection::FileMode { is_checked, mode },
+        Section::Binary {
+          is_checked,
+          old_description,
+          new_description,
+        } => WireSection::Binary {
+          is_checked,
+          old_description,
+          new_description,
+          contents: None,
+        },
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn example_state() -> RecordState<'static> {
+    RecordState {
+      is_read_only: false,
+      commits: vec![
+        Commit {
+          message: Some("first".to_owned()),
+        },
+        Commit { message: None },
+        Commit { message: None },
+      ],
+      files: vec![
+        File {
+          old_path: Some(Cow::Borrowed(Path::new("old.txt"))),
+          path: Cow::Borrowed(Path::new("new.txt")),
+          file_mode: FileMode::FILE_DEFAULT,
+          sections: vec![
+            Section::FileMode {
+              is_checked: true,
+              mode: FileMode::Unix(0o100755),
+            },
+            Section::Unchanged {
+              lines: vec![Cow::Borrowed("context\n")],
+            },
+            Section::Omitted {
+              num_lines: Some(10),
+            },
+            Section::Changed {
+              lines: vec![
+                SectionChangedLine {
+                  is_checked: true,
+                  change_type: ChangeType::Removed,
+                  line: Cow::Borrowed("before\n"),
+                },
+                SectionChangedLine {
+                  is_checked: false,
+                  change_type: ChangeType::Added,
+                  line: Cow::Borrowed("after\n"),
+                },
+              ],
+            },
+          ],
+          commit_assignments: vec![CommitAssignment {
+            section_idx: 3,
+            line_idx: 1,
+            commit_idx: 2,
+          }],
+          similarity: None,
+          unloaded: None,
+          #[cfg(feature = "tree-sitter")]
+          containers: None,
+        },
+        File {
+          old_path: None,
+          path: Cow::Borrowed(Path::new("image.png")),
+          file_mode: FileMode::Absent,
+          sections: vec![Section::Binary {
+            is_checked: false,
+            old_description: None,
+            new_description: Some(Cow::Borrowed("abc123 (10 bytes)")),
+            contents: Some(BinaryContents {
+              old: Cow::Borrowed(b""),
+              new: Cow::Borrowed(b"\x89PNG\r\n\x1a\n\x00\xff"),
+            }),
+          }],
+          commit_assignments: Vec::new(),
+          similarity: None,
+          unloaded: None,
+          #[cfg(feature = "tree-sitter")]
+          containers: None,
+        },
+      ],
+    }
+  }
+
+  #[test]
+  fn test_interchange_round_trip() -> Result<(), RecordError> {
+    let state = example_state();
+    let json = to_json(&state)?;
+    insta::assert_snapshot!(json, @r#"
+    {
+      "version": 2,
+      "is_read_only": false,
+      "commits": [
+        {
+          "message": "first"
+        },
+        {
+          "message": null
+        },
+        {
+          "message": null
+        }
+      ],
+      "files": [
+        {
+          "old_path": "old.txt",
+          "path": "new.txt",
+          "file_mode": "100644",
+          "sections": [
+            {
+              "type": "file_mode",
+              "is_checked": true,
+              "mode": "100755"
+            },
+            {
+              "type": "unchanged",
+              "lines": [
+                "context\n"
+              ]
+            },
+            {
+              "type": "omitted",
+              "num_lines": 10
+            },
+            {
+              "type": "changed",
+              "lines": [
+                {
+                  "is_checked": true,
+                  "change_type": "removed",
+                  "line": "before\n"
+                },
+                {
+                  "is_checked": false,
+                  "change_type": "added",
+                  "line": "after\n"
+                }
+              ]
+            }
+          ],
+          "commit_assignments": [
+            {
+              "section_idx": 3,
+              "line_idx": 1,
+              "commit_idx": 2
+            }
+          ],
+          "similarity": null,
+          "unloaded": null
+        },
+        {
+          "old_path": null,
+          "path": "image.png",
+          "file_mode": null,
+          "sections": [
+            {
+              "type": "binary",
+              "is_checked": false,
+              "old_description": null,
+              "new_description": "abc123 (10 bytes)",
+              "contents": {
+                "old": "",
+                "new": "89504e470d0a1a0a00ff"
+              }
+            }
+          ],
+          "commit_assignments": [],
+          "similarity": null,
+          "unloaded": null
+        }
+      ]
+    }
+    "#);
+    assert_eq!(from_json(&json)?, state);
+    Ok(())
+  }
+
+  #[test]
+  fn test_interchange_migrate_legacy() -> Result<(), RecordError> {
+    let legacy_json = r#"{
+      "is_read_only": true,
+      "commits": [{ "message": null }],
+      "files": [
+        {
+          "old_path": null,
+          "path": "foo.txt",
+          "file_mode": { "Unix": 33188 },
+          "sections": [
+            { "Unchanged": { "lines": ["a\n"] } },
+            {
+              "Changed": {
+                "lines": [
+                  { "is_checked": true, "change_type": "Added", "line": "b\n" }
+                ]
+              }
+            },
+            { "FileMode": { "is_checked": false, "mode": "Absent" } }
+          ],
+          "containers": null
+        }
+      ]
+    }"#;
+    let state = from_json(legacy_json)?;
+    insta::assert_debug_snapshot!(state, @r#"
+    RecordState {
+        is_read_only: true,
+        commits: [
+            Commit {
+                message: None,
+            },
+        ],
+        files: [
+            File {
+                old_path: None,
+                path: "foo.txt",
+                file_mode: Unix(
+                    33188,
+                ),
+                sections: [
+                    Unchanged {
+                        lines: [
+                            "a\n",
+                        ],
+                    },
+                    Changed {
+                        lines: [
+                            SectionChangedLine {
+                                is_checked: true,
+                                change_type: Added,
+                                line: "b\n",
+                            },
+                        ],
+                    },
+                    FileMode {
+                        is_checked: false,
+                        mode: Absent,
+                    },
+                ],
+                commit_assignments: [],
+                similarity: None,
+                unloaded: None,
+                containers: None,
+            },
+        ],
+    }
+    "#);
+    Ok(())
+  }
+
+  #[test]
+  fn test_interchange_read_v1() -> Result<(), RecordError> {
+    // Written by the first release of the format, before any of the fields
+    // added in version 2 existed.
+    let v1_json = r#"{
+      "version": 1,
+      "is_read_only": false,
+      "commits": [{ "message": "first" }, { "message": null }],
+      "files": [
+        {
+          "old_path": "old.txt",
+          "path": "new.txt",
+          "file_mode": "100644",
+          "sections": [
+            { "type": "unchanged", "lines": ["a\n"] },
+            { "type": "omitted", "num_lines": 3 },
+            {
+              "type": "changed",
+              "lines": [
+                { "is_checked": true, "change_type": "added", "line": "b\n" }
+              ]
+            },
+            { "type": "file_mode", "is_checked": false, "mode": null },
+            {
+              "type": "binary",
+              "is_checked": true,
+              "old_description": "abc123",
+              "new_description": null
+            }
+          ],
+          "commit_assignments": []
+        }
+      ]
+    }"#;
+    let state = from_json(v1_json)?;
+    insta::assert_debug_snapshot!(state, @r#"
+    RecordState {
+        is_read_only: false,
+        commits: [
+            Commit {
+                message: Some(
+                    "first",
+                ),
+            },
+            Commit {
+                message: None,
+            },
+        ],
+        files: [
+            File {
+                old_path: Some(
+                    "old.txt",
+                ),
+                path: "new.txt",
+                file_mode: Unix(
+                    33188,
+                ),
+                sections: [
+                    Unchanged {
+                        lines: [
+                            "a\n",
+                        ],
+                    },
+                    Omitted {
+                        num_lines: Some(
+                            3,
+                        ),
+                    },
+                    Changed {
+                        lines: [
+                            SectionChangedLine {
+                                is_checked: true,
+                                change_type: Added,
+                                line: "b\n",
+                            },
+                        ],
+                    },
+                    FileMode {
+                        is_checked: false,
+                        mode: Absent,
+                    },
+                    Binary {
+                        is_checked: true,
+                        old_description: Some(
+                            "abc123",
+                        ),
+                        new_description: None,
+                        contents: None,
+                    },
+                ],
+                commit_assignments: [],
+                similarity: None,
+                unloaded: None,
+                containers: None,
+            },
+        ],
+    }
+    "#);
+    Ok(())
+  }
+
+  #[test]
+  fn test_interchange_errors() {
+    insta::assert_snapshot!(
+      from_json(r#"{ "version": 99, "is_read_only": false, "commits": [], "files": [] }"#).unwrap_err(),
+      @"unsupported interchange format version: 99"
+    );
+    insta::assert_snapshot!(
+      from_json(r#"{ "version": 1, "is_read_only": false, "commits": [], "files": [{ "old_path": null, "path": "foo", "file_mode": "rwx", "sections": [] }] }"#).unwrap_err(),
+      @r#"invalid file mode: "rwx""#
+    );
+  }
+
+  #[test]
+  fn test_interchange_read_v1_with_v2_sections() {
+    // Sections added in version 2 can't appear in a version 1 document.
+    let v1_json = |section: &str| {
+      format!(
+        r#"{{ "version": 1, "is_read_only": false, "commits": [], "files": [{{ "old_path": "foo", "path": "bar", "file_mode": "100644", "sections": [{section}] }}] }}"#
+      )
+    };
+    insta::assert_snapshot!(
+      from_json(&v1_json(r#"{ "type": "rename", "is_checked": false, "is_copy": false }"#)).unwrap_err(),
+      @"failed to deserialize JSON: unknown variant `rename`, expected one of `unchanged`, `omitted`, `changed`, `file_mode`, `binary`"
+    );
+    insta::assert_snapshot!(
+      from_json(&v1_json(r#"{ "type": "omitted", "num_lines": null }"#)).unwrap_err(),
+      @"failed to deserialize JSON: invalid type: null, expected usize"
+    );
+
+    // But they can in a version 2 document.
+    let v2_json = v1_json(r#"{ "type": "rename", "is_checked": false, "is_copy": false }"#)
+      .replace(r#""version": 1"#, r#""version": 2"#);
+    assert!(from_json(&v2_json).is_ok());
+  }
+
+  #[test]
+  fn test_json_schema() {
+    let schema: serde_json::Value = serde_json::from_str(JSON_SCHEMA).unwrap();
+    assert_eq!(
+      schema["properties"]["version"]["const"],
+      serde_json::json!(INTERCHANGE_FORMAT_VERSION)
+    );
+
+    // Every property written by `to_json` should be described by the schema.
+    let json: serde_json::Value =
+      serde_json::from_str(&to_json(&example_state()).unwrap()).unwrap();
+    let definitions = &schema["$defs"];
+    let check_properties = |value: &serde_json::Value, definition: &serde_json::Value| {
+      for key in value.as_object().unwrap().keys() {
+        assert!(
+          definition["properties"].get(key).is_some(),
+          "property {key:?} missing from schema definition {definition}"
+        );
+      }
+    };
+    check_properties(&json, &schema);
+    for file in json["files"].as_array().unwrap() {
+      check_properties(file, &definitions["file"]);
+      for section in file["sections"].as_array().unwrap() {
+        let section_type = section["type"].as_str().unwrap();
+        check_properties(section, &definitions[format!("{section_type}_section")]);
+      }
+    }
+  }
+}
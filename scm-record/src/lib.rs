@@ -15,15 +15,28 @@ mod ui;
 mod util;
 
 pub mod consts;
+pub mod directory_tree;
+pub mod fuzzy;
 pub mod helpers;
+pub mod hex_dump;
+pub mod highlight;
+pub mod inline_diff;
+#[cfg(feature = "serde")]
+pub mod interchange;
+pub mod moved;
+pub mod patch;
+pub mod query;
+pub mod search;
+pub mod selection;
 
 #[cfg(feature = "tree-sitter")]
 pub mod semantic;
 pub use types::{
-  ChangeType, Commit, File, FileMode, RecordError, RecordState, Section, SectionChangedLine,
+  BinaryContents, ChangeType, Commit, CommitAssignment, DiffStat, File, FileCounts, FileMode,
+  FileStats, LineCounts, LineNumbers, RecordError, RecordState, Section, SectionChangedLine,
   SelectedChanges, SelectedContents, Tristate,
 };
 
 #[cfg(feature = "tree-sitter")]
 pub use types::SemanticContainer;
-pub use ui::{Event, RecordInput, Recorder, TerminalKind, TestingScreenshot};
+pub use ui::{Event, RecordInput, Recorder, SectionProvider, TerminalKind, TestingScreenshot};
@@ -0,0 +1,437 @@
+//! Detection of lines which were moved, rather than removed and added.
+//!
+//! When a block of code is moved, within a file or between files, it shows up
+//! as a removal and an unrelated addition. If only one of them is selected,
+//! the commit either deletes or duplicates the code. [`MovedLines::detect`]
+//! pairs removed lines with identical added lines elsewhere, so that they can
+//! be shown in a different style, like `git diff --color-moved`, and selected
+//! together.
+
+use std::collections::HashMap;
+
+use crate::{ChangeType, File, Section, SectionChangedLine};
+
+/// The minimum number of alphanumeric characters in a block of moved lines.
+/// Shorter blocks, like a lone `}`, are often identical by coincidence, so
+/// they're shown as removed and added as usual.
+pub const MIN_ALPHANUMERIC_CHARS: usize = 20;
+
+/// The maximum number of places a block of lines can have been removed from
+/// for it to be considered moved. Blocks which were removed more often than
+/// this, like repeated boilerplate, are too common to tell which one was
+/// moved, and comparing each of them would be slow.
+pub const MAX_CANDIDATES: usize = 16;
+
+/// The location of a changed line in a list of [`File`]s.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
+pub struct LineLocation {
+  /// The index of the file.
+  pub file_idx: usize,
+
+  /// The index of the [`Section::Changed`] in the file. Syntax highlights
+  /// (see [`crate::highlight`]) also use it for lines in a
+  /// [`Section::Unchanged`].
+  pub section_idx: usize,
+
+  /// The index of the line in the section.
+  pub line_idx: usize,
+}
+
+/// A block of consecutive removed lines which were added elsewhere, as
+/// consecutive lines with the same contents.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MovedBlock {
+  /// The removed lines.
+  pub removed: Vec<LineLocation>,
+
+  /// The added lines, in the same order as the removed lines they correspond
+  /// to.
+  pub added: Vec<LineLocation>,
+}
+
+/// The blocks of moved lines in a list of [`File`]s.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MovedLines {
+  blocks: Vec<MovedBlock>,
+  partners: HashMap<LineLocation, LineLocation>,
+}
+
+/// A run of consecutive changed lines of the same type in a section.
+struct Run<'a> {
+  change_type: ChangeType,
+  locations: Vec<LineLocation>,
+  lines: Vec<&'a str>,
+}
+
+impl MovedLines {
+  /// Find the removed lines in `files` which were added elsewhere. Each
+  /// removed line is paired with at most one added line, and vice versa.
+  pub fn detect(files: &[File]) -> Self {
+    let runs = changed_line_runs(files);
+    let min_lens: Vec<Vec<Option<usize>>> =
+      runs.iter().map(|run| min_block_lens(&run.lines)).collect();
+
+    // Index the removed lines by the shortest block starting with each of
+    // them which is long enough to be moved, rather than by the line alone,
+    // so that common short lines like `}` don't each have to be compared with
+    // every other occurrence.
+    let mut removed_block_positions: HashMap<&[&str], Vec<(usize, usize)>> = HashMap::new();
+    for (run_idx, run) in runs.iter().enumerate() {
+      match run.change_type {
+        ChangeType::Removed => {
+          for (line_pos, min_len) in min_lens[run_idx].iter().enumerate() {
+            if let Some(min_len) = min_len {
+              removed_block_positions
+                .entry(&run.lines[line_pos..line_pos + min_len])
+                .or_default()
+                .push((run_idx, line_pos));
+            }
+          }
+        }
+        ChangeType::Added => {}
+      }
+    }
+
+    let mut moved_lines = Self::default();
+    for (added_run_idx, added_run) in runs.iter().enumerate() {
+      match added_run.change_type {
+        ChangeType::Added => {}
+        ChangeType::Removed => continue,
+      }
+
+      let mut added_pos = 0;
+      while added_pos < added_run.lines.len() {
+        let min_len = match min_lens[added_run_idx][added_pos] {
+          Some(min_len) => min_len,
+          None => {
+            added_pos += 1;
+            continue;
+          }
+        };
+
+        // Find the longest block of removed lines, which haven't been moved
+        // yet, starting with this line.
+        let mut best_block: Option<(usize, usize, usize)> = None;
+        let candidates = removed_block_positions
+          .get(&added_run.lines[added_pos..added_pos + min_len])
+          .map(Vec::as_slice)
+          .filter(|candidates| candidates.len() <= MAX_CANDIDATES)
+          .unwrap_or_default();
+        for (removed_run_idx, removed_pos) in candidates.iter().copied() {
+          let removed_run = &runs[removed_run_idx];
+          let len = added_run.lines[added_pos..]
+            .iter()
+            .zip(&removed_run.lines[removed_pos..])
+            .zip(&removed_run.locations[removed_pos..])
+            .take_while(|((added_line, removed_line), removed_location)| {
+              added_line == removed_line && !moved_lines.partners.contains_key(removed_location)
+            })
+            .count();
+          if len > best_block.map_or(0, |(_, _, best_len)| best_len) {
+            best_block = Some((removed_run_idx, removed_pos, len));
+          }
+        }
+
+        match best_block {
+          Some((removed_run_idx, removed_pos, len)) if len >= min_len => {
+            let removed = runs[removed_run_idx].locations[removed_pos..removed_pos + len].to_vec();
+            let added = added_run.locations[added_pos..added_pos + len].to_vec();
+            for (removed_location, added_location) in removed.iter().zip(&added) {
+              moved_lines
+                .partners
+                .insert(*removed_location, *added_location);
+              moved_lines
+                .partners
+                .insert(*added_location, *removed_location);
+            }
+            moved_lines.blocks.push(MovedBlock { removed, added });
+            added_pos += len;
+          }
+          Some(_) | None => {
+            added_pos += 1;
+          }
+        }
+      }
+    }
+    moved_lines
+  }
+
+  /// Update the locations of the moved lines after the lines of a file have
+  /// been divided into sections differently. `map_location` returns the new
+  /// location of a line.
+  pub fn map_locations(
+    &self,
+    map_location: impl Fn(LineLocation) -> LineLocation,
+  ) -> Self {
+    let Self { blocks, partners } = self;
+    Self {
+      blocks: blocks
+        .iter()
+        .map(|MovedBlock { removed, added }| MovedBlock {
+          removed: removed.iter().copied().map(&map_location).collect(),
+          added: added.iter().copied().map(&map_location).collect(),
+        })
+        .collect(),
+      partners: partners
+        .iter()
+        .map(|(location, partner)| (map_location(*location), map_location(*partner)))
+        .collect(),
+    }
+  }
+
+  /// The blocks of moved lines, in the order of their added lines.
+  pub fn blocks(&self) -> &[MovedBlock] {
+    &self.blocks
+  }
+
+  /// The line which the line at `location` was moved from or to, if any.
+  pub fn partner(
+    &self,
+    location: LineLocation,
+  ) -> Option<LineLocation> {
+    self.partners.get(&location).copied()
+  }
+
+  /// Whether the line at `location` was moved.
+  pub fn is_moved(
+    &self,
+    location: LineLocation,
+  ) -> bool {
+    self.partners.contains_key(&location)
+  }
+}
+
+fn changed_line_runs<'a>(files: &'a [File]) -> Vec<Run<'a>> {
+  let mut runs: Vec<Run> = Vec::new();
+  for (file_idx, file) in files.iter().enumerate() {
+    for (section_idx, section) in file.sections.iter().enumerate() {
+      let lines = match section {
+        Section::Changed { lines } => lines,
+        Section::Unchanged { .. }
+        | Section::Omitted { .. }
+        | Section::FileMode { .. }
+        | Section::Rename { .. }
+        | Section::Binary { .. }
+        | Section::Symlink { .. }
+        | Section::Submodule { .. } => continue,
+      };
+      let mut prev_change_type = None;
+      for (line_idx, line) in lines.iter().enumerate() {
+        let SectionChangedLine {
+          is_checked: _,
+          change_type,
+          line,
+        } = line;
+        let location = LineLocation {
+          file_idx,
+          section_idx,
+          line_idx,
+        };
+        // A missing newline at the end of the file doesn't stop a line from
+        // being moved.
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        match runs.last_mut() {
+          Some(run) if prev_change_type == Some(*change_type) => {
+            run.locations.push(location);
+            run.lines.push(line);
+          }
+          Some(_) | None => runs.push(Run {
+            change_type: *change_type,
+            locations: vec![location],
+            lines: vec![line],
+          }),
+        }
+        prev_change_type = Some(*change_type);
+      }
+    }
+  }
+  runs
+}
+
+/// For each line, the number of lines in the shortest block starting with it
+/// which has at least [`MIN_ALPHANUMERIC_CHARS`], or `None` if there's no
+/// such block. A line without any alphanumeric characters doesn't start a
+/// block, although it can be part of one.
+fn min_block_lens(lines: &[&str]) -> Vec<Option<usize>> {
+  let num_chars: Vec<usize> = lines
+    .iter()
+    .map(|line| line.chars().filter(|c| c.is_alphanumeric()).count())
+    .collect();
+  let mut min_lens = Vec::with_capacity(lines.len());
+  let mut end = 0;
+  let mut block_num_chars = 0;
+  for start in 0..lines.len() {
+    while end < lines.len() && block_num_chars < MIN_ALPHANUMERIC_CHARS {
+      block_num_chars += num_chars[end];
+      end += 1;
+    }
+    min_lens.push(
+      (num_chars[start] > 0 && block_num_chars >= MIN_ALPHANUMERIC_CHARS).then_some(end - start),
+    );
+    block_num_chars -= num_chars[start];
+  }
+  min_lens
+}
+
+#[cfg(test)]
+mod tests {
+  use std::borrow::Cow;
+
+  use super::*;
+  use crate::FileMode;
+
+  fn changed_file(
+    path: &str,
+    lines: &[(ChangeType, &'static str)],
+  ) -> File<'static> {
+    File {
+      old_path: None,
+      path: Cow::Owned(path.into()),
+      file_mode: FileMode::FILE_DEFAULT,
+      sections: vec![
+        Section::Unchanged {
+          lines: vec![Cow::Borrowed("fn main() {}\n")],
+        },
+        Section::Changed {
+          lines: lines
+            .iter()
+            .map(|(change_type, line)| SectionChangedLine {
+              is_checked: false,
+              change_type: *change_type,
+              line: Cow::Borrowed(line),
+            })
+            .collect(),
+        },
+      ],
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
+      #[cfg(feature = "tree-sitter")]
+      containers: None,
+    }
+  }
+
+  #[test]
+  fn test_detect_moved_lines() {
+    let files = [
+      changed_file(
+        "foo.rs",
+        &[
+          (ChangeType::Removed, "fn helper() {\n"),
+          (ChangeType::Removed, "    compute_something();\n"),
+          (ChangeType::Removed, "}\n"),
+          (ChangeType::Added, "// helper moved\n"),
+        ],
+      ),
+      changed_file(
+        "bar.rs",
+        &[
+          (ChangeType::Removed, "}\n"),
+          (ChangeType::Added, "fn helper() {\n"),
+          (ChangeType::Added, "    compute_something();\n"),
+          (ChangeType::Added, "}"),
+        ],
+      ),
+    ];
+    let moved_lines = MovedLines::detect(&files);
+    insta::assert_debug_snapshot!(moved_lines.blocks(), @"
+    [
+        MovedBlock {
+            removed: [
+                LineLocation {
+                    file_idx: 0,
+                    section_idx: 1,
+                    line_idx: 0,
+                },
+                LineLocation {
+                    file_idx: 0,
+                    section_idx: 1,
+                    line_idx: 1,
+                },
+                LineLocation {
+                    file_idx: 0,
+                    section_idx: 1,
+                    line_idx: 2,
+                },
+            ],
+            added: [
+                LineLocation {
+                    file_idx: 1,
+                    section_idx: 1,
+                    line_idx: 1,
+                },
+                LineLocation {
+                    file_idx: 1,
+                    section_idx: 1,
+                    line_idx: 2,
+                },
+                LineLocation {
+                    file_idx: 1,
+                    section_idx: 1,
+                    line_idx: 3,
+                },
+            ],
+        },
+    ]
+    ");
+    let location = |file_idx, line_idx| LineLocation {
+      file_idx,
+      section_idx: 1,
+      line_idx,
+    };
+    assert_eq!(moved_lines.partner(location(0, 1)), Some(location(1, 2)));
+    assert_eq!(moved_lines.partner(location(1, 2)), Some(location(0, 1)));
+    // The lone `}` isn't moved.
+    assert!(!moved_lines.is_moved(location(1, 0)));
+    assert!(!moved_lines.is_moved(location(0, 3)));
+  }
+
+  #[test]
+  fn test_detect_moved_lines_too_short() {
+    let files = [changed_file(
+      "foo.rs",
+      &[
+        (ChangeType::Removed, "}\n"),
+        (ChangeType::Removed, "x += 1;\n"),
+        (ChangeType::Added, "foo();\n"),
+        (ChangeType::Added, "}\n"),
+        (ChangeType::Added, "x += 1;\n"),
+      ],
+    )];
+    assert_eq!(MovedLines::detect(&files), MovedLines::default());
+  }
+
+  #[test]
+  fn test_detect_moved_lines_repeated_short_lines() {
+    let moved_block = ["fn helper() {\n", "    compute_something();\n", "}\n"];
+    let repeated_lines = || ["}\n", "x += 1;\n"].repeat(5000);
+    let lines: Vec<(ChangeType, &'static str)> = repeated_lines()
+      .into_iter()
+      .chain(moved_block)
+      .map(|line| (ChangeType::Removed, line))
+      .chain(
+        moved_block
+          .into_iter()
+          .chain(repeated_lines())
+          .map(|line| (ChangeType::Added, line)),
+      )
+      .collect();
+    let files = [changed_file("foo.rs", &lines)];
+    let moved_lines = MovedLines::detect(&files);
+
+    // The repeated lines are too common to be moved, but the block is found
+    // among them.
+    let location = |line_idx| LineLocation {
+      file_idx: 0,
+      section_idx: 1,
+      line_idx,
+    };
+    assert_eq!(
+      moved_lines.blocks(),
+      [MovedBlock {
+        removed: (10000..10003).map(location).collect(),
+        added: (10003..10006).map(location).collect(),
+      }]
+    );
+  }
+}
@@ -0,0 +1,1887 @@
+//! Parsing and formatting of unified diffs and Git patches.
+//!
+//! This allows the output of tools like `git diff` or `jj diff --git` to be
+//! recorded directly, without first reconstructing both versions of each
+//! file. Since a patch only contains the lines near each change, the
+//! unchanged lines between hunks are represented as [`Section::Omitted`].
+//!
+//! Conversely, the selected changes can be formatted as a patch with
+//! [`File::get_selected_patches`] or [`crate::RecordState::get_selected_patches`],
+//! which is suitable for tools like `git apply`.
+
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::{ChangeType, File, FileMode, Section, SectionChangedLine};
+
+/// An error which occurred when parsing a patch.
+#[derive(Debug, Error)]
+pub enum PatchError {
+  /// A hunk header (the line starting with `@@`) could not be parsed.
+  #[error("line {line_num}: invalid hunk header: {line:?}")]
+  InvalidHunkHeader {
+    /// The 1-based line number in the patch.
+    line_num: usize,
+    /// The contents of the line.
+    line: String,
+  },
+
+  /// A line inside a hunk didn't start with ` `, `-`, `+`, or `\`.
+  #[error("line {line_num}: invalid line in hunk: {line:?}")]
+  InvalidHunkLine {
+    /// The 1-based line number in the patch.
+    line_num: usize,
+    /// The contents of the line.
+    line: String,
+  },
+
+  /// The patch ended before all of the lines declared by a hunk header were
+  /// seen.
+  #[error("line {line_num}: hunk is missing lines")]
+  TruncatedHunk {
+    /// The 1-based line number in the patch where the hunk ended.
+    line_num: usize,
+  },
+
+  /// A hunk started before the end of the previous hunk in the same file.
+  #[error("line {line_num}: hunk overlaps the previous hunk")]
+  OverlappingHunk {
+    /// The 1-based line number of the hunk header in the patch.
+    line_num: usize,
+  },
+
+  /// A hunk appeared without a preceding file header.
+  #[error("line {line_num}: hunk without a file header")]
+  MissingFileHeader {
+    /// The 1-based line number of the hunk header in the patch.
+    line_num: usize,
+  },
+
+  /// A file mode in a Git extended header line could not be parsed.
+  #[error("line {line_num}: invalid file mode: {mode:?}")]
+  InvalidFileMode {
+    /// The 1-based line number in the patch.
+    line_num: usize,
+    /// The unparsed file mode.
+    mode: String,
+  },
+}
+
+/// The path used in patches to indicate that one side of the file is absent.
+const DEV_NULL: &str = "/dev/null";
+
+/// Parse a unified diff or Git patch containing any number of files.
+///
+/// Any text which isn't part of a file's diff, such as a commit message
+/// preceding it, is ignored. All changes are initially unchecked.
+///
+/// Git extended headers are supported:
+///
+/// - `rename from`/`rename to` and `copy from`/`copy to` set
+///   [`File::old_path`] and produce a [`Section::Rename`]. `similarity index`
+///   sets [`File::similarity`].
+/// - `old mode`/`new mode`, `new file mode`, and `deleted file mode` produce
+///   a [`Section::FileMode`].
+/// - `Binary files ... differ` and `GIT binary patch` produce a
+///   [`Section::Binary`], described by the object IDs from the `index` line,
+///   if any. The binary data itself is skipped.
+///
+/// The unchanged lines between hunks are represented as
+/// [`Section::Omitted`]. Since the length of the file usually isn't known, the
+/// lines after the last hunk are represented as a [`Section::Omitted`] with an
+/// unknown number of lines, unless the hunks are known to reach the end of
+/// the old file (such as when the file is created or deleted).
+pub fn parse_patch(patch: &str) -> Result<Vec<File<'_>>, PatchError> {
+  let lines: Vec<&str> = patch.split_inclusive('\n').collect();
+  let mut files = Vec::new();
+  let mut line_idx = 0;
+  while let Some(line) = lines.get(line_idx) {
+    if let Some(rest) = line.strip_prefix("diff --git ") {
+      let (file, next_line_idx) = parse_git_file(&lines, line_idx, rest)?;
+      files.push(file);
+      line_idx = next_line_idx;
+    } else if line.starts_with("--- ")
+      && lines
+        .get(line_idx + 1)
+        .is_some_and(|next_line| next_line.starts_with("+++ "))
+    {
+      let (file, next_line_idx) = parse_unified_file(&lines, line_idx)?;
+      files.push(file);
+      line_idx = next_line_idx;
+    } else if line.starts_with("@@ ") {
+      return Err(PatchError::MissingFileHeader {
+        line_num: line_idx + 1,
+      });
+    } else {
+      // Some text outside of a file diff, like a commit message or `Index:`
+      // line.
+      line_idx += 1;
+    }
+  }
+  Ok(files)
+}
+
+/// The information about a file collected from its headers.
+#[derive(Debug, Default)]
+struct FileHeader<'a> {
+  /// The old path, or `None` if the file was created.
+  old_path: Option<Cow<'a, str>>,
+  /// The new path, or `None` if the file was deleted.
+  new_path: Option<Cow<'a, str>>,
+  old_mode: Option<FileMode>,
+  new_mode: Option<FileMode>,
+  old_hash: Option<&'a str>,
+  new_hash: Option<&'a str>,
+  is_binary: bool,
+  /// `Some(is_copy)` if there was a `rename` or `copy` header.
+  rename: Option<bool>,
+  similarity: Option<u8>,
+}
+
+impl<'a> FileHeader<'a> {
+  fn into_file(
+    self,
+    hunk_sections: Vec<Section<'a>>,
+  ) -> File<'a> {
+    let Self {
+      old_path,
+      new_path,
+      old_mode,
+      new_mode,
+      old_hash,
+      new_hash,
+      is_binary,
+      rename,
+      similarity,
+    } = self;
+
+    let (file_mode, mode_section) = match (&old_path, &new_path) {
+      (None, _) => (
+        FileMode::Absent,
+        Some(new_mode.unwrap_or(FileMode::FILE_DEFAULT)),
+      ),
+      (Some(_), None) => (
+        old_mode.unwrap_or(FileMode::FILE_DEFAULT),
+        Some(FileMode::Absent),
+      ),
+      (Some(_), Some(_)) => {
+        let old_mode = old_mode.unwrap_or(FileMode::FILE_DEFAULT);
+        let new_mode = new_mode.unwrap_or(old_mode);
+        (old_mode, (old_mode != new_mode).then_some(new_mode))
+      }
+    };
+
+    let mut sections = Vec::new();
+    match (rename, &old_path, &new_path) {
+      (Some(is_copy), Some(old_path), Some(new_path)) if old_path != new_path => {
+        sections.push(Section::Rename {
+          is_checked: false,
+          is_copy,
+        });
+      }
+      _ => {}
+    }
+    if let Some(mode) = mode_section {
+      sections.push(Section::FileMode {
+        is_checked: false,
+        mode,
+      });
+    }
+    if is_binary {
+      let describe = |is_present: bool, hash: Option<&'a str>| {
+        hash
+          .filter(|hash| is_present && !hash.bytes().all(|c| c == b'0'))
+          .map(Cow::Borrowed)
+      };
+      sections.push(Section::Binary {
+        is_checked: false,
+        old_description: describe(old_path.is_some(), old_hash),
+        new_description: describe(new_path.is_some(), new_hash),
+        contents: None,
+      });
+    }
+    sections.extend(hunk_sections);
+
+    let (old_path, path) = match (old_path, new_path) {
+      (Some(old_path), Some(new_path)) if old_path != new_path => (Some(old_path), new_path),
+      (_, Some(path)) | (Some(path), None) => (None, path),
+      (None, None) => (None, Cow::Borrowed("")),
+    };
+    File {
+      old_path: old_path.map(to_path),
+      path: to_path(path),
+      file_mode,
+      sections,
+      commit_assignments: Default::default(),
+      similarity,
+      unloaded: None,
+      #[cfg(feature = "tree-sitter")]
+      containers: None,
+    }
+  }
+}
+
+fn to_path(path: Cow<'_, str>) -> Cow<'_, Path> {
+  match path {
+    Cow::Borrowed(path) => Cow::Borrowed(Path::new(path)),
+    Cow::Owned(path) => Cow::Owned(path.into()),
+  }
+}
+
+/// Parse the file starting with the `diff --git` line at `start_line_idx`.
+/// Returns the file and the index of the first line after it.
+fn parse_git_file<'a>(
+  lines: &[&'a str],
+  start_line_idx: usize,
+  diff_git_args: &'a str,
+) -> Result<(File<'a>, usize), PatchError> {
+  let mut header = FileHeader::default();
+  if let Some((old_path, new_path)) = parse_diff_git_paths(trim_newline(diff_git_args)) {
+    header.old_path = Some(old_path);
+    header.new_path = Some(new_path);
+  }
+
+  let mut line_idx = start_line_idx + 1;
+  let mut is_created = false;
+  let mut is_deleted = false;
+  while let Some(line) = lines.get(line_idx) {
+    let line_num = line_idx + 1;
+    let line = trim_newline(line);
+    if let Some(mode) = line.strip_prefix("old mode ") {
+      header.old_mode = Some(parse_mode(line_num, mode)?);
+    } else if let Some(mode) = line.strip_prefix("new mode ") {
+      header.new_mode = Some(parse_mode(line_num, mode)?);
+    } else if let Some(mode) = line.strip_prefix("deleted file mode ") {
+      header.old_mode = Some(parse_mode(line_num, mode)?);
+      is_deleted = true;
+    } else if let Some(mode) = line.strip_prefix("new file mode ") {
+      header.new_mode = Some(parse_mode(line_num, mode)?);
+      is_created = true;
+    } else if let Some(path) = line.strip_prefix("rename from ") {
+      header.old_path = Some(unquote(path));
+      header.rename = Some(false);
+    } else if let Some(path) = line.strip_prefix("copy from ") {
+      header.old_path = Some(unquote(path));
+      header.rename = Some(true);
+    } else if let Some(path) = line
+      .strip_prefix("rename to ")
+      .or_else(|| line.strip_prefix("copy to "))
+    {
+      header.new_path = Some(unquote(path));
+    } else if let Some(index) = line.strip_prefix("index ") {
+      let (hashes, mode) = match index.split_once(' ') {
+        Some((hashes, mode)) => (hashes, Some(parse_mode(line_num, mode)?)),
+        None => (index, None),
+      };
+      if let Some((old_hash, new_hash)) = hashes.split_once("..") {
+        header.old_hash = Some(old_hash);
+        header.new_hash = Some(new_hash);
+      }
+      if let Some(mode) = mode {
+        header.old_mode.get_or_insert(mode);
+        header.new_mode.get_or_insert(mode);
+      }
+    } else if let Some(similarity) = line.strip_prefix("similarity index ") {
+      header.similarity = similarity
+        .strip_suffix('%')
+        .and_then(|similarity| similarity.parse().ok());
+    } else if line.starts_with("dissimilarity index ") {
+      // The dissimilarity isn't needed to record changes.
+    } else {
+      break;
+    }
+    line_idx += 1;
+  }
+  if is_created {
+    header.old_path = None;
+  }
+  if is_deleted {
+    header.new_path = None;
+  }
+
+  let line = lines.get(line_idx).copied().map(trim_newline);
+  match line {
+    Some(line) if line.starts_with("Binary files ") && line.ends_with(" differ") => {
+      header.is_binary = true;
+      line_idx += 1;
+      Ok((header.into_file(Vec::new()), line_idx))
+    }
+    Some("GIT binary patch") => {
+      header.is_binary = true;
+      line_idx += 1;
+      // Skip the encoded binary data.
+      while lines
+        .get(line_idx)
+        .is_some_and(|line| !line.starts_with("diff --git "))
+      {
+        line_idx += 1;
+      }
+      Ok((header.into_file(Vec::new()), line_idx))
+    }
+    Some(line) if line.starts_with("--- ") => {
+      let (old_path, new_path, next_line_idx) = parse_old_new_paths(lines, line_idx);
+      // Prefer the paths from the `---`/`+++` lines, since the paths on the
+      // `diff --git` line can be ambiguous.
+      header.old_path = old_path.map(strip_git_prefix);
+      header.new_path = new_path.map(strip_git_prefix);
+      let (sections, next_line_idx) = parse_hunks(lines, next_line_idx)?;
+      Ok((header.into_file(sections), next_line_idx))
+    }
+    _ => {
+      // There are no contents changes, such as for a mode change or a pure
+      // rename.
+      Ok((header.into_file(Vec::new()), line_idx))
+    }
+  }
+}
+
+/// Parse the file starting with the `---` line at `start_line_idx` in a
+/// non-Git unified diff. Returns the file and the index of the first line
+/// after it.
+fn parse_unified_file<'a>(
+  lines: &[&'a str],
+  start_line_idx: usize,
+) -> Result<(File<'a>, usize), PatchError> {
+  let (old_path, new_path, line_idx) = parse_old_new_paths(lines, start_line_idx);
+  // Strip the `a/` and `b/` prefixes only if both paths have them, since
+  // otherwise they're probably part of the actual paths.
+  let has_git_prefixes = match (&old_path, &new_path) {
+    (Some(old_path), Some(new_path)) => old_path.starts_with("a/") && new_path.starts_with("b/"),
+    (Some(path), None) => path.starts_with("a/"),
+    (None, Some(path)) => path.starts_with("b/"),
+    (None, None) => false,
+  };
+  let (old_path, new_path) = if has_git_prefixes {
+    (
+      old_path.map(strip_git_prefix),
+      new_path.map(strip_git_prefix),
+    )
+  } else {
+    (old_path, new_path)
+  };
+  let header = FileHeader {
+    old_path,
+    new_path,
+    ..Default::default()
+  };
+  let (sections, line_idx) = parse_hunks(lines, line_idx)?;
+  Ok((header.into_file(sections), line_idx))
+}
+
+/// Parse the `---` and `+++` lines starting at `line_idx`. A path is `None`
+/// if that side of the file is absent.
+fn parse_old_new_paths<'a>(
+  lines: &[&'a str],
+  line_idx: usize,
+) -> (Option<Cow<'a, str>>, Option<Cow<'a, str>>, usize) {
+  let parse_path = |line: Option<&&'a str>, prefix: &str| {
+    let path = trim_newline(line?.strip_prefix(prefix)?);
+    // Some tools add a timestamp after the path, separated by a tab.
+    let path = match path.split_once('\t') {
+      Some((path, _timestamp)) if !path.starts_with('"') => path,
+      Some(_) | None => path,
+    };
+    if path == DEV_NULL {
+      None
+    } else {
+      Some(unquote(path))
+    }
+  };
+  let old_path = parse_path(lines.get(line_idx), "--- ");
+  let new_path = parse_path(lines.get(line_idx + 1), "+++ ");
+  (old_path, new_path, line_idx + 2)
+}
+
+/// Parse the hunks of a single file starting at `line_idx` into sections.
+/// Returns the sections and the index of the first line after the hunks.
+fn parse_hunks<'a>(
+  lines: &[&'a str],
+  mut line_idx: usize,
+) -> Result<(Vec<Section<'a>>, usize), PatchError> {
+  let mut sections = Vec::new();
+  // The 1-based line number in the old file of the next line which hasn't
+  // been accounted for yet.
+  let mut next_old_line_num = 1;
+  let mut has_hunks = false;
+  // Whether the hunks are known to reach the end of the old file.
+  let mut is_old_end_known = false;
+  while let Some(line) = lines.get(line_idx) {
+    if !line.starts_with("@@ ") {
+      break;
+    }
+    let header_line_num = line_idx + 1;
+    let HunkHeader {
+      old_start,
+      mut old_len,
+      new_start,
+      mut new_len,
+    } = parse_hunk_header(line).ok_or_else(|| PatchError::InvalidHunkHeader {
+      line_num: header_line_num,
+      line: trim_newline(line).to_owned(),
+    })?;
+    has_hunks = true;
+    // If either file is empty, such as when a file is created or deleted, the
+    // hunk contains all of the old file.
+    if (old_start == 0 && old_len == 0) || (new_start == 0 && new_len == 0) {
+      is_old_end_known = true;
+    }
+
+    // For hunks which don't remove or keep any old lines, the start is the
+    // line after which the new lines are added.
+    let old_start = if old_len == 0 {
+      old_start + 1
+    } else {
+      old_start
+    };
+    let num_omitted_lines =
+      old_start
+        .checked_sub(next_old_line_num)
+        .ok_or(PatchError::OverlappingHunk {
+          line_num: header_line_num,
+        })?;
+    if num_omitted_lines > 0 {
+      sections.push(Section::Omitted {
+        num_lines: Some(num_omitted_lines),
+      });
+    }
+    next_old_line_num = old_start + old_len;
+
+    line_idx += 1;
+    let mut prev_kind = None;
+    while old_len > 0 || new_len > 0 || is_no_newline_marker(lines.get(line_idx)) {
+      let line_num = line_idx + 1;
+      let line = match lines.get(line_idx) {
+        Some(line) => *line,
+        None => return Err(PatchError::TruncatedHunk { line_num }),
+      };
+      line_idx += 1;
+
+      let (kind, contents) = match line.chars().next() {
+        Some(kind @ (' ' | '-' | '+' | '\\')) => (kind, &line[1..]),
+        // Some tools strip the trailing whitespace from empty context lines.
+        Some('\n') | Some('\r') => (' ', line),
+        Some(_) | None => {
+          return Err(PatchError::InvalidHunkLine {
+            line_num,
+            line: trim_newline(line).to_owned(),
+          })
+        }
+      };
+      match kind {
+        ' ' if old_len > 0 && new_len > 0 => {
+          old_len -= 1;
+          new_len -= 1;
+          match sections.last_mut() {
+            Some(Section::Unchanged { lines }) => lines.push(Cow::Borrowed(contents)),
+            _ => sections.push(Section::Unchanged {
+              lines: vec![Cow::Borrowed(contents)],
+            }),
+          }
+        }
+        '-' if old_len > 0 => {
+          old_len -= 1;
+          push_changed_line(&mut sections, ChangeType::Removed, contents);
+        }
+        '+' if new_len > 0 => {
+          new_len -= 1;
+          push_changed_line(&mut sections, ChangeType::Added, contents);
+        }
+        '\\' => {
+          // `\ No newline at end of file` applies to the preceding line.
+          strip_last_line_newline(&mut sections);
+          if let Some(' ' | '-') = prev_kind {
+            is_old_end_known = true;
+          }
+        }
+        _ => {
+          return Err(PatchError::InvalidHunkLine {
+            line_num,
+            line: trim_newline(line).to_owned(),
+          })
+        }
+      }
+      prev_kind = Some(kind);
+    }
+  }
+  // The number of lines after the last hunk isn't known, but they still
+  // prevent the file's contents from being calculated.
+  if has_hunks && !is_old_end_known {
+    sections.push(Section::Omitted { num_lines: None });
+  }
+  Ok((sections, line_idx))
+}
+
+fn is_no_newline_marker(line: Option<&&str>) -> bool {
+  line.is_some_and(|line| line.starts_with('\\'))
+}
+
+fn push_changed_line<'a>(
+  sections: &mut Vec<Section<'a>>,
+  change_type: ChangeType,
+  line: &'a str,
+) {
+  let line = SectionChangedLine {
+    is_checked: false,
+    change_type,
+    line: Cow::Borrowed(line),
+  };
+  match sections.last_mut() {
+    Some(Section::Changed { lines }) => lines.push(line),
+    _ => sections.push(Section::Changed { lines: vec![line] }),
+  }
+}
+
+fn strip_last_line_newline(sections: &mut [Section]) {
+  let line = match sections.last_mut() {
+    Some(Section::Unchanged { lines }) => lines.last_mut(),
+    Some(Section::Changed { lines }) => lines.last_mut().map(|line| &mut line.line),
+    Some(
+      Section::Omitted { .. }
+      | Section::FileMode { .. }
+      | Section::Rename { .. }
+      | Section::Binary { .. }
+      | Section::Symlink { .. }
+      | Section::Submodule { .. },
+    )
+    | None => None,
+  };
+  if let Some(line) = line {
+    match line {
+      Cow::Borrowed(contents) => {
+        if let Some(contents) = contents.strip_suffix('\n') {
+          *line = Cow::Borrowed(contents);
+        }
+      }
+      Cow::Owned(contents) => {
+        if contents.ends_with('\n') {
+          contents.pop();
+        }
+      }
+    }
+  }
+}
+
+struct HunkHeader {
+  old_start: usize,
+  old_len: usize,
+  new_start: usize,
+  new_len: usize,
+}
+
+/// Parse a hunk header like `@@ -1,2 +1,3 @@ fn foo() {`.
+fn parse_hunk_header(line: &str) -> Option<HunkHeader> {
+  let rest = line.strip_prefix("@@ -")?;
+  let (old_range, rest) = rest.split_once(" +")?;
+  let (new_range, _rest) = rest.split_once(" @@")?;
+  let parse_range = |range: &str| -> Option<(usize, usize)> {
+    match range.split_once(',') {
+      Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+      None => Some((range.parse().ok()?, 1)),
+    }
+  };
+  let (old_start, old_len) = parse_range(old_range)?;
+  let (new_start, new_len) = parse_range(new_range)?;
+  Some(HunkHeader {
+    old_start,
+    old_len,
+    new_start,
+    new_len,
+  })
+}
+
+fn parse_mode(
+  line_num: usize,
+  mode: &str,
+) -> Result<FileMode, PatchError> {
+  match usize::from_str_radix(mode.trim(), 8) {
+    Ok(mode) => Ok(FileMode::Unix(mode)),
+    Err(_) => Err(PatchError::InvalidFileMode {
+      line_num,
+      mode: mode.to_owned(),
+    }),
+  }
+}
+
+/// Parse the `a/old b/new` arguments of a `diff --git` line. Unquoted paths
+/// containing spaces are ambiguous, so this assumes that both paths are the
+/// same when possible.
+fn parse_diff_git_paths(args: &str) -> Option<(Cow<'_, str>, Cow<'_, str>)> {
+  if args.starts_with('"') {
+    let (old_path, rest) = split_quoted(args)?;
+    let new_path = unquote(rest.strip_prefix(' ')?);
+    return Some((strip_git_prefix(old_path), strip_git_prefix(new_path)));
+  }
+  if let Some(idx) = args.find(" \"") {
+    let (old_path, new_path) = (&args[..idx], &args[idx + 1..]);
+    return Some((
+      strip_git_prefix(Cow::Borrowed(old_path)),
+      strip_git_prefix(unquote(new_path)),
+    ));
+  }
+
+  let candidates: Vec<(&str, &str)> = args
+    .match_indices(' ')
+    .map(|(idx, _)| (&args[..idx], &args[idx + 1..]))
+    .collect();
+  let (old_path, new_path) = candidates
+    .iter()
+    .find(|(old_path, new_path)| {
+      strip_git_prefix(Cow::Borrowed(old_path)) == strip_git_prefix(Cow::Borrowed(new_path))
+    })
+    .or_else(|| candidates.first())?;
+  Some((
+    strip_git_prefix(Cow::Borrowed(old_path)),
+    strip_git_prefix(Cow::Borrowed(new_path)),
+  ))
+}
+
+/// Remove the `a/` or `b/` prefix that Git adds to paths by default.
+fn strip_git_prefix(path: Cow<'_, str>) -> Cow<'_, str> {
+  match path {
+    Cow::Borrowed(path) => Cow::Borrowed(
+      path
+        .strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path),
+    ),
+    Cow::Owned(path) => Cow::Owned(
+      path
+        .strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(&path)
+        .to_owned(),
+    ),
+  }
+}
+
+/// Unquote a path which Git quoted because it contains special characters.
+/// Paths which aren't quoted are returned as-is.
+fn unquote(path: &str) -> Cow<'_, str> {
+  match split_quoted(path) {
+    Some((unquoted, "")) => unquoted,
+    Some(_) | None => Cow::Borrowed(path),
+  }
+}
+
+/// Parse a C-style quoted string at the start of `input`. Returns the
+/// unquoted string and the rest of the input after the closing quote.
+fn split_quoted(input: &str) -> Option<(Cow<'_, str>, &str)> {
+  let rest = input.strip_prefix('"')?;
+  let mut bytes = Vec::new();
+  let mut iter = rest.char_indices();
+  while let Some((idx, c)) = iter.next() {
+    match c {
+      '"' => {
+        let unquoted = String::from_utf8_lossy(&bytes).into_owned();
+        return Some((Cow::Owned(unquoted), &rest[idx + 1..]));
+      }
+      '\\' => {
+        let (_, escaped) = iter.next()?;
+        match escaped {
+          'a' => bytes.push(0x07),
+          'b' => bytes.push(0x08),
+          'f' => bytes.push(0x0c),
+          'n' => bytes.push(b'\n'),
+          'r' => bytes.push(b'\r'),
+          't' => bytes.push(b'\t'),
+          'v' => bytes.push(0x0b),
+          '0'..='7' => {
+            // An octal escape of exactly three digits, used for non-ASCII
+            // bytes.
+            let mut value = escaped.to_digit(8)?;
+            for _ in 0..2 {
+              let (_, digit) = iter.next()?;
+              value = value * 8 + digit.to_digit(8)?;
+            }
+            bytes.push(u8::try_from(value).ok()?);
+          }
+          escaped => {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(escaped.encode_utf8(&mut buf).as_bytes());
+          }
+        }
+      }
+      c => {
+        let mut buf = [0; 4];
+        bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+      }
+    }
+  }
+  None
+}
+
+fn trim_newline(line: &str) -> &str {
+  let line = line.strip_suffix('\n').unwrap_or(line);
+  line.strip_suffix('\r').unwrap_or(line)
+}
+
+/// Options for formatting patches.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PatchOptions {
+  /// The number of unchanged lines to include before and after each change.
+  pub num_context_lines: usize,
+}
+
+impl Default for PatchOptions {
+  fn default() -> Self {
+    Self {
+      num_context_lines: 3,
+    }
+  }
+}
+
+/// How a file's path changes in a patch.
+#[derive(Clone, Copy, Debug)]
+enum RenameKind {
+  Rename,
+  Copy,
+}
+
+/// A line of a file's diff, after deciding which changes are included.
+#[derive(Clone, Debug)]
+enum PatchLine<'a> {
+  Context(Cow<'a, str>),
+  Removed(Cow<'a, str>),
+  Added(Cow<'a, str>),
+  Omitted(usize),
+}
+
+impl PatchLine<'_> {
+  fn is_change(&self) -> bool {
+    match self {
+      PatchLine::Context(_) | PatchLine::Omitted(_) => false,
+      PatchLine::Removed(_) | PatchLine::Added(_) => true,
+    }
+  }
+}
+
+/// Format the changes of `file` for which `is_included` returns `true` as a
+/// Git patch. Returns an empty string if no changes are included.
+pub(crate) fn format_file_patch(
+  file: &File,
+  is_included: impl Fn(usize, usize) -> bool,
+  options: &PatchOptions,
+) -> String {
+  let File {
+    old_path,
+    path,
+    file_mode: old_mode,
+    sections,
+    similarity,
+    ..
+  } = file;
+
+  // Without a `Section::Rename`, a rename or copy is always included.
+  let mut rename = old_path
+    .as_deref()
+    .filter(|old_path| *old_path != path.as_ref())
+    .map(|_| RenameKind::Rename);
+  let mut new_mode = *old_mode;
+  let mut mode_change = None;
+  let mut is_binary_changed = false;
+  let mut lines = Vec::new();
+  for (section_idx, section) in sections.iter().enumerate() {
+    match section {
+      Section::Unchanged {
+        lines: section_lines,
+      } => {
+        lines.extend(
+          section_lines
+            .iter()
+            .map(|line| PatchLine::Context(Cow::Borrowed(line))),
+        );
+      }
+      Section::Omitted { num_lines } => {
+        // Only the lines at the end of the file can be an unknown number of
+        // lines, so they don't affect the line numbers of any hunk.
+        lines.push(PatchLine::Omitted(num_lines.unwrap_or_default()));
+      }
+      Section::Changed {
+        lines: section_lines,
+      } => {
+        for (line_idx, section_line) in section_lines.iter().enumerate() {
+          let SectionChangedLine {
+            is_checked: _,
+            change_type,
+            line,
+          } = section_line;
+          match (change_type, is_included(section_idx, line_idx)) {
+            (ChangeType::Added, true) => lines.push(PatchLine::Added(Cow::Borrowed(line))),
+            (ChangeType::Added, false) => {}
+            (ChangeType::Removed, true) => lines.push(PatchLine::Removed(Cow::Borrowed(line))),
+            (ChangeType::Removed, false) => lines.push(PatchLine::Context(Cow::Borrowed(line))),
+          }
+        }
+      }
+      Section::FileMode {
+        is_checked: _,
+        mode,
+      } => {
+        mode_change = Some(*mode);
+        if is_included(section_idx, 0) {
+          new_mode = *mode;
+        }
+      }
+      Section::Rename {
+        is_checked: _,
+        is_copy,
+      } => {
+        rename = match (is_included(section_idx, 0), is_copy) {
+          (true, false) => Some(RenameKind::Rename),
+          (true, true) => Some(RenameKind::Copy),
+          (false, false) => None,
+          // The copied file doesn't exist without the copy.
+          (false, true) => return String::new(),
+        };
+      }
+      Section::Binary {
+        is_checked: _,
+        old_description: _,
+        new_description: _,
+        contents: _,
+      } => {
+        if is_included(section_idx, 0) {
+          is_binary_changed = true;
+        }
+      }
+      Section::Symlink {
+        is_checked: _,
+        old_target,
+        new_target,
+      } => {
+        // Git stores the target of a symlink as its contents, without a
+        // trailing newline.
+        push_replaced_line(
+          &mut lines,
+          old_target.as_deref().map(Cow::Borrowed),
+          new_target.as_deref().map(Cow::Borrowed),
+          is_included(section_idx, 0),
+        );
+      }
+      Section::Submodule {
+        is_checked: _,
+        old_commit,
+        new_commit,
+      } => {
+        let format_commit = |commit: &Cow<str>| Cow::Owned(format!("Subproject commit {commit}\n"));
+        push_replaced_line(
+          &mut lines,
+          old_commit.as_ref().map(format_commit),
+          new_commit.as_ref().map(format_commit),
+          is_included(section_idx, 0),
+        );
+      }
+    }
+  }
+
+  let hunks = make_hunks(&lines, options.num_context_lines);
+  // Lines can't be added to a file without creating it.
+  if *old_mode == FileMode::Absent && new_mode == FileMode::Absent && !hunks.is_empty() {
+    new_mode = mode_change
+      .filter(|mode| *mode != FileMode::Absent)
+      .unwrap_or(FileMode::FILE_DEFAULT);
+  }
+  if hunks.is_empty() && !is_binary_changed && new_mode == *old_mode && rename.is_none() {
+    return String::new();
+  }
+
+  let old_path = old_path.as_deref().unwrap_or(path).to_string_lossy();
+  let new_path = match rename {
+    Some(RenameKind::Rename | RenameKind::Copy) => path.to_string_lossy(),
+    None => old_path.clone(),
+  };
+  let mut result = String::new();
+  writeln!(result, "diff --git a/{old_path} b/{new_path}").unwrap();
+  match (old_mode, new_mode) {
+    (FileMode::Absent, FileMode::Absent) => {}
+    (FileMode::Absent, new_mode) => writeln!(result, "new file mode {new_mode}").unwrap(),
+    (old_mode, FileMode::Absent) => writeln!(result, "deleted file mode {old_mode}").unwrap(),
+    (old_mode, new_mode) => {
+      if *old_mode != new_mode {
+        writeln!(result, "old mode {old_mode}").unwrap();
+        writeln!(result, "new mode {new_mode}").unwrap();
+      }
+    }
+  }
+  if let Some(rename) = rename {
+    if let Some(similarity) = similarity {
+      writeln!(result, "similarity index {similarity}%").unwrap();
+    }
+    let verb = match rename {
+      RenameKind::Rename => "rename",
+      RenameKind::Copy => "copy",
+    };
+    writeln!(result, "{verb} from {old_path}").unwrap();
+    writeln!(result, "{verb} to {new_path}").unwrap();
+  }
+
+  let old_label = if *old_mode == FileMode::Absent {
+    Cow::Borrowed(DEV_NULL)
+  } else {
+    Cow::Owned(format!("a/{old_path}"))
+  };
+  let new_label = if new_mode == FileMode::Absent {
+    Cow::Borrowed(DEV_NULL)
+  } else {
+    Cow::Owned(format!("b/{new_path}"))
+  };
+  if is_binary_changed {
+    writeln!(result, "Binary files {old_label} and {new_label} differ").unwrap();
+  } else if !hunks.is_empty() {
+    writeln!(result, "--- {old_label}").unwrap();
+    writeln!(result, "+++ {new_label}").unwrap();
+    for hunk in hunks {
+      write_hunk(&mut result, &hunk);
+    }
+  }
+  result
+}
+
+/// Push the lines for a change from `old` to `new`, which is either included
+/// as a whole or not at all.
+fn push_replaced_line<'a>(
+  lines: &mut Vec<PatchLine<'a>>,
+  old: Option<Cow<'a, str>>,
+  new: Option<Cow<'a, str>>,
+  is_included: bool,
+) {
+  if is_included {
+    lines.extend(old.map(PatchLine::Removed));
+    lines.extend(new.map(PatchLine::Added));
+  } else {
+    lines.extend(old.map(PatchLine::Context));
+  }
+}
+
+struct Hunk<'a> {
+  old_start: usize,
+  new_start: usize,
+  lines: &'a [PatchLine<'a>],
+}
+
+/// Group the changes in `lines` into hunks with up to `num_context_lines` of
+/// context around each change. Changes separated by at most twice that many
+/// context lines are put into the same hunk. Omitted lines can't be included
+/// in a hunk, so they always separate hunks.
+fn make_hunks<'a>(
+  lines: &'a [PatchLine<'a>],
+  num_context_lines: usize,
+) -> Vec<Hunk<'a>> {
+  // The 1-based line numbers in the old and new files of the line at each
+  // index.
+  let mut line_nums = Vec::with_capacity(lines.len());
+  let (mut old_line_num, mut new_line_num) = (1, 1);
+  for line in lines {
+    line_nums.push((old_line_num, new_line_num));
+    match line {
+      PatchLine::Context(_) => {
+        old_line_num += 1;
+        new_line_num += 1;
+      }
+      PatchLine::Omitted(num_lines) => {
+        old_line_num += num_lines;
+        new_line_num += num_lines;
+      }
+      PatchLine::Removed(_) => old_line_num += 1,
+      PatchLine::Added(_) => new_line_num += 1,
+    }
+  }
+
+  let is_context = |idx: usize| matches!(lines.get(idx), Some(PatchLine::Context(_)));
+  let is_change = |idx: usize| lines.get(idx).is_some_and(|line| line.is_change());
+  let mut hunks = Vec::new();
+  let mut idx = 0;
+  while idx < lines.len() {
+    if !is_change(idx) {
+      idx += 1;
+      continue;
+    }
+
+    let mut start = idx;
+    while start > 0 && idx - start < num_context_lines && is_context(start - 1) {
+      start -= 1;
+    }
+    let mut end = idx;
+    loop {
+      while is_change(end) {
+        end += 1;
+      }
+      let mut context_end = end;
+      while is_context(context_end) {
+        context_end += 1;
+      }
+      let num_context = context_end - end;
+      if is_change(context_end) && num_context <= 2 * num_context_lines {
+        end = context_end;
+      } else {
+        end += num_context.min(num_context_lines);
+        break;
+      }
+    }
+
+    let (old_start, new_start) = line_nums[start];
+    hunks.push(Hunk {
+      old_start,
+      new_start,
+      lines: &lines[start..end],
+    });
+    idx = end;
+  }
+  hunks
+}
+
+fn write_hunk(
+  result: &mut String,
+  hunk: &Hunk,
+) {
+  let Hunk {
+    old_start,
+    new_start,
+    lines,
+  } = hunk;
+  let old_len = lines
+    .iter()
+    .filter(|line| matches!(line, PatchLine::Context(_) | PatchLine::Removed(_)))
+    .count();
+  let new_len = lines
+    .iter()
+    .filter(|line| matches!(line, PatchLine::Context(_) | PatchLine::Added(_)))
+    .count();
+  let format_range = |start: usize, len: usize| match len {
+    // An empty range refers to the line before the hunk.
+    0 => format!("{},0", start - 1),
+    1 => start.to_string(),
+    len => format!("{start},{len}"),
+  };
+  writeln!(
+    result,
+    "@@ -{} +{} @@",
+    format_range(*old_start, old_len),
+    format_range(*new_start, new_len)
+  )
+  .unwrap();
+
+  for line in lines.iter() {
+    let (prefix, contents) = match line {
+      PatchLine::Context(contents) => (' ', contents),
+      PatchLine::Removed(contents) => ('-', contents),
+      PatchLine::Added(contents) => ('+', contents),
+      PatchLine::Omitted(_) => {
+        // Hunks never contain omitted lines.
+        continue;
+      }
+    };
+    result.push(prefix);
+    result.push_str(contents);
+    if !contents.ends_with('\n') {
+      result.push_str("\n\\ No newline at end of file\n");
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::SelectedContents;
+
+  use super::*;
+
+  #[test]
+  fn test_parse_git_patch() {
+    let patch = "\
+commit message
+
+diff --git a/foo b/foo
+index 1234567..89abcde 100644
+--- a/foo
++++ b/foo
+@@ -2,3 +2,3 @@ context
+ line 2
+-line 3
++line 3 changed
+ line 4
+@@ -10,2 +10,3 @@
+ line 10
++line 10.5
+ line 11
+diff --git a/new b/new
+new file mode 100755
+index 0000000..1234567
+--- /dev/null
++++ b/new
+@@ -0,0 +1 @@
++new line
+\\ No newline at end of file
+diff --git a/deleted b/deleted
+deleted file mode 100644
+index 1234567..0000000
+--- a/deleted
++++ /dev/null
+@@ -1 +0,0 @@
+-old line
+";
+    insta::assert_debug_snapshot!(parse_patch(patch).unwrap(), @r#"
+    [
+        File {
+            old_path: None,
+            path: "foo",
+            file_mode: Unix(
+                33188,
+            ),
+            sections: [
+                Omitted {
+                    num_lines: Some(
+                        1,
+                    ),
+                },
+                Unchanged {
+                    lines: [
+                        "line 2\n",
+                    ],
+                },
+                Changed {
+                    lines: [
+                        SectionChangedLine {
+                            is_checked: false,
+                            change_type: Removed,
+                            line: "line 3\n",
+                        },
+                        SectionChangedLine {
+                            is_checked: false,
+                            change_type: Added,
+                            line: "line 3 changed\n",
+                        },
+                    ],
+                },
+                Unchanged {
+                    lines: [
+                        "line 4\n",
+                    ],
+                },
+                Omitted {
+                    num_lines: Some(
+                        5,
+                    ),
+                },
+                Unchanged {
+                    lines: [
+                        "line 10\n",
+                    ],
+                },
+                Changed {
+                    lines: [
+                        SectionChangedLine {
+                            is_checked: false,
+                            change_type: Added,
+                            line: "line 10.5\n",
+                        },
+                    ],
+                },
+                Unchanged {
+                    lines: [
+                        "line 11\n",
+                    ],
+                },
+                Omitted {
+                    num_lines: None,
+                },
+            ],
+            commit_assignments: [],
+            similarity: None,
+            unloaded: None,
+            containers: None,
+        },
+        File {
+            old_path: None,
+            path: "new",
+            file_mode: Absent,
+            sections: [
+                FileMode {
+                    is_checked: false,
+                    mode: Unix(
+                        33261,
+                    ),
+                },
+                Changed {
+                    lines: [
+                        SectionChangedLine {
+                            is_checked: false,
+                            change_type: Added,
+                            line: "new line",
+                        },
+                    ],
+                },
+            ],
+            commit_assignments: [],
+            similarity: None,
+            unloaded: None,
+            containers: None,
+        },
+        File {
+            old_path: None,
+            path: "deleted",
+            file_mode: Unix(
+                33188,
+            ),
+            sections: [
+                FileMode {
+                    is_checked: false,
+                    mode: Absent,
+                },
+                Changed {
+                    lines: [
+                        SectionChangedLine {
+                            is_checked: false,
+                            change_type: Removed,
+                            line: "old line\n",
+                        },
+                    ],
+                },
+            ],
+            commit_assignments: [],
+            similarity: None,
+            unloaded: None,
+            containers: None,
+        },
+    ]
+    "#);
+  }
+
+  #[test]
+  fn test_parse_git_extended_headers() {
+    let patch = "\
+diff --git a/old name b/new name
+similarity index 90%
+rename from old name
+rename to new name
+diff --git a/script b/script
+old mode 100644
+new mode 100755
+diff --git a/image.png b/image.png
+index 1234567..89abcde 100644
+Binary files a/image.png and b/image.png differ
+diff --git a/created.bin b/created.bin
+new file mode 100644
+index 0000000..1234567
+GIT binary patch
+literal 3
+KcmZQzWMT jh0RR91
+
+literal 0
+HcmV?d00001
+
+diff --git \"a/tab\\there\" \"b/tab\\there\"
+index 1234567..89abcde 100644
+--- \"a/tab\\there\"
++++ \"b/tab\\there\"
+@@ -1 +1 @@
+-a
++b
+";
+    insta::assert_debug_snapshot!(parse_patch(patch).unwrap(), @r#"
+    [
+        File {
+            old_path: Some(
+                "old name",
+            ),
+            path: "new name",
+            file_mode: Unix(
+                33188,
+            ),
+            sections: [
+                Rename {
+                    is_checked: false,
+                    is_copy: false,
+                },
+            ],
+            commit_assignments: [],
+            similarity: Some(
+                90,
+            ),
+            unloaded: None,
+            containers: None,
+        },
+        File {
+            old_path: None,
+            path: "script",
+            file_mode: Unix(
+                33188,
+            ),
+            sections: [
+                FileMode {
+                    is_checked: false,
+                    mode: Unix(
+                        33261,
+                    ),
+                },
+            ],
+            commit_assignments: [],
+            similarity: None,
+            unloaded: None,
+            containers: None,
+        },
+        File {
+            old_path: None,
+            path: "image.png",
+            file_mode: Unix(
+                33188,
+            ),
+            sections: [
+                Binary {
+                    is_checked: false,
+                    old_description: Some(
+                        "1234567",
+                    ),
+                    new_description: Some(
+                        "89abcde",
+                    ),
+                    contents: None,
+                },
+            ],
+            commit_assignments: [],
+            similarity: None,
+            unloaded: None,
+            containers: None,
+        },
+        File {
+            old_path: None,
+            path: "created.bin",
+            file_mode: Absent,
+            sections: [
+                FileMode {
+                    is_checked: false,
+                    mode: Unix(
+                        33188,
+                    ),
+                },
+                Binary {
+                    is_checked: false,
+                    old_description: None,
+                    new_description: Some(
+                        "1234567",
+                    ),
+                    contents: None,
+                },
+            ],
+            commit_assignments: [],
+            similarity: None,
+            unloaded: None,
+            containers: None,
+        },
+        File {
+            old_path: None,
+            path: "tab\there",
+            file_mode: Unix(
+                33188,
+            ),
+            sections: [
+                Changed {
+                    lines: [
+                        SectionChangedLine {
+                            is_checked: false,
+                            change_type: Removed,
+                            line: "a\n",
+                        },
+                        SectionChangedLine {
+                            is_checked: false,
+                            change_type: Added,
+                            line: "b\n",
+                        },
+                    ],
+                },
+                Omitted {
+                    num_lines: None,
+                },
+            ],
+            commit_assignments: [],
+            similarity: None,
+            unloaded: None,
+            containers: None,
+        },
+    ]
+    "#);
+  }
+
+  #[test]
+  fn test_parse_unified_diff() {
+    let patch = "\
+--- foo.orig\t2024-01-01 00:00:00.000000000 +0000
++++ foo\t2024-01-01 00:00:00.000000000 +0000
+@@ -1,4 +1,3 @@
+-old
+
+ unchanged
+-last
+\\ No newline at end of file
++last
+";
+    insta::assert_debug_snapshot!(parse_patch(patch), @r#"
+    Ok(
+        [
+            File {
+                old_path: Some(
+                    "foo.orig",
+                ),
+                path: "foo",
+                file_mode: Unix(
+                    33188,
+                ),
+                sections: [
+                    Changed {
+                        lines: [
+                            SectionChangedLine {
+                                is_checked: false,
+                                change_type: Removed,
+                                line: "old\n",
+                            },
+                        ],
+                    },
+                    Unchanged {
+                        lines: [
+                            "\n",
+                            "unchanged\n",
+                        ],
+                    },
+                    Changed {
+                        lines: [
+                            SectionChangedLine {
+                                is_checked: false,
+                                change_type: Removed,
+                                line: "last",
+                            },
+                            SectionChangedLine {
+                                is_checked: false,
+                                change_type: Added,
+                                line: "last\n",
+                            },
+                        ],
+                    },
+                ],
+                commit_assignments: [],
+                similarity: None,
+                unloaded: None,
+                containers: None,
+            },
+        ],
+    )
+    "#);
+  }
+
+  #[test]
+  fn test_parse_patch_errors() {
+    insta::assert_snapshot!(parse_patch("@@ -1 +1 @@\n").unwrap_err(), @"line 1: hunk without a file header");
+    insta::assert_snapshot!(
+      parse_patch("--- a\n+++ b\n@@ -1,2 +1,2 @@\n-a\n+b\n").unwrap_err(),
+      @"line 6: hunk is missing lines"
+    );
+    insta::assert_snapshot!(
+      parse_patch("--- a\n+++ b\n@@ -5 +5 @@\n-a\n+b\n@@ -3 +3 @@\n-a\n+b\n").unwrap_err(),
+      @"line 6: hunk overlaps the previous hunk"
+    );
+    insta::assert_snapshot!(parse_patch("--- a\n+++ b\n@@ -1 +1 @@\nfoo\n").unwrap_err(), @r#"line 4: invalid line in hunk: "foo""#);
+    insta::assert_snapshot!(
+      parse_patch("diff --git a/a b/a\nold mode 9\n").unwrap_err(),
+      @r#"line 2: invalid file mode: "9""#
+    );
+  }
+
+  fn select_all(files: &mut [File]) {
+    for file in files {
+      file.set_checked(true);
+    }
+  }
+
+  #[test]
+  fn test_format_patch_round_trip() {
+    let patch = "\
+diff --git a/foo b/foo
+--- a/foo
++++ b/foo
+@@ -2,3 +2,3 @@
+ line 2
+-line 3
++line 3 changed
+ line 4
+@@ -10,2 +10,3 @@
+ line 10
++line 10.5
+ line 11
+diff --git a/new b/new
+new file mode 100755
+--- /dev/null
++++ b/new
+@@ -0,0 +1 @@
++new line
+\\ No newline at end of file
+diff --git a/old b/old
+deleted file mode 100644
+--- a/old
++++ /dev/null
+@@ -1 +0,0 @@
+-old line
+diff --git a/script b/script
+old mode 100644
+new mode 100755
+diff --git a/from b/to
+rename from from
+rename to to
+diff --git a/image.png b/image.png
+Binary files a/image.png and b/image.png differ
+";
+    let mut files = parse_patch(patch).unwrap();
+    select_all(&mut files);
+    let state = crate::RecordState {
+      is_read_only: false,
+      commits: Default::default(),
+      files,
+    };
+    let (selected, unselected) = state.get_selected_patches(&PatchOptions::default());
+    assert_eq!(selected, patch);
+    insta::assert_snapshot!(unselected, @"");
+  }
+
+  #[test]
+  fn test_selected_contents_with_omitted_lines() {
+    let patch = "\
+--- foo
++++ foo
+@@ -1 +1 @@
+-old
++new
+@@ -10 +10 @@
+-old
++new
+";
+    let mut files = parse_patch(patch).unwrap();
+    files[0].set_checked(true);
+    let (selected, unselected) = files[0].get_selected_contents();
+    insta::assert_debug_snapshot!((selected.contents, unselected.contents), @"
+    (
+        Incomplete {
+            num_omitted_lines: None,
+        },
+        Incomplete {
+            num_omitted_lines: None,
+        },
+    )
+    ");
+  }
+
+  #[test]
+  fn test_selected_contents_after_last_hunk() {
+    // The file may continue after the hunk, so its contents aren't known.
+    let patch = "\
+--- a/foo
++++ b/foo
+@@ -1,2 +1,2 @@
+-old
++new
+ ctx
+";
+    let mut files = parse_patch(patch).unwrap();
+    files[0].set_checked(true);
+    let (selected, unselected) = files[0].get_selected_contents();
+    assert!(!matches!(selected.contents, SelectedContents::Text { .. }));
+    assert!(!matches!(
+      unselected.contents,
+      SelectedContents::Text { .. }
+    ));
+
+    // Unless the hunk reaches the end of the old file.
+    let patch = "\
+--- a/foo
++++ b/foo
+@@ -1,2 +1,2 @@
+-old
++new
+ ctx
+\\ No newline at end of file
+";
+    let mut files = parse_patch(patch).unwrap();
+    files[0].set_checked(true);
+    let (selected, _unselected) = files[0].get_selected_contents();
+    insta::assert_debug_snapshot!(selected.contents, @r#"
+    Text {
+        contents: "new\nctx",
+    }
+    "#);
+  }
+
+  #[test]
+  fn test_format_patch_rename() {
+    let patch = "\
+diff --git a/from b/to
+similarity index 90%
+rename from from
+rename to to
+--- a/from
++++ b/to
+@@ -1 +1 @@
+-old line
++new line
+diff --git a/source b/copy
+similarity index 100%
+copy from source
+copy to copy
+";
+    let mut files = parse_patch(patch).unwrap();
+    // Select the changed lines, but not the rename or copy.
+    for file in &mut files {
+      for section in &mut file.sections {
+        if let Section::Changed { .. } = section {
+          section.set_checked(true);
+        }
+      }
+    }
+    let state = crate::RecordState {
+      is_read_only: false,
+      commits: Default::default(),
+      files,
+    };
+    let (selected, unselected) = state.get_selected_patches(&PatchOptions::default());
+    insta::assert_snapshot!(selected, @"
+    diff --git a/from b/from
+    --- a/from
+    +++ b/from
+    @@ -1 +1 @@
+    -old line
+    +new line
+    ");
+    insta::assert_snapshot!(unselected, @"
+    diff --git a/from b/to
+    similarity index 90%
+    rename from from
+    rename to to
+    diff --git a/source b/copy
+    similarity index 100%
+    copy from source
+    copy to copy
+    ");
+  }
+
+  #[test]
+  fn test_format_patch_symlink_and_submodule() {
+    let make_file = |path: &'static str, section: Section<'static>| File {
+      old_path: None,
+      path: Cow::Borrowed(Path::new(path)),
+      file_mode: FileMode::Unix(0o120000),
+      sections: vec![section],
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
+      #[cfg(feature = "tree-sitter")]
+      containers: None,
+    };
+    let symlink = make_file(
+      "link",
+      Section::Symlink {
+        is_checked: true,
+        old_target: Some(Cow::Borrowed("old-target")),
+        new_target: Some(Cow::Borrowed("new-target")),
+      },
+    );
+    let (selected, unselected) = symlink.get_selected_patches(&PatchOptions::default());
+    insta::assert_snapshot!(selected, @r"
+    diff --git a/link b/link
+    --- a/link
+    +++ b/link
+    @@ -1 +1 @@
+    -old-target
+    \ No newline at end of file
+    +new-target
+    \ No newline at end of file
+    ");
+    insta::assert_snapshot!(unselected, @"");
+
+    let mut submodule = make_file(
+      "sub",
+      Section::Submodule {
+        is_checked: false,
+        old_commit: Some(Cow::Borrowed("1111111111111111111111111111111111111111")),
+        new_commit: Some(Cow::Borrowed("2222222222222222222222222222222222222222")),
+      },
+    );
+    submodule.file_mode = FileMode::Unix(0o160000);
+    let (selected, unselected) = submodule.get_selected_patches(&PatchOptions::default());
+    insta::assert_snapshot!(selected, @"");
+    insta::assert_snapshot!(unselected, @"
+    diff --git a/sub b/sub
+    --- a/sub
+    +++ b/sub
+    @@ -1 +1 @@
+    -Subproject commit 1111111111111111111111111111111111111111
+    +Subproject commit 2222222222222222222222222222222222222222
+    ");
+  }
+
+  #[test]
+  fn test_format_patch_partial_selection() {
+    let file = File {
+      old_path: None,
+      path: Cow::Borrowed(Path::new("foo")),
+      file_mode: FileMode::FILE_DEFAULT,
+      sections: vec![
+        Section::Unchanged {
+          lines: (1..=5).map(|i| Cow::Owned(format!("line {i}\n"))).collect(),
+        },
+        Section::Changed {
+          lines: vec![
+            SectionChangedLine {
+              is_checked: false,
+              change_type: ChangeType::Removed,
+              line: Cow::Borrowed("line 6\n"),
+            },
+            SectionChangedLine {
+              is_checked: true,
+              change_type: ChangeType::Added,
+              line: Cow::Borrowed("line 6 changed\n"),
+            },
+            SectionChangedLine {
+              is_checked: true,
+              change_type: ChangeType::Added,
+              line: Cow::Borrowed("line 6.5\n"),
+            },
+          ],
+        },
+        Section::Unchanged {
+          lines: (7..=14)
+            .map(|i| Cow::Owned(format!("line {i}\n")))
+            .collect(),
+        },
+        Section::Changed {
+          lines: vec![SectionChangedLine {
+            is_checked: false,
+            change_type: ChangeType::Removed,
+            line: Cow::Borrowed("line 15\n"),
+          }],
+        },
+        Section::Unchanged {
+          lines: vec![Cow::Borrowed("line 16\n")],
+        },
+      ],
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
+      #[cfg(feature = "tree-sitter")]
+      containers: None,
+    };
+
+    let (selected, unselected) = file.get_selected_patches(&PatchOptions::default());
+    insta::assert_snapshot!(selected, @"
+    diff --git a/foo b/foo
+    --- a/foo
+    +++ b/foo
+    @@ -4,6 +4,8 @@
+     line 4
+     line 5
+     line 6
+    +line 6 changed
+    +line 6.5
+     line 7
+     line 8
+     line 9
+    ");
+    insta::assert_snapshot!(unselected, @"
+    diff --git a/foo b/foo
+    --- a/foo
+    +++ b/foo
+    @@ -3,7 +3,6 @@
+     line 3
+     line 4
+     line 5
+    -line 6
+     line 7
+     line 8
+     line 9
+    @@ -12,5 +11,4 @@
+     line 12
+     line 13
+     line 14
+    -line 15
+     line 16
+    ");
+
+    let (selected, unselected) = file.get_selected_patches(&PatchOptions {
+      num_context_lines: 1,
+    });
+    insta::assert_snapshot!(selected, @"
+    diff --git a/foo b/foo
+    --- a/foo
+    +++ b/foo
+    @@ -6,2 +6,4 @@
+     line 6
+    +line 6 changed
+    +line 6.5
+     line 7
+    ");
+    insta::assert_snapshot!(unselected, @"
+    diff --git a/foo b/foo
+    --- a/foo
+    +++ b/foo
+    @@ -5,3 +5,2 @@
+     line 5
+    -line 6
+     line 7
+    @@ -14,3 +13,2 @@
+     line 14
+    -line 15
+     line 16
+    ");
+  }
+
+  #[test]
+  fn test_format_patch_created_file_without_mode() {
+    let patch = "\
+diff --git a/foo b/foo
+new file mode 100755
+--- /dev/null
++++ b/foo
+@@ -0,0 +1,2 @@
++line 1
++line 2
+";
+    let mut files = parse_patch(patch).unwrap();
+    match &mut files[0].sections[..] {
+      [Section::FileMode { .. }, Section::Changed { lines }] => lines[0].is_checked = true,
+      sections => panic!("unexpected sections: {sections:?}"),
+    }
+    let (selected, unselected) = files[0].get_selected_patches(&PatchOptions::default());
+    insta::assert_snapshot!(selected, @"
+    diff --git a/foo b/foo
+    new file mode 100755
+    --- /dev/null
+    +++ b/foo
+    @@ -0,0 +1 @@
+    +line 1
+    ");
+    insta::assert_snapshot!(unselected, @"
+    diff --git a/foo b/foo
+    new file mode 100755
+    --- /dev/null
+    +++ b/foo
+    @@ -0,0 +1 @@
+    +line 2
+    ");
+  }
+
+  #[test]
+  fn test_format_patch_no_changes() {
+    let mut files = parse_patch("--- a/foo\n+++ b/foo\n@@ -1 +1 @@\n-a\n+b\n").unwrap();
+    let (selected, unselected) = files[0].get_selected_patches(&PatchOptions::default());
+    assert_eq!(selected, "");
+    assert_eq!(
+      unselected,
+      "diff --git a/foo b/foo\n--- a/foo\n+++ b/foo\n@@ -1 +1 @@\n-a\n+b\n"
+    );
+
+    select_all(&mut files);
+    let (selected, unselected) = files[0].get_selected_patches(&PatchOptions::default());
+    assert_eq!(
+      selected,
+      "diff --git a/foo b/foo\n--- a/foo\n+++ b/foo\n@@ -1 +1 @@\n-a\n+b\n"
+    );
+    assert_eq!(unselected, "");
+  }
+}
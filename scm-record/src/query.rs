@@ -0,0 +1,656 @@
+//! Selecting changes without user interaction.
+//!
+//! A [`SelectionQuery`] describes a set of changes, such as "the added lines
+//! in files under `src/generated/`", and can select or unselect those
+//! changes in a [`RecordState`] directly. This is useful for scripts which
+//! want to make a partial commit without rendering the UI.
+//!
+//! A query is written as a sequence of whitespace-separated terms, all of
+//! which must match for a change to be included:
+//!
+//! - `path:<glob>` matches changes in files whose path (or previous path, if
+//!   the file was renamed) matches the glob. `*` doesn't match `/`, while
+//!   `**` does.
+//! - `line:<regex>` matches changed lines whose contents (without the
+//!   trailing newline) match the regular expression.
+//! - `hunk:<regex>` matches every changed line in a [`Section::Changed`] if
+//!   any of its lines match the regular expression, as with `line:`. For
+//!   example, `hunk:"fn parse"` matches the whole of each hunk which touches
+//!   `fn parse`, including the lines which don't mention it.
+//! - `type:<type>` matches changes of the given type, which is one of
+//!   `added`, `removed`, `file-mode`, `rename`, `binary`, `symlink`, or
+//!   `submodule`.
+//! - `container:<glob>` matches changes inside a [`SemanticContainer`] (such
+//!   as a function or class) whose name matches the glob, including changes
+//!   in nested containers. This requires the `tree-sitter` feature.
+//!
+//! A value containing whitespace can be surrounded by double quotes, as in
+//! `line:"fn parse"`. Inside quotes, `\"` stands for a literal quote.
+//!
+//! [`SemanticContainer`]: crate::SemanticContainer
+
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use globset::{Glob, GlobBuilder, GlobMatcher};
+use regex::Regex;
+use thiserror::Error;
+
+use crate::selection::ChangeKind;
+use crate::{ChangeType, File, RecordState, Section};
+
+/// An error which occurred when parsing a [`SelectionQuery`].
+#[derive(Debug, Error)]
+pub enum QueryError {
+  /// The query didn't contain any terms.
+  #[error("empty query")]
+  Empty,
+
+  /// A term wasn't of the form `<key>:<value>`, or the key wasn't
+  /// recognized.
+  #[error(
+    "invalid query term {term:?}: expected one of `path:`, `line:`, `hunk:`, `type:`, or `container:`"
+  )]
+  InvalidTerm {
+    /// The contents of the term.
+    term: String,
+  },
+
+  /// A quoted value wasn't terminated.
+  #[error("unterminated quote in query term {term:?}")]
+  UnterminatedQuote {
+    /// The contents of the term up to the end of the query.
+    term: String,
+  },
+
+  /// A glob in a `path:` or `container:` term couldn't be parsed.
+  #[error("invalid glob {glob:?}: {source}")]
+  InvalidGlob {
+    /// The unparsed glob.
+    glob: String,
+    /// The underlying error.
+    #[source]
+    source: globset::Error,
+  },
+
+  /// A regular expression in a `line:` or `hunk:` term couldn't be parsed.
+  #[error("invalid regular expression {regex:?}: {source}")]
+  InvalidRegex {
+    /// The unparsed regular expression.
+    regex: String,
+    /// The underlying error.
+    #[source]
+    source: regex::Error,
+  },
+
+  /// The type in a `type:` term wasn't recognized.
+  #[error(
+    "invalid change type {change_type:?}: expected one of `added`, `removed`, `file-mode`, `rename`, `binary`, `symlink`, or `submodule`"
+  )]
+  InvalidChangeType {
+    /// The unrecognized type.
+    change_type: String,
+  },
+
+  /// A `container:` term was used without the `tree-sitter` feature.
+  #[error("`container:` terms require the `tree-sitter` feature")]
+  ContainersUnsupported,
+}
+
+/// A single term of a [`SelectionQuery`].
+#[derive(Clone, Debug)]
+enum QueryTerm {
+  Path(GlobMatcher),
+  Line(Regex),
+  Hunk(Regex),
+  Kind(ChangeKind),
+  #[cfg_attr(not(feature = "tree-sitter"), allow(dead_code))]
+  Container(GlobMatcher),
+}
+
+/// A description of a set of changes to select or unselect. See the
+/// [module documentation](self) for the syntax.
+#[derive(Clone, Debug)]
+pub struct SelectionQuery {
+  terms: Vec<QueryTerm>,
+}
+
+impl FromStr for SelectionQuery {
+  type Err = QueryError;
+
+  fn from_str(query: &str) -> Result<Self, Self::Err> {
+    let terms = split_terms(query)?
+      .into_iter()
+      .map(|(key, value)| parse_term(&key, value))
+      .collect::<Result<Vec<_>, _>>()?;
+    if terms.is_empty() {
+      return Err(QueryError::Empty);
+    }
+    Ok(Self { terms })
+  }
+}
+
+impl SelectionQuery {
+  /// Get the `(section_idx, line_idx)` of each change in `file` which
+  /// matches this query.
+  pub fn matching_changes(
+    &self,
+    file: &File,
+  ) -> Vec<(usize, usize)> {
+    let Self { terms } = self;
+
+    // Precompute the matching sections for each `hunk:` and `container:`
+    // term.
+    let mut term_sections = Vec::with_capacity(terms.len());
+    for term in terms {
+      let sections = match term {
+        QueryTerm::Path(glob) => {
+          let path_matches = glob.is_match(&file.path)
+            || file
+              .old_path
+              .as_ref()
+              .is_some_and(|old_path| glob.is_match(old_path));
+          if !path_matches {
+            return Vec::new();
+          }
+          None
+        }
+        QueryTerm::Line(_) | QueryTerm::Kind(_) => None,
+        QueryTerm::Hunk(regex) => Some(matching_hunk_sections(file, regex)),
+        QueryTerm::Container(glob) => Some(matching_container_sections(file, glob)),
+      };
+      term_sections.push(sections);
+    }
+
+    file
+      .change_keys()
+      .filter(|&(section_idx, line_idx)| {
+        terms
+          .iter()
+          .zip(&term_sections)
+          .all(|(term, sections)| match term {
+            QueryTerm::Path(_) => true,
+            QueryTerm::Line(regex) => match &file.sections[section_idx] {
+              Section::Changed { lines } => line_matches(regex, &lines[line_idx].line),
+              Section::Unchanged { .. }
+              | Section::Omitted { .. }
+              | Section::FileMode { .. }
+              | Section::Rename { .. }
+              | Section::Binary { .. }
+              | Section::Symlink { .. }
+              | Section::Submodule { .. } => false,
+            },
+            QueryTerm::Kind(kind) => change_kind(file, section_idx, line_idx) == Some(*kind),
+            QueryTerm::Hunk(_) | QueryTerm::Container(_) => sections
+              .as_ref()
+              .is_some_and(|sections| sections.contains(&section_idx)),
+          })
+      })
+      .collect()
+  }
+
+  /// Select (if `select` is `true`) or unselect every change in `state`
+  /// which matches this query, and return the number of matching changes.
+  ///
+  /// When there are more than two [`RecordState::commits`], selected changes
+  /// are assigned to the first commit and unselected changes to the second.
+  pub fn apply(
+    &self,
+    state: &mut RecordState,
+    select: bool,
+  ) -> usize {
+    let commit_idx = if select { 0 } else { 1 };
+    let mut num_matches = 0;
+    for file in &mut state.files {
+      let changes = self.matching_changes(file);
+      if changes.is_empty() {
+        continue;
+      }
+      num_matches += changes.len();
+      for (section_idx, line_idx) in changes {
+        file.set_commit_idx(section_idx, line_idx, commit_idx);
+      }
+
+      #[cfg(feature = "tree-sitter")]
+      if let Some(containers) = &mut file.containers {
+        for container in containers {
+          container.update_checked(&file.sections);
+        }
+      }
+    }
+    num_matches
+  }
+}
+
+/// Split a query into `(key, value)` pairs, removing any quotes from the
+/// values.
+fn split_terms(query: &str) -> Result<Vec<(String, String)>, QueryError> {
+  let mut result = Vec::new();
+  let mut chars = query.chars().peekable();
+  loop {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    if chars.peek().is_none() {
+      break;
+    }
+
+    let mut key = String::new();
+    while let Some(c) = chars.next_if(|c| *c != ':' && !c.is_whitespace()) {
+      key.push(c);
+    }
+    if chars.next_if_eq(&':').is_none() {
+      return Err(QueryError::InvalidTerm { term: key });
+    }
+
+    let mut value = String::new();
+    if chars.next_if_eq(&'"').is_some() {
+      loop {
+        match chars.next() {
+          Some('"') => break,
+          Some('\\') if chars.peek() == Some(&'"') => value.push(chars.next().unwrap()),
+          Some(c) => value.push(c),
+          None => {
+            return Err(QueryError::UnterminatedQuote {
+              term: format!("{key}:\"{value}"),
+            })
+          }
+        }
+      }
+    } else {
+      while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+        value.push(c);
+      }
+    }
+    result.push((key, value));
+  }
+  Ok(result)
+}
+
+fn parse_term(
+  key: &str,
+  value: String,
+) -> Result<QueryTerm, QueryError> {
+  match key {
+    "path" => Ok(QueryTerm::Path(compile_glob(value)?)),
+    "line" => Ok(QueryTerm::Line(compile_regex(value)?)),
+    "hunk" => Ok(QueryTerm::Hunk(compile_regex(value)?)),
+    "type" => match value.as_str() {
+      "added" => Ok(QueryTerm::Kind(ChangeKind::Added)),
+      "removed" => Ok(QueryTerm::Kind(ChangeKind::Removed)),
+      "file-mode" => Ok(QueryTerm::Kind(ChangeKind::FileMode)),
+      "rename" => Ok(QueryTerm::Kind(ChangeKind::Rename)),
+      "binary" => Ok(QueryTerm::Kind(ChangeKind::Binary)),
+      "symlink" => Ok(QueryTerm::Kind(ChangeKind::Symlink)),
+      "submodule" => Ok(QueryTerm::Kind(ChangeKind::Submodule)),
+      _ => Err(QueryError::InvalidChangeType { change_type: value }),
+    },
+    "container" => {
+      if cfg!(feature = "tree-sitter") {
+        Ok(QueryTerm::Container(compile_glob(value)?))
+      } else {
+        Err(QueryError::ContainersUnsupported)
+      }
+    }
+    _ => Err(QueryError::InvalidTerm {
+      term: format!("{key}:{value}"),
+    }),
+  }
+}
+
+fn compile_glob(glob: String) -> Result<GlobMatcher, QueryError> {
+  let result: Result<Glob, _> = GlobBuilder::new(&glob).literal_separator(true).build();
+  match result {
+    Ok(compiled) => Ok(compiled.compile_matcher()),
+    Err(source) => Err(QueryError::InvalidGlob { glob, source }),
+  }
+}
+
+fn compile_regex(regex: String) -> Result<Regex, QueryError> {
+  match Regex::new(&regex) {
+    Ok(compiled) => Ok(compiled),
+    Err(source) => Err(QueryError::InvalidRegex { regex, source }),
+  }
+}
+
+/// Whether the contents of `line`, without its trailing newline, match
+/// `regex`.
+fn line_matches(
+  regex: &Regex,
+  line: &str,
+) -> bool {
+  regex.is_match(line.strip_suffix('\n').unwrap_or(line))
+}
+
+/// Get the indices of the [`Section::Changed`]s in `file` with any line
+/// matching `regex`.
+fn matching_hunk_sections(
+  file: &File,
+  regex: &Regex,
+) -> HashSet<usize> {
+  file
+    .sections
+    .iter()
+    .enumerate()
+    .filter_map(|(section_idx, section)| match section {
+      Section::Changed { lines } => lines
+        .iter()
+        .any(|line| line_matches(regex, &line.line))
+        .then_some(section_idx),
+      Section::Unchanged { .. }
+      | Section::Omitted { .. }
+      | Section::FileMode { .. }
+      | Section::Rename { .. }
+      | Section::Binary { .. }
+      | Section::Symlink { .. }
+      | Section::Submodule { .. } => None,
+    })
+    .collect()
+}
+
+fn change_kind(
+  file: &File,
+  section_idx: usize,
+  line_idx: usize,
+) -> Option<ChangeKind> {
+  match &file.sections[section_idx] {
+    Section::Changed { lines } => Some(match lines[line_idx].change_type {
+      ChangeType::Added => ChangeKind::Added,
+      ChangeType::Removed => ChangeKind::Removed,
+    }),
+    Section::FileMode { .. } => Some(ChangeKind::FileMode),
+    Section::Rename { .. } => Some(ChangeKind::Rename),
+    Section::Binary { .. } => Some(ChangeKind::Binary),
+    Section::Symlink { .. } => Some(ChangeKind::Symlink),
+    Section::Submodule { .. } => Some(ChangeKind::Submodule),
+    Section::Unchanged { .. } | Section::Omitted { .. } => None,
+  }
+}
+
+/// Get the indices of the sections inside any container in `file` whose
+/// name matches `glob`.
+#[cfg(feature = "tree-sitter")]
+fn matching_container_sections(
+  file: &File,
+  glob: &GlobMatcher,
+) -> HashSet<usize> {
+  fn visit(
+    container: &crate::SemanticContainer,
+    glob: &GlobMatcher,
+    acc: &mut HashSet<usize>,
+  ) {
+    use crate::SemanticContainer;
+
+    let children: &[SemanticContainer] = match container {
+      SemanticContainer::Impl { children, .. }
+      | SemanticContainer::Struct { children, .. }
+      | SemanticContainer::Class { children, .. }
+      | SemanticContainer::Interface { children, .. } => children,
+      SemanticContainer::Function { .. }
+      | SemanticContainer::Enum { .. }
+      | SemanticContainer::Object { .. }
+      | SemanticContainer::Module { .. }
+      | SemanticContainer::Section { .. }
+      | SemanticContainer::Method { .. }
+      | SemanticContainer::Field { .. } => &[],
+    };
+    if container
+      .names()
+      .into_iter()
+      .any(|name| glob.is_match(name))
+    {
+      acc.extend(container.section_indices());
+    } else {
+      for child in children {
+        visit(child, glob, acc);
+      }
+    }
+  }
+
+  let mut result = HashSet::new();
+  for container in file.containers.iter().flatten() {
+    visit(container, glob, &mut result);
+  }
+  result
+}
+
+#[cfg(not(feature = "tree-sitter"))]
+fn matching_container_sections(
+  _file: &File,
+  _glob: &GlobMatcher,
+) -> HashSet<usize> {
+  HashSet::new()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::borrow::Cow;
+  use std::path::Path;
+
+  use super::*;
+  use crate::{FileMode, SectionChangedLine};
+
+  fn changed_line(
+    change_type: ChangeType,
+    line: &'static str,
+  ) -> SectionChangedLine<'static> {
+    SectionChangedLine {
+      is_checked: false,
+      change_type,
+      line: Cow::Borrowed(line),
+    }
+  }
+
+  fn make_file(
+    path: &'static str,
+    sections: Vec<Section<'static>>,
+  ) -> File<'static> {
+    File {
+      old_path: None,
+      path: Cow::Borrowed(Path::new(path)),
+      file_mode: FileMode::FILE_DEFAULT,
+      sections,
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
+      #[cfg(feature = "tree-sitter")]
+      containers: None,
+    }
+  }
+
+  fn example_state() -> RecordState<'static> {
+    RecordState {
+      is_read_only: false,
+      commits: Default::default(),
+      files: vec![
+        make_file(
+          "src/generated/bindings.rs",
+          vec![Section::Changed {
+            lines: vec![
+              changed_line(ChangeType::Removed, "old binding\n"),
+              changed_line(ChangeType::Added, "new binding\n"),
+            ],
+          }],
+        ),
+        make_file(
+          "src/main.rs",
+          vec![
+            Section::Unchanged {
+              lines: vec![Cow::Borrowed("fn main() {\n")],
+            },
+            Section::Changed {
+              lines: vec![
+                changed_line(ChangeType::Removed, "    run();\n"),
+                changed_line(ChangeType::Added, "    dbg!(run());\n"),
+                changed_line(ChangeType::Added, "    cleanup();\n"),
+              ],
+            },
+          ],
+        ),
+        make_file(
+          "README.md",
+          vec![Section::FileMode {
+            is_checked: false,
+            mode: FileMode::Unix(0o100755),
+          }],
+        ),
+      ],
+    }
+  }
+
+  fn checked_lines(state: &RecordState) -> Vec<String> {
+    state
+      .files
+      .iter()
+      .flat_map(|file| {
+        file.change_keys().filter_map(|(section_idx, line_idx)| {
+          if file.commit_idx(section_idx, line_idx) != 0 {
+            return None;
+          }
+          let description = match &file.sections[section_idx] {
+            Section::Changed { lines } => lines[line_idx].line.trim().to_owned(),
+            section => format!("{section:?}"),
+          };
+          Some(format!("{}: {}", file.path.display(), description))
+        })
+      })
+      .collect()
+  }
+
+  #[test]
+  fn test_parse_query() {
+    insta::assert_debug_snapshot!(
+      SelectionQuery::from_str(r#"path:src/** line:"fn \"parse\"" type:added"#)
+        .map(|query| query.terms.len()),
+      @r"
+    Ok(
+        3,
+    )
+    "
+    );
+    insta::assert_snapshot!(SelectionQuery::from_str("  ").unwrap_err(), @"empty query");
+    insta::assert_snapshot!(SelectionQuery::from_str("src/**").unwrap_err(), @r#"invalid query term "src/**": expected one of `path:`, `line:`, `hunk:`, `type:`, or `container:`"#);
+    insta::assert_snapshot!(SelectionQuery::from_str("name:foo").unwrap_err(), @r#"invalid query term "name:foo": expected one of `path:`, `line:`, `hunk:`, `type:`, or `container:`"#);
+    insta::assert_snapshot!(SelectionQuery::from_str(r#"line:"foo"#).unwrap_err(), @r#"unterminated quote in query term "line:\"foo""#);
+    insta::assert_snapshot!(SelectionQuery::from_str("type:moved").unwrap_err(), @r#"invalid change type "moved": expected one of `added`, `removed`, `file-mode`, `rename`, `binary`, `symlink`, or `submodule`"#);
+    assert!(matches!(
+      SelectionQuery::from_str("line:("),
+      Err(QueryError::InvalidRegex { .. })
+    ));
+    assert!(matches!(
+      SelectionQuery::from_str("path:[a"),
+      Err(QueryError::InvalidGlob { .. })
+    ));
+  }
+
+  #[test]
+  fn test_apply_query() {
+    let mut state = example_state();
+    let query = |query: &str| SelectionQuery::from_str(query).unwrap();
+
+    assert_eq!(query("path:src/*").apply(&mut state, true), 3);
+    insta::assert_debug_snapshot!(checked_lines(&state), @r#"
+    [
+        "src/main.rs: run();",
+        "src/main.rs: dbg!(run());",
+        "src/main.rs: cleanup();",
+    ]
+    "#);
+
+    assert_eq!(
+      query("path:src/generated/** type:added").apply(&mut state, true),
+      1
+    );
+    assert_eq!(query(r"line:dbg!\(").apply(&mut state, false), 1);
+    assert_eq!(query("type:file-mode").apply(&mut state, true), 1);
+    insta::assert_debug_snapshot!(checked_lines(&state), @r#"
+    [
+        "src/generated/bindings.rs: new binding",
+        "src/main.rs: run();",
+        "src/main.rs: cleanup();",
+        "README.md: FileMode { is_checked: true, mode: Unix(33261) }",
+    ]
+    "#);
+
+    assert_eq!(query("path:*.rs").apply(&mut state, false), 0);
+  }
+
+  #[test]
+  fn test_apply_query_hunk() {
+    let mut state = example_state();
+    let query = |query: &str| SelectionQuery::from_str(query).unwrap();
+
+    assert_eq!(query(r"hunk:dbg!\(").apply(&mut state, true), 3);
+    insta::assert_debug_snapshot!(checked_lines(&state), @r#"
+    [
+        "src/main.rs: run();",
+        "src/main.rs: dbg!(run());",
+        "src/main.rs: cleanup();",
+    ]
+    "#);
+
+    assert_eq!(query("hunk:cleanup line:run").apply(&mut state, false), 2);
+    assert!(matches!(
+      SelectionQuery::from_str("hunk:("),
+      Err(QueryError::InvalidRegex { .. })
+    ));
+  }
+
+  #[test]
+  fn test_apply_query_multiple_commits() {
+    let mut state = example_state();
+    state.commits = vec![Default::default(); 3];
+    state.files[1].set_commit_idx(1, 2, 2);
+
+    assert_eq!(
+      SelectionQuery::from_str("path:src/main.rs")
+        .unwrap()
+        .apply(&mut state, true),
+      3
+    );
+    assert_eq!(state.files[1].commit_idx(1, 2), 0);
+    assert!(state.files[1].commit_assignments.is_empty());
+  }
+
+  #[cfg(feature = "tree-sitter")]
+  #[test]
+  fn test_apply_query_container() {
+    use crate::SemanticContainer;
+
+    let mut state = example_state();
+    state.files[1].containers = Some(vec![SemanticContainer::Impl {
+      type_name: "App".to_owned(),
+      trait_name: None,
+      children: vec![SemanticContainer::Method {
+        name: "parse_args".to_owned(),
+        section_indices: vec![1],
+        is_checked: false,
+        is_partial: false,
+      }],
+      is_checked: false,
+      is_partial: false,
+    }]);
+    let query = |query: &str| SelectionQuery::from_str(query).unwrap();
+
+    assert_eq!(query("container:parse").apply(&mut state, true), 0);
+    assert_eq!(
+      query("container:parse* type:added").apply(&mut state, true),
+      2
+    );
+    insta::assert_debug_snapshot!(checked_lines(&state), @r#"
+    [
+        "src/main.rs: dbg!(run());",
+        "src/main.rs: cleanup();",
+    ]
+    "#);
+    match &state.files[1].containers.as_ref().unwrap()[0] {
+      SemanticContainer::Impl {
+        is_checked,
+        is_partial,
+        ..
+      } => assert_eq!((*is_checked, *is_partial), (true, true)),
+      container => panic!("unexpected container: {container:?}"),
+    }
+
+    assert_eq!(query("container:App").apply(&mut state, true), 3);
+    assert_eq!(checked_lines(&state).len(), 3);
+  }
+}
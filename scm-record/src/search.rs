@@ -0,0 +1,142 @@
+//! Matching the text of files, lines, and semantic containers when searching
+//! in the recorder with `/` and `?`.
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use regex::{Regex, RegexBuilder};
+
+/// Whether letter case matters when searching.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CaseSensitivity {
+  /// Ignore case unless the query contains an uppercase letter, like Vim's
+  /// `smartcase` option.
+  #[default]
+  Smart,
+
+  /// Always match case.
+  Sensitive,
+
+  /// Never match case.
+  Insensitive,
+}
+
+/// How the recorder interprets search queries. See
+/// [`crate::Recorder::with_search_options`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SearchOptions {
+  /// Whether letter case matters.
+  pub case_sensitivity: CaseSensitivity,
+
+  /// Whether the query is a regular expression, rather than literal text.
+  pub is_regex: bool,
+}
+
+/// A compiled search query.
+#[derive(Clone, Debug)]
+pub struct SearchPattern {
+  regex: Regex,
+}
+
+impl SearchPattern {
+  /// Compile `query` according to `options`. Returns an error if `options`
+  /// requires a regular expression and `query` isn't a valid one, which is
+  /// common while it's still being typed.
+  pub fn new(
+    query: &str,
+    options: SearchOptions,
+  ) -> Result<Self, regex::Error> {
+    let SearchOptions {
+      case_sensitivity,
+      is_regex,
+    } = options;
+    let is_case_insensitive = match case_sensitivity {
+      CaseSensitivity::Smart => !query.chars().any(char::is_uppercase),
+      CaseSensitivity::Sensitive => false,
+      CaseSensitivity::Insensitive => true,
+    };
+    let pattern = if is_regex {
+      Cow::Borrowed(query)
+    } else {
+      Cow::Owned(regex::escape(query))
+    };
+    let regex = RegexBuilder::new(&pattern)
+      .case_insensitive(is_case_insensitive)
+      .build()?;
+    Ok(Self { regex })
+  }
+
+  /// Whether `text` contains a match.
+  pub fn is_match(
+    &self,
+    text: &str,
+  ) -> bool {
+    self.regex.is_match(text)
+  }
+
+  /// The byte ranges of the non-empty matches in `text`.
+  pub fn find_matches(
+    &self,
+    text: &str,
+  ) -> Vec<Range<usize>> {
+    self
+      .regex
+      .find_iter(text)
+      .map(|m| m.range())
+      .filter(|range| !range.is_empty())
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn find_matches(
+    query: &str,
+    options: SearchOptions,
+    text: &str,
+  ) -> Vec<Range<usize>> {
+    SearchPattern::new(query, options)
+      .unwrap()
+      .find_matches(text)
+  }
+
+  #[test]
+  fn test_case_sensitivity() {
+    let text = "foo Foo FOO";
+    let smart = SearchOptions::default();
+    assert_eq!(find_matches("foo", smart, text), vec![0..3, 4..7, 8..11]);
+    assert_eq!(find_matches("Foo", smart, text), vec![4..7]);
+
+    let sensitive = SearchOptions {
+      case_sensitivity: CaseSensitivity::Sensitive,
+      ..Default::default()
+    };
+    assert_eq!(find_matches("foo", sensitive, text), vec![0..3]);
+
+    let insensitive = SearchOptions {
+      case_sensitivity: CaseSensitivity::Insensitive,
+      ..Default::default()
+    };
+    assert_eq!(
+      find_matches("FOO", insensitive, text),
+      vec![0..3, 4..7, 8..11]
+    );
+  }
+
+  #[test]
+  fn test_regex() {
+    let literal = SearchOptions::default();
+    assert_eq!(find_matches("a.c", literal, "abc a.c"), vec![4..7]);
+
+    let regex = SearchOptions {
+      is_regex: true,
+      ..Default::default()
+    };
+    assert_eq!(find_matches("a.c", regex, "abc a.c"), vec![0..3, 4..7]);
+    assert_eq!(find_matches("x*", regex, "abc"), Vec::<Range<usize>>::new());
+    assert!(SearchPattern::new("foo(", regex).is_err());
+    assert!(SearchPattern::new("foo(", literal).is_ok());
+  }
+}
@@ -0,0 +1,630 @@
+//! Saving and restoring the user's selections across sessions.
+//!
+//! The indexes of a change inside a [`File`] aren't stable: if the file is
+//! modified slightly and the [`RecordState`] is rebuilt, the same change may
+//! end up in a different section or at a different line. Instead, each change
+//! is given a [`ChangeId`] derived from its contents and the lines around it.
+//! A [`SelectionDocument`] records which commit each change was assigned to,
+//! and can be applied to a freshly built [`RecordState`] with
+//! [`SelectionDocument::apply`].
+
+use std::collections::HashMap;
+
+#[cfg(feature = "serde")]
+use crate::RecordError;
+use crate::{ChangeType, File, FileMode, RecordState, Section};
+
+/// The current version of the [`SelectionDocument`] format.
+pub const SELECTION_DOCUMENT_VERSION: u32 = 1;
+
+/// The kind of change identified by a [`ChangeId`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum ChangeKind {
+  /// An added line in a [`Section::Changed`].
+  Added,
+
+  /// A removed line in a [`Section::Changed`].
+  Removed,
+
+  /// A [`Section::FileMode`].
+  FileMode,
+
+  /// A [`Section::Rename`].
+  Rename,
+
+  /// A [`Section::Binary`].
+  Binary,
+
+  /// A [`Section::Symlink`].
+  Symlink,
+
+  /// A [`Section::Submodule`].
+  Submodule,
+}
+
+/// A stable, content-based identity for a single change in a [`File`].
+///
+/// A [`Section::Changed`] is identified by the IDs of its lines, while a
+/// [`Section::FileMode`], [`Section::Rename`], [`Section::Binary`],
+/// [`Section::Symlink`], or [`Section::Submodule`] has a single ID of its own.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct ChangeId {
+  /// The path of the file containing the change.
+  pub path: String,
+
+  /// The kind of change.
+  pub kind: ChangeKind,
+
+  /// A hash of the contents of the change, such as the text of the line.
+  pub content_hash: String,
+
+  /// A hash of the nearest unchanged line before the change. Together with
+  /// [`ChangeId::after_hash`], this distinguishes identical changes in
+  /// different parts of the file.
+  pub before_hash: String,
+
+  /// A hash of the nearest unchanged line after the change.
+  pub after_hash: String,
+
+  /// The number of earlier changes in the file with the same kind and
+  /// contents.
+  pub occurrence: usize,
+}
+
+/// The commit that a change was assigned to.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct SavedChange {
+  /// The identity of the change.
+  pub id: ChangeId,
+
+  /// The index of the commit that the change was assigned to. See
+  /// [`File::commit_idx`]; `0` means that the change was selected.
+  pub commit_idx: usize,
+}
+
+/// A serializable record of the selection state of every change in a
+/// [`RecordState`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct SelectionDocument {
+  /// The version of the document format. See
+  /// [`SELECTION_DOCUMENT_VERSION`].
+  pub version: u32,
+
+  /// The saved changes.
+  pub changes: Vec<SavedChange>,
+}
+
+/// The outcome of [`SelectionDocument::apply`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ApplySelectionStats {
+  /// The number of changes whose ID matched a saved change exactly.
+  pub num_exact: usize,
+
+  /// The number of changes which matched a saved change with the same
+  /// contents, but whose surrounding lines had changed.
+  pub num_fuzzy: usize,
+
+  /// The number of saved changes which didn't match any change.
+  pub num_unmatched: usize,
+}
+
+/// Calculate the [`ChangeId`] of each change in `file`, along with the
+/// `(section_idx, line_idx)` of the change.
+pub fn change_ids(file: &File) -> Vec<(usize, usize, ChangeId)> {
+  let File {
+    old_path,
+    path,
+    file_mode: _,
+    sections,
+    commit_assignments: _,
+    similarity: _,
+    unloaded: _,
+    #[cfg(feature = "tree-sitter")]
+      containers: _,
+  } = file;
+  let path = path.to_string_lossy().into_owned();
+
+  // The lines of the file in display order, where `None` is a changed line
+  // and `Some` is an unchanged line, used to find the context of each change.
+  // Omitted lines are represented by an empty unchanged line, since the
+  // actual lines aren't known.
+  let mut all_lines: Vec<Option<&str>> = Vec::new();
+  // For each change, its key, kind, contents hash, and index into `all_lines`.
+  let mut changes = Vec::new();
+  for (section_idx, section) in sections.iter().enumerate() {
+    match section {
+      Section::Unchanged { lines } => {
+        all_lines.extend(lines.iter().map(|line| Some(line.as_ref())));
+      }
+      Section::Omitted { num_lines: _ } => all_lines.push(Some("")),
+      Section::Changed { lines } => {
+        for (line_idx, line) in lines.iter().enumerate() {
+          let kind = match line.change_type {
+            ChangeType::Added => ChangeKind::Added,
+            ChangeType::Removed => ChangeKind::Removed,
+          };
+          changes.push((
+            section_idx,
+            line_idx,
+            kind,
+            hash_strs([line.line.as_ref()]),
+            Some(all_lines.len()),
+          ));
+          all_lines.push(None);
+        }
+      }
+      Section::FileMode {
+        is_checked: _,
+        mode,
+      } => {
+        let mode = match mode {
+          FileMode::Unix(mode) => format!("{mode:o}"),
+          FileMode::Absent => "absent".to_string(),
+        };
+        changes.push((
+          section_idx,
+          0,
+          ChangeKind::FileMode,
+          hash_strs([mode.as_str()]),
+          None,
+        ));
+      }
+      Section::Rename {
+        is_checked: _,
+        is_copy,
+      } => {
+        let old_path = old_path
+          .as_deref()
+          .map(|old_path| old_path.to_string_lossy())
+          .unwrap_or_default();
+        changes.push((
+          section_idx,
+          0,
+          ChangeKind::Rename,
+          hash_strs([if *is_copy { "copy" } else { "rename" }, old_path.as_ref()]),
+          None,
+        ));
+      }
+      Section::Binary {
+        is_checked: _,
+        old_description,
+        new_description,
+        contents: _,
+      } => {
+        changes.push((
+          section_idx,
+          0,
+          ChangeKind::Binary,
+          hash_strs([
+            old_description.as_deref().unwrap_or_default(),
+            new_description.as_deref().unwrap_or_default(),
+          ]),
+          None,
+        ));
+      }
+      Section::Symlink {
+        is_checked: _,
+        old_target,
+        new_target,
+      } => {
+        changes.push((
+          section_idx,
+          0,
+          ChangeKind::Symlink,
+          hash_strs([
+            old_target.as_deref().unwrap_or_default(),
+            new_target.as_deref().unwrap_or_default(),
+          ]),
+          None,
+        ));
+      }
+      Section::Submodule {
+        is_checked: _,
+        old_commit,
+        new_commit,
+      } => {
+        changes.push((
+          section_idx,
+          0,
+          ChangeKind::Submodule,
+          hash_strs([
+            old_commit.as_deref().unwrap_or_default(),
+            new_commit.as_deref().unwrap_or_default(),
+          ]),
+          None,
+        ));
+      }
+    }
+  }
+
+  // The nearest unchanged line at or before/after each index of `all_lines`.
+  let before_lines: Vec<Option<&str>> = all_lines
+    .iter()
+    .scan(None, |prev, line| {
+      *prev = line.or(*prev);
+      Some(*prev)
+    })
+    .collect();
+  let mut after_lines: Vec<Option<&str>> = all_lines
+    .iter()
+    .rev()
+    .scan(None, |next, line| {
+      *next = line.or(*next);
+      Some(*next)
+    })
+    .collect();
+  after_lines.reverse();
+
+  let mut occurrences: HashMap<(ChangeKind, u64), usize> = HashMap::new();
+  changes
+    .into_iter()
+    .map(|(section_idx, line_idx, kind, content_hash, line_num)| {
+      let (before, after) = match line_num {
+        Some(line_num) => (
+          line_num
+            .checked_sub(1)
+            .and_then(|line_num| before_lines[line_num]),
+          after_lines.get(line_num + 1).copied().flatten(),
+        ),
+        None => (None, None),
+      };
+      let occurrence = occurrences.entry((kind, content_hash)).or_default();
+      let id = ChangeId {
+        path: path.clone(),
+        kind,
+        content_hash: format!("{content_hash:016x}"),
+        before_hash: format!("{:016x}", hash_strs(before)),
+        after_hash: format!("{:016x}", hash_strs(after)),
+        occurrence: *occurrence,
+      };
+      *occurrence += 1;
+      (section_idx, line_idx, id)
+    })
+    .collect()
+}
+
+impl SelectionDocument {
+  /// Record the commit that each change in `state` is assigned to.
+  pub fn from_state(state: &RecordState) -> Self {
+    let changes = state
+      .files
+      .iter()
+      .flat_map(|file| {
+        change_ids(file)
+          .into_iter()
+          .map(|(section_idx, line_idx, id)| SavedChange {
+            id,
+            commit_idx: file.commit_idx(section_idx, line_idx),
+          })
+      })
+      .collect();
+    Self {
+      version: SELECTION_DOCUMENT_VERSION,
+      changes,
+    }
+  }
+
+  /// Assign the changes in `state` to the commits recorded in this document.
+  ///
+  /// Each change is first matched against a saved change in the same file
+  /// with the same kind, contents, and surrounding lines. Changes which don't
+  /// match exactly are then matched against a saved change with the same kind
+  /// and contents, preferring one where at least one of the surrounding lines
+  /// is the same. Ties are broken by the closest [`ChangeId::occurrence`].
+  /// Changes which don't match any saved change are left as-is.
+  pub fn apply(
+    &self,
+    state: &mut RecordState,
+  ) -> ApplySelectionStats {
+    let Self {
+      version: _,
+      changes: saved_changes,
+    } = self;
+    let num_commits = state.commits.len().max(2);
+
+    let mut saved_by_path: HashMap<&str, Vec<(&SavedChange, bool)>> = HashMap::new();
+    for saved_change in saved_changes {
+      saved_by_path
+        .entry(saved_change.id.path.as_str())
+        .or_default()
+        .push((saved_change, false));
+    }
+
+    let mut stats = ApplySelectionStats::default();
+    for file in &mut state.files {
+      let saved = match saved_by_path.get_mut(file.path.to_string_lossy().as_ref()) {
+        Some(saved) => saved,
+        None => continue,
+      };
+      let ids = change_ids(file);
+
+      let mut matches = vec![None; ids.len()];
+      // Exact matches, including those which differ only in their occurrence.
+      for (change_idx, (_, _, id)) in ids.iter().enumerate() {
+        let saved_idx = closest_match(saved, id, |saved_id| {
+          saved_id.before_hash == id.before_hash && saved_id.after_hash == id.after_hash
+        });
+        if let Some(saved_idx) = saved_idx {
+          saved[saved_idx].1 = true;
+          matches[change_idx] = Some(saved_idx);
+          stats.num_exact += 1;
+        }
+      }
+      // Fuzzy matches, where the surrounding lines have changed.
+      for (change_idx, (_, _, id)) in ids.iter().enumerate() {
+        if matches[change_idx].is_some() {
+          continue;
+        }
+        if let Some(saved_idx) = closest_match(saved, id, |_| true) {
+          saved[saved_idx].1 = true;
+          matches[change_idx] = Some(saved_idx);
+          stats.num_fuzzy += 1;
+        }
+      }
+
+      for ((section_idx, line_idx, _), saved_idx) in ids.iter().zip(matches) {
+        if let Some(saved_idx) = saved_idx {
+          let commit_idx = saved[saved_idx].0.commit_idx.min(num_commits - 1);
+          file.set_commit_idx(*section_idx, *line_idx, commit_idx);
+        }
+      }
+    }
+
+    stats.num_unmatched = saved_by_path
+      .values()
+      .flatten()
+      .filter(|(_, is_used)| !is_used)
+      .count();
+    stats
+  }
+
+  /// Serialize this document as JSON.
+  #[cfg(feature = "serde")]
+  pub fn to_json(&self) -> Result<String, RecordError> {
+    serde_json::to_string_pretty(self).map_err(RecordError::SerializeJson)
+  }
+
+  /// Deserialize a document previously serialized with
+  /// [`SelectionDocument::to_json`].
+  #[cfg(feature = "serde")]
+  pub fn from_json(json: &str) -> Result<Self, RecordError> {
+    serde_json::from_str(json).map_err(RecordError::DeserializeJson)
+  }
+}
+
+/// Find the index of the unused saved change with the same kind and contents
+/// as `id` which satisfies `is_match`, preferring the one with the most
+/// surrounding lines in common and then the closest occurrence.
+fn closest_match(
+  saved: &[(&SavedChange, bool)],
+  id: &ChangeId,
+  is_match: impl Fn(&ChangeId) -> bool,
+) -> Option<usize> {
+  saved
+    .iter()
+    .enumerate()
+    .filter(|(_, (saved_change, is_used))| {
+      !is_used
+        && saved_change.id.kind == id.kind
+        && saved_change.id.content_hash == id.content_hash
+        && is_match(&saved_change.id)
+    })
+    .min_by_key(|(_, (saved_change, _))| {
+      let ChangeId {
+        path: _,
+        kind: _,
+        content_hash: _,
+        before_hash,
+        after_hash,
+        occurrence,
+      } = &saved_change.id;
+      let num_context_differences =
+        usize::from(*before_hash != id.before_hash) + usize::from(*after_hash != id.after_hash);
+      (num_context_differences, occurrence.abs_diff(id.occurrence))
+    })
+    .map(|(saved_idx, _)| saved_idx)
+}
+
+/// Hash a sequence of strings with 64-bit FNV-1a. Unlike the standard
+/// library's hashers, this is guaranteed to be stable across Rust versions
+/// and platforms.
+fn hash_strs<'a>(strs: impl IntoIterator<Item = &'a str>) -> u64 {
+  const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+  const FNV_PRIME: u64 = 0x100000001b3;
+  let mut hash = FNV_OFFSET_BASIS;
+  for s in strs {
+    // Include a terminator so that `["ab", "c"]` and `["a", "bc"]` differ.
+    for byte in s.bytes().chain([0xff]) {
+      hash ^= u64::from(byte);
+      hash = hash.wrapping_mul(FNV_PRIME);
+    }
+  }
+  hash
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::patch::parse_patch;
+
+  fn make_state(patch: &str) -> RecordState<'_> {
+    RecordState {
+      is_read_only: false,
+      commits: Default::default(),
+      files: parse_patch(patch).unwrap(),
+    }
+  }
+
+  fn checked_lines(state: &RecordState) -> Vec<String> {
+    state
+      .files
+      .iter()
+      .flat_map(|file| {
+        file
+          .change_keys()
+          .filter(|(section_idx, line_idx)| file.commit_idx(*section_idx, *line_idx) == 0)
+          .map(
+            |(section_idx, line_idx)| match &file.sections[section_idx] {
+              Section::Changed { lines } => lines[line_idx].line.trim_end().to_string(),
+              section => format!("{section:?}"),
+            },
+          )
+          .collect::<Vec<_>>()
+      })
+      .collect()
+  }
+
+  const PATCH: &str = "\
+diff --git a/foo b/foo
+old mode 100644
+new mode 100755
+--- a/foo
++++ b/foo
+@@ -1,6 +1,6 @@
+ fn a() {
+-  x
++  y
+ }
+ fn b() {
+-  x
++  y
+ }
+";
+
+  #[test]
+  fn test_change_ids_distinguish_identical_lines() {
+    let state = make_state(PATCH);
+    let ids = change_ids(&state.files[0]);
+    insta::assert_debug_snapshot!(
+      ids
+        .iter()
+        .map(|(section_idx, line_idx, id)| (section_idx, line_idx, id.kind, id.occurrence))
+        .collect::<Vec<_>>(),
+      @"
+    [
+        (
+            0,
+            0,
+            FileMode,
+            0,
+        ),
+        (
+            2,
+            0,
+            Removed,
+            0,
+        ),
+        (
+            2,
+            1,
+            Added,
+            0,
+        ),
+        (
+            4,
+            0,
+            Removed,
+            1,
+        ),
+        (
+            4,
+            1,
+            Added,
+            1,
+        ),
+    ]
+    "
+    );
+    assert_eq!(ids[1].2.content_hash, ids[3].2.content_hash);
+    assert_ne!(ids[1].2.before_hash, ids[3].2.before_hash);
+  }
+
+  #[test]
+  fn test_apply_selection_exact() {
+    let mut state = make_state(PATCH);
+    state.files[0].set_commit_idx(0, 0, 0);
+    state.files[0].set_commit_idx(4, 0, 0);
+    state.files[0].set_commit_idx(4, 1, 0);
+    let document = SelectionDocument::from_state(&state);
+
+    let mut new_state = make_state(PATCH);
+    let stats = document.apply(&mut new_state);
+    assert_eq!(
+      stats,
+      ApplySelectionStats {
+        num_exact: 5,
+        num_fuzzy: 0,
+        num_unmatched: 0,
+      }
+    );
+    assert_eq!(new_state, state);
+  }
+
+  #[test]
+  fn test_apply_selection_fuzzy() {
+    let mut state = make_state(PATCH);
+    state.files[0].set_commit_idx(4, 0, 0);
+    state.files[0].set_commit_idx(4, 1, 0);
+    let document = SelectionDocument::from_state(&state);
+
+    // The first change no longer exists, and the line after the second
+    // change has changed.
+    let mut new_state = make_state(
+      "\
+--- a/foo
++++ b/foo
+@@ -10,3 +10,3 @@
+ fn b() {
+-  x
++  y
+ } // end of b
+",
+    );
+    let stats = document.apply(&mut new_state);
+    assert_eq!(
+      stats,
+      ApplySelectionStats {
+        num_exact: 0,
+        num_fuzzy: 2,
+        num_unmatched: 3,
+      }
+    );
+    insta::assert_debug_snapshot!(checked_lines(&new_state), @r#"
+    [
+        "  x",
+        "  y",
+    ]
+    "#);
+  }
+
+  #[test]
+  fn test_apply_selection_multiple_commits() {
+    let mut state = make_state(PATCH);
+    state.commits = vec![Default::default(); 3];
+    state.files[0].set_commit_idx(4, 1, 2);
+    let document = SelectionDocument::from_state(&state);
+
+    let mut new_state = make_state(PATCH);
+    new_state.commits = vec![Default::default(); 3];
+    document.apply(&mut new_state);
+    assert_eq!(new_state.files[0].commit_idx(4, 1), 2);
+
+    // With only two commits, the change is left unselected.
+    let mut new_state = make_state(PATCH);
+    document.apply(&mut new_state);
+    assert_eq!(new_state.files[0].commit_idx(4, 1), 1);
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn test_selection_document_json() {
+    let document = SelectionDocument::from_state(&make_state(PATCH));
+    let json = document.to_json().unwrap();
+    assert_eq!(SelectionDocument::from_json(&json).unwrap(), document);
+    assert!(SelectionDocument::from_json("{}").is_err());
+  }
+}
@@ -69,6 +69,22 @@ impl SupportedLanguage {
       Self::Yaml => tree_sitter_yaml::LANGUAGE.into(),
     }
   }
+
+  /// Get the tree-sitter query which captures the parts of the source to
+  /// highlight, such as keywords and strings. The grammars for Kotlin and HCL
+  /// don't come with one, so a smaller query is provided for them.
+  #[cfg(feature = "tree-sitter")]
+  pub fn highlights_query(&self) -> &'static str {
+    match self {
+      Self::Rust => tree_sitter_rust::HIGHLIGHTS_QUERY,
+      Self::Kotlin => kotlin::HIGHLIGHTS_QUERY,
+      Self::Java => tree_sitter_java::HIGHLIGHTS_QUERY,
+      Self::Hcl => hcl::HIGHLIGHTS_QUERY,
+      Self::Python => tree_sitter_python::HIGHLIGHTS_QUERY,
+      Self::Markdown => tree_sitter_md::HIGHLIGHT_QUERY_BLOCK,
+      Self::Yaml => tree_sitter_yaml::HIGHLIGHTS_QUERY,
+    }
+  }
 }
 
 /// Creates and configures a tree-sitter parser for the given language.
@@ -1097,6 +1113,9 @@ pub enum SemanticError {
 ///     path: Cow::Owned(right_display_path),
 ///     file_mode: left_file_mode,
 ///     sections,
+///     commit_assignments: Default::default(),
+///     similarity: None,
+///     unloaded: None,
 ///     #[cfg(feature = "tree-sitter")]
 ///     containers: None,
 /// };
@@ -1142,6 +1161,9 @@ fn calculate_section_line_ranges(sections: &[crate::Section<'_>]) -> Vec<Section
         // Unchanged lines exist in both files at the same positions
         current_line += lines.len();
       }
+      Section::Omitted { num_lines } => {
+        current_line += num_lines.unwrap_or_default();
+      }
       Section::Changed { lines } => {
         // Count only Added lines (they're in the new file)
         let added_count = lines
@@ -1150,7 +1172,11 @@ fn calculate_section_line_ranges(sections: &[crate::Section<'_>]) -> Vec<Section
           .count();
         current_line += added_count;
       }
-      Section::FileMode { .. } | Section::Binary { .. } => {
+      Section::FileMode { .. }
+      | Section::Rename { .. }
+      | Section::Binary { .. }
+      | Section::Symlink { .. }
+      | Section::Submodule { .. } => {
         // These don't represent actual file content lines
         continue;
       }
@@ -1228,6 +1254,9 @@ fn filter_section_indices_by_range(
 /// #     path: Cow::Borrowed(Path::new("foo.rs")),
 /// #     file_mode: scm_record::FileMode::FILE_DEFAULT,
 /// #     sections: vec![],
+/// #     commit_assignments: Default::default(),
+/// #     similarity: None,
+/// #     unloaded: None,
 /// #     containers: None,
 /// # };
 /// # let old_source = "";
@@ -1686,6 +1715,9 @@ More new content.
           ],
         },
       ],
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       containers: None,
     };
 
@@ -2,6 +2,27 @@
 
 use super::*;
 
+/// Highlights query for HCL, since `tree-sitter-hcl` doesn't provide one. See
+/// [`SupportedLanguage::highlights_query`].
+#[cfg(feature = "tree-sitter")]
+pub const HIGHLIGHTS_QUERY: &str = r#"
+(comment) @comment
+
+[(string_lit) (heredoc_template)] @string
+
+(numeric_lit) @number
+
+[(bool_lit) (null_lit)] @constant.builtin
+
+(block (identifier) @keyword)
+
+(attribute (identifier) @property)
+
+(function_call (identifier) @function)
+
+["for" "in" "if" "else" "endif" "endfor"] @keyword
+"#;
+
 /// Extract containers with their members from a parsed HCL file.
 #[cfg(feature = "tree-sitter")]
 pub fn extract_containers_with_members(parsed: &ParsedFile) -> Vec<ContainerWithMembers> {
@@ -31,103 +52,99 @@ fn extract_hcl_blocks(
 ) {
   let mut cursor = body_node.walk();
   for child in body_node.children(&mut cursor) {
-    match child.kind() {
-      "block" => {
-        // HCL blocks have children in order:
-        // identifier "label1" "label2" { body }
-        // Examples:
-        // resource "aws_instance" "example" { ... }
-        // variable "name" { ... }
-        // output "name" { ... }
-
-        let mut block_cursor = child.walk();
-        let children: Vec<_> = child.children(&mut block_cursor).collect();
-
-        // First child should be identifier (block type)
-        let block_type = if !children.is_empty() && children[0].kind() == "identifier" {
-          children[0]
-            .utf8_text(source_bytes)
-            .unwrap_or("<unknown>")
-            .to_string()
-        } else {
-          continue;
-        };
-
-        // Collect all string_lit children as labels
-        let labels: Vec<String> = children
-          .iter()
-          .filter(|n| n.kind() == "string_lit")
-          .filter_map(|label| {
-            label.utf8_text(source_bytes).ok().map(|s| {
-              // Remove quotes from string literals
-              s.trim_matches('"').to_string()
-            })
+    if child.kind() == "block" {
+      // HCL blocks have children in order:
+      // identifier "label1" "label2" { body }
+      // Examples:
+      // resource "aws_instance" "example" { ... }
+      // variable "name" { ... }
+      // output "name" { ... }
+
+      let mut block_cursor = child.walk();
+      let children: Vec<_> = child.children(&mut block_cursor).collect();
+
+      // First child should be identifier (block type)
+      let block_type = if !children.is_empty() && children[0].kind() == "identifier" {
+        children[0]
+          .utf8_text(source_bytes)
+          .unwrap_or("<unknown>")
+          .to_string()
+      } else {
+        continue;
+      };
+
+      // Collect all string_lit children as labels
+      let labels: Vec<String> = children
+        .iter()
+        .filter(|n| n.kind() == "string_lit")
+        .filter_map(|label| {
+          label.utf8_text(source_bytes).ok().map(|s| {
+            // Remove quotes from string literals
+            s.trim_matches('"').to_string()
           })
-          .collect();
-
-        let (kind, name) = match block_type.as_str() {
-          "resource" => {
-            if labels.len() >= 2 {
-              (
-                ContainerKind::Resource {
-                  resource_type: labels[0].clone(),
-                },
-                labels[1].clone(),
-              )
-            } else {
-              continue;
-            }
+        })
+        .collect();
+
+      let (kind, name) = match block_type.as_str() {
+        "resource" => {
+          if labels.len() >= 2 {
+            (
+              ContainerKind::Resource {
+                resource_type: labels[0].clone(),
+              },
+              labels[1].clone(),
+            )
+          } else {
+            continue;
           }
-          "data" => {
-            if labels.len() >= 2 {
-              (
-                ContainerKind::DataSource {
-                  data_type: labels[0].clone(),
-                },
-                labels[1].clone(),
-              )
-            } else {
-              continue;
-            }
+        }
+        "data" => {
+          if labels.len() >= 2 {
+            (
+              ContainerKind::DataSource {
+                data_type: labels[0].clone(),
+              },
+              labels[1].clone(),
+            )
+          } else {
+            continue;
           }
-          "variable" => {
-            if let Some(name) = labels.first() {
-              (ContainerKind::Variable, name.clone())
-            } else {
-              continue;
-            }
+        }
+        "variable" => {
+          if let Some(name) = labels.first() {
+            (ContainerKind::Variable, name.clone())
+          } else {
+            continue;
           }
-          "output" => {
-            if let Some(name) = labels.first() {
-              (ContainerKind::Output, name.clone())
-            } else {
-              continue;
-            }
+        }
+        "output" => {
+          if let Some(name) = labels.first() {
+            (ContainerKind::Output, name.clone())
+          } else {
+            continue;
           }
-          "module" => {
-            if let Some(name) = labels.first() {
-              (ContainerKind::Module, name.clone())
-            } else {
-              continue;
-            }
+        }
+        "module" => {
+          if let Some(name) = labels.first() {
+            (ContainerKind::Module, name.clone())
+          } else {
+            continue;
           }
-          _ => continue, // Skip other block types (locals, terraform, etc.)
-        };
-
-        let (start_line, end_line) =
-          expand_range_for_trivia(child, root_node, &TriviaConfig::hcl());
-
-        containers.push(ContainerWithMembers {
-          container: Container {
-            kind,
-            name,
-            start_line,
-            end_line,
-          },
-          members: Vec::new(), // HCL blocks don't have members in our model
-        });
-      }
-      _ => {}
+        }
+        _ => continue, // Skip other block types (locals, terraform, etc.)
+      };
+
+      let (start_line, end_line) = expand_range_for_trivia(child, root_node, &TriviaConfig::hcl());
+
+      containers.push(ContainerWithMembers {
+        container: Container {
+          kind,
+          name,
+          start_line,
+          end_line,
+        },
+        members: Vec::new(), // HCL blocks don't have members in our model
+      });
     }
   }
 }
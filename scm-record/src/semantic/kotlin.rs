@@ -2,6 +2,37 @@
 
 use super::*;
 
+/// Highlights query for Kotlin, since `tree-sitter-kotlin-ng` doesn't provide
+/// one. See [`SupportedLanguage::highlights_query`].
+#[cfg(feature = "tree-sitter")]
+pub const HIGHLIGHTS_QUERY: &str = r#"
+[(line_comment) (block_comment)] @comment
+
+[(string_literal) (multiline_string_literal) (character_literal)] @string
+
+[(number_literal) (float_literal)] @number
+
+(annotation) @attribute
+
+(function_declaration name: (identifier) @function)
+
+[
+  (class_declaration name: (identifier) @type)
+  (object_declaration name: (identifier) @type)
+]
+
+(user_type (identifier) @type)
+
+[
+  "abstract" "as" "by" "catch" "class" "companion" "const" "constructor" "data"
+  "do" "else" "enum" "finally" "for" "fun" "if" "import" "in" "init" "inline"
+  "interface" "internal" "is" "lateinit" "object" "open" "operator" "out"
+  "override" "package" "private" "protected" "public" "return" "sealed"
+  "suspend" "this" "throw" "try" "typealias" "val" "var" "vararg" "when"
+  "where" "while"
+] @keyword
+"#;
+
 /// Extract members (properties and methods) from a Kotlin class/object/interface.
 #[cfg(feature = "tree-sitter")]
 pub fn extract_members(
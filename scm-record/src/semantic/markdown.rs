@@ -105,13 +105,10 @@ pub fn extract_containers_with_members(parsed: &ParsedFile) -> Vec<ContainerWith
 
   let mut cursor = root_node.walk();
   for child in root_node.children(&mut cursor) {
-    match child.kind() {
-      "section" => {
-        // Markdown wraps headings in section nodes
-        // Extract headings from within sections
-        extract_headings_from_section(child, source_bytes, root_node, &mut containers);
-      }
-      _ => {}
+    if child.kind() == "section" {
+      // Markdown wraps headings in section nodes
+      // Extract headings from within sections
+      extract_headings_from_section(child, source_bytes, root_node, &mut containers);
     }
   }
 
@@ -150,7 +147,7 @@ Some content here.
     };
 
     let containers = extract_containers_with_members(&parsed);
-    assert!(containers.len() >= 1);
+    assert!(!containers.is_empty());
     assert_eq!(containers[0].container.name, "Main Header");
     if let ContainerKind::Section { level } = containers[0].container.kind {
       assert_eq!(level, 1);
@@ -216,7 +213,7 @@ Some content.
     };
 
     let containers = extract_containers_with_members(&parsed);
-    assert!(containers.len() >= 1);
+    assert!(!containers.is_empty());
     assert_eq!(containers[0].container.name, "Main Header");
     if let ContainerKind::Section { level } = containers[0].container.kind {
       assert_eq!(level, 1);
@@ -16,10 +16,10 @@ pub fn extract_containers_with_members(parsed: &ParsedFile) -> Vec<ContainerWith
     if child.kind() == "stream" || child.kind() == "document" {
       let mut doc_cursor = child.walk();
       for doc_child in child.children(&mut doc_cursor) {
-        extract_yaml_mappings(doc_child, source_bytes, root_node, &mut containers);
+        extract_yaml_mappings(doc_child, source_bytes, &mut containers);
       }
     } else if child.kind() == "block_mapping" || child.kind() == "block_sequence" {
-      extract_yaml_mappings(child, source_bytes, root_node, &mut containers);
+      extract_yaml_mappings(child, source_bytes, &mut containers);
     }
   }
 
@@ -31,7 +31,6 @@ pub fn extract_containers_with_members(parsed: &ParsedFile) -> Vec<ContainerWith
 fn extract_yaml_mappings(
   node: tree_sitter::Node,
   source_bytes: &[u8],
-  root_node: tree_sitter::Node,
   containers: &mut Vec<ContainerWithMembers>,
 ) {
   if node.kind() == "block_mapping" {
@@ -64,7 +63,7 @@ fn extract_yaml_mappings(
   } else if node.kind() == "block_node" || node.kind() == "document" {
     let mut cursor = node.walk();
     for child in node.children(&mut cursor) {
-      extract_yaml_mappings(child, source_bytes, root_node, containers);
+      extract_yaml_mappings(child, source_bytes, containers);
     }
   }
 }
@@ -101,7 +100,7 @@ description: A test application
     };
 
     let containers = extract_containers_with_members(&parsed);
-    assert!(containers.len() >= 1);
+    assert!(!containers.is_empty());
 
     // Check that we found at least one of the expected keys
     let names: Vec<_> = containers
@@ -132,7 +131,7 @@ server:
     };
 
     let containers = extract_containers_with_members(&parsed);
-    assert!(containers.len() >= 1);
+    assert!(!containers.is_empty());
 
     // Check for top-level keys
     let names: Vec<_> = containers
@@ -162,7 +161,7 @@ devDependencies:
     };
 
     let containers = extract_containers_with_members(&parsed);
-    assert!(containers.len() >= 1);
+    assert!(!containers.is_empty());
 
     let names: Vec<_> = containers
       .iter()
@@ -187,7 +186,7 @@ app:
     };
 
     let containers = extract_containers_with_members(&parsed);
-    assert!(containers.len() >= 1);
+    assert!(!containers.is_empty());
 
     // Find the app container
     let app_container = containers.iter().find(|c| c.container.name == "app");
@@ -4,10 +4,13 @@ use std::borrow::Cow;
 use std::fmt::Display;
 use std::io;
 use std::num::TryFromIntError;
+use std::ops::AddAssign;
 use std::path::Path;
 
 use thiserror::Error;
 
+use crate::patch::{format_file_patch, PatchOptions};
+
 /// The state used to render the changes. This is passed into
 /// [`crate::Recorder::new`] and then updated and returned with
 /// [`crate::Recorder::run`].
@@ -24,6 +27,11 @@ pub struct RecordState<'a> {
   /// If there are fewer than two commits in this list, then it is padded to
   /// two commits using `Commit::default` before being returned.
   ///
+  /// With exactly two commits, a change belongs to the first commit if it is
+  /// checked and to the second commit otherwise. With more commits, changes
+  /// may additionally be assigned to later commits via
+  /// [`File::commit_assignments`].
+  ///
   /// It's important to note that the `Commit`s do not literally contain the
   /// selected changes. They are stored out-of-band in the `files` field. It
   /// would be possible to store the changes in the `Commit`s, but we would no
@@ -40,6 +48,61 @@ pub struct RecordState<'a> {
   pub files: Vec<File<'a>>,
 }
 
+impl RecordState<'_> {
+  /// Calculate the `(selected, unselected)` changes of all files as patches.
+  /// See [`File::get_selected_patches`].
+  pub fn get_selected_patches(
+    &self,
+    options: &PatchOptions,
+  ) -> (String, String) {
+    let mut selected = String::new();
+    let mut unselected = String::new();
+    for file in &self.files {
+      let (file_selected, file_unselected) = file.get_selected_patches(options);
+      selected.push_str(&file_selected);
+      unselected.push_str(&file_unselected);
+    }
+    (selected, unselected)
+  }
+
+  /// Count the lines added and removed by all files. See [`File::diff_stat`].
+  pub fn diff_stat(&self) -> DiffStat {
+    self.commit_diff_stat(0)
+  }
+
+  /// Count the lines added and removed by all files, where the selected lines
+  /// are those assigned to the commit at `commit_idx`. See
+  /// [`File::commit_diff_stat`].
+  pub fn commit_diff_stat(
+    &self,
+    commit_idx: usize,
+  ) -> DiffStat {
+    let mut diff_stat = DiffStat::default();
+    for file in &self.files {
+      diff_stat += file.commit_diff_stat(commit_idx);
+    }
+    diff_stat
+  }
+
+  /// Count the files by whether their changes are selected. See
+  /// [`File::tristate`].
+  pub fn file_counts(&self) -> FileCounts {
+    let mut file_counts = FileCounts {
+      num_files: self.files.len(),
+      num_selected: 0,
+      num_partially_selected: 0,
+    };
+    for file in &self.files {
+      match file.tristate() {
+        Tristate::False => {}
+        Tristate::Partial => file_counts.num_partially_selected += 1,
+        Tristate::True => file_counts.num_selected += 1,
+      }
+    }
+    file_counts
+  }
+}
+
 /// An error which occurred when attempting to record changes.
 #[allow(missing_docs)]
 #[derive(Debug, Error)]
@@ -64,6 +127,14 @@ pub enum RecordError {
   #[error("failed to serialize JSON: {0}")]
   SerializeJson(#[source] serde_json::Error),
 
+  #[cfg(feature = "serde")]
+  #[error("failed to deserialize JSON: {0}")]
+  DeserializeJson(#[source] serde_json::Error),
+
+  #[cfg(feature = "serde")]
+  #[error("unsupported interchange format version: {0}")]
+  UnsupportedInterchangeVersion(u64),
+
   #[error("failed to wrote file: {0}")]
   WriteFile(#[source] io::Error),
 
@@ -88,6 +159,12 @@ pub enum FileMode {
 impl FileMode {
   /// The default Unix permissions for files.
   pub const FILE_DEFAULT: FileMode = FileMode::Unix(0o100644);
+
+  /// The file mode Git uses for symbolic links.
+  pub const SYMLINK: FileMode = FileMode::Unix(0o120000);
+
+  /// The file mode Git uses for submodules ("gitlinks").
+  pub const GITLINK: FileMode = FileMode::Unix(0o160000);
 }
 
 impl Display for FileMode {
@@ -305,6 +382,65 @@ pub enum SemanticContainer {
 
 #[cfg(feature = "tree-sitter")]
 impl SemanticContainer {
+  /// Get the names of this container, such as the type and trait names of an
+  /// impl block. The names of its nested items aren't included.
+  pub fn names(&self) -> Vec<&str> {
+    match self {
+      SemanticContainer::Impl {
+        type_name,
+        trait_name,
+        ..
+      } => std::iter::once(type_name.as_str())
+        .chain(trait_name.as_deref())
+        .collect(),
+      SemanticContainer::Struct { name, .. }
+      | SemanticContainer::Class { name, .. }
+      | SemanticContainer::Interface { name, .. }
+      | SemanticContainer::Function { name, .. }
+      | SemanticContainer::Enum { name, .. }
+      | SemanticContainer::Object { name, .. }
+      | SemanticContainer::Module { name, .. }
+      | SemanticContainer::Section { name, .. }
+      | SemanticContainer::Method { name, .. }
+      | SemanticContainer::Field { name, .. } => vec![name.as_str()],
+    }
+  }
+
+  /// Get the indices of the file's sections within this container, including
+  /// those of its nested items.
+  pub fn section_indices(&self) -> Vec<usize> {
+    match self {
+      SemanticContainer::Struct { children, .. }
+      | SemanticContainer::Impl { children, .. }
+      | SemanticContainer::Class { children, .. }
+      | SemanticContainer::Interface { children, .. } => children
+        .iter()
+        .flat_map(|child| child.section_indices())
+        .collect(),
+      SemanticContainer::Function {
+        section_indices, ..
+      }
+      | SemanticContainer::Method {
+        section_indices, ..
+      }
+      | SemanticContainer::Field {
+        section_indices, ..
+      }
+      | SemanticContainer::Enum {
+        section_indices, ..
+      }
+      | SemanticContainer::Object {
+        section_indices, ..
+      }
+      | SemanticContainer::Module {
+        section_indices, ..
+      }
+      | SemanticContainer::Section {
+        section_indices, ..
+      } => section_indices.clone(),
+    }
+  }
+
   /// Set the checked state for this container and all its nested items.
   /// Requires access to the file's sections to update the actual section state.
   pub fn set_checked(
@@ -395,14 +531,155 @@ impl SemanticContainer {
       }
     }
   }
+
+  /// Recalculate the checked state of this container and all its nested
+  /// items from the sections they contain. This is needed after the sections
+  /// were selected directly rather than through [`SemanticContainer::set_checked`].
+  pub fn update_checked(
+    &mut self,
+    file_sections: &[Section],
+  ) {
+    let section_indices = self.section_indices();
+    let (container_checked, is_partial, children): (_, _, &mut [SemanticContainer]) = match self {
+      SemanticContainer::Struct {
+        children,
+        is_checked,
+        is_partial,
+        ..
+      }
+      | SemanticContainer::Impl {
+        children,
+        is_checked,
+        is_partial,
+        ..
+      }
+      | SemanticContainer::Class {
+        children,
+        is_checked,
+        is_partial,
+        ..
+      }
+      | SemanticContainer::Interface {
+        children,
+        is_checked,
+        is_partial,
+        ..
+      } => (is_checked, is_partial, children),
+      SemanticContainer::Function {
+        is_checked,
+        is_partial,
+        ..
+      }
+      | SemanticContainer::Method {
+        is_checked,
+        is_partial,
+        ..
+      }
+      | SemanticContainer::Field {
+        is_checked,
+        is_partial,
+        ..
+      }
+      | SemanticContainer::Enum {
+        is_checked,
+        is_partial,
+        ..
+      }
+      | SemanticContainer::Object {
+        is_checked,
+        is_partial,
+        ..
+      }
+      | SemanticContainer::Module {
+        is_checked,
+        is_partial,
+        ..
+      }
+      | SemanticContainer::Section {
+        is_checked,
+        is_partial,
+        ..
+      } => (is_checked, is_partial, &mut []),
+    };
+    for child in children {
+      child.update_checked(file_sections);
+    }
+
+    let mut tristate = None;
+    for section in section_indices
+      .into_iter()
+      .filter_map(|section_idx| file_sections.get(section_idx))
+      .filter(|section| section.is_editable())
+    {
+      tristate = match (tristate, section.tristate()) {
+        (None, section_tristate) => Some(section_tristate),
+        (Some(Tristate::True), Tristate::True) => Some(Tristate::True),
+        (Some(Tristate::False), Tristate::False) => Some(Tristate::False),
+        _ => Some(Tristate::Partial),
+      };
+    }
+    let tristate = tristate.unwrap_or(Tristate::False);
+    *container_checked = tristate != Tristate::False;
+    *is_partial = tristate == Tristate::Partial;
+  }
+
+  /// Replace each section index in this container and its nested items with
+  /// the indices returned by `map_section_idx`. This is needed after the
+  /// file's sections were split or merged.
+  pub fn map_section_indices(
+    &mut self,
+    map_section_idx: &impl Fn(usize) -> std::ops::Range<usize>,
+  ) {
+    match self {
+      SemanticContainer::Struct { children, .. }
+      | SemanticContainer::Impl { children, .. }
+      | SemanticContainer::Class { children, .. }
+      | SemanticContainer::Interface { children, .. } => {
+        for child in children {
+          child.map_section_indices(map_section_idx);
+        }
+      }
+      SemanticContainer::Function {
+        section_indices, ..
+      }
+      | SemanticContainer::Method {
+        section_indices, ..
+      }
+      | SemanticContainer::Field {
+        section_indices, ..
+      }
+      | SemanticContainer::Enum {
+        section_indices, ..
+      }
+      | SemanticContainer::Object {
+        section_indices, ..
+      }
+      | SemanticContainer::Module {
+        section_indices, ..
+      }
+      | SemanticContainer::Section {
+        section_indices, ..
+      } => {
+        let mut new_section_indices: Vec<usize> = section_indices
+          .iter()
+          .flat_map(|section_idx| map_section_idx(*section_idx))
+          .collect();
+        new_section_indices.dedup();
+        *section_indices = new_section_indices;
+      }
+    }
+  }
 }
 
 /// The state of a file to be recorded.
 #[derive(Clone, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct File<'a> {
-  /// The path to the previous version of the file, for display purposes. This
-  /// should be set if the file was renamed or copied from another file.
+  /// The path to the previous version of the file. This should be set if the
+  /// file was renamed or copied from another file.
+  ///
+  /// On its own, this is for display purposes only. To let the user decide
+  /// whether to record the rename or copy, also add a [`Section::Rename`].
   pub old_path: Option<Cow<'a, Path>>,
 
   /// The path to the current version of the file, for display purposes.
@@ -422,6 +699,30 @@ pub struct File<'a> {
   /// traditional diff-first sections.
   pub sections: Vec<Section<'a>>,
 
+  /// Assignments of changes in this file to commits other than the first two.
+  ///
+  /// Only needed when [`RecordState::commits`] has more than two entries.
+  /// Changes which don't appear in this list are assigned to the first
+  /// commit if they are checked and to the second commit otherwise. Prefer
+  /// [`File::set_commit_idx`] to editing this list directly, since it is
+  /// kept sorted by `(section_idx, line_idx)`.
+  #[cfg_attr(feature = "serde", serde(default))]
+  pub commit_assignments: Vec<CommitAssignment>,
+
+  /// How similar the contents at [`File::old_path`] and [`File::path`] are,
+  /// as a percentage from 0 to 100, if the file was detected as renamed or
+  /// copied. This is only used for display purposes.
+  #[cfg_attr(feature = "serde", serde(default))]
+  pub similarity: Option<u8>,
+
+  /// If `Some`, the sections of this file haven't been loaded yet, and
+  /// [`File::sections`] should be empty. They are loaded on demand by the
+  /// [`crate::SectionProvider`] passed to
+  /// [`crate::Recorder::with_section_provider`] when the user expands the
+  /// file or selects it. Until then, the stats are shown in its place.
+  #[cfg_attr(feature = "serde", serde(default))]
+  pub unloaded: Option<FileStats>,
+
   /// Semantic containers for this file (when tree-sitter parsing is enabled).
   ///
   /// When `Some`, the file uses semantic-first navigation where changes are
@@ -431,9 +732,155 @@ pub struct File<'a> {
   pub containers: Option<Vec<SemanticContainer>>,
 }
 
+/// The old and new contents of a [`Section::Binary`]. See
+/// [`crate::hex_dump`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct BinaryContents<'a> {
+  /// The old contents, which are empty if the file didn't exist.
+  pub old: Cow<'a, [u8]>,
+
+  /// The new contents, which are empty if the file doesn't exist anymore.
+  pub new: Cow<'a, [u8]>,
+}
+
+/// Summary statistics for a [`File`] whose sections haven't been loaded yet.
+/// See [`File::unloaded`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct FileStats {
+  /// The number of added lines, if known without loading the file.
+  pub num_added: Option<usize>,
+
+  /// The number of removed lines, if known without loading the file.
+  pub num_removed: Option<usize>,
+}
+
+/// The number of lines added and removed by some changes.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct LineCounts {
+  /// The number of added lines.
+  pub num_added: usize,
+
+  /// The number of removed lines.
+  pub num_removed: usize,
+}
+
+impl LineCounts {
+  /// The number of added and removed lines.
+  pub fn num_changed(&self) -> usize {
+    let Self {
+      num_added,
+      num_removed,
+    } = self;
+    num_added + num_removed
+  }
+
+  fn add_line(
+    &mut self,
+    change_type: ChangeType,
+  ) {
+    match change_type {
+      ChangeType::Added => self.num_added += 1,
+      ChangeType::Removed => self.num_removed += 1,
+    }
+  }
+}
+
+impl AddAssign for LineCounts {
+  fn add_assign(
+    &mut self,
+    other: Self,
+  ) {
+    let Self {
+      num_added,
+      num_removed,
+    } = other;
+    self.num_added += num_added;
+    self.num_removed += num_removed;
+  }
+}
+
+/// The line numbers of a line in the old and new versions of its [`File`],
+/// starting from 1. See [`File::line_numbers`].
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct LineNumbers {
+  /// The line number in the old version, unless the line was added.
+  pub old: Option<usize>,
+
+  /// The line number in the new version, unless the line was removed.
+  pub new: Option<usize>,
+}
+
+/// The number of lines added and removed by the changes to a [`File`] or a
+/// [`RecordState`], like `git diff --stat`, in total and split by whether
+/// they're selected. See [`File::diff_stat`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DiffStat {
+  /// The lines changed by all changes.
+  pub total: LineCounts,
+
+  /// The lines changed by the selected changes.
+  pub selected: LineCounts,
+
+  /// The lines changed by the unselected changes.
+  pub unselected: LineCounts,
+}
+
+impl AddAssign for DiffStat {
+  fn add_assign(
+    &mut self,
+    other: Self,
+  ) {
+    let Self {
+      total,
+      selected,
+      unselected,
+    } = other;
+    self.total += total;
+    self.selected += selected;
+    self.unselected += unselected;
+  }
+}
+
+/// The number of files in a [`RecordState`], by whether their changes are
+/// selected. See [`RecordState::file_counts`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FileCounts {
+  /// The number of files.
+  pub num_files: usize,
+
+  /// The number of files whose changes are all selected.
+  pub num_selected: usize,
+
+  /// The number of files with both selected and unselected changes.
+  pub num_partially_selected: usize,
+}
+
+/// The commit that a single change inside a [`File`] is assigned to. See
+/// [`File::commit_assignments`].
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct CommitAssignment {
+  /// The index of the section in [`File::sections`].
+  pub section_idx: usize,
+
+  /// The index of the line inside a [`Section::Changed`]. This is always `0`
+  /// for [`Section::FileMode`], [`Section::Rename`], and [`Section::Binary`].
+  pub line_idx: usize,
+
+  /// The index of the commit in [`RecordState::commits`].
+  pub commit_idx: usize,
+}
+
 /// The changes for a particular file selected as part of the record operation.
 #[derive(Debug)]
 pub struct SelectedChanges<'a> {
+  /// The file's path. This is [`File::path`], unless the file was renamed
+  /// and the [`Section::Rename`] wasn't selected, in which case it's
+  /// [`File::old_path`].
+  pub path: &'a Path,
+
   /// The file's mode.
   pub file_mode: FileMode,
 
@@ -460,6 +907,28 @@ pub enum SelectedContents<'a> {
     /// The contents of the file.
     contents: String,
   },
+
+  /// The file is a symbolic link.
+  Symlink {
+    /// The target of the link.
+    target: Cow<'a, str>,
+  },
+
+  /// The file is a submodule.
+  Submodule {
+    /// The ID of the commit that the submodule points to.
+    commit: Cow<'a, str>,
+  },
+
+  /// The file's contents can't be calculated, because some of its lines
+  /// aren't available (see [`Section::Omitted`]). This is usually the case
+  /// for files parsed from a patch, whose changes should be exported with
+  /// [`File::get_selected_patches`] instead.
+  Incomplete {
+    /// The number of lines which aren't available, or `None` if it isn't
+    /// known.
+    num_omitted_lines: Option<usize>,
+  },
 }
 
 impl SelectedContents<'_> {
@@ -476,6 +945,11 @@ impl SelectedContents<'_> {
       SelectedContents::Binary {
         old_description: _,
         new_description: _,
+      }
+      | SelectedContents::Symlink { target: _ }
+      | SelectedContents::Submodule { commit: _ }
+      | SelectedContents::Incomplete {
+        num_omitted_lines: _,
       } => {
         // Do nothing.
       }
@@ -490,39 +964,388 @@ impl File<'_> {
   /// Calculate the `(selected, unselected)` contents of the file. For
   /// example, the first value would be suitable for staging or committing,
   /// and the second value would be suitable for potentially recording again.
+  ///
+  /// If some of the file's lines aren't available (see [`Section::Omitted`]),
+  /// its text contents are [`SelectedContents::Incomplete`].
   pub fn get_selected_contents(&self) -> (SelectedChanges<'_>, SelectedChanges<'_>) {
+    self.get_contents_by(|section_idx, line_idx| self.commit_idx(section_idx, line_idx) == 0)
+  }
+
+  /// Calculate the `(selected, unselected)` changes of the file as patches in
+  /// Git's unified diff format, such as for use with `git apply`. Like
+  /// [`File::get_selected_contents`], both patches apply to the old contents
+  /// of the file. A patch is empty if it has no changes.
+  ///
+  /// A rename or copy without a [`Section::Rename`] (see [`File::old_path`])
+  /// isn't a selectable change, so it's included in both patches.
+  /// Similarly, a patch which adds lines to a created file also creates it,
+  /// even if its [`Section::FileMode`] isn't included.
+  pub fn get_selected_patches(
+    &self,
+    options: &PatchOptions,
+  ) -> (String, String) {
+    let is_selected = |section_idx, line_idx| self.commit_idx(section_idx, line_idx) == 0;
+    (
+      format_file_patch(self, is_selected, options),
+      format_file_patch(
+        self,
+        |section_idx, line_idx| !is_selected(section_idx, line_idx),
+        options,
+      ),
+    )
+  }
+
+  /// Calculate the contents of the file with only the changes assigned to the
+  /// commit at `commit_idx` applied. With two commits, this is equivalent to
+  /// the selected (`commit_idx == 0`) or unselected (`commit_idx == 1`)
+  /// contents returned by [`File::get_selected_contents`].
+  pub fn get_commit_contents(
+    &self,
+    commit_idx: usize,
+  ) -> SelectedChanges<'_> {
+    let (selected, _unselected) = self.get_contents_by(|section_idx, line_idx| {
+      self.commit_idx(section_idx, line_idx) == commit_idx
+    });
+    selected
+  }
+
+  /// Count the lines added and removed by this file's changes, like `git diff
+  /// --stat`. Changes which aren't to lines, such as [`Section::FileMode`]
+  /// and [`Section::Binary`], aren't counted. If the file hasn't been loaded
+  /// (see [`File::unloaded`]), its [`FileStats`] are used, and its changes are
+  /// all unselected.
+  pub fn diff_stat(&self) -> DiffStat {
+    self.commit_diff_stat(0)
+  }
+
+  /// Like [`File::diff_stat`], but the selected lines are those assigned to
+  /// the commit at `commit_idx`. With two commits, `commit_idx == 0` counts
+  /// the selected lines as usual, and `commit_idx == 1` swaps the selected
+  /// and unselected lines.
+  pub fn commit_diff_stat(
+    &self,
+    commit_idx: usize,
+  ) -> DiffStat {
+    let mut diff_stat = DiffStat::default();
+    if let Some(FileStats {
+      num_added,
+      num_removed,
+    }) = self.unloaded
+    {
+      let line_counts = LineCounts {
+        num_added: num_added.unwrap_or_default(),
+        num_removed: num_removed.unwrap_or_default(),
+      };
+      diff_stat.total = line_counts;
+      // The changes of an unloaded file are all unchecked, so they belong to
+      // the second commit.
+      if commit_idx == 1 {
+        diff_stat.selected = line_counts;
+      } else {
+        diff_stat.unselected = line_counts;
+      }
+      return diff_stat;
+    }
+
+    for (section_idx, section) in self.sections.iter().enumerate() {
+      match section {
+        Section::Changed { lines } => {
+          for (line_idx, line) in lines.iter().enumerate() {
+            let SectionChangedLine {
+              is_checked: _,
+              change_type,
+              line: _,
+            } = line;
+            diff_stat.total.add_line(*change_type);
+            if self.commit_idx(section_idx, line_idx) == commit_idx {
+              diff_stat.selected.add_line(*change_type);
+            } else {
+              diff_stat.unselected.add_line(*change_type);
+            }
+          }
+        }
+        Section::Unchanged { .. }
+        | Section::Omitted { .. }
+        | Section::FileMode { .. }
+        | Section::Rename { .. }
+        | Section::Binary { .. }
+        | Section::Symlink { .. }
+        | Section::Submodule { .. } => {}
+      }
+    }
+    diff_stat
+  }
+
+  /// Calculate the line numbers of every line in the file's sections, indexed
+  /// by section index and then line index, like [`File::sections`], so that
+  /// a position in the UI can be mapped to a position in the file. The lines
+  /// of a [`Section::Omitted`] are counted, but it has no entry of its own,
+  /// nor do sections without lines, such as [`Section::FileMode`].
+  pub fn line_numbers(&self) -> Vec<Vec<LineNumbers>> {
+    let mut old_line_num = 1;
+    let mut new_line_num = 1;
+    self
+      .sections
+      .iter()
+      .map(|section| match section {
+        Section::Unchanged { lines } => lines
+          .iter()
+          .map(|_| {
+            let line_numbers = LineNumbers {
+              old: Some(old_line_num),
+              new: Some(new_line_num),
+            };
+            old_line_num += 1;
+            new_line_num += 1;
+            line_numbers
+          })
+          .collect(),
+        Section::Omitted { num_lines } => {
+          old_line_num += num_lines.unwrap_or_default();
+          new_line_num += num_lines.unwrap_or_default();
+          Vec::new()
+        }
+        Section::Changed { lines } => lines
+          .iter()
+          .map(|line| match line.change_type {
+            ChangeType::Removed => {
+              let line_numbers = LineNumbers {
+                old: Some(old_line_num),
+                new: None,
+              };
+              old_line_num += 1;
+              line_numbers
+            }
+            ChangeType::Added => {
+              let line_numbers = LineNumbers {
+                old: None,
+                new: Some(new_line_num),
+              };
+              new_line_num += 1;
+              line_numbers
+            }
+          })
+          .collect(),
+        Section::FileMode { .. }
+        | Section::Rename { .. }
+        | Section::Binary { .. }
+        | Section::Symlink { .. }
+        | Section::Submodule { .. } => Vec::new(),
+      })
+      .collect()
+  }
+
+  /// Calculate the line numbers of the line at `line_idx` in the section at
+  /// `section_idx`, if there is such a line. See [`File::line_numbers`].
+  pub fn line_numbers_at(
+    &self,
+    section_idx: usize,
+    line_idx: usize,
+  ) -> Option<LineNumbers> {
+    self
+      .line_numbers()
+      .get(section_idx)
+      .and_then(|section_line_numbers| section_line_numbers.get(line_idx))
+      .copied()
+  }
+
+  /// Get the index of the commit that the change at `section_idx` and
+  /// `line_idx` is assigned to. See [`File::commit_assignments`].
+  pub fn commit_idx(
+    &self,
+    section_idx: usize,
+    line_idx: usize,
+  ) -> usize {
+    match self
+      .commit_assignments
+      .binary_search_by_key(&(section_idx, line_idx), |assignment| {
+        (assignment.section_idx, assignment.line_idx)
+      }) {
+      Ok(idx) => self.commit_assignments[idx].commit_idx,
+      Err(_) => {
+        let is_checked = match self.sections.get(section_idx) {
+          Some(Section::Changed { lines }) => {
+            lines.get(line_idx).is_some_and(|line| line.is_checked)
+          }
+          Some(
+            Section::FileMode { is_checked, .. }
+            | Section::Rename { is_checked, .. }
+            | Section::Binary { is_checked, .. }
+            | Section::Symlink { is_checked, .. }
+            | Section::Submodule { is_checked, .. },
+          ) => *is_checked,
+          Some(Section::Unchanged { .. } | Section::Omitted { .. }) | None => false,
+        };
+        if is_checked {
+          0
+        } else {
+          1
+        }
+      }
+    }
+  }
+
+  /// Assign the change at `section_idx` and `line_idx` to the commit at
+  /// `commit_idx`. Assigning a change to the first or second commit updates
+  /// its checked state; assigning it to a later commit records it in
+  /// [`File::commit_assignments`].
+  pub fn set_commit_idx(
+    &mut self,
+    section_idx: usize,
+    line_idx: usize,
+    commit_idx: usize,
+  ) {
+    let is_checked = match self.sections.get_mut(section_idx) {
+      Some(Section::Changed { lines }) => match lines.get_mut(line_idx) {
+        Some(line) => &mut line.is_checked,
+        None => return,
+      },
+      Some(
+        Section::FileMode { is_checked, .. }
+        | Section::Rename { is_checked, .. }
+        | Section::Binary { is_checked, .. }
+        | Section::Symlink { is_checked, .. }
+        | Section::Submodule { is_checked, .. },
+      ) => is_checked,
+      Some(Section::Unchanged { .. } | Section::Omitted { .. }) | None => return,
+    };
+    *is_checked = commit_idx == 0;
+
+    let key = (section_idx, line_idx);
+    let search = self
+      .commit_assignments
+      .binary_search_by_key(&key, |assignment| {
+        (assignment.section_idx, assignment.line_idx)
+      });
+    match (search, commit_idx) {
+      (Ok(idx), 0 | 1) => {
+        self.commit_assignments.remove(idx);
+      }
+      (Err(_), 0 | 1) => {}
+      (Ok(idx), commit_idx) => {
+        self.commit_assignments[idx].commit_idx = commit_idx;
+      }
+      (Err(idx), commit_idx) => {
+        self.commit_assignments.insert(
+          idx,
+          CommitAssignment {
+            section_idx,
+            line_idx,
+            commit_idx,
+          },
+        );
+      }
+    }
+  }
+
+  /// Iterate over the `(section_idx, line_idx)` keys of every change in
+  /// this file which can be assigned to a commit.
+  pub(crate) fn change_keys(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+    self
+      .sections
+      .iter()
+      .enumerate()
+      .flat_map(|(section_idx, section)| {
+        let num_changes = match section {
+          Section::Unchanged { .. } | Section::Omitted { .. } => 0,
+          Section::Changed { lines } => lines.len(),
+          Section::FileMode { .. }
+          | Section::Rename { .. }
+          | Section::Binary { .. }
+          | Section::Symlink { .. }
+          | Section::Submodule { .. } => 1,
+        };
+        (0..num_changes).map(move |line_idx| (section_idx, line_idx))
+      })
+  }
+
+  /// Calculate the `(selected, unselected)` contents of the file, where
+  /// `is_selected` decides whether the change at a given `section_idx` and
+  /// `line_idx` is selected.
+  fn get_contents_by(
+    &self,
+    is_selected: impl Fn(usize, usize) -> bool,
+  ) -> (SelectedChanges<'_>, SelectedChanges<'_>) {
     let mut acc_selected = SelectedContents::Unchanged;
     let mut acc_unselected = SelectedContents::Unchanged;
+    // `Some(n)` if `n` lines are omitted, or `None` if an unknown number of
+    // lines are.
+    let mut num_omitted_lines = Some(0);
 
     let Self {
-      old_path: _,
-      path: _,
+      old_path,
+      path,
       file_mode,
       sections,
       ..
     } = self;
 
-    let file_mode_section = sections.iter().find_map(|section| match section {
-      Section::Unchanged { .. } | Section::Changed { .. } | Section::Binary { .. } => None,
+    let file_mode_section = sections
+      .iter()
+      .enumerate()
+      .find_map(|(section_idx, section)| match section {
+        Section::Unchanged { .. }
+        | Section::Omitted { .. }
+        | Section::Changed { .. }
+        | Section::Rename { .. }
+        | Section::Binary { .. }
+        | Section::Symlink { .. }
+        | Section::Submodule { .. } => None,
 
-      Section::FileMode { is_checked, mode } => Some((mode, is_checked)),
-    });
+        Section::FileMode {
+          is_checked: _,
+          mode,
+        } => Some((mode, is_selected(section_idx, 0))),
+      });
 
     // The file mode for the selected changes is the selected file mode, if one was selected,
     // or the original mode of the file, if not.
     let selected_file_mode = file_mode_section
-      .filter(|(_, is_checked)| **is_checked)
+      .filter(|(_, is_checked)| *is_checked)
       .map(|(change, _)| *change)
       .unwrap_or(*file_mode);
 
     // The file mode for the unselected changes is the unselected file mode, if one was provided,
     // or the original mode of the file, if not
     let unselected_file_mode = file_mode_section
-      .filter(|(_, is_checked)| !**is_checked)
+      .filter(|(_, is_checked)| !*is_checked)
       .map(|(change, _)| *change)
       .unwrap_or(*file_mode);
 
-    for section in sections {
+    let rename_section =
+      sections
+        .iter()
+        .enumerate()
+        .find_map(|(section_idx, section)| match section {
+          Section::Unchanged { .. }
+          | Section::Omitted { .. }
+          | Section::Changed { .. }
+          | Section::FileMode { .. }
+          | Section::Binary { .. }
+          | Section::Symlink { .. }
+          | Section::Submodule { .. } => None,
+
+          Section::Rename {
+            is_checked: _,
+            is_copy,
+          } => Some((*is_copy, is_selected(section_idx, 0))),
+        });
+
+    // If the rename wasn't selected, the file stays at its old path. If the
+    // copy wasn't selected, the file isn't created at all.
+    let old_path = old_path.as_deref().unwrap_or(path);
+    let (selected_path, unselected_path) = match rename_section {
+      Some((false, true)) => (path.as_ref(), old_path),
+      Some((false, false)) => (old_path, path.as_ref()),
+      Some((true, _)) | None => (path.as_ref(), path.as_ref()),
+    };
+    let (selected_file_mode, unselected_file_mode) = match rename_section {
+      Some((true, true)) => (selected_file_mode, FileMode::Absent),
+      Some((true, false)) => (FileMode::Absent, unselected_file_mode),
+      Some((false, _)) | None => (selected_file_mode, unselected_file_mode),
+    };
+
+    for (section_idx, section) in sections.iter().enumerate() {
       match section {
         Section::Unchanged { lines } => {
           for line in lines {
@@ -531,14 +1354,21 @@ impl File<'_> {
           }
         }
 
+        Section::Omitted { num_lines } => {
+          // The contents aren't available, so they can't be included.
+          num_omitted_lines = num_omitted_lines
+            .zip(*num_lines)
+            .map(|(num_omitted_lines, num_lines)| num_omitted_lines + num_lines);
+        }
+
         Section::Changed { lines } => {
-          for line in lines {
+          for (line_idx, line) in lines.iter().enumerate() {
             let SectionChangedLine {
-              is_checked,
+              is_checked: _,
               change_type,
               line,
             } = line;
-            match (change_type, is_checked) {
+            match (change_type, is_selected(section_idx, line_idx)) {
               (ChangeType::Added, true) | (ChangeType::Removed, false) => {
                 acc_selected.push_str(line);
               }
@@ -557,20 +1387,21 @@ impl File<'_> {
           }
         }
 
-        Section::FileMode { .. } => {
+        Section::FileMode { .. } | Section::Rename { .. } => {
           // Do nothing - this is handled outside of the loop
         }
 
         Section::Binary {
-          is_checked,
+          is_checked: _,
           old_description,
           new_description,
+          contents: _,
         } => {
           let selected_contents = SelectedContents::Binary {
             old_description: old_description.clone(),
             new_description: new_description.clone(),
           };
-          if *is_checked {
+          if is_selected(section_idx, 0) {
             acc_selected = selected_contents;
             acc_unselected = SelectedContents::Unchanged;
           } else {
@@ -578,9 +1409,62 @@ impl File<'_> {
             acc_unselected = selected_contents;
           }
         }
+
+        // The side without a link or submodule keeps the text or binary
+        // contents from the other sections.
+        Section::Symlink {
+          is_checked: _,
+          old_target,
+          new_target,
+        } => {
+          let (selected, unselected) = if is_selected(section_idx, 0) {
+            (new_target, old_target)
+          } else {
+            (old_target, new_target)
+          };
+          if let Some(target) = selected {
+            acc_selected = SelectedContents::Symlink {
+              target: target.clone(),
+            };
+          }
+          if let Some(target) = unselected {
+            acc_unselected = SelectedContents::Symlink {
+              target: target.clone(),
+            };
+          }
+        }
+
+        Section::Submodule {
+          is_checked: _,
+          old_commit,
+          new_commit,
+        } => {
+          let (selected, unselected) = if is_selected(section_idx, 0) {
+            (new_commit, old_commit)
+          } else {
+            (old_commit, new_commit)
+          };
+          if let Some(commit) = selected {
+            acc_selected = SelectedContents::Submodule {
+              commit: commit.clone(),
+            };
+          }
+          if let Some(commit) = unselected {
+            acc_unselected = SelectedContents::Submodule {
+              commit: commit.clone(),
+            };
+          }
+        }
       }
     }
 
+    // Without the copy, there's no file to have contents.
+    match rename_section {
+      Some((true, true)) => acc_unselected = SelectedContents::Unchanged,
+      Some((true, false)) => acc_selected = SelectedContents::Unchanged,
+      Some((false, _)) | None => {}
+    }
+
     // If an empty file was added, we won't have seen any lines in order to ensure the selected contents is "", so handle it here for the
     // selected and un-selected cases
     if *file_mode == FileMode::Absent
@@ -597,12 +1481,23 @@ impl File<'_> {
       acc_unselected.push_str("");
     }
 
+    // Text missing some of its lines would look like the whole file.
+    if num_omitted_lines != Some(0) {
+      for acc in [&mut acc_selected, &mut acc_unselected] {
+        if let SelectedContents::Text { contents: _ } = acc {
+          *acc = SelectedContents::Incomplete { num_omitted_lines };
+        }
+      }
+    }
+
     (
       SelectedChanges {
+        path: selected_path,
         contents: acc_selected,
         file_mode: selected_file_mode,
       },
       SelectedChanges {
+        path: unselected_path,
         contents: acc_unselected,
         file_mode: unselected_file_mode,
       },
@@ -622,7 +1517,7 @@ impl File<'_> {
     let mut seen_value = None;
     for section in sections {
       match section {
-        Section::Unchanged { .. } => {}
+        Section::Unchanged { .. } | Section::Omitted { .. } => {}
         Section::Changed { lines } => {
           for line in lines {
             seen_value = match (seen_value, line.is_checked) {
@@ -637,10 +1532,25 @@ impl File<'_> {
           is_checked,
           mode: _,
         }
+        | Section::Rename {
+          is_checked,
+          is_copy: _,
+        }
         | Section::Binary {
           is_checked,
           old_description: _,
           new_description: _,
+          contents: _,
+        }
+        | Section::Symlink {
+          is_checked,
+          old_target: _,
+          new_target: _,
+        }
+        | Section::Submodule {
+          is_checked,
+          old_commit: _,
+          new_commit: _,
         } => {
           seen_value = match (seen_value, is_checked) {
             (None, is_checked) => Some(*is_checked),
@@ -658,6 +1568,8 @@ impl File<'_> {
   }
 
   /// Set the selection of all sections and lines in this file.
+  ///
+  /// This also clears any [`File::commit_assignments`].
   pub fn set_checked(
     &mut self,
     checked: bool,
@@ -667,22 +1579,28 @@ impl File<'_> {
       path: _,
       file_mode: _,
       sections,
+      commit_assignments,
       ..
     } = self;
+    commit_assignments.clear();
     for section in sections {
       section.set_checked(checked);
     }
   }
 
   /// Toggle the selection of all sections in this file.
+  ///
+  /// This also clears any [`File::commit_assignments`].
   pub fn toggle_all(&mut self) {
     let Self {
       old_path: _,
       path: _,
       file_mode: _,
       sections,
+      commit_assignments,
       ..
     } = self;
+    commit_assignments.clear();
     for section in sections {
       section.toggle_all();
     }
@@ -704,6 +1622,20 @@ pub enum Section<'a> {
     lines: Vec<Cow<'a, str>>,
   },
 
+  /// This section of the file is unchanged, but its contents aren't
+  /// available, such as for the lines between the hunks of a patch (see
+  /// [`crate::patch`]). Only the number of lines is known, which is used to
+  /// number the surrounding lines correctly.
+  ///
+  /// The selected contents of a file with omitted lines can't be calculated
+  /// in full, since the omitted lines are skipped.
+  Omitted {
+    /// The number of lines which were omitted, or `None` if it isn't known,
+    /// such as for the lines after the last hunk of a patch. Only the last
+    /// section of a file may have an unknown number of lines.
+    num_lines: Option<usize>,
+  },
+
   /// This section of the file is changed, and the user needs to select which
   /// specific changed lines to record.
   Changed {
@@ -724,6 +1656,24 @@ pub enum Section<'a> {
     mode: FileMode,
   },
 
+  /// This indicates that the file was renamed or copied from
+  /// [`File::old_path`] to [`File::path`], and that the user needs to accept
+  /// the new path or not, independently of the changes to the file's
+  /// contents. Like [`Section::FileMode`], it's rendered inline.
+  ///
+  /// The other sections of the file describe the changes relative to the
+  /// contents at [`File::old_path`].
+  Rename {
+    /// Whether or not the rename or copy was selected for inclusion in the
+    /// UI.
+    is_checked: bool,
+
+    /// Whether the file was copied, rather than renamed. If so, the file at
+    /// [`File::old_path`] is left alone, and the file at [`File::path`]
+    /// is only created if the copy is selected.
+    is_copy: bool,
+  },
+
   /// This file contains binary contents.
   Binary {
     /// Whether or not the binary contents change was selected for inclusion
@@ -735,6 +1685,46 @@ pub enum Section<'a> {
 
     /// The description of the new binary contents, for use in the UI only.
     new_description: Option<Cow<'a, str>>,
+
+    /// The old and new binary contents. If provided, the section can be
+    /// expanded to show a hex dump of the bytes which changed. For use in
+    /// the UI only.
+    #[cfg_attr(feature = "serde", serde(default))]
+    contents: Option<BinaryContents<'a>>,
+  },
+
+  /// The target of a symbolic link changed. Like [`Section::Binary`], this
+  /// can only be selected as a whole. The file should have the mode
+  /// [`FileMode::SYMLINK`] on the side(s) where it's a symbolic link.
+  Symlink {
+    /// Whether or not the change to the target was selected for inclusion
+    /// in the UI.
+    is_checked: bool,
+
+    /// The old target of the link, or `None` if the file wasn't a symbolic
+    /// link before.
+    old_target: Option<Cow<'a, str>>,
+
+    /// The new target of the link, or `None` if the file isn't a symbolic
+    /// link anymore.
+    new_target: Option<Cow<'a, str>>,
+  },
+
+  /// The commit that a submodule (a Git "gitlink") points to changed. The
+  /// file should have the mode [`FileMode::GITLINK`] on the side(s) where
+  /// it's a submodule.
+  Submodule {
+    /// Whether or not the change to the commit was selected for inclusion
+    /// in the UI.
+    is_checked: bool,
+
+    /// The ID of the old commit, or `None` if the file wasn't a submodule
+    /// before.
+    old_commit: Option<Cow<'a, str>>,
+
+    /// The ID of the new commit, or `None` if the file isn't a submodule
+    /// anymore.
+    new_commit: Option<Cow<'a, str>>,
   },
 }
 
@@ -743,8 +1733,13 @@ impl Section<'_> {
   /// to simply contextual content).
   pub fn is_editable(&self) -> bool {
     match self {
-      Section::Unchanged { .. } => false,
-      Section::Changed { .. } | Section::FileMode { .. } | Section::Binary { .. } => true,
+      Section::Unchanged { .. } | Section::Omitted { .. } => false,
+      Section::Changed { .. }
+      | Section::FileMode { .. }
+      | Section::Rename { .. }
+      | Section::Binary { .. }
+      | Section::Symlink { .. }
+      | Section::Submodule { .. } => true,
     }
   }
 
@@ -753,7 +1748,7 @@ impl Section<'_> {
   pub fn tristate(&self) -> Tristate {
     let mut seen_value = None;
     match self {
-      Section::Unchanged { .. } => {}
+      Section::Unchanged { .. } | Section::Omitted { .. } => {}
       Section::Changed { lines } => {
         for line in lines {
           seen_value = match (seen_value, line.is_checked) {
@@ -768,10 +1763,25 @@ impl Section<'_> {
         is_checked,
         mode: _,
       }
+      | Section::Rename {
+        is_checked,
+        is_copy: _,
+      }
       | Section::Binary {
         is_checked,
         old_description: _,
         new_description: _,
+        contents: _,
+      }
+      | Section::Symlink {
+        is_checked,
+        old_target: _,
+        new_target: _,
+      }
+      | Section::Submodule {
+        is_checked,
+        old_commit: _,
+        new_commit: _,
       } => {
         seen_value = match (seen_value, is_checked) {
           (None, is_checked) => Some(*is_checked),
@@ -793,7 +1803,7 @@ impl Section<'_> {
     checked: bool,
   ) {
     match self {
-      Section::Unchanged { .. } => {}
+      Section::Unchanged { .. } | Section::Omitted { .. } => {}
       Section::Changed { lines } => {
         for line in lines {
           line.is_checked = checked;
@@ -805,7 +1815,12 @@ impl Section<'_> {
       } => {
         *is_checked = checked;
       }
-      Section::Binary { is_checked, .. } => {
+      Section::Rename { is_checked, .. } => {
+        *is_checked = checked;
+      }
+      Section::Binary { is_checked, .. }
+      | Section::Symlink { is_checked, .. }
+      | Section::Submodule { is_checked, .. } => {
         *is_checked = checked;
       }
     }
@@ -814,7 +1829,7 @@ impl Section<'_> {
   /// Toggle the selection of this section.
   pub fn toggle_all(&mut self) {
     match self {
-      Section::Unchanged { .. } => {}
+      Section::Unchanged { .. } | Section::Omitted { .. } => {}
       Section::Changed { lines } => {
         for line in lines {
           line.is_checked = !line.is_checked;
@@ -823,7 +1838,12 @@ impl Section<'_> {
       Section::FileMode { is_checked, .. } => {
         *is_checked = !*is_checked;
       }
-      Section::Binary { is_checked, .. } => {
+      Section::Rename { is_checked, .. } => {
+        *is_checked = !*is_checked;
+      }
+      Section::Binary { is_checked, .. }
+      | Section::Symlink { is_checked, .. }
+      | Section::Submodule { is_checked, .. } => {
         *is_checked = !*is_checked;
       }
     }
@@ -3,11 +3,11 @@
 use std::any::Any;
 use std::borrow::Cow;
 use std::cell::RefCell;
-use std::cmp::min;
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fmt::Write;
 use std::fmt::{Debug, Display};
 use std::hash::Hash;
+use std::ops::Range;
 use std::path::Path;
 use std::rc::Rc;
 use std::{io, iter, mem, panic};
@@ -30,14 +30,33 @@ use tracing::warn;
 use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 use crate::consts::ENV_VAR_DEBUG_UI;
+use crate::directory_tree::{DirectoryNode, DirectoryTree};
+use crate::fuzzy::{self, FuzzyMatch};
+use crate::hex_dump::{self, HexDumpLine};
+use crate::highlight::{Highlight, HighlightKind, SyntaxHighlights};
+use crate::inline_diff;
+use crate::moved::{LineLocation, MovedLines};
 use crate::render::{
   centered_rect, Component, DrawnRect, DrawnRects, Mask, Rect, RectSize, Viewport,
 };
-use crate::types::{ChangeType, Commit, RecordError, RecordState, Tristate};
+use crate::search::{SearchOptions, SearchPattern};
+use crate::types::{
+  ChangeType, Commit, CommitAssignment, LineNumbers, RecordError, RecordState, Tristate,
+};
 use crate::util::{IsizeExt, UsizeExt};
-use crate::{File, FileMode, Section, SectionChangedLine};
+use crate::{
+  BinaryContents, DiffStat, File, FileMode, FileStats, LineCounts, Section, SectionChangedLine,
+};
+
+/// The number of unchanged lines shown before and after each change, unless
+/// changed with [`Recorder::with_num_context_lines`].
+const DEFAULT_NUM_CONTEXT_LINES: usize = 3;
+
+/// The number of hidden unchanged lines shown by each "show more" action.
+const NUM_CONTEXT_LINES_PER_EXPANSION: usize = 10;
 
-const NUM_CONTEXT_LINES: usize = 3;
+/// The number of updates which can be undone. Older updates are forgotten.
+const MAX_UNDO_DEPTH: usize = 100;
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
 struct FileKey {
@@ -97,6 +116,45 @@ impl Default for SelectionKey {
   }
 }
 
+impl SelectionKey {
+  fn commit_idx(self) -> Option<usize> {
+    match self {
+      SelectionKey::None => None,
+      SelectionKey::File(FileKey { commit_idx, .. })
+      | SelectionKey::Container(ContainerKey { commit_idx, .. })
+      | SelectionKey::Member(MemberKey { commit_idx, .. })
+      | SelectionKey::Section(SectionKey { commit_idx, .. })
+      | SelectionKey::Line(LineKey { commit_idx, .. }) => Some(commit_idx),
+    }
+  }
+
+  fn file_idx(self) -> Option<usize> {
+    match self {
+      SelectionKey::None => None,
+      SelectionKey::File(FileKey { file_idx, .. })
+      | SelectionKey::Container(ContainerKey { file_idx, .. })
+      | SelectionKey::Member(MemberKey { file_idx, .. })
+      | SelectionKey::Section(SectionKey { file_idx, .. })
+      | SelectionKey::Line(LineKey { file_idx, .. }) => Some(file_idx),
+    }
+  }
+
+  /// Get the same key, but for the view of the commit at `commit_idx`.
+  fn with_commit_idx(
+    self,
+    commit_idx: usize,
+  ) -> Self {
+    match self {
+      SelectionKey::None => SelectionKey::None,
+      SelectionKey::File(key) => SelectionKey::File(FileKey { commit_idx, ..key }),
+      SelectionKey::Container(key) => SelectionKey::Container(ContainerKey { commit_idx, ..key }),
+      SelectionKey::Member(key) => SelectionKey::Member(MemberKey { commit_idx, ..key }),
+      SelectionKey::Section(key) => SelectionKey::Section(SectionKey { commit_idx, ..key }),
+      SelectionKey::Line(key) => SelectionKey::Line(LineKey { commit_idx, ..key }),
+    }
+  }
+}
+
 /// A copy of the contents of the screen at a certain point in time.
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct TestingScreenshot {
@@ -167,12 +225,70 @@ pub enum Event {
   ToggleAllUniform,
   ExpandItem,
   ExpandAll,
+  /// Split the focused section into its smallest independent hunks.
+  SplitSection,
+  /// Merge the focused section with the adjacent changed sections, undoing
+  /// [`Event::SplitSection`].
+  MergeSections,
+  /// Show or hide the summary of the changed lines in each file.
+  ToggleSummary,
+  /// Show the sidebar which groups the files by directory and move focus to
+  /// it, or hide it if it's already focused.
+  ToggleDirectoryTree,
+  /// Switch between showing removed lines above added lines, and showing the
+  /// old and new lines side by side.
+  ToggleDiffLayout,
+  /// Show or hide the old and new line numbers of every line.
+  ToggleLineNumbers,
+  /// Show more of the unchanged lines above the focused section.
+  ShowMoreContextAbove,
+  /// Show more of the unchanged lines below the focused section.
+  ShowMoreContextBelow,
+  /// Show all of the unchanged lines above and below the focused section.
+  ShowAllContext,
   Click {
     row: usize,
     column: usize,
   },
   ToggleCommitViewMode, // no key binding currently
   EditCommitMessage,
+  /// Show the changes of the previous commit, if there are more than two
+  /// commits.
+  FocusPrevCommit,
+  /// Show the changes of the next commit, if there are more than two commits.
+  FocusNextCommit,
+  /// Move the focused item and all of its changes to the commit with the
+  /// given index.
+  AssignToCommit(usize),
+  /// Revert the most recent change to the selection, expansion, or commit
+  /// messages.
+  Undo,
+  /// Reapply the most recently undone change.
+  Redo,
+  /// Start typing a query to search for files, lines, and semantic containers
+  /// after the focused item, or before it if `is_backward` is true.
+  StartSearch {
+    is_backward: bool,
+  },
+  /// Move focus to the next match of the search query, in the direction that
+  /// it was searched.
+  SearchNext,
+  /// Move focus to the next match of the search query, in the opposite
+  /// direction to that which it was searched.
+  SearchPrev,
+  /// Open a dialog to pick a file or semantic container to focus by typing
+  /// part of its name.
+  OpenPicker,
+  /// Type a character into the search prompt or picker. See
+  /// [`Event::from_text_input`].
+  TextInput(char),
+  /// Delete the last character typed into the search prompt or picker.
+  TextBackspace,
+  /// Accept the query typed into the search prompt, or the item chosen in
+  /// the picker.
+  TextSubmit,
+  /// Discard the query typed into the search prompt, or close the picker.
+  TextCancel,
   Help,
 }
 
@@ -209,7 +325,7 @@ impl From<crossterm::event::Event> for Event {
       }) => Self::QuitAccept,
 
       Event::Key(KeyEvent {
-        code: KeyCode::Char('?'),
+        code: KeyCode::F(1),
         modifiers: KeyModifiers::NONE,
         kind: KeyEventKind::Press,
         state: _,
@@ -354,6 +470,93 @@ impl From<crossterm::event::Event> for Event {
         state: _,
       }) => Self::ExpandAll,
 
+      Event::Key(KeyEvent {
+        code: KeyCode::Char('s'),
+        modifiers: KeyModifiers::NONE,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::SplitSection,
+      Event::Key(KeyEvent {
+        code: KeyCode::Char('S'),
+        modifiers: KeyModifiers::SHIFT,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::MergeSections,
+
+      Event::Key(KeyEvent {
+        code: KeyCode::Char('d'),
+        modifiers: KeyModifiers::NONE,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::ToggleSummary,
+      Event::Key(KeyEvent {
+        code: KeyCode::Char('t'),
+        modifiers: KeyModifiers::NONE,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::ToggleDirectoryTree,
+      Event::Key(KeyEvent {
+        code: KeyCode::Char('v'),
+        modifiers: KeyModifiers::NONE,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::ToggleDiffLayout,
+      Event::Key(KeyEvent {
+        code: KeyCode::Char('#'),
+        modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::ToggleLineNumbers,
+      Event::Key(KeyEvent {
+        code: KeyCode::Char('{'),
+        modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::ShowMoreContextAbove,
+      Event::Key(KeyEvent {
+        code: KeyCode::Char('}'),
+        modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::ShowMoreContextBelow,
+      Event::Key(KeyEvent {
+        code: KeyCode::Char('='),
+        modifiers: KeyModifiers::NONE,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::ShowAllContext,
+
+      Event::Key(KeyEvent {
+        code: KeyCode::Char('/'),
+        modifiers: KeyModifiers::NONE,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::StartSearch { is_backward: false },
+      Event::Key(KeyEvent {
+        code: KeyCode::Char('?'),
+        modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::StartSearch { is_backward: true },
+      Event::Key(KeyEvent {
+        code: KeyCode::Char('n'),
+        modifiers: KeyModifiers::NONE,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::SearchNext,
+      Event::Key(KeyEvent {
+        code: KeyCode::Char('N'),
+        modifiers: KeyModifiers::SHIFT,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::SearchPrev,
+      Event::Key(KeyEvent {
+        code: KeyCode::Char('p'),
+        modifiers: KeyModifiers::CONTROL,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::OpenPicker,
+
       Event::Key(KeyEvent {
         code: KeyCode::Char('e'),
         modifiers: KeyModifiers::NONE,
@@ -361,6 +564,45 @@ impl From<crossterm::event::Event> for Event {
         state: _event,
       }) => Self::EditCommitMessage,
 
+      Event::Key(KeyEvent {
+        code: KeyCode::Char('u'),
+        modifiers: KeyModifiers::NONE,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::Undo,
+      Event::Key(KeyEvent {
+        code: KeyCode::Char('r'),
+        modifiers: KeyModifiers::CONTROL,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::Redo,
+
+      Event::Key(KeyEvent {
+        code: KeyCode::Char('['),
+        modifiers: KeyModifiers::NONE,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::FocusPrevCommit,
+      Event::Key(KeyEvent {
+        code: KeyCode::Char(']'),
+        modifiers: KeyModifiers::NONE,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::FocusNextCommit,
+
+      Event::Key(KeyEvent {
+        code: KeyCode::Char(digit @ '1'..='9'),
+        modifiers: KeyModifiers::NONE,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => match digit
+        .to_digit(10)
+        .and_then(|digit| usize::try_from(digit).ok())
+      {
+        Some(commit_num) => Self::AssignToCommit(commit_num - 1),
+        None => Self::None,
+      },
+
       Event::Mouse(MouseEvent {
         kind: MouseEventKind::Down(MouseButton::Left),
         column,
@@ -376,6 +618,42 @@ impl From<crossterm::event::Event> for Event {
   }
 }
 
+impl Event {
+  /// Convert a terminal event into an event for typing into the search
+  /// prompt or picker, which is used while the user is typing a query.
+  /// Events which don't edit text are converted as usual.
+  pub fn from_text_input(event: crossterm::event::Event) -> Self {
+    use crossterm::event::Event;
+    match event {
+      Event::Key(KeyEvent {
+        code: KeyCode::Char(char),
+        modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::TextInput(char),
+      Event::Key(KeyEvent {
+        code: KeyCode::Backspace,
+        modifiers: KeyModifiers::NONE,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::TextBackspace,
+      Event::Key(KeyEvent {
+        code: KeyCode::Enter,
+        modifiers: KeyModifiers::NONE,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::TextSubmit,
+      Event::Key(KeyEvent {
+        code: KeyCode::Esc,
+        modifiers: KeyModifiers::NONE,
+        kind: KeyEventKind::Press,
+        state: _,
+      }) => Self::TextCancel,
+      event => event.into(),
+    }
+  }
+}
+
 /// The terminal backend to use.
 pub enum TerminalKind {
   /// Use the `CrosstermBackend` backend.
@@ -400,6 +678,14 @@ pub trait RecordInput {
   /// one available event.
   fn next_events(&mut self) -> Result<Vec<Event>, RecordError>;
 
+  /// Like [`RecordInput::next_events`], but called while the user is typing
+  /// text, such as a search query. Typed characters should be returned as
+  /// [`Event::TextInput`] rather than as the events which they're bound to
+  /// (see [`Event::from_text_input`]).
+  fn next_text_events(&mut self) -> Result<Vec<Event>, RecordError> {
+    self.next_events()
+  }
+
   /// Open a commit editor and interactively edit the given message.
   ///
   /// This function will only be invoked if one of the provided `Commit`s had
@@ -410,6 +696,18 @@ pub trait RecordInput {
   ) -> Result<String, RecordError>;
 }
 
+/// Load the sections of files which were provided to the [`Recorder`]
+/// without them. See [`File::unloaded`].
+pub trait SectionProvider {
+  /// Load the sections (and semantic containers, if any) of `file`, whose
+  /// [`File::unloaded`] is `Some`, and return the complete file. All of its
+  /// changes should be unchecked.
+  fn load_file(
+    &mut self,
+    file: &File,
+  ) -> Result<File<'static>, RecordError>;
+}
+
 /// Copied from internal implementation of `tui`.
 fn buffer_view(buffer: &Buffer) -> String {
   let mut view = String::with_capacity(buffer.content.len() + usize::from(buffer.area.height) * 3);
@@ -456,6 +754,30 @@ enum StateUpdate {
   SetExpandItem(SelectionKey, bool),
   ToggleExpandItem(SelectionKey),
   ToggleExpandAll,
+  SplitSection(SelectionKey),
+  MergeSections(SelectionKey),
+  ToggleSummary,
+  ToggleDirectoryTree,
+  FocusDirectoryNode {
+    node_idx: usize,
+    selection_key: SelectionKey,
+  },
+  ToggleDirectoryNode(usize),
+  UnfocusDirectoryTree,
+  ToggleDiffLayout,
+  ToggleLineNumbers,
+  ExpandContext(Vec<SectionKey>, ContextAction),
+  StartSearch {
+    is_backward: bool,
+  },
+  SetSearchQuery(String),
+  FinishSearch,
+  CancelSearch,
+  OpenPicker,
+  SetPickerQuery(String),
+  SetPickerSelection(usize),
+  ChoosePickerItem(SelectionKey),
+  ClosePicker,
   UnfocusMenuBar,
   ClickMenu {
     menu_idx: usize,
@@ -465,94 +787,665 @@ enum StateUpdate {
   EditCommitMessage {
     commit_idx: usize,
   },
+  FocusCommit(usize),
+  AssignToCommit(SelectionKey, usize),
+  Undo,
+  Redo,
 }
 
-#[derive(Clone, Copy, Debug)]
-enum CommitViewMode {
-  Inline,
-  Adjacent,
+impl StateUpdate {
+  /// Whether applying this update can change the state that is restored by
+  /// undo and redo.
+  fn is_undoable(&self) -> bool {
+    match self {
+      StateUpdate::ToggleItem(_)
+      | StateUpdate::ToggleItemAndAdvance(_, _)
+      | StateUpdate::ToggleAll
+      | StateUpdate::ToggleAllUniform
+      | StateUpdate::SetExpandItem(_, _)
+      | StateUpdate::ToggleExpandItem(_)
+      | StateUpdate::ToggleExpandAll
+      | StateUpdate::ToggleDirectoryNode(_)
+      | StateUpdate::ExpandContext(_, _)
+      | StateUpdate::EditCommitMessage { .. }
+      | StateUpdate::AssignToCommit(_, _) => true,
+      StateUpdate::None
+      | StateUpdate::SetQuitDialog(_)
+      | StateUpdate::QuitAccept
+      | StateUpdate::QuitCancel
+      | StateUpdate::SetHelpDialog(_)
+      | StateUpdate::TakeScreenshot(_)
+      | StateUpdate::Redraw
+      | StateUpdate::EnsureSelectionInViewport
+      | StateUpdate::ScrollTo(_)
+      | StateUpdate::SelectItem { .. }
+      | StateUpdate::SplitSection(_)
+      | StateUpdate::MergeSections(_)
+      | StateUpdate::ToggleSummary
+      | StateUpdate::ToggleDirectoryTree
+      | StateUpdate::FocusDirectoryNode { .. }
+      | StateUpdate::UnfocusDirectoryTree
+      | StateUpdate::ToggleDiffLayout
+      | StateUpdate::ToggleLineNumbers
+      | StateUpdate::StartSearch { .. }
+      | StateUpdate::SetSearchQuery(_)
+      | StateUpdate::FinishSearch
+      | StateUpdate::CancelSearch
+      | StateUpdate::OpenPicker
+      | StateUpdate::SetPickerQuery(_)
+      | StateUpdate::SetPickerSelection(_)
+      | StateUpdate::ChoosePickerItem(_)
+      | StateUpdate::ClosePicker
+      | StateUpdate::UnfocusMenuBar
+      | StateUpdate::ClickMenu { .. }
+      | StateUpdate::ClickMenuItem(_)
+      | StateUpdate::ToggleCommitViewMode
+      | StateUpdate::FocusCommit(_)
+      | StateUpdate::Undo
+      | StateUpdate::Redo => false,
+    }
+  }
 }
 
-#[allow(clippy::enum_variant_names)]
-enum ToggleSideEffects {
-  ToggledModeChangeSection(SectionKey, FileMode, FileMode, bool),
-  ToggledChangedSection(SectionKey, bool),
-  ToggledChangedLine(LineKey, bool),
+/// The parts of the UI state which can be restored by undo and redo.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct UndoSnapshot {
+  /// For each file and section, whether each of its changes is checked.
+  checked: Vec<Vec<Vec<bool>>>,
+  commit_assignments: Vec<Vec<CommitAssignment>>,
+  commit_messages: Vec<Option<String>>,
+  expanded_items: HashSet<SelectionKey>,
+  expanded_context: HashMap<SectionKey, ContextExpansion>,
 }
 
-/// UI component to record the user's changes.
-pub struct Recorder<'state, 'input> {
-  state: RecordState<'state>,
-  input: &'input mut dyn RecordInput,
-  pending_events: Vec<Event>,
-  use_unicode: bool,
-  commit_view_mode: CommitViewMode,
-  expanded_items: HashSet<SelectionKey>,
-  expanded_menu_idx: Option<usize>,
+impl UndoSnapshot {
+  /// The checked state of each change in `section`, as stored in
+  /// [`UndoSnapshot::checked`].
+  fn section_checked<'a>(section: &'a Section) -> impl Iterator<Item = bool> + 'a {
+    let (lines, is_checked): (&[SectionChangedLine], Option<bool>) = match section {
+      Section::Unchanged { .. } | Section::Omitted { .. } => (&[], None),
+      Section::Changed { lines } => (lines, None),
+      Section::FileMode { is_checked, .. }
+      | Section::Rename { is_checked, .. }
+      | Section::Binary { is_checked, .. }
+      | Section::Symlink { is_checked, .. }
+      | Section::Submodule { is_checked, .. } => (&[], Some(*is_checked)),
+    };
+    lines.iter().map(|line| line.is_checked).chain(is_checked)
+  }
+
+  /// Whether the checked state in `checked` matches the sections of `file`.
+  fn is_file_checked_state(
+    file: &File,
+    checked: &[Vec<bool>],
+  ) -> bool {
+    file.sections.len() == checked.len()
+      && file
+        .sections
+        .iter()
+        .zip(checked)
+        .all(|(section, checked)| Self::section_checked(section).eq(checked.iter().copied()))
+  }
+}
+
+/// An entry in the undo or redo stack. The focus is restored along with the
+/// snapshot so that the user can see what changed.
+#[derive(Clone, Debug)]
+struct UndoEntry {
+  snapshot: UndoSnapshot,
   selection_key: SelectionKey,
   focused_commit_idx: usize,
-  quit_dialog: Option<QuitDialog>,
-  help_dialog: Option<HelpDialog>,
-  scroll_offset_y: isize,
 }
 
-impl<'state, 'input> Recorder<'state, 'input> {
-  /// Constructor.
-  pub fn new(
-    mut state: RecordState<'state>,
-    input: &'input mut dyn RecordInput,
-  ) -> Self {
-    // Ensure that there are at least two commits.
-    state
-      .commits
-      .extend(iter::repeat_with(Commit::default).take(2_usize.saturating_sub(state.commits.len())));
-    if state.commits.len() > 2 {
-      unimplemented!("more than two commits");
+/// An incremental search for files, lines, and semantic containers, started
+/// by [`Event::StartSearch`]. Only the paths of files which haven't been
+/// loaded are searched, so that searching doesn't load every file.
+#[derive(Clone, Debug)]
+struct Search {
+  query: String,
+  is_backward: bool,
+  /// Whether the user is still typing the query.
+  is_editing: bool,
+  /// The item which was focused when the search started. Matches are
+  /// searched for from here while the query is typed, and it's focused again
+  /// if the search is cancelled.
+  origin_key: SelectionKey,
+  /// The compiled query, or `None` if it's empty or isn't a valid regex.
+  pattern: Option<SearchPattern>,
+}
+
+/// The dialog opened by [`Event::OpenPicker`], which lists the files and
+/// semantic containers whose names fuzzy-match the query (see
+/// [`crate::fuzzy`]).
+#[derive(Clone, Debug)]
+struct Picker {
+  query: String,
+  /// Every file in the focused commit, and the semantic containers of the
+  /// files which have been loaded.
+  items: Vec<PickerItem>,
+  /// The indices of the items which match the query, best first.
+  matches: Vec<(usize, FuzzyMatch)>,
+  /// The index in `matches` of the item which is focused if Enter is pressed.
+  selected_idx: usize,
+}
+
+#[derive(Clone, Debug)]
+struct PickerItem {
+  selection_key: SelectionKey,
+  /// The text which is matched against the query: the path of a file, or
+  /// the names of a semantic container.
+  label: String,
+  /// The path of a semantic container's file.
+  path: Option<String>,
+}
+
+/// The sidebar opened by [`Event::ToggleDirectoryTree`], which groups the
+/// files by directory.
+#[derive(Clone, Debug)]
+struct DirectoryTreeSidebar {
+  tree: DirectoryTree,
+  /// The index of the focused node in the tree.
+  focused_idx: usize,
+  /// Whether keys move between the nodes of the tree, rather than the items
+  /// in the main pane.
+  is_focused: bool,
+}
+
+/// A change in how a run of adjacent [`Section::Changed`]s in a file divide up
+/// their lines, made by splitting a section or merging sections. The lines
+/// themselves stay in the same order, so state which refers to them by
+/// section and line index can be mapped to the new sections.
+#[derive(Clone, Debug)]
+struct SectionRegrouping {
+  file_idx: usize,
+  /// The index of the first regrouped section.
+  section_idx: usize,
+  /// The number of lines in each of the regrouped sections beforehand.
+  old_lens: Vec<usize>,
+  /// The number of lines in each of the regrouped sections afterwards.
+  new_lens: Vec<usize>,
+}
+
+impl SectionRegrouping {
+  /// Split the section at `section_idx` into its smallest independent hunks,
+  /// each consisting of a run of removed lines followed by a run of added
+  /// lines. Returns `None` if the section can't be split any further.
+  fn split(
+    file_idx: usize,
+    section_idx: usize,
+    lines: &[SectionChangedLine],
+  ) -> Option<Self> {
+    let mut new_lens: Vec<usize> = Vec::new();
+    let mut prev_change_type = None;
+    for line in lines {
+      let SectionChangedLine {
+        is_checked: _,
+        change_type,
+        line: _,
+      } = line;
+      let starts_hunk = match (prev_change_type, change_type) {
+        (None, _) | (Some(ChangeType::Added), ChangeType::Removed) => true,
+        (Some(ChangeType::Removed), ChangeType::Removed | ChangeType::Added)
+        | (Some(ChangeType::Added), ChangeType::Added) => false,
+      };
+      match new_lens.last_mut() {
+        Some(len) if !starts_hunk => *len += 1,
+        Some(_) | None => new_lens.push(1),
+      }
+      prev_change_type = Some(*change_type);
     }
+    if new_lens.len() < 2 {
+      return None;
+    }
+    Some(Self {
+      file_idx,
+      section_idx,
+      old_lens: vec![lines.len()],
+      new_lens,
+    })
+  }
 
-    let mut recorder = Self {
-      state,
-      input,
-      pending_events: Default::default(),
-      use_unicode: true,
-      commit_view_mode: CommitViewMode::Inline,
-      expanded_items: Default::default(),
-      expanded_menu_idx: Default::default(),
-      selection_key: SelectionKey::None,
-      focused_commit_idx: 0,
-      quit_dialog: None,
-      help_dialog: None,
-      scroll_offset_y: 0,
+  /// Merge the section at `section_idx` with the [`Section::Changed`]s
+  /// directly before and after it, undoing [`SectionRegrouping::split`].
+  /// Returns `None` if there are no such sections.
+  fn merge(
+    file_idx: usize,
+    sections: &[Section],
+    section_idx: usize,
+  ) -> Option<Self> {
+    let changed_lines_len = |section: &Section| match section {
+      Section::Changed { lines } => Some(lines.len()),
+      Section::Unchanged { .. }
+      | Section::Omitted { .. }
+      | Section::FileMode { .. }
+      | Section::Rename { .. }
+      | Section::Binary { .. }
+      | Section::Symlink { .. }
+      | Section::Submodule { .. } => None,
     };
-    recorder.expand_initial_items();
-    recorder
+    changed_lines_len(sections.get(section_idx)?)?;
+    let start_idx = sections[..section_idx]
+      .iter()
+      .rposition(|section| changed_lines_len(section).is_none())
+      .map_or(0, |idx| idx + 1);
+    let old_lens: Vec<usize> = sections[start_idx..]
+      .iter()
+      .map_while(changed_lines_len)
+      .collect();
+    if old_lens.len() < 2 {
+      return None;
+    }
+    Some(Self {
+      file_idx,
+      section_idx: start_idx,
+      new_lens: vec![old_lens.iter().sum()],
+      old_lens,
+    })
   }
 
-  /// Run the terminal user interface and have the user interactively select
-  /// changes.
-  pub fn run(self) -> Result<RecordState<'state>, RecordError> {
-    #[cfg(feature = "debug")]
-    if std::env::var_os(crate::consts::ENV_VAR_DUMP_UI_STATE).is_some() {
-      let ui_state =
-        serde_json::to_string_pretty(&self.state).map_err(RecordError::SerializeJson)?;
-      std::fs::write(crate::consts::DUMP_UI_STATE_FILENAME, ui_state)
-        .map_err(RecordError::WriteFile)?;
+  /// The new section and line indices of the line at `line_idx` in the section
+  /// at `section_idx`.
+  fn map_line(
+    &self,
+    section_idx: usize,
+    line_idx: usize,
+  ) -> (usize, usize) {
+    let Self {
+      file_idx: _,
+      section_idx: start_idx,
+      old_lens,
+      new_lens,
+    } = self;
+    if section_idx < *start_idx {
+      return (section_idx, line_idx);
     }
-
-    match self.input.terminal_kind() {
-      TerminalKind::Crossterm => self.run_crossterm(),
-      TerminalKind::Testing { width, height } => self.run_testing(width, height),
+    let old_idx = section_idx - start_idx;
+    if old_idx >= old_lens.len() {
+      return (section_idx - old_lens.len() + new_lens.len(), line_idx);
+    }
+    let mut offset = old_lens[..old_idx].iter().sum::<usize>() + line_idx;
+    for (new_idx, len) in new_lens.iter().enumerate() {
+      if offset < *len {
+        return (start_idx + new_idx, offset);
+      }
+      offset -= len;
     }
+    (start_idx + new_lens.len(), offset)
   }
 
-  /// Run the recorder UI using `crossterm` as the backend connected to stdout.
-  fn run_crossterm(self) -> Result<RecordState<'state>, RecordError> {
-    Self::set_up_crossterm()?;
-    Self::install_panic_hook();
-    let backend = CrosstermBackend::new(io::stdout());
-    let mut term = Terminal::new(backend).map_err(RecordError::SetUpTerminal)?;
+  /// The new indices of the sections containing the lines of the section at
+  /// `section_idx`.
+  fn map_section(
+    &self,
+    section_idx: usize,
+  ) -> Range<usize> {
+    let (first_idx, _) = self.map_line(section_idx, 0);
+    let last_line_idx = section_idx
+      .checked_sub(self.section_idx)
+      .and_then(|old_idx| self.old_lens.get(old_idx))
+      .map_or(0, |len| len.saturating_sub(1));
+    let (last_idx, _) = self.map_line(section_idx, last_line_idx);
+    first_idx..last_idx + 1
+  }
+
+  /// The new keys of the item at `selection_key`. A section which was split
+  /// has one key for each new section.
+  fn map_selection_key(
+    &self,
+    selection_key: SelectionKey,
+  ) -> Vec<SelectionKey> {
+    match selection_key {
+      SelectionKey::Section(SectionKey {
+        commit_idx,
+        file_idx,
+        section_idx,
+      }) if file_idx == self.file_idx => self
+        .map_section(section_idx)
+        .map(|section_idx| {
+          SelectionKey::Section(SectionKey {
+            commit_idx,
+            file_idx,
+            section_idx,
+          })
+        })
+        .collect(),
+      SelectionKey::Line(LineKey {
+        commit_idx,
+        file_idx,
+        section_idx,
+        line_idx,
+      }) if file_idx == self.file_idx => {
+        let (section_idx, line_idx) = self.map_line(section_idx, line_idx);
+        vec![SelectionKey::Line(LineKey {
+          commit_idx,
+          file_idx,
+          section_idx,
+          line_idx,
+        })]
+      }
+      SelectionKey::None
+      | SelectionKey::File(_)
+      | SelectionKey::Container(_)
+      | SelectionKey::Member(_)
+      | SelectionKey::Section(_)
+      | SelectionKey::Line(_) => vec![selection_key],
+    }
+  }
+
+  fn map_expanded_items(
+    &self,
+    expanded_items: &HashSet<SelectionKey>,
+  ) -> HashSet<SelectionKey> {
+    expanded_items
+      .iter()
+      .flat_map(|selection_key| self.map_selection_key(*selection_key))
+      .collect()
+  }
+
+  fn map_expanded_context(
+    &self,
+    expanded_context: &HashMap<SectionKey, ContextExpansion>,
+  ) -> HashMap<SectionKey, ContextExpansion> {
+    expanded_context
+      .iter()
+      .map(|(section_key, context_expansion)| {
+        let SectionKey {
+          commit_idx,
+          file_idx,
+          section_idx,
+        } = *section_key;
+        let section_idx = if file_idx == self.file_idx {
+          self.map_section(section_idx).start
+        } else {
+          section_idx
+        };
+        let section_key = SectionKey {
+          commit_idx,
+          file_idx,
+          section_idx,
+        };
+        (section_key, *context_expansion)
+      })
+      .collect()
+  }
+
+  fn map_commit_assignments(
+    &self,
+    commit_assignments: &mut [CommitAssignment],
+  ) {
+    for assignment in commit_assignments {
+      let CommitAssignment {
+        section_idx,
+        line_idx,
+        commit_idx: _,
+      } = assignment;
+      (*section_idx, *line_idx) = self.map_line(*section_idx, *line_idx);
+    }
+  }
+
+  /// Divide `items`, which correspond to the lines of the regrouped sections
+  /// in order, into the new sections.
+  fn regroup<T>(
+    &self,
+    items: impl IntoIterator<Item = T>,
+  ) -> Vec<Vec<T>> {
+    let mut items = items.into_iter();
+    self
+      .new_lens
+      .iter()
+      .map(|len| items.by_ref().take(*len).collect())
+      .collect()
+  }
+
+  /// Regroup the entries of `per_section`, which has one list of items for
+  /// each of the file's sections and one item for each changed line.
+  fn map_per_section<T>(
+    &self,
+    per_section: &mut Vec<Vec<T>>,
+  ) {
+    let range = self.section_idx..self.section_idx + self.old_lens.len();
+    if per_section.len() < range.end {
+      return;
+    }
+    let items: Vec<T> = per_section.drain(range.clone()).flatten().collect();
+    per_section.splice(range.start..range.start, self.regroup(items));
+  }
+
+  fn apply(
+    &self,
+    file: &mut File,
+  ) {
+    let range = self.section_idx..self.section_idx + self.old_lens.len();
+    let lines: Vec<SectionChangedLine> = file
+      .sections
+      .drain(range.clone())
+      .flat_map(|section| match section {
+        Section::Changed { lines } => lines,
+        Section::Unchanged { .. }
+        | Section::Omitted { .. }
+        | Section::FileMode { .. }
+        | Section::Rename { .. }
+        | Section::Binary { .. }
+        | Section::Symlink { .. }
+        | Section::Submodule { .. } => Vec::new(),
+      })
+      .collect();
+    let new_sections = self
+      .regroup(lines)
+      .into_iter()
+      .map(|lines| Section::Changed { lines });
+    file.sections.splice(range.start..range.start, new_sections);
+
+    self.map_commit_assignments(&mut file.commit_assignments);
+    #[cfg(feature = "tree-sitter")]
+    if let Some(containers) = &mut file.containers {
+      for container in containers {
+        container.map_section_indices(&|section_idx| self.map_section(section_idx));
+      }
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum CommitViewMode {
+  Inline,
+  Adjacent,
+}
+
+/// How the lines of a section are laid out, toggled with
+/// [`Event::ToggleDiffLayout`].
+#[derive(Clone, Copy, Debug)]
+enum DiffLayout {
+  /// The removed lines are shown above the added lines.
+  Unified,
+  /// The old lines are shown on the left and the new lines on the right,
+  /// with each removed line next to the added line paired with it by
+  /// [`inline_diff::align_changed_lines`].
+  SideBySide,
+}
+
+/// How many more lines than the context size are shown at each end of an
+/// unchanged section, between changes.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct ContextExpansion {
+  /// The number of extra lines shown at the start of the section, below the
+  /// change above it.
+  num_start_lines: usize,
+  /// The number of extra lines shown at the end of the section, above the
+  /// change below it.
+  num_end_lines: usize,
+}
+
+/// A way of showing more of the lines hidden in an unchanged section.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
+enum ContextAction {
+  /// Show more lines at the start of the section, moving down from the change
+  /// above it.
+  MoreDown,
+  /// Show more lines at the end of the section, moving up from the change
+  /// below it.
+  MoreUp,
+  /// Show every line in the section.
+  All,
+}
+
+#[allow(clippy::enum_variant_names)]
+enum ToggleSideEffects {
+  ToggledModeChangeSection(SectionKey, FileMode, FileMode, bool),
+  ToggledChangedSection(SectionKey, bool),
+  ToggledChangedLine(LineKey, bool),
+}
+
+/// UI component to record the user's changes.
+pub struct Recorder<'state, 'input> {
+  state: RecordState<'state>,
+  input: &'input mut dyn RecordInput,
+  pending_events: Vec<Event>,
+  use_unicode: bool,
+  commit_view_mode: CommitViewMode,
+  diff_layout: DiffLayout,
+  num_context_lines: usize,
+  expanded_items: HashSet<SelectionKey>,
+  expanded_context: HashMap<SectionKey, ContextExpansion>,
+  expanded_menu_idx: Option<usize>,
+  selection_key: SelectionKey,
+  focused_commit_idx: usize,
+  quit_dialog: Option<QuitDialog>,
+  help_dialog: Option<HelpDialog>,
+  scroll_offset_y: isize,
+  undo_stack: VecDeque<UndoEntry>,
+  redo_stack: VecDeque<UndoEntry>,
+  section_provider: Option<&'input mut dyn SectionProvider>,
+  moved_lines: MovedLines,
+  syntax_highlights: SyntaxHighlights,
+  is_summary_visible: bool,
+  is_line_number_gutter_visible: bool,
+  directory_tree: Option<DirectoryTreeSidebar>,
+  search_options: SearchOptions,
+  search: Option<Search>,
+  picker: Option<Picker>,
+}
+
+impl<'state, 'input> Recorder<'state, 'input> {
+  /// Constructor.
+  pub fn new(
+    mut state: RecordState<'state>,
+    input: &'input mut dyn RecordInput,
+  ) -> Self {
+    // Ensure that there are at least two commits.
+    state
+      .commits
+      .extend(iter::repeat_with(Commit::default).take(2_usize.saturating_sub(state.commits.len())));
+
+    let moved_lines = MovedLines::detect(&state.files);
+    let syntax_highlights = SyntaxHighlights::detect(&state.files);
+    let mut recorder = Self {
+      state,
+      input,
+      pending_events: Default::default(),
+      use_unicode: true,
+      commit_view_mode: CommitViewMode::Inline,
+      diff_layout: DiffLayout::Unified,
+      num_context_lines: DEFAULT_NUM_CONTEXT_LINES,
+      expanded_items: Default::default(),
+      expanded_context: Default::default(),
+      expanded_menu_idx: Default::default(),
+      selection_key: SelectionKey::None,
+      focused_commit_idx: 0,
+      quit_dialog: None,
+      help_dialog: None,
+      scroll_offset_y: 0,
+      undo_stack: Default::default(),
+      redo_stack: Default::default(),
+      section_provider: None,
+      moved_lines,
+      syntax_highlights,
+      is_summary_visible: false,
+      is_line_number_gutter_visible: false,
+      directory_tree: None,
+      search_options: Default::default(),
+      search: None,
+      picker: None,
+    };
+    recorder.expand_initial_items();
+    recorder
+  }
+
+  /// Load the sections of files whose [`File::unloaded`] is `Some` from
+  /// `section_provider`. A file is loaded when the user selects or expands
+  /// it, or performs an action which affects every file. All remaining files
+  /// are loaded before the state is returned from [`Recorder::run`].
+  pub fn with_section_provider(
+    mut self,
+    section_provider: &'input mut dyn SectionProvider,
+  ) -> Self {
+    self.section_provider = Some(section_provider);
+    self
+  }
+
+  /// Set how queries are matched when searching with `/` and `?`. By default,
+  /// they're matched literally, and case-insensitively unless they contain an
+  /// uppercase letter.
+  pub fn with_search_options(
+    mut self,
+    search_options: SearchOptions,
+  ) -> Self {
+    self.search_options = search_options;
+    self
+  }
+
+  /// Set how many unchanged lines are shown before and after each change.
+  /// Defaults to 3. The user can show more of the remaining unchanged lines
+  /// with [`Event::ShowMoreContextAbove`], [`Event::ShowMoreContextBelow`],
+  /// and [`Event::ShowAllContext`], or by clicking the ellipsis which stands
+  /// in for them.
+  pub fn with_num_context_lines(
+    mut self,
+    num_context_lines: usize,
+  ) -> Self {
+    self.num_context_lines = num_context_lines;
+    self
+  }
+
+  /// Run the terminal user interface and have the user interactively select
+  /// changes.
+  pub fn run(self) -> Result<RecordState<'state>, RecordError> {
+    let mut cancelled_state = None;
+    self.run_with_cancelled_state(&mut cancelled_state)
+  }
+
+  /// Like [`Recorder::run`], but if the user cancels, `cancelled_state` is set
+  /// to the state at the time that they cancelled. This allows the caller to
+  /// save the user's selections so that they can be restored later (see
+  /// [`crate::selection`]).
+  pub fn run_with_cancelled_state(
+    self,
+    cancelled_state: &mut Option<RecordState<'state>>,
+  ) -> Result<RecordState<'state>, RecordError> {
+    #[cfg(feature = "debug")]
+    if std::env::var_os(crate::consts::ENV_VAR_DUMP_UI_STATE).is_some() {
+      let ui_state = crate::interchange::to_json(&self.state)?;
+      std::fs::write(crate::consts::DUMP_UI_STATE_FILENAME, ui_state)
+        .map_err(RecordError::WriteFile)?;
+    }
+
+    match self.input.terminal_kind() {
+      TerminalKind::Crossterm => self.run_crossterm(cancelled_state),
+      TerminalKind::Testing { width, height } => self.run_testing(width, height, cancelled_state),
+    }
+  }
+
+  /// Run the recorder UI using `crossterm` as the backend connected to stdout.
+  fn run_crossterm(
+    self,
+    cancelled_state: &mut Option<RecordState<'state>>,
+  ) -> Result<RecordState<'state>, RecordError> {
+    Self::set_up_crossterm()?;
+    Self::install_panic_hook();
+    let backend = CrosstermBackend::new(io::stdout());
+    let mut term = Terminal::new(backend).map_err(RecordError::SetUpTerminal)?;
     term.clear().map_err(RecordError::RenderFrame)?;
-    let result = self.run_inner(&mut term);
+    let result = self.run_inner(&mut term, cancelled_state);
     Self::clean_up_crossterm()?;
     result
   }
@@ -597,17 +1490,20 @@ impl<'state, 'input> Recorder<'state, 'input> {
     self,
     width: usize,
     height: usize,
+    cancelled_state: &mut Option<RecordState<'state>>,
   ) -> Result<RecordState<'state>, RecordError> {
     let backend = TestBackend::new(width.clamp_into_u16(), height.clamp_into_u16());
     let mut term = Terminal::new(backend).map_err(RecordError::SetUpTerminal)?;
-    self.run_inner(&mut term)
+    self.run_inner(&mut term, cancelled_state)
   }
 
   fn run_inner(
     mut self,
     term: &mut Terminal<impl Backend + Any>,
+    cancelled_state: &mut Option<RecordState<'state>>,
   ) -> Result<RecordState<'state>, RecordError> {
     self.selection_key = self.first_selection_key();
+    self.load_file_for_key(self.selection_key)?;
     let debug = if cfg!(feature = "debug") {
       std::env::var_os(ENV_VAR_DEBUG_UI).is_some()
     } else {
@@ -653,16 +1549,25 @@ impl<'state, 'input> Recorder<'state, 'input> {
           .map_err(RecordError::RenderFrame)?;
       }
 
-      let events = if self.pending_events.is_empty() {
-        self.input.next_events()?
-      } else {
+      let events = if !self.pending_events.is_empty() {
         // FIXME: the pending events should be applied without redrawing
         // the screen, as otherwise there may be a flash of content
         // containing the screen contents before the event is applied.
         mem::take(&mut self.pending_events)
+      } else if self.is_typing_text() {
+        self.input.next_text_events()?
+      } else {
+        self.input.next_events()?
       };
       for event in events {
-        match self.handle_event(event, term_height, &drawn_rects, &menu_bar)? {
+        let state_update = self.handle_event(event, term_height, &drawn_rects, &menu_bar)?;
+        self.load_files_for_update(&state_update)?;
+        let undo_entry = if state_update.is_undoable() {
+          Some(self.make_undo_entry())
+        } else {
+          None
+        };
+        match state_update {
           StateUpdate::None => {}
           StateUpdate::SetQuitDialog(quit_dialog) => {
             self.quit_dialog = quit_dialog;
@@ -677,7 +1582,10 @@ impl<'state, 'input> Recorder<'state, 'input> {
               break 'outer;
             }
           }
-          StateUpdate::QuitCancel => return Err(RecordError::Cancelled),
+          StateUpdate::QuitCancel => {
+            *cancelled_state = Some(self.state);
+            return Err(RecordError::Cancelled);
+          }
           StateUpdate::TakeScreenshot(screenshot) => {
             let backend: &dyn Any = term.backend();
             let test_backend = backend
@@ -696,10 +1604,8 @@ impl<'state, 'input> Recorder<'state, 'input> {
             }
           }
           StateUpdate::ScrollTo(scroll_offset_y) => {
-            self.scroll_offset_y = scroll_offset_y.clamp(0, {
-              let DrawnRect { rect, timestamp: _ } = drawn_rects[&ComponentId::App];
-              rect.height.unwrap_isize() - 1
-            });
+            self.scroll_offset_y =
+              scroll_offset_y.clamp(0, Self::scrollable_height(&drawn_rects) - 1);
           }
           StateUpdate::SelectItem {
             selection_key,
@@ -737,6 +1643,96 @@ impl<'state, 'input> Recorder<'state, 'input> {
             self.toggle_expand_all()?;
             self.pending_events.push(Event::EnsureSelectionInViewport);
           }
+          StateUpdate::SplitSection(selection_key) => {
+            self.split_section(selection_key);
+            self.pending_events.push(Event::EnsureSelectionInViewport);
+          }
+          StateUpdate::MergeSections(selection_key) => {
+            self.merge_sections(selection_key);
+            self.pending_events.push(Event::EnsureSelectionInViewport);
+          }
+          StateUpdate::ToggleSummary => {
+            self.is_summary_visible = !self.is_summary_visible;
+          }
+          StateUpdate::ToggleDirectoryTree => {
+            self.toggle_directory_tree();
+          }
+          StateUpdate::FocusDirectoryNode {
+            node_idx,
+            selection_key,
+          } => {
+            if let Some(sidebar) = &mut self.directory_tree {
+              sidebar.focused_idx = node_idx;
+              sidebar.is_focused = true;
+            }
+            self.selection_key = selection_key;
+            self.pending_events.push(Event::EnsureSelectionInViewport);
+          }
+          StateUpdate::ToggleDirectoryNode(node_idx) => {
+            self.toggle_directory_node(node_idx)?;
+          }
+          StateUpdate::UnfocusDirectoryTree => {
+            if let Some(sidebar) = &mut self.directory_tree {
+              sidebar.is_focused = false;
+            }
+          }
+          StateUpdate::ToggleDiffLayout => {
+            self.diff_layout = match self.diff_layout {
+              DiffLayout::Unified => DiffLayout::SideBySide,
+              DiffLayout::SideBySide => DiffLayout::Unified,
+            };
+            self.pending_events.push(Event::EnsureSelectionInViewport);
+          }
+          StateUpdate::ToggleLineNumbers => {
+            self.is_line_number_gutter_visible = !self.is_line_number_gutter_visible;
+          }
+          StateUpdate::ExpandContext(section_keys, context_action) => {
+            self.expand_context(&section_keys, context_action);
+            self.pending_events.push(Event::EnsureSelectionInViewport);
+          }
+          StateUpdate::StartSearch { is_backward } => {
+            self.search = Some(Search {
+              query: String::new(),
+              is_backward,
+              is_editing: true,
+              origin_key: self.selection_key,
+              pattern: None,
+            });
+          }
+          StateUpdate::SetSearchQuery(query) => {
+            self.set_search_query(query)?;
+            self.pending_events.push(Event::EnsureSelectionInViewport);
+          }
+          StateUpdate::FinishSearch => {
+            self.finish_search();
+          }
+          StateUpdate::CancelSearch => {
+            self.cancel_search();
+            self.pending_events.push(Event::EnsureSelectionInViewport);
+          }
+          StateUpdate::OpenPicker => {
+            if self.editing_search().is_some() {
+              self.cancel_search();
+            }
+            self.open_picker()?;
+          }
+          StateUpdate::SetPickerQuery(query) => {
+            self.set_picker_query(query);
+          }
+          StateUpdate::SetPickerSelection(selected_idx) => {
+            if let Some(picker) = &mut self.picker {
+              picker.selected_idx = selected_idx;
+            }
+          }
+          StateUpdate::ChoosePickerItem(selection_key) => {
+            self.picker = None;
+            self.selection_key = selection_key;
+            self.expand_item_ancestors(selection_key);
+            self.pending_events.push(Event::EnsureSelectionInViewport);
+          }
+          StateUpdate::ClosePicker => {
+            self.picker = None;
+          }
           StateUpdate::UnfocusMenuBar => {
             self.unfocus_menu_bar();
           }
@@ -756,6 +1752,24 @@ impl<'state, 'input> Recorder<'state, 'input> {
             self.pending_events.push(Event::Redraw);
             self.edit_commit_message(commit_idx)?;
           }
+          StateUpdate::FocusCommit(commit_idx) => {
+            self.focus_commit(commit_idx);
+            self.pending_events.push(Event::EnsureSelectionInViewport);
+          }
+          StateUpdate::AssignToCommit(selection_key, commit_idx) => {
+            self.assign_item(selection_key, commit_idx)?;
+          }
+          StateUpdate::Undo => {
+            self.undo();
+            self.pending_events.push(Event::EnsureSelectionInViewport);
+          }
+          StateUpdate::Redo => {
+            self.redo();
+            self.pending_events.push(Event::EnsureSelectionInViewport);
+          }
+        }
+        if let Some(undo_entry) = undo_entry {
+          self.push_undo_entry(undo_entry);
         }
       }
     }
@@ -764,6 +1778,8 @@ impl<'state, 'input> Recorder<'state, 'input> {
   }
 
   fn make_menu_bar(&self) -> MenuBar<'static> {
+    let num_commits = self.state.commits.len();
+    let is_multi_commit = num_commits > 2;
     MenuBar {
       menus: vec![
         Menu {
@@ -782,6 +1798,14 @@ impl<'state, 'input> Recorder<'state, 'input> {
         Menu {
           label: Cow::Borrowed("Edit"),
           items: vec![
+            MenuItem {
+              label: Cow::Borrowed("Undo (u)"),
+              event: Event::Undo,
+            },
+            MenuItem {
+              label: Cow::Borrowed("Redo (ctrl-r)"),
+              event: Event::Redo,
+            },
             MenuItem {
               label: Cow::Borrowed("Edit message (e)"),
               event: Event::EditCommitMessage,
@@ -802,7 +1826,21 @@ impl<'state, 'input> Recorder<'state, 'input> {
               label: Cow::Borrowed("Invert all items uniformly (A)"),
               event: Event::ToggleAllUniform,
             },
-          ],
+          ]
+          .into_iter()
+          .chain(
+            (0..num_commits)
+              .take(9)
+              .filter(|_| is_multi_commit)
+              .map(|commit_idx| MenuItem {
+                label: Cow::Owned(format!(
+                  "Move to commit {commit_num} ({commit_num})",
+                  commit_num = commit_idx + 1
+                )),
+                event: Event::AssignToCommit(commit_idx),
+              }),
+          )
+          .collect(),
         },
         Menu {
           label: Cow::Borrowed("Select"),
@@ -845,22 +1883,94 @@ impl<'state, 'input> Recorder<'state, 'input> {
               label: Cow::Borrowed("Next page (ctrl-d)"),
               event: Event::FocusNextPage,
             },
-          ],
-        },
-        Menu {
-          label: Cow::Borrowed("View"),
-          items: vec![
             MenuItem {
-              label: Cow::Borrowed("Fold/unfold current (f)"),
-              event: Event::ExpandItem,
+              label: Cow::Borrowed("Search forward (/)"),
+              event: Event::StartSearch { is_backward: false },
             },
             MenuItem {
-              label: Cow::Borrowed("Fold/unfold all (F)"),
-              event: Event::ExpandAll,
+              label: Cow::Borrowed("Search backward (?)"),
+              event: Event::StartSearch { is_backward: true },
             },
             MenuItem {
-              label: Cow::Borrowed("Scroll up (ctrl-up, ctrl-y)"),
-              event: Event::ScrollUp,
+              label: Cow::Borrowed("Next match (n)"),
+              event: Event::SearchNext,
+            },
+            MenuItem {
+              label: Cow::Borrowed("Previous match (N)"),
+              event: Event::SearchPrev,
+            },
+            MenuItem {
+              label: Cow::Borrowed("Go to file or symbol (ctrl-p)"),
+              event: Event::OpenPicker,
+            },
+          ]
+          .into_iter()
+          .chain(
+            [
+              MenuItem {
+                label: Cow::Borrowed("Previous commit ([)"),
+                event: Event::FocusPrevCommit,
+              },
+              MenuItem {
+                label: Cow::Borrowed("Next commit (])"),
+                event: Event::FocusNextCommit,
+              },
+            ]
+            .into_iter()
+            .filter(|_| is_multi_commit),
+          )
+          .collect(),
+        },
+        Menu {
+          label: Cow::Borrowed("View"),
+          items: vec![
+            MenuItem {
+              label: Cow::Borrowed("Fold/unfold current (f)"),
+              event: Event::ExpandItem,
+            },
+            MenuItem {
+              label: Cow::Borrowed("Fold/unfold all (F)"),
+              event: Event::ExpandAll,
+            },
+            MenuItem {
+              label: Cow::Borrowed("Split current section (s)"),
+              event: Event::SplitSection,
+            },
+            MenuItem {
+              label: Cow::Borrowed("Unsplit current section (S)"),
+              event: Event::MergeSections,
+            },
+            MenuItem {
+              label: Cow::Borrowed("Show/hide summary (d)"),
+              event: Event::ToggleSummary,
+            },
+            MenuItem {
+              label: Cow::Borrowed("Show/hide directory tree (t)"),
+              event: Event::ToggleDirectoryTree,
+            },
+            MenuItem {
+              label: Cow::Borrowed("Unified/side-by-side diff (v)"),
+              event: Event::ToggleDiffLayout,
+            },
+            MenuItem {
+              label: Cow::Borrowed("Show/hide line numbers (#)"),
+              event: Event::ToggleLineNumbers,
+            },
+            MenuItem {
+              label: Cow::Borrowed("Show more context above ({)"),
+              event: Event::ShowMoreContextAbove,
+            },
+            MenuItem {
+              label: Cow::Borrowed("Show more context below (})"),
+              event: Event::ShowMoreContextBelow,
+            },
+            MenuItem {
+              label: Cow::Borrowed("Show all context (=)"),
+              event: Event::ShowAllContext,
+            },
+            MenuItem {
+              label: Cow::Borrowed("Scroll up (ctrl-up, ctrl-y)"),
+              event: Event::ScrollUp,
             },
             MenuItem {
               label: Cow::Borrowed("Scroll down (ctrl-down, ctrl-e)"),
@@ -897,6 +2007,7 @@ impl<'state, 'input> Recorder<'state, 'input> {
           debug_info: None,
           commit_message_view: CommitMessageView {
             commit_idx: self.focused_commit_idx,
+            num_commits: commits.len(),
             commit: &commits[self.focused_commit_idx],
           },
           file_views: self.make_file_views(
@@ -913,21 +2024,137 @@ impl<'state, 'input> Recorder<'state, 'input> {
         .enumerate()
         .map(|(commit_idx, commit)| CommitView {
           debug_info: None,
-          commit_message_view: CommitMessageView { commit_idx, commit },
+          commit_message_view: CommitMessageView {
+            commit_idx,
+            num_commits: commits.len(),
+            commit,
+          },
           file_views: self.make_file_views(commit_idx, files, &debug_info, *is_read_only),
         })
         .collect(),
     };
+    let summary_view = if self.is_summary_visible {
+      Some(self.make_summary_view(files))
+    } else {
+      None
+    };
     AppView {
       debug_info: None,
       menu_bar,
+      summary_view,
+      directory_tree: self
+        .directory_tree
+        .as_ref()
+        .map(|sidebar| self.make_directory_tree_view(sidebar)),
       commit_view_mode: self.commit_view_mode,
       commit_views,
+      search_prompt: self.editing_search().map(|search| SearchPrompt {
+        query: &search.query,
+        is_backward: search.is_backward,
+        is_valid: search.query.is_empty() || search.pattern.is_some(),
+      }),
+      picker_dialog: self.picker.as_ref().map(|picker| PickerDialog { picker }),
       quit_dialog: self.quit_dialog.clone(),
       help_dialog: self.help_dialog.clone(),
     }
   }
 
+  fn make_directory_tree_view(
+    &'state self,
+    sidebar: &'state DirectoryTreeSidebar,
+  ) -> DirectoryTreeView<'state> {
+    let DirectoryTreeSidebar {
+      tree,
+      focused_idx,
+      is_focused,
+    } = sidebar;
+    let node_views = tree
+      .nodes()
+      .iter()
+      .enumerate()
+      .map(|(node_idx, node)| DirectoryNodeView {
+        node_idx,
+        node,
+        toggle_box: TristateBox {
+          use_unicode: self.use_unicode,
+          id: ComponentId::DirectoryNodeToggleBox(node_idx),
+          tristate: self.directory_node_tristate(node).unwrap(),
+          icon_style: TristateIconStyle::Check,
+          is_focused: false,
+          is_read_only: self.state.is_read_only,
+        },
+        is_focused: *is_focused && node_idx == *focused_idx,
+      })
+      .collect();
+    DirectoryTreeView {
+      use_unicode: self.use_unicode,
+      node_views,
+      focused_idx: *focused_idx,
+    }
+  }
+
+  fn make_summary_view(
+    &'state self,
+    files: &'state [File<'state>],
+  ) -> SummaryView<'state> {
+    let commit_idx = self.focused_commit_idx;
+    let file_views = files
+      .iter()
+      .enumerate()
+      .map(|(file_idx, file)| {
+        let file_key = FileKey {
+          commit_idx,
+          file_idx,
+        };
+        (
+          file_key,
+          &file.path,
+          self.file_tristate(file_key).unwrap(),
+          file.commit_diff_stat(commit_idx),
+        )
+      })
+      .collect::<Vec<_>>();
+    let path_width = file_views
+      .iter()
+      .map(|(_, path, _, _)| path.to_string_lossy().width())
+      .max()
+      .unwrap_or_default()
+      .min(SummaryFileView::MAX_PATH_WIDTH);
+    let max_changed = file_views
+      .iter()
+      .map(|(_, _, _, diff_stat)| diff_stat.total.num_changed())
+      .max()
+      .unwrap_or_default();
+    let file_views = file_views
+      .into_iter()
+      .map(|(file_key, path, tristate, diff_stat)| SummaryFileView {
+        file_key,
+        path,
+        toggle_box: TristateBox {
+          use_unicode: self.use_unicode,
+          id: ComponentId::SummaryFile(file_key),
+          tristate,
+          icon_style: TristateIconStyle::Check,
+          is_focused: false,
+          is_read_only: self.state.is_read_only,
+        },
+        diff_stat,
+        path_width,
+        count_width: max_changed.to_string().len(),
+        max_changed,
+      })
+      .collect();
+    SummaryView {
+      selected_label: if self.state.commits.len() <= 2 {
+        Cow::Borrowed("selected")
+      } else {
+        Cow::Owned(format!("in commit {}", commit_idx + 1))
+      },
+      diff_stat: self.state.commit_diff_stat(commit_idx),
+      file_views,
+    }
+  }
+
   fn make_file_views(
     &'state self,
     commit_idx: usize,
@@ -1102,6 +2329,8 @@ impl<'state, 'input> Recorder<'state, 'input> {
           is_header_selected: is_focused,
           old_path: file.old_path.as_deref(),
           path: &file.path,
+          similarity: file.similarity,
+          unloaded: file.unloaded,
           #[cfg(feature = "tree-sitter")]
           container_views,
           section_views: {
@@ -1113,7 +2342,7 @@ impl<'state, 'input> Recorder<'state, 'input> {
               .filter(|section| section.is_editable())
               .count();
 
-            let mut line_num = 1;
+            let line_numbers = file.line_numbers();
             let mut editable_section_num = 0;
             for (section_idx, section) in file.sections.iter().enumerate() {
               let section_key = SectionKey {
@@ -1193,21 +2422,22 @@ impl<'state, 'input> Recorder<'state, 'input> {
                 total_num_sections,
                 editable_section_num,
                 total_num_editable_sections,
+                is_multi_commit: self.state.commits.len() > 2,
+                moved_lines: &self.moved_lines,
+                syntax_highlights: &self.syntax_highlights,
+                search_pattern: self.search_pattern(),
+                file,
                 section,
-                line_start_num: line_num,
+                diff_layout: self.diff_layout,
+                line_numbers: line_numbers[section_idx].clone(),
+                is_line_number_gutter_visible: self.is_line_number_gutter_visible,
+                num_context_lines: self.num_context_lines,
+                context_expansion: self
+                  .expanded_context
+                  .get(&section_key)
+                  .copied()
+                  .unwrap_or_default(),
               });
-
-              line_num += match section {
-                Section::Unchanged { lines } => lines.len(),
-                Section::Changed { lines } => lines
-                  .iter()
-                  .filter(|changed_line| match changed_line.change_type {
-                    ChangeType::Added => false,
-                    ChangeType::Removed => true,
-                  })
-                  .count(),
-                Section::FileMode { .. } | Section::Binary { .. } => 0,
-              };
             }
             section_views
           },
@@ -1306,7 +2536,7 @@ impl<'state, 'input> Recorder<'state, 'input> {
       .filter(|section| section.is_editable())
       .count();
 
-    let mut line_num = 1;
+    let line_numbers = file.line_numbers();
     let mut editable_section_num = 0;
 
     let views: Vec<_> = section_indices
@@ -1320,7 +2550,7 @@ impl<'state, 'input> Recorder<'state, 'input> {
         };
         Some((section_idx, section, section_key))
       })
-      .map(|(_section_idx, section, section_key)| {
+      .map(|(section_idx, section, section_key)| {
         let section_toggled = self
           .section_tristate(section_key)
           .unwrap_or(Tristate::False);
@@ -1391,20 +2621,21 @@ impl<'state, 'input> Recorder<'state, 'input> {
           total_num_sections,
           editable_section_num,
           total_num_editable_sections,
+          is_multi_commit: self.state.commits.len() > 2,
+          moved_lines: &self.moved_lines,
+          syntax_highlights: &self.syntax_highlights,
+          search_pattern: self.search_pattern(),
+          file,
           section,
-          line_start_num: line_num,
-        };
-
-        line_num += match section {
-          Section::Unchanged { lines } => lines.len(),
-          Section::Changed { lines } => lines
-            .iter()
-            .filter(|changed_line| match changed_line.change_type {
-              ChangeType::Added => false,
-              ChangeType::Removed => true,
-            })
-            .count(),
-          Section::FileMode { .. } | Section::Binary { .. } => 0,
+          diff_layout: self.diff_layout,
+          line_numbers: line_numbers[section_idx].clone(),
+          is_line_number_gutter_visible: self.is_line_number_gutter_visible,
+          num_context_lines: self.num_context_lines,
+          context_expansion: self
+            .expanded_context
+            .get(&section_key)
+            .copied()
+            .unwrap_or_default(),
         };
 
         section_view
@@ -1426,6 +2657,54 @@ impl<'state, 'input> Recorder<'state, 'input> {
       (_, Event::Redraw) => StateUpdate::Redraw,
       (_, Event::EnsureSelectionInViewport) => StateUpdate::EnsureSelectionInViewport,
 
+      // Edit the picker's query, and choose one of its matches, while it's
+      // open.
+      (
+        None,
+        event @ (Event::TextInput(_)
+        | Event::TextBackspace
+        | Event::TextSubmit
+        | Event::TextCancel
+        | Event::FocusPrev
+        | Event::FocusNext),
+      ) if self.help_dialog.is_none() && self.picker.is_some() => {
+        self.handle_picker_event(self.picker.as_ref().unwrap(), event)
+      }
+
+      // Edit the search query while it's being typed.
+      (None, Event::TextInput(char)) => match self.editing_search() {
+        Some(search) => {
+          let mut query = search.query.clone();
+          query.push(char);
+          StateUpdate::SetSearchQuery(query)
+        }
+        None => StateUpdate::None,
+      },
+      (None, Event::TextBackspace) => {
+        match self.editing_search() {
+          // Like in Vim, deleting past the start of the query cancels the
+          // search.
+          Some(search) if search.query.is_empty() => StateUpdate::CancelSearch,
+          Some(search) => {
+            let mut query = search.query.clone();
+            query.pop();
+            StateUpdate::SetSearchQuery(query)
+          }
+          None => StateUpdate::None,
+        }
+      }
+      (None, Event::TextSubmit) => match self.editing_search() {
+        Some(_) => StateUpdate::FinishSearch,
+        None => StateUpdate::None,
+      },
+      (None, Event::TextCancel) => match self.editing_search() {
+        Some(_) => StateUpdate::CancelSearch,
+        None => StateUpdate::None,
+      },
+      (_, Event::TextInput(_) | Event::TextBackspace | Event::TextSubmit | Event::TextCancel) => {
+        StateUpdate::None
+      }
+
       (
         _,
         Event::Help
@@ -1439,6 +2718,21 @@ impl<'state, 'input> Recorder<'state, 'input> {
       }
       (_, Event::Help) => StateUpdate::SetHelpDialog(Some(HelpDialog())),
 
+      // Move between the nodes of the directory tree, and toggle them, while
+      // it's focused.
+      (
+        None,
+        event @ (Event::FocusPrev
+        | Event::FocusNext
+        | Event::FocusOuter { .. }
+        | Event::FocusInner
+        | Event::ToggleItem
+        | Event::ToggleItemAndAdvance
+        | Event::QuitEscape),
+      ) if self.is_directory_tree_focused() => {
+        self.handle_directory_tree_event(self.directory_tree.as_ref().unwrap(), event)
+      }
+
       // Confirm the changes.
       (None, Event::QuitAccept) => StateUpdate::QuitAccept,
       // Ignore the confirm action if the quit dialog is open.
@@ -1504,7 +2798,25 @@ impl<'state, 'input> Recorder<'state, 'input> {
         | Event::ToggleAllUniform
         | Event::ExpandItem
         | Event::ExpandAll
-        | Event::EditCommitMessage,
+        | Event::SplitSection
+        | Event::MergeSections
+        | Event::ToggleSummary
+        | Event::ToggleDirectoryTree
+        | Event::ToggleDiffLayout
+        | Event::ToggleLineNumbers
+        | Event::ShowMoreContextAbove
+        | Event::ShowMoreContextBelow
+        | Event::ShowAllContext
+        | Event::StartSearch { .. }
+        | Event::SearchNext
+        | Event::SearchPrev
+        | Event::OpenPicker
+        | Event::EditCommitMessage
+        | Event::FocusPrevCommit
+        | Event::FocusNextCommit
+        | Event::AssignToCommit(_)
+        | Event::Undo
+        | Event::Redo,
       ) => StateUpdate::None,
 
       (Some(_) | None, Event::TakeScreenshot(screenshot)) => {
@@ -1620,9 +2932,64 @@ impl<'state, 'input> Recorder<'state, 'input> {
       (None, Event::ToggleAllUniform) => StateUpdate::ToggleAllUniform,
       (None, Event::ExpandItem) => StateUpdate::ToggleExpandItem(self.selection_key),
       (None, Event::ExpandAll) => StateUpdate::ToggleExpandAll,
+      (None, Event::SplitSection) => StateUpdate::SplitSection(self.selection_key),
+      (None, Event::MergeSections) => StateUpdate::MergeSections(self.selection_key),
+      (None, Event::ToggleSummary) => StateUpdate::ToggleSummary,
+      (None, Event::ToggleDirectoryTree) => StateUpdate::ToggleDirectoryTree,
+      (None, Event::ToggleDiffLayout) => StateUpdate::ToggleDiffLayout,
+      (None, Event::ToggleLineNumbers) => StateUpdate::ToggleLineNumbers,
+      (None, Event::ShowMoreContextAbove) => {
+        let (gap_above, _gap_below) = self.context_gaps(self.selection_key);
+        StateUpdate::ExpandContext(gap_above.into_iter().collect(), ContextAction::MoreUp)
+      }
+      (None, Event::ShowMoreContextBelow) => {
+        let (_gap_above, gap_below) = self.context_gaps(self.selection_key);
+        StateUpdate::ExpandContext(gap_below.into_iter().collect(), ContextAction::MoreDown)
+      }
+      (None, Event::ShowAllContext) => {
+        let (gap_above, gap_below) = self.context_gaps(self.selection_key);
+        StateUpdate::ExpandContext(
+          gap_above.into_iter().chain(gap_below).collect(),
+          ContextAction::All,
+        )
+      }
+      (None, Event::StartSearch { is_backward }) => StateUpdate::StartSearch { is_backward },
+      (None, Event::SearchNext) => self.select_next_search_match(/*is_reversed=*/ false)?,
+      (None, Event::SearchPrev) => self.select_next_search_match(/*is_reversed=*/ true)?,
+      (None, Event::OpenPicker) => StateUpdate::OpenPicker,
       (None, Event::EditCommitMessage) => StateUpdate::EditCommitMessage {
         commit_idx: self.focused_commit_idx,
       },
+      // Moving between commits only makes sense when splitting into more than
+      // two commits; otherwise, toggling an item already moves it between the
+      // two commits.
+      (None, Event::FocusPrevCommit | Event::FocusNextCommit | Event::AssignToCommit(_))
+        if self.state.commits.len() <= 2 =>
+      {
+        StateUpdate::None
+      }
+      (None, Event::FocusPrevCommit) => match self.focused_commit_idx.checked_sub(1) {
+        Some(commit_idx) => StateUpdate::FocusCommit(commit_idx),
+        None => StateUpdate::None,
+      },
+      (None, Event::FocusNextCommit) => {
+        let commit_idx = self.focused_commit_idx + 1;
+        if commit_idx < self.state.commits.len() {
+          StateUpdate::FocusCommit(commit_idx)
+        } else {
+          StateUpdate::None
+        }
+      }
+      (None, Event::AssignToCommit(commit_idx)) => {
+        if commit_idx < self.state.commits.len() {
+          StateUpdate::AssignToCommit(self.selection_key, commit_idx)
+        } else {
+          StateUpdate::None
+        }
+      }
+
+      (None, Event::Undo) => StateUpdate::Undo,
+      (None, Event::Redo) => StateUpdate::Redo,
 
       (_, Event::Click { row, column }) => {
         let component_id = self.find_component_at(drawn_rects, row, column);
@@ -1690,8 +3057,9 @@ impl<'state, 'input> Recorder<'state, 'input> {
   fn all_selection_keys(&self) -> Vec<SelectionKey> {
     let mut result = Vec::new();
     for (commit_idx, _) in self.state.commits.iter().enumerate() {
-      if commit_idx > 0 {
-        // TODO: implement adjacent `CommitView s.
+      if commit_idx != self.focused_commit_idx {
+        // TODO: implement navigation across adjacent `CommitView`s. For now,
+        // only the focused commit can be navigated.
         continue;
       }
       for (file_idx, file) in self.state.files.iter().enumerate() {
@@ -1846,7 +3214,7 @@ impl<'state, 'input> Recorder<'state, 'input> {
     section: &Section,
   ) {
     match section {
-      Section::Unchanged { .. } => {}
+      Section::Unchanged { .. } | Section::Omitted { .. } => {}
       Section::Changed { lines } => {
         result.push(SelectionKey::Section(SectionKey {
           commit_idx,
@@ -1862,7 +3230,11 @@ impl<'state, 'input> Recorder<'state, 'input> {
           }));
         }
       }
-      Section::FileMode { .. } | Section::Binary { .. } => {
+      Section::FileMode { .. }
+      | Section::Rename { .. }
+      | Section::Binary { .. }
+      | Section::Symlink { .. }
+      | Section::Submodule { .. } => {
         result.push(SelectionKey::Section(SectionKey {
           commit_idx,
           file_idx,
@@ -2345,16 +3717,14 @@ impl<'state, 'input> Recorder<'state, 'input> {
       | SelectionKey::Section(_)
       | SelectionKey::Line(_) => ComponentId::SelectableItem(selection_key),
     };
-    match drawn_rects.get(&id) {
-      Some(DrawnRect { rect, timestamp: _ }) => Some(*rect),
-      None => {
-        // This can happen when navigating to a component that hasn't been drawn yet
-        // (e.g., it's off-screen or just became visible after a collapse/expand).
-        // This is a normal case when jumping large distances in the hierarchy.
-        // The component will be rendered and scrolled into view in the next frame.
-        None
-      }
-    }
+    // The rect may be missing when navigating to a component that hasn't been
+    // drawn yet (e.g., it's off-screen or just became visible after a
+    // collapse/expand). This is a normal case when jumping large distances in
+    // the hierarchy. The component will be rendered and scrolled into view in
+    // the next frame.
+    drawn_rects
+      .get(&id)
+      .map(|DrawnRect { rect, timestamp: _ }| *rect)
   }
 
   fn ensure_in_viewport(
@@ -2412,6 +3782,47 @@ impl<'state, 'input> Recorder<'state, 'input> {
     Some(result)
   }
 
+  /// The height of the parts of the app which scroll, not counting the
+  /// components which stay in place on the screen, such as the menu bar and
+  /// the directory tree.
+  fn scrollable_height(drawn_rects: &DrawnRects<ComponentId>) -> isize {
+    drawn_rects
+      .iter()
+      .filter(|(id, _)| match id {
+        ComponentId::App
+        | ComponentId::MenuBar
+        | ComponentId::MenuHeader
+        | ComponentId::Menu(_)
+        | ComponentId::MenuItem(_)
+        | ComponentId::SearchPrompt
+        | ComponentId::PickerDialog
+        | ComponentId::DirectoryTree
+        | ComponentId::DirectoryNode(_)
+        | ComponentId::DirectoryNodeToggleBox(_)
+        | ComponentId::QuitDialog
+        | ComponentId::QuitDialogButton(_)
+        | ComponentId::HelpDialog
+        | ComponentId::HelpDialogQuitButton => false,
+        ComponentId::AppFiles
+        | ComponentId::CommitMessageView
+        | ComponentId::CommitEditMessageButton(_)
+        | ComponentId::Summary
+        | ComponentId::SummaryFile(_)
+        | ComponentId::FileViewHeader(_)
+        | ComponentId::SelectableItem(_)
+        | ComponentId::ToggleBox(_)
+        | ComponentId::ExpandBox(_)
+        | ComponentId::ContextGap(_)
+        | ComponentId::ContextButton(_, _) => true,
+      })
+      .map(|(_, drawn_rect)| {
+        let DrawnRect { rect, timestamp: _ } = drawn_rect;
+        *rect
+      })
+      .reduce(Rect::union_bounding)
+      .map_or(1, |rect| rect.height.unwrap_isize().max(1))
+  }
+
   fn find_component_at(
     &self,
     drawn_rects: &DrawnRects<ComponentId>,
@@ -2429,15 +3840,24 @@ impl<'state, 'input> Recorder<'state, 'input> {
             ComponentId::App
             | ComponentId::AppFiles
             | ComponentId::MenuHeader
-            | ComponentId::CommitMessageView => false,
+            | ComponentId::CommitMessageView
+            | ComponentId::Summary
+            | ComponentId::DirectoryTree => false,
             ComponentId::MenuBar
             | ComponentId::MenuItem(_)
             | ComponentId::Menu(_)
             | ComponentId::CommitEditMessageButton(_)
+            | ComponentId::SummaryFile(_)
+            | ComponentId::SearchPrompt
+            | ComponentId::PickerDialog
+            | ComponentId::DirectoryNode(_)
+            | ComponentId::DirectoryNodeToggleBox(_)
             | ComponentId::FileViewHeader(_)
             | ComponentId::SelectableItem(_)
             | ComponentId::ToggleBox(_)
             | ComponentId::ExpandBox(_)
+            | ComponentId::ContextGap(_)
+            | ComponentId::ContextButton(_, _)
             | ComponentId::HelpDialog
             | ComponentId::HelpDialogQuitButton
             | ComponentId::QuitDialog
@@ -2462,6 +3882,10 @@ impl<'state, 'input> Recorder<'state, 'input> {
       | ComponentId::AppFiles
       | ComponentId::MenuHeader
       | ComponentId::CommitMessageView
+      | ComponentId::Summary
+      | ComponentId::SearchPrompt
+      | ComponentId::PickerDialog
+      | ComponentId::DirectoryTree
       | ComponentId::QuitDialog => StateUpdate::None,
       ComponentId::MenuBar => StateUpdate::UnfocusMenuBar,
       ComponentId::Menu(section_idx) => StateUpdate::ClickMenu {
@@ -2477,6 +3901,15 @@ impl<'state, 'input> Recorder<'state, 'input> {
         selection_key: SelectionKey::File(file_key),
         ensure_in_viewport: false,
       },
+      ComponentId::SummaryFile(file_key) => StateUpdate::SelectItem {
+        selection_key: SelectionKey::File(file_key),
+        ensure_in_viewport: true,
+      },
+      ComponentId::DirectoryNode(node_idx) => match &self.directory_tree {
+        Some(sidebar) => self.focus_directory_node(&sidebar.tree, node_idx),
+        None => StateUpdate::None,
+      },
+      ComponentId::DirectoryNodeToggleBox(node_idx) => StateUpdate::ToggleDirectoryNode(node_idx),
       ComponentId::SelectableItem(selection_key) => StateUpdate::SelectItem {
         selection_key,
         ensure_in_viewport: false,
@@ -2501,6 +3934,12 @@ impl<'state, 'input> Recorder<'state, 'input> {
           }
         }
       }
+      ComponentId::ContextGap(section_key) => {
+        StateUpdate::ExpandContext(vec![section_key], ContextAction::All)
+      }
+      ComponentId::ContextButton(section_key, context_action) => {
+        StateUpdate::ExpandContext(vec![section_key], context_action)
+      }
       ComponentId::QuitDialogButton(QuitDialogButtonId::GoBack) => StateUpdate::SetQuitDialog(None),
       ComponentId::QuitDialogButton(QuitDialogButtonId::Quit) => StateUpdate::QuitCancel,
       ComponentId::HelpDialog => StateUpdate::None,
@@ -2552,7 +3991,11 @@ impl<'state, 'input> Recorder<'state, 'input> {
     if self.state.is_read_only {
       return Ok(());
     }
+    if self.state.commits.len() > 2 {
+      return self.toggle_item_in_commit(selection);
+    }
 
+    let changes = self.selection_changes(selection)?;
     let side_effects = match selection {
       SelectionKey::None => None,
       SelectionKey::File(file_key) => {
@@ -2721,106 +4164,355 @@ impl<'state, 'input> Recorder<'state, 'input> {
         }
         ToggleSideEffects::ToggledChangedSection(section_key, toggled_to) => {
           self.visit_file_for_section(section_key, |file| {
-            for section in &mut file.sections {
-              if let Section::FileMode { mode, is_checked } = section {
-                // If we removed a line and the file was being deleted, it can no longer
-                // be deleted as it needs to contain that line
-                if !toggled_to && *mode == FileMode::Absent {
-                  *is_checked = false;
-                }
-
-                // If we added a line and the file was not being created, it must be created
-                // in order to contain that line
-                if toggled_to && file.file_mode == FileMode::Absent {
-                  *is_checked = true;
-                }
-              }
-            }
+            Self::update_file_mode_for_changed_line(file, toggled_to);
           })?;
         }
         ToggleSideEffects::ToggledChangedLine(line_key, toggled_to) => {
           self.visit_file_for_line(line_key, |file| {
-            for section in &mut file.sections {
-              if let Section::FileMode { mode, is_checked } = section {
-                // If we removed a line and the file was being deleted, it can no longer
-                // be deleted as it needs to contain that line
-                if !toggled_to && *mode == FileMode::Absent {
-                  *is_checked = false;
-                }
-
-                // If we added a line and the file was not being created, it must be created
-                // in order to contain that line
-                if toggled_to && file.file_mode == FileMode::Absent {
-                  *is_checked = true;
-                }
-              }
-            }
+            Self::update_file_mode_for_changed_line(file, toggled_to);
           })?;
         }
       }
     };
 
+    self.toggle_moved_partners(&changes)?;
     Ok(())
   }
 
-  fn toggle_all(&mut self) {
-    if self.state.is_read_only {
-      return;
-    }
+  /// Check or uncheck the file mode section of `file` after one of its changed
+  /// lines was toggled to `toggled_to`, so that the file can contain it.
+  fn update_file_mode_for_changed_line(
+    file: &mut File,
+    toggled_to: bool,
+  ) {
+    for section in &mut file.sections {
+      if let Section::FileMode { mode, is_checked } = section {
+        // If we removed a line and the file was being deleted, it can no longer
+        // be deleted as it needs to contain that line
+        if !toggled_to && *mode == FileMode::Absent {
+          *is_checked = false;
+        }
 
-    for file in &mut self.state.files {
-      file.toggle_all();
+        // If we added a line and the file was not being created, it must be created
+        // in order to contain that line
+        if toggled_to && file.file_mode == FileMode::Absent {
+          *is_checked = true;
+        }
+      }
     }
   }
 
-  fn toggle_all_uniform(&mut self) {
-    if self.state.is_read_only {
-      return;
+  /// Give the lines which were moved to or from the lines in `changes` the
+  /// same checked state, so that the moved code is neither deleted nor
+  /// duplicated (see [`MovedLines`]).
+  fn toggle_moved_partners(
+    &mut self,
+    changes: &[(usize, usize, usize)],
+  ) -> Result<(), RecordError> {
+    for &(file_idx, section_idx, line_idx) in changes {
+      let LineLocation {
+        file_idx: partner_file_idx,
+        section_idx: partner_section_idx,
+        line_idx: partner_line_idx,
+      } = match self.moved_lines.partner(LineLocation {
+        file_idx,
+        section_idx,
+        line_idx,
+      }) {
+        Some(partner) => partner,
+        None => continue,
+      };
+      let line_key = LineKey {
+        commit_idx: self.focused_commit_idx,
+        file_idx,
+        section_idx,
+        line_idx,
+      };
+      let partner_key = LineKey {
+        commit_idx: self.focused_commit_idx,
+        file_idx: partner_file_idx,
+        section_idx: partner_section_idx,
+        line_idx: partner_line_idx,
+      };
+      if let Some(toggled_to) = self.visit_line(line_key, |line| Some(line.is_checked))? {
+        self.visit_line(partner_key, |line| {
+          line.is_checked = toggled_to;
+          Some(())
+        })?;
+        self.visit_file_for_line(partner_key, |file| {
+          Self::update_file_mode_for_changed_line(file, toggled_to);
+        })?;
+      }
     }
+    Ok(())
+  }
 
-    let checked = {
-      let tristate = self
-        .state
-        .files
-        .iter()
-        .map(|file| file.tristate())
-        .fold(None, |acc, elem| match (acc, elem) {
-          (None, tristate) => Some(tristate),
-          (Some(acc_tristate), tristate) if acc_tristate == tristate => Some(tristate),
-          _ => Some(Tristate::Partial),
+  /// Toggle whether the changes for the given item belong to the commit that
+  /// the item is being viewed in, when there are more than two commits.
+  ///
+  /// If none of the item's changes belong to the commit, then all of them are
+  /// moved to it. Otherwise, they are all moved out of it (see
+  /// [`Recorder::complement_commit_idx`]).
+  fn toggle_item_in_commit(
+    &mut self,
+    selection: SelectionKey,
+  ) -> Result<(), RecordError> {
+    let commit_idx = match selection.commit_idx() {
+      Some(commit_idx) => commit_idx,
+      None => return Ok(()),
+    };
+    let mut changes = self.selection_changes(selection)?;
+    // Moved lines are assigned to the same commit as the lines they were moved
+    // from or to (see [`MovedLines`]).
+    let partners: Vec<_> = changes
+      .iter()
+      .filter_map(|&(file_idx, section_idx, line_idx)| {
+        self.moved_lines.partner(LineLocation {
+          file_idx,
+          section_idx,
+          line_idx,
         })
-        .unwrap_or(Tristate::False);
-      match tristate {
-        Tristate::False | Tristate::Partial => true,
-        Tristate::True => false,
-      }
+      })
+      .map(
+        |LineLocation {
+           file_idx,
+           section_idx,
+           line_idx,
+         }| (file_idx, section_idx, line_idx),
+      )
+      .collect();
+    let any_in_commit = changes.iter().any(|&(file_idx, section_idx, line_idx)| {
+      self.state.files[file_idx].commit_idx(section_idx, line_idx) == commit_idx
+    });
+    let commit_idx_new = if any_in_commit {
+      self.complement_commit_idx(commit_idx)
+    } else {
+      commit_idx
     };
-    for file in &mut self.state.files {
-      file.set_checked(checked);
+    changes.extend(partners);
+    for (file_idx, section_idx, line_idx) in changes {
+      self.state.files[file_idx].set_commit_idx(section_idx, line_idx, commit_idx_new);
     }
+    Ok(())
   }
 
-  fn expand_item_ancestors(
+  /// The commit that changes are moved to when they're removed from the commit
+  /// at `commit_idx`. Like unchecked changes which were never assigned to a
+  /// commit (see [`File::commit_idx`]), this is the second commit, or the first
+  /// commit when removing changes from the second commit.
+  fn complement_commit_idx(
+    &self,
+    commit_idx: usize,
+  ) -> usize {
+    if commit_idx == 1 {
+      0
+    } else {
+      1
+    }
+  }
+
+  fn assign_item(
     &mut self,
     selection: SelectionKey,
-  ) {
-    match selection {
-      SelectionKey::None | SelectionKey::File(_) => {}
-      SelectionKey::Container(ContainerKey {
-        commit_idx,
-        file_idx,
-        container_idx: _,
-      }) => {
-        self.expanded_items.insert(SelectionKey::File(FileKey {
+    commit_idx: usize,
+  ) -> Result<(), RecordError> {
+    if self.state.is_read_only {
+      return Ok(());
+    }
+
+    for (file_idx, section_idx, line_idx) in self.selection_changes(selection)? {
+      self.state.files[file_idx].set_commit_idx(section_idx, line_idx, commit_idx);
+    }
+    Ok(())
+  }
+
+  /// Get the `(file_idx, section_idx, line_idx)` of each change contained in
+  /// the given item.
+  fn selection_changes(
+    &self,
+    selection: SelectionKey,
+  ) -> Result<Vec<(usize, usize, usize)>, RecordError> {
+    let (file_key, section_indices): (FileKey, Option<Vec<usize>>) = match selection {
+      SelectionKey::None => return Ok(Vec::new()),
+      SelectionKey::File(file_key) => (file_key, None),
+      SelectionKey::Container(container_key) => {
+        let ContainerKey {
           commit_idx,
           file_idx,
-        }));
-      }
-      SelectionKey::Member(MemberKey {
-        commit_idx,
-        file_idx,
-        container_idx,
-        member_idx: _,
+          container_idx: _,
+        } = container_key;
+        #[cfg(feature = "tree-sitter")]
+        let section_indices = self.container(container_key)?.section_indices();
+        #[cfg(not(feature = "tree-sitter"))]
+        let section_indices = Vec::new();
+        (
+          FileKey {
+            commit_idx,
+            file_idx,
+          },
+          Some(section_indices),
+        )
+      }
+      SelectionKey::Member(member_key) => {
+        let MemberKey {
+          commit_idx,
+          file_idx,
+          container_idx: _,
+          member_idx: _,
+        } = member_key;
+        #[cfg(feature = "tree-sitter")]
+        let section_indices = self.member(member_key)?.section_indices();
+        #[cfg(not(feature = "tree-sitter"))]
+        let section_indices = Vec::new();
+        (
+          FileKey {
+            commit_idx,
+            file_idx,
+          },
+          Some(section_indices),
+        )
+      }
+      SelectionKey::Section(SectionKey {
+        commit_idx,
+        file_idx,
+        section_idx,
+      }) => (
+        FileKey {
+          commit_idx,
+          file_idx,
+        },
+        Some(vec![section_idx]),
+      ),
+      SelectionKey::Line(LineKey {
+        commit_idx: _,
+        file_idx,
+        section_idx,
+        line_idx,
+      }) => return Ok(vec![(file_idx, section_idx, line_idx)]),
+    };
+
+    let file = self.file(file_key)?;
+    Ok(
+      file
+        .change_keys()
+        .filter(|(section_idx, _line_idx)| match &section_indices {
+          Some(section_indices) => section_indices.contains(section_idx),
+          None => true,
+        })
+        .map(|(section_idx, line_idx)| (file_key.file_idx, section_idx, line_idx))
+        .collect(),
+    )
+  }
+
+  fn focus_commit(
+    &mut self,
+    commit_idx: usize,
+  ) {
+    self.expanded_items = self
+      .expanded_items
+      .iter()
+      .map(|key| key.with_commit_idx(commit_idx))
+      .collect();
+    self.selection_key = self.selection_key.with_commit_idx(commit_idx);
+    self.focused_commit_idx = commit_idx;
+  }
+
+  fn toggle_all(&mut self) {
+    if self.state.is_read_only {
+      return;
+    }
+
+    if self.state.commits.len() > 2 {
+      let commit_idx = self.focused_commit_idx;
+      let complement_commit_idx = self.complement_commit_idx(commit_idx);
+      for file in &mut self.state.files {
+        let changes: Vec<_> = file.change_keys().collect();
+        for (section_idx, line_idx) in changes {
+          let commit_idx_new = if file.commit_idx(section_idx, line_idx) == commit_idx {
+            complement_commit_idx
+          } else {
+            commit_idx
+          };
+          file.set_commit_idx(section_idx, line_idx, commit_idx_new);
+        }
+      }
+      return;
+    }
+
+    for file in &mut self.state.files {
+      file.toggle_all();
+    }
+  }
+
+  fn toggle_all_uniform(&mut self) {
+    if self.state.is_read_only {
+      return;
+    }
+
+    if self.state.commits.len() > 2 {
+      let commit_idx = self.focused_commit_idx;
+      let all_in_commit = self.state.files.iter().all(|file| {
+        file
+          .change_keys()
+          .all(|(section_idx, line_idx)| file.commit_idx(section_idx, line_idx) == commit_idx)
+      });
+      let commit_idx_new = if all_in_commit {
+        self.complement_commit_idx(commit_idx)
+      } else {
+        commit_idx
+      };
+      for file in &mut self.state.files {
+        let changes: Vec<_> = file.change_keys().collect();
+        for (section_idx, line_idx) in changes {
+          file.set_commit_idx(section_idx, line_idx, commit_idx_new);
+        }
+      }
+      return;
+    }
+
+    let checked = {
+      let tristate = self
+        .state
+        .files
+        .iter()
+        .map(|file| file.tristate())
+        .fold(None, |acc, elem| match (acc, elem) {
+          (None, tristate) => Some(tristate),
+          (Some(acc_tristate), tristate) if acc_tristate == tristate => Some(tristate),
+          _ => Some(Tristate::Partial),
+        })
+        .unwrap_or(Tristate::False);
+      match tristate {
+        Tristate::False | Tristate::Partial => true,
+        Tristate::True => false,
+      }
+    };
+    for file in &mut self.state.files {
+      file.set_checked(checked);
+    }
+  }
+
+  fn expand_item_ancestors(
+    &mut self,
+    selection: SelectionKey,
+  ) {
+    match selection {
+      SelectionKey::None | SelectionKey::File(_) => {}
+      SelectionKey::Container(ContainerKey {
+        commit_idx,
+        file_idx,
+        container_idx: _,
+      }) => {
+        self.expanded_items.insert(SelectionKey::File(FileKey {
+          commit_idx,
+          file_idx,
+        }));
+      }
+      SelectionKey::Member(MemberKey {
+        commit_idx,
+        file_idx,
+        container_idx,
+        member_idx: _,
       }) => {
         self.expanded_items.insert(SelectionKey::File(FileKey {
           commit_idx,
@@ -3040,6 +4732,78 @@ impl<'state, 'input> Recorder<'state, 'input> {
       .collect();
   }
 
+  /// The unchanged sections directly above and below the section containing
+  /// `selection_key`, whose hidden lines can be shown with
+  /// [`Self::expand_context`].
+  fn context_gaps(
+    &self,
+    selection_key: SelectionKey,
+  ) -> (Option<SectionKey>, Option<SectionKey>) {
+    let section_key = match selection_key {
+      SelectionKey::None
+      | SelectionKey::File(_)
+      | SelectionKey::Container(_)
+      | SelectionKey::Member(_) => return (None, None),
+      SelectionKey::Section(section_key) => section_key,
+      SelectionKey::Line(LineKey {
+        commit_idx,
+        file_idx,
+        section_idx,
+        line_idx: _,
+      }) => SectionKey {
+        commit_idx,
+        file_idx,
+        section_idx,
+      },
+    };
+    let gap = |section_idx: Option<usize>| {
+      let section_key = SectionKey {
+        section_idx: section_idx?,
+        ..section_key
+      };
+      match self.section(section_key).ok()? {
+        Section::Unchanged { lines } if !lines.is_empty() => Some(section_key),
+        Section::Unchanged { .. }
+        | Section::Changed { .. }
+        | Section::Omitted { .. }
+        | Section::FileMode { .. }
+        | Section::Rename { .. }
+        | Section::Binary { .. }
+        | Section::Symlink { .. }
+        | Section::Submodule { .. } => None,
+      }
+    };
+    (
+      gap(section_key.section_idx.checked_sub(1)),
+      gap(section_key.section_idx.checked_add(1)),
+    )
+  }
+
+  fn expand_context(
+    &mut self,
+    section_keys: &[SectionKey],
+    context_action: ContextAction,
+  ) {
+    for section_key in section_keys {
+      let ContextExpansion {
+        num_start_lines,
+        num_end_lines,
+      } = self.expanded_context.entry(*section_key).or_default();
+      match context_action {
+        ContextAction::MoreDown => {
+          *num_start_lines = num_start_lines.saturating_add(NUM_CONTEXT_LINES_PER_EXPANSION);
+        }
+        ContextAction::MoreUp => {
+          *num_end_lines = num_end_lines.saturating_add(NUM_CONTEXT_LINES_PER_EXPANSION);
+        }
+        ContextAction::All => {
+          *num_start_lines = usize::MAX;
+          *num_end_lines = usize::MAX;
+        }
+      }
+    }
+  }
+
   fn toggle_expand_all(&mut self) -> Result<(), RecordError> {
     let all_selection_keys: HashSet<_> = self.all_selection_keys().into_iter().collect();
     self.expanded_items = if self.expanded_items == all_selection_keys {
@@ -3095,82 +4859,1064 @@ impl<'state, 'input> Recorder<'state, 'input> {
     };
   }
 
-  fn click_menu_item(
+  /// Load the files which `state_update` will select or modify, if they
+  /// haven't been loaded yet.
+  fn load_files_for_update(
     &mut self,
-    event: Event,
-  ) {
-    self.expanded_menu_idx = None;
-    self.pending_events.push(event);
+    state_update: &StateUpdate,
+  ) -> Result<(), RecordError> {
+    match state_update {
+      StateUpdate::SelectItem {
+        selection_key,
+        ensure_in_viewport: _,
+      }
+      | StateUpdate::ToggleItem(selection_key)
+      | StateUpdate::SetExpandItem(selection_key, _)
+      | StateUpdate::ToggleExpandItem(selection_key)
+      | StateUpdate::SplitSection(selection_key)
+      | StateUpdate::MergeSections(selection_key)
+      | StateUpdate::ChoosePickerItem(selection_key)
+      | StateUpdate::FocusDirectoryNode {
+        node_idx: _,
+        selection_key,
+      }
+      | StateUpdate::AssignToCommit(selection_key, _) => self.load_file_for_key(*selection_key),
+      StateUpdate::ToggleDirectoryNode(node_idx) => self.load_directory_node(*node_idx),
+      StateUpdate::ToggleItemAndAdvance(selection_key, new_key) => {
+        self.load_file_for_key(*selection_key)?;
+        self.load_file_for_key(*new_key)
+      }
+      // The search focuses matches while the query is typed without loading
+      // them, so load the final match.
+      StateUpdate::FinishSearch => self.load_file_for_key(self.selection_key),
+      StateUpdate::ToggleAll | StateUpdate::ToggleAllUniform | StateUpdate::ToggleExpandAll => {
+        self.load_all_files()
+      }
+      StateUpdate::QuitAccept => {
+        if self.help_dialog.is_none() {
+          self.load_all_files()?;
+        }
+        Ok(())
+      }
+      StateUpdate::None
+      | StateUpdate::SetQuitDialog(_)
+      | StateUpdate::QuitCancel
+      | StateUpdate::SetHelpDialog(_)
+      | StateUpdate::TakeScreenshot(_)
+      | StateUpdate::Redraw
+      | StateUpdate::EnsureSelectionInViewport
+      | StateUpdate::ScrollTo(_)
+      | StateUpdate::ToggleSummary
+      | StateUpdate::ToggleDirectoryTree
+      | StateUpdate::UnfocusDirectoryTree
+      | StateUpdate::ToggleDiffLayout
+      | StateUpdate::ToggleLineNumbers
+      | StateUpdate::ExpandContext(_, _)
+      | StateUpdate::StartSearch { .. }
+      | StateUpdate::SetSearchQuery(_)
+      | StateUpdate::CancelSearch
+      | StateUpdate::OpenPicker
+      | StateUpdate::SetPickerQuery(_)
+      | StateUpdate::SetPickerSelection(_)
+      | StateUpdate::ClosePicker
+      | StateUpdate::UnfocusMenuBar
+      | StateUpdate::ClickMenu { .. }
+      | StateUpdate::ClickMenuItem(_)
+      | StateUpdate::ToggleCommitViewMode
+      | StateUpdate::EditCommitMessage { .. }
+      | StateUpdate::FocusCommit(_)
+      | StateUpdate::Undo
+      | StateUpdate::Redo => Ok(()),
+    }
   }
 
-  fn edit_commit_message(
+  fn load_all_files(&mut self) -> Result<(), RecordError> {
+    self.load_files(0..self.state.files.len())
+  }
+
+  fn load_file_for_key(
     &mut self,
-    commit_idx: usize,
+    selection_key: SelectionKey,
   ) -> Result<(), RecordError> {
-    let message = &mut self.state.commits[commit_idx].message;
-    let message_str = match message.as_ref() {
-      Some(message) => message,
+    let file_idx = match selection_key {
+      SelectionKey::None => return Ok(()),
+      SelectionKey::File(FileKey {
+        commit_idx: _,
+        file_idx,
+      })
+      | SelectionKey::Container(ContainerKey {
+        commit_idx: _,
+        file_idx,
+        container_idx: _,
+      })
+      | SelectionKey::Member(MemberKey {
+        commit_idx: _,
+        file_idx,
+        container_idx: _,
+        member_idx: _,
+      })
+      | SelectionKey::Section(SectionKey {
+        commit_idx: _,
+        file_idx,
+        section_idx: _,
+      })
+      | SelectionKey::Line(LineKey {
+        commit_idx: _,
+        file_idx,
+        section_idx: _,
+        line_idx: _,
+      }) => file_idx,
+    };
+    self.load_files([file_idx])
+  }
+
+  /// Load the sections of the files at `file_idxs` from the section provider,
+  /// if they haven't been loaded yet. The moved lines and selection keys are
+  /// only recalculated once for the whole batch.
+  fn load_files(
+    &mut self,
+    file_idxs: impl IntoIterator<Item = usize>,
+  ) -> Result<(), RecordError> {
+    let section_provider = match self.section_provider.as_deref_mut() {
+      Some(section_provider) => section_provider,
       None => return Ok(()),
     };
-    let new_message = {
-      match self.input.terminal_kind() {
-        TerminalKind::Testing { .. } => {}
-        TerminalKind::Crossterm => {
-          Self::clean_up_crossterm()?;
+    let mut loaded_file_idxs = HashSet::new();
+    let mut result = Ok(());
+    for file_idx in file_idxs {
+      let file = match self.state.files.get_mut(file_idx) {
+        Some(file) if file.unloaded.is_some() => file,
+        Some(_) | None => continue,
+      };
+      match section_provider.load_file(file) {
+        Ok(loaded_file) => {
+          *file = File {
+            unloaded: None,
+            ..loaded_file
+          };
+          loaded_file_idxs.insert(file_idx);
         }
-      }
-      let result = self.input.edit_commit_message(message_str);
-      match self.input.terminal_kind() {
-        TerminalKind::Testing { .. } => {}
-        TerminalKind::Crossterm => {
-          Self::set_up_crossterm()?;
+        Err(err) => {
+          // Still finish loading the files loaded so far.
+          result = Err(err);
+          break;
         }
       }
-      result?
-    };
-    *message = Some(new_message);
-    Ok(())
-  }
+    }
+    if loaded_file_idxs.is_empty() {
+      return result;
+    }
 
-  fn file(
-    &self,
-    file_key: FileKey,
-  ) -> Result<&File<'_>, RecordError> {
-    let FileKey {
-      commit_idx: _,
-      file_idx,
-    } = file_key;
-    match self.state.files.get(file_idx) {
-      Some(file) => Ok(file),
-      None => Err(RecordError::Bug(format!(
-        "Out-of-bounds file key: {file_key:?}"
-      ))),
+    self.moved_lines = MovedLines::detect(&self.state.files);
+    for file_idx in &loaded_file_idxs {
+      self
+        .syntax_highlights
+        .update_file(&self.state.files, *file_idx);
     }
-  }
 
-  fn section(
-    &self,
-    section_key: SectionKey,
-  ) -> Result<&Section<'_>, RecordError> {
-    let SectionKey {
-      commit_idx,
-      file_idx,
-      section_idx,
-    } = section_key;
-    let file = self.file(FileKey {
-      commit_idx,
-      file_idx,
-    })?;
-    match file.sections.get(section_idx) {
-      Some(section) => Ok(section),
-      None => Err(RecordError::Bug(format!(
-        "Out-of-bounds section key: {section_key:?}"
-      ))),
+    // Expand the new sections, as in `expand_initial_items`. Older undo
+    // entries didn't know about them, so they're expanded there too.
+    let section_keys: Vec<_> = self
+      .all_selection_keys()
+      .into_iter()
+      .filter(|selection_key| match selection_key {
+        SelectionKey::Section(section_key) => loaded_file_idxs.contains(&section_key.file_idx),
+        SelectionKey::None
+        | SelectionKey::File(_)
+        | SelectionKey::Container(_)
+        | SelectionKey::Member(_)
+        | SelectionKey::Line(_) => false,
+      })
+      .collect();
+    for undo_entry in self.undo_stack.iter_mut().chain(&mut self.redo_stack) {
+      undo_entry
+        .snapshot
+        .expanded_items
+        .extend(section_keys.iter().copied());
     }
+    self.expanded_items.extend(section_keys);
+    result
   }
 
-  fn visit_file_for_section<T>(
-    &mut self,
+  fn make_undo_entry(&self) -> UndoEntry {
+    let RecordState {
+      is_read_only: _,
+      commits,
+      files,
+    } = &self.state;
+    let checked = files
+      .iter()
+      .map(|file| {
+        file
+          .sections
+          .iter()
+          .map(|section| UndoSnapshot::section_checked(section).collect())
+          .collect()
+      })
+      .collect();
+    UndoEntry {
+      snapshot: UndoSnapshot {
+        checked,
+        commit_assignments: files
+          .iter()
+          .map(|file| file.commit_assignments.clone())
+          .collect(),
+        commit_messages: commits
+          .iter()
+          .map(|commit| commit.message.clone())
+          .collect(),
+        expanded_items: self.expanded_items.clone(),
+        expanded_context: self.expanded_context.clone(),
+      },
+      selection_key: self.selection_key,
+      focused_commit_idx: self.focused_commit_idx,
+    }
+  }
+
+  fn restore_undo_entry(
+    &mut self,
+    undo_entry: UndoEntry,
+  ) {
+    let UndoEntry {
+      snapshot:
+        UndoSnapshot {
+          checked,
+          commit_assignments,
+          commit_messages,
+          expanded_items,
+          expanded_context,
+        },
+      selection_key,
+      focused_commit_idx,
+    } = undo_entry;
+    for ((file, checked), commit_assignments) in self
+      .state
+      .files
+      .iter_mut()
+      .zip(checked)
+      .zip(commit_assignments)
+    {
+      #[cfg(feature = "tree-sitter")]
+      let is_changed = !UndoSnapshot::is_file_checked_state(file, &checked);
+      for (section, checked) in file.sections.iter_mut().zip(checked) {
+        match section {
+          Section::Unchanged { .. } | Section::Omitted { .. } => {}
+          Section::Changed { lines } => {
+            for (line, is_checked) in lines.iter_mut().zip(checked) {
+              line.is_checked = is_checked;
+            }
+          }
+          Section::FileMode { is_checked, .. }
+          | Section::Rename { is_checked, .. }
+          | Section::Binary { is_checked, .. }
+          | Section::Symlink { is_checked, .. }
+          | Section::Submodule { is_checked, .. } => {
+            *is_checked = checked[0];
+          }
+        }
+      }
+      file.commit_assignments = commit_assignments;
+
+      // The containers store their own checked state, which is only updated
+      // when they're toggled directly.
+      #[cfg(feature = "tree-sitter")]
+      if is_changed {
+        if let Some(containers) = &mut file.containers {
+          for container in containers {
+            container.update_checked(&file.sections);
+          }
+        }
+      }
+    }
+    for (commit, message) in self.state.commits.iter_mut().zip(commit_messages) {
+      commit.message = message;
+    }
+    self.expanded_items = expanded_items;
+    self.expanded_context = expanded_context;
+    self.selection_key = selection_key;
+    self.focused_commit_idx = focused_commit_idx;
+  }
+
+  /// Whether `snapshot` matches the current state, compared in place so that
+  /// each handled event only takes a single snapshot.
+  fn is_current_snapshot(
+    &self,
+    snapshot: &UndoSnapshot,
+  ) -> bool {
+    let UndoSnapshot {
+      checked,
+      commit_assignments,
+      commit_messages,
+      expanded_items,
+      expanded_context,
+    } = snapshot;
+    let RecordState {
+      is_read_only: _,
+      commits,
+      files,
+    } = &self.state;
+    *expanded_items == self.expanded_items
+      && *expanded_context == self.expanded_context
+      && commit_messages
+        .iter()
+        .eq(commits.iter().map(|commit| &commit.message))
+      && commit_assignments
+        .iter()
+        .eq(files.iter().map(|file| &file.commit_assignments))
+      && checked.len() == files.len()
+      && files
+        .iter()
+        .zip(checked)
+        .all(|(file, checked)| UndoSnapshot::is_file_checked_state(file, checked))
+  }
+
+  /// Record `undo_entry`, the state before an update was applied, unless the
+  /// update didn't change anything.
+  fn push_undo_entry(
+    &mut self,
+    undo_entry: UndoEntry,
+  ) {
+    if !self.is_current_snapshot(&undo_entry.snapshot) {
+      Self::push_bounded(&mut self.undo_stack, undo_entry);
+      self.redo_stack.clear();
+    }
+  }
+
+  /// Push `entry` onto the undo or redo `stack`, forgetting the oldest entry
+  /// if the stack would be deeper than [`MAX_UNDO_DEPTH`].
+  fn push_bounded(
+    stack: &mut VecDeque<UndoEntry>,
+    entry: UndoEntry,
+  ) {
+    if stack.len() >= MAX_UNDO_DEPTH {
+      stack.pop_front();
+    }
+    stack.push_back(entry);
+  }
+
+  fn undo(&mut self) {
+    if let Some(undo_entry) = self.undo_stack.pop_back() {
+      let redo_entry = self.make_undo_entry();
+      Self::push_bounded(&mut self.redo_stack, redo_entry);
+      self.restore_undo_entry(undo_entry);
+    }
+  }
+
+  fn redo(&mut self) {
+    if let Some(redo_entry) = self.redo_stack.pop_back() {
+      let undo_entry = self.make_undo_entry();
+      Self::push_bounded(&mut self.undo_stack, undo_entry);
+      self.restore_undo_entry(redo_entry);
+    }
+  }
+
+  /// The file and section indices of the section containing the item at
+  /// `selection_key`, if it's a section or a line.
+  fn section_of_key(selection_key: SelectionKey) -> Option<(usize, usize)> {
+    match selection_key {
+      SelectionKey::Section(SectionKey {
+        commit_idx: _,
+        file_idx,
+        section_idx,
+      })
+      | SelectionKey::Line(LineKey {
+        commit_idx: _,
+        file_idx,
+        section_idx,
+        line_idx: _,
+      }) => Some((file_idx, section_idx)),
+      SelectionKey::None
+      | SelectionKey::File(_)
+      | SelectionKey::Container(_)
+      | SelectionKey::Member(_) => None,
+    }
+  }
+
+  fn split_section(
+    &mut self,
+    selection_key: SelectionKey,
+  ) {
+    let (file_idx, section_idx) = match Self::section_of_key(selection_key) {
+      Some(indices) => indices,
+      None => return,
+    };
+    let regrouping = match self.state.files[file_idx].sections.get(section_idx) {
+      Some(Section::Changed { lines }) => SectionRegrouping::split(file_idx, section_idx, lines),
+      Some(
+        Section::Unchanged { .. }
+        | Section::Omitted { .. }
+        | Section::FileMode { .. }
+        | Section::Rename { .. }
+        | Section::Binary { .. }
+        | Section::Symlink { .. }
+        | Section::Submodule { .. },
+      )
+      | None => None,
+    };
+    if let Some(regrouping) = regrouping {
+      self.regroup_sections(&regrouping);
+    }
+  }
+
+  fn merge_sections(
+    &mut self,
+    selection_key: SelectionKey,
+  ) {
+    let (file_idx, section_idx) = match Self::section_of_key(selection_key) {
+      Some(indices) => indices,
+      None => return,
+    };
+    let regrouping =
+      SectionRegrouping::merge(file_idx, &self.state.files[file_idx].sections, section_idx);
+    if let Some(regrouping) = regrouping {
+      self.regroup_sections(&regrouping);
+    }
+  }
+
+  /// Apply `regrouping` to its file, and update everything which refers to
+  /// the file's sections by index, including the undo and redo stacks, so
+  /// that the selection and expansion state are preserved. Regrouping isn't
+  /// itself undoable, since it doesn't change which changes are selected.
+  fn regroup_sections(
+    &mut self,
+    regrouping: &SectionRegrouping,
+  ) {
+    regrouping.apply(&mut self.state.files[regrouping.file_idx]);
+    for undo_entry in self.undo_stack.iter_mut().chain(&mut self.redo_stack) {
+      let UndoEntry {
+        snapshot:
+          UndoSnapshot {
+            checked,
+            commit_assignments,
+            commit_messages: _,
+            expanded_items,
+            expanded_context,
+          },
+        selection_key,
+        focused_commit_idx: _,
+      } = undo_entry;
+      if let Some(checked) = checked.get_mut(regrouping.file_idx) {
+        regrouping.map_per_section(checked);
+      }
+      if let Some(commit_assignments) = commit_assignments.get_mut(regrouping.file_idx) {
+        regrouping.map_commit_assignments(commit_assignments);
+      }
+      *expanded_items = regrouping.map_expanded_items(expanded_items);
+      *expanded_context = regrouping.map_expanded_context(expanded_context);
+      *selection_key = regrouping.map_selection_key(*selection_key)[0];
+    }
+    self.expanded_items = regrouping.map_expanded_items(&self.expanded_items);
+    self.expanded_context = regrouping.map_expanded_context(&self.expanded_context);
+    self.selection_key = regrouping.map_selection_key(self.selection_key)[0];
+    // The lines themselves don't change, so the same lines are still moved.
+    self.moved_lines = self.moved_lines.map_locations(|location| {
+      let LineLocation {
+        file_idx,
+        section_idx,
+        line_idx,
+      } = location;
+      if file_idx != regrouping.file_idx {
+        return location;
+      }
+      let (section_idx, line_idx) = regrouping.map_line(section_idx, line_idx);
+      LineLocation {
+        file_idx,
+        section_idx,
+        line_idx,
+      }
+    });
+    self
+      .syntax_highlights
+      .update_file(&self.state.files, regrouping.file_idx);
+  }
+
+  /// Whether the user is typing text, such as a search query, so that keys
+  /// should be read as text rather than as their key bindings.
+  fn is_typing_text(&self) -> bool {
+    self.quit_dialog.is_none()
+      && self.help_dialog.is_none()
+      && (self.picker.is_some() || self.editing_search().is_some())
+  }
+
+  fn editing_search(&self) -> Option<&Search> {
+    self.search.as_ref().filter(|search| search.is_editing)
+  }
+
+  /// The pattern whose matches are highlighted.
+  fn search_pattern(&self) -> Option<&SearchPattern> {
+    self
+      .search
+      .as_ref()
+      .and_then(|search| search.pattern.as_ref())
+  }
+
+  fn set_search_query(
+    &mut self,
+    query: String,
+  ) -> Result<(), RecordError> {
+    let search = match &mut self.search {
+      Some(search) => search,
+      None => return Ok(()),
+    };
+    search.pattern = if query.is_empty() {
+      None
+    } else {
+      SearchPattern::new(&query, self.search_options).ok()
+    };
+    search.query = query;
+
+    // Focus the first match from where the search started, like Vim's
+    // `incsearch` option.
+    let Search {
+      query: _,
+      is_backward,
+      is_editing: _,
+      origin_key,
+      pattern,
+    } = search.clone();
+    let selection_key = match pattern {
+      Some(pattern) => self
+        .find_search_match(&pattern, origin_key, is_backward)?
+        .unwrap_or(origin_key),
+      None => origin_key,
+    };
+    self.selection_key = selection_key;
+    self.expand_item_ancestors(selection_key);
+    Ok(())
+  }
+
+  fn finish_search(&mut self) {
+    match &mut self.search {
+      Some(search) if search.query.is_empty() => {
+        self.search = None;
+      }
+      Some(search) => {
+        search.is_editing = false;
+      }
+      None => {}
+    }
+  }
+
+  fn cancel_search(&mut self) {
+    if let Some(search) = self.search.take() {
+      self.selection_key = search.origin_key;
+    }
+  }
+
+  fn select_next_search_match(
+    &self,
+    is_reversed: bool,
+  ) -> Result<StateUpdate, RecordError> {
+    let (pattern, is_backward) = match &self.search {
+      Some(Search {
+        query: _,
+        is_backward,
+        is_editing: _,
+        origin_key: _,
+        pattern: Some(pattern),
+      }) => (pattern, *is_backward),
+      Some(Search { pattern: None, .. }) | None => return Ok(StateUpdate::None),
+    };
+    match self.find_search_match(pattern, self.selection_key, is_backward != is_reversed)? {
+      Some(selection_key) => Ok(StateUpdate::SelectItem {
+        selection_key,
+        ensure_in_viewport: true,
+      }),
+      None => Ok(StateUpdate::None),
+    }
+  }
+
+  /// Find the first item after `from_key`, or before it if `is_backward` is
+  /// true, which matches `pattern`. The search wraps around, so `from_key`
+  /// itself is checked last.
+  fn find_search_match(
+    &self,
+    pattern: &SearchPattern,
+    from_key: SelectionKey,
+    is_backward: bool,
+  ) -> Result<Option<SelectionKey>, RecordError> {
+    let keys = self.all_selection_keys();
+    let num_keys = keys.len();
+    let from_idx = match keys.iter().position(|key| *key == from_key) {
+      Some(from_idx) => from_idx,
+      // Start from the first item.
+      None if is_backward => 0,
+      None => num_keys.saturating_sub(1),
+    };
+    for offset in 1..=num_keys {
+      let idx = if is_backward {
+        (from_idx + num_keys - offset) % num_keys
+      } else {
+        (from_idx + offset) % num_keys
+      };
+      if self.is_search_match(pattern, keys[idx])? {
+        return Ok(Some(keys[idx]));
+      }
+    }
+    Ok(None)
+  }
+
+  /// Whether the file path, changed line, or semantic container name of
+  /// `selection_key` matches `pattern`.
+  fn is_search_match(
+    &self,
+    pattern: &SearchPattern,
+    selection_key: SelectionKey,
+  ) -> Result<bool, RecordError> {
+    match selection_key {
+      SelectionKey::None | SelectionKey::Section(_) => Ok(false),
+      SelectionKey::File(file_key) => {
+        let file = self.file(file_key)?;
+        Ok(
+          iter::once(&file.path)
+            .chain(&file.old_path)
+            .any(|path| pattern.is_match(&path.to_string_lossy())),
+        )
+      }
+      #[cfg(feature = "tree-sitter")]
+      SelectionKey::Container(container_key) => Ok(
+        self
+          .container(container_key)?
+          .names()
+          .into_iter()
+          .any(|name| pattern.is_match(name)),
+      ),
+      #[cfg(feature = "tree-sitter")]
+      SelectionKey::Member(member_key) => Ok(
+        self
+          .member(member_key)?
+          .names()
+          .into_iter()
+          .any(|name| pattern.is_match(name)),
+      ),
+      #[cfg(not(feature = "tree-sitter"))]
+      SelectionKey::Container(_) | SelectionKey::Member(_) => Ok(false),
+      SelectionKey::Line(LineKey {
+        commit_idx,
+        file_idx,
+        section_idx,
+        line_idx,
+      }) => {
+        let section = self.section(SectionKey {
+          commit_idx,
+          file_idx,
+          section_idx,
+        })?;
+        match section {
+          Section::Changed { lines } => Ok(
+            lines
+              .get(line_idx)
+              .is_some_and(|line| pattern.is_match(&line.line)),
+          ),
+          Section::Unchanged { .. }
+          | Section::Omitted { .. }
+          | Section::FileMode { .. }
+          | Section::Rename { .. }
+          | Section::Binary { .. }
+          | Section::Symlink { .. }
+          | Section::Submodule { .. } => Ok(false),
+        }
+      }
+    }
+  }
+
+  fn open_picker(&mut self) -> Result<(), RecordError> {
+    let mut items = Vec::new();
+    for selection_key in self.all_selection_keys() {
+      let item = match selection_key {
+        SelectionKey::None | SelectionKey::Section(_) | SelectionKey::Line(_) => continue,
+        SelectionKey::File(file_key) => PickerItem {
+          selection_key,
+          label: self.file(file_key)?.path.to_string_lossy().into_owned(),
+          path: None,
+        },
+        #[cfg(feature = "tree-sitter")]
+        SelectionKey::Container(container_key) => {
+          let ContainerKey {
+            commit_idx,
+            file_idx,
+            container_idx: _,
+          } = container_key;
+          self.container_picker_item(
+            selection_key,
+            FileKey {
+              commit_idx,
+              file_idx,
+            },
+            self.container(container_key)?,
+          )?
+        }
+        #[cfg(feature = "tree-sitter")]
+        SelectionKey::Member(member_key) => {
+          let MemberKey {
+            commit_idx,
+            file_idx,
+            container_idx: _,
+            member_idx: _,
+          } = member_key;
+          self.container_picker_item(
+            selection_key,
+            FileKey {
+              commit_idx,
+              file_idx,
+            },
+            self.member(member_key)?,
+          )?
+        }
+        #[cfg(not(feature = "tree-sitter"))]
+        SelectionKey::Container(_) | SelectionKey::Member(_) => continue,
+      };
+      items.push(item);
+    }
+    self.picker = Some(Picker {
+      query: String::new(),
+      items,
+      matches: Vec::new(),
+      selected_idx: 0,
+    });
+    self.set_picker_query(String::new());
+    Ok(())
+  }
+
+  #[cfg(feature = "tree-sitter")]
+  fn container_picker_item(
+    &self,
+    selection_key: SelectionKey,
+    file_key: FileKey,
+    container: &crate::SemanticContainer,
+  ) -> Result<PickerItem, RecordError> {
+    Ok(PickerItem {
+      selection_key,
+      label: container.names().join(" "),
+      path: Some(self.file(file_key)?.path.to_string_lossy().into_owned()),
+    })
+  }
+
+  fn set_picker_query(
+    &mut self,
+    query: String,
+  ) {
+    if let Some(picker) = &mut self.picker {
+      picker.matches = fuzzy::rank(&query, picker.items.iter().map(|item| item.label.as_str()));
+      picker.query = query;
+      picker.selected_idx = 0;
+    }
+  }
+
+  /// Handle an event while the picker is open, which edits its query or
+  /// chooses one of its matches.
+  fn handle_picker_event(
+    &self,
+    picker: &Picker,
+    event: Event,
+  ) -> StateUpdate {
+    let Picker {
+      query,
+      items,
+      matches,
+      selected_idx,
+    } = picker;
+    match event {
+      Event::TextInput(char) => {
+        let mut query = query.clone();
+        query.push(char);
+        StateUpdate::SetPickerQuery(query)
+      }
+      Event::TextBackspace => {
+        let mut query = query.clone();
+        query.pop();
+        StateUpdate::SetPickerQuery(query)
+      }
+      Event::TextSubmit => match matches.get(*selected_idx) {
+        Some((item_idx, _)) => StateUpdate::ChoosePickerItem(items[*item_idx].selection_key),
+        None => StateUpdate::None,
+      },
+      Event::TextCancel => StateUpdate::ClosePicker,
+      Event::FocusPrev => StateUpdate::SetPickerSelection(selected_idx.saturating_sub(1)),
+      Event::FocusNext => {
+        StateUpdate::SetPickerSelection((selected_idx + 1).min(matches.len().saturating_sub(1)))
+      }
+      _ => StateUpdate::None,
+    }
+  }
+
+  fn is_directory_tree_focused(&self) -> bool {
+    self.help_dialog.is_none()
+      && self.picker.is_none()
+      && self
+        .directory_tree
+        .as_ref()
+        .is_some_and(|sidebar| sidebar.is_focused)
+  }
+
+  /// Show the directory tree and focus the node for the focused file, or hide
+  /// the tree if it's already focused.
+  fn toggle_directory_tree(&mut self) {
+    match &mut self.directory_tree {
+      Some(sidebar) if sidebar.is_focused => {
+        self.directory_tree = None;
+      }
+      Some(sidebar) => {
+        if let Some(focused_idx) = self
+          .selection_key
+          .file_idx()
+          .and_then(|file_idx| sidebar.tree.file_node_idx(file_idx))
+        {
+          sidebar.focused_idx = focused_idx;
+        }
+        sidebar.is_focused = true;
+      }
+      None => {
+        let tree = DirectoryTree::new(&self.state.files);
+        let focused_idx = self
+          .selection_key
+          .file_idx()
+          .and_then(|file_idx| tree.file_node_idx(file_idx))
+          .unwrap_or_default();
+        self.directory_tree = Some(DirectoryTreeSidebar {
+          tree,
+          focused_idx,
+          is_focused: true,
+        });
+      }
+    }
+  }
+
+  fn handle_directory_tree_event(
+    &self,
+    sidebar: &DirectoryTreeSidebar,
+    event: Event,
+  ) -> StateUpdate {
+    let DirectoryTreeSidebar {
+      tree,
+      focused_idx,
+      is_focused: _,
+    } = sidebar;
+    let num_nodes = tree.nodes().len();
+    match event {
+      Event::FocusPrev => self.focus_directory_node(tree, focused_idx.saturating_sub(1)),
+      Event::FocusNext => {
+        self.focus_directory_node(tree, (focused_idx + 1).min(num_nodes.saturating_sub(1)))
+      }
+      Event::FocusOuter { .. } => match tree.nodes().get(*focused_idx) {
+        Some(DirectoryNode {
+          parent_idx: Some(parent_idx),
+          ..
+        }) => self.focus_directory_node(tree, *parent_idx),
+        Some(_) | None => StateUpdate::None,
+      },
+      Event::ToggleItem => {
+        if *focused_idx < num_nodes {
+          StateUpdate::ToggleDirectoryNode(*focused_idx)
+        } else {
+          StateUpdate::None
+        }
+      }
+      Event::FocusInner | Event::ToggleItemAndAdvance | Event::QuitEscape => {
+        StateUpdate::UnfocusDirectoryTree
+      }
+      _ => StateUpdate::None,
+    }
+  }
+
+  /// Focus the node at `node_idx` in the directory tree, and the first file
+  /// below it in the main pane.
+  fn focus_directory_node(
+    &self,
+    tree: &DirectoryTree,
+    node_idx: usize,
+  ) -> StateUpdate {
+    match tree
+      .nodes()
+      .get(node_idx)
+      .and_then(|node| node.file_idxs.first())
+    {
+      Some(file_idx) => StateUpdate::FocusDirectoryNode {
+        node_idx,
+        selection_key: SelectionKey::File(FileKey {
+          commit_idx: self.focused_commit_idx,
+          file_idx: *file_idx,
+        }),
+      },
+      None => StateUpdate::None,
+    }
+  }
+
+  /// The keys of the files at or below the node at `node_idx` in the
+  /// directory tree.
+  fn directory_node_file_keys(
+    &self,
+    node_idx: usize,
+  ) -> Vec<FileKey> {
+    let node = self
+      .directory_tree
+      .as_ref()
+      .and_then(|sidebar| sidebar.tree.nodes().get(node_idx));
+    match node {
+      Some(node) => node
+        .file_idxs
+        .iter()
+        .map(|file_idx| FileKey {
+          commit_idx: self.focused_commit_idx,
+          file_idx: *file_idx,
+        })
+        .collect(),
+      None => Vec::new(),
+    }
+  }
+
+  fn load_directory_node(
+    &mut self,
+    node_idx: usize,
+  ) -> Result<(), RecordError> {
+    let file_idxs: Vec<_> = self
+      .directory_node_file_keys(node_idx)
+      .into_iter()
+      .map(
+        |FileKey {
+           commit_idx: _,
+           file_idx,
+         }| file_idx,
+      )
+      .collect();
+    self.load_files(file_idxs)
+  }
+
+  /// Calculate whether the files at or below `node` are selected.
+  fn directory_node_tristate(
+    &self,
+    node: &DirectoryNode,
+  ) -> Result<Tristate, RecordError> {
+    let mut seen_value = None;
+    for file_idx in &node.file_idxs {
+      let tristate = self.file_tristate(FileKey {
+        commit_idx: self.focused_commit_idx,
+        file_idx: *file_idx,
+      })?;
+      seen_value = match (seen_value, tristate) {
+        (_, Tristate::Partial) => return Ok(Tristate::Partial),
+        (None, tristate) => Some(tristate),
+        (Some(Tristate::True), Tristate::True) => Some(Tristate::True),
+        (Some(Tristate::False), Tristate::False) => Some(Tristate::False),
+        (Some(Tristate::True), Tristate::False)
+        | (Some(Tristate::False), Tristate::True)
+        | (Some(Tristate::Partial), _) => return Ok(Tristate::Partial),
+      };
+    }
+    Ok(seen_value.unwrap_or(Tristate::False))
+  }
+
+  /// Select every file at or below the node at `node_idx` in the directory
+  /// tree if none of them are selected, or else unselect all of them. The
+  /// files are toggled one at a time, so that moved lines and commits are
+  /// handled as for [`Recorder::toggle_item`].
+  fn toggle_directory_node(
+    &mut self,
+    node_idx: usize,
+  ) -> Result<(), RecordError> {
+    let node = match self
+      .directory_tree
+      .as_ref()
+      .and_then(|sidebar| sidebar.tree.nodes().get(node_idx))
+    {
+      Some(node) => node,
+      None => return Ok(()),
+    };
+    let is_checked_new = match self.directory_node_tristate(node)? {
+      Tristate::False => true,
+      Tristate::Partial | Tristate::True => false,
+    };
+    for file_key in self.directory_node_file_keys(node_idx) {
+      // Toggling a partially-selected file unselects it, so it has to be
+      // toggled twice to select it.
+      let num_toggles = match (self.file_tristate(file_key)?, is_checked_new) {
+        (Tristate::False, false) | (Tristate::True, true) => 0,
+        (Tristate::False, true) | (Tristate::Partial | Tristate::True, false) => 1,
+        (Tristate::Partial, true) => 2,
+      };
+      for _ in 0..num_toggles {
+        self.toggle_item(SelectionKey::File(file_key))?;
+      }
+    }
+    Ok(())
+  }
+
+  fn click_menu_item(
+    &mut self,
+    event: Event,
+  ) {
+    self.expanded_menu_idx = None;
+    self.pending_events.push(event);
+  }
+
+  fn edit_commit_message(
+    &mut self,
+    commit_idx: usize,
+  ) -> Result<(), RecordError> {
+    let message = &mut self.state.commits[commit_idx].message;
+    let message_str = match message.as_ref() {
+      Some(message) => message,
+      None => return Ok(()),
+    };
+    let new_message = {
+      match self.input.terminal_kind() {
+        TerminalKind::Testing { .. } => {}
+        TerminalKind::Crossterm => {
+          Self::clean_up_crossterm()?;
+        }
+      }
+      let result = self.input.edit_commit_message(message_str);
+      match self.input.terminal_kind() {
+        TerminalKind::Testing { .. } => {}
+        TerminalKind::Crossterm => {
+          Self::set_up_crossterm()?;
+        }
+      }
+      result?
+    };
+    *message = Some(new_message);
+    Ok(())
+  }
+
+  fn file(
+    &self,
+    file_key: FileKey,
+  ) -> Result<&File<'_>, RecordError> {
+    let FileKey {
+      commit_idx: _,
+      file_idx,
+    } = file_key;
+    match self.state.files.get(file_idx) {
+      Some(file) => Ok(file),
+      None => Err(RecordError::Bug(format!(
+        "Out-of-bounds file key: {file_key:?}"
+      ))),
+    }
+  }
+
+  fn section(
+    &self,
+    section_key: SectionKey,
+  ) -> Result<&Section<'_>, RecordError> {
+    let SectionKey {
+      commit_idx,
+      file_idx,
+      section_idx,
+    } = section_key;
+    let file = self.file(FileKey {
+      commit_idx,
+      file_idx,
+    })?;
+    match file.sections.get(section_idx) {
+      Some(section) => Ok(section),
+      None => Err(RecordError::Bug(format!(
+        "Out-of-bounds section key: {section_key:?}"
+      ))),
+    }
+  }
+
+  fn visit_file_for_section<T>(
+    &mut self,
     section_key: SectionKey,
     f: impl Fn(&mut File) -> T,
   ) -> Result<T, RecordError> {
@@ -3368,7 +6114,41 @@ impl<'state, 'input> Recorder<'state, 'input> {
     file_key: FileKey,
   ) -> Result<Tristate, RecordError> {
     let file = self.file(file_key)?;
-    Ok(file.tristate())
+    if self.state.commits.len() <= 2 {
+      Ok(file.tristate())
+    } else {
+      Ok(Self::commit_tristate(
+        file,
+        file_key.commit_idx,
+        |_section_idx| true,
+      ))
+    }
+  }
+
+  /// Calculate whether the changes in the sections of `file` matching
+  /// `include_section` belong to the commit at `commit_idx`.
+  fn commit_tristate(
+    file: &File,
+    commit_idx: usize,
+    include_section: impl Fn(usize) -> bool,
+  ) -> Tristate {
+    let mut seen_value = None;
+    for (section_idx, line_idx) in file.change_keys() {
+      if !include_section(section_idx) {
+        continue;
+      }
+      let is_in_commit = file.commit_idx(section_idx, line_idx) == commit_idx;
+      seen_value = match (seen_value, is_in_commit) {
+        (None, is_in_commit) => Some(is_in_commit),
+        (Some(true), true) => Some(true),
+        (Some(false), false) => Some(false),
+        (Some(true), false) | (Some(false), true) => return Tristate::Partial,
+      };
+    }
+    match seen_value {
+      Some(true) => Tristate::True,
+      None | Some(false) => Tristate::False,
+    }
   }
 
   fn file_expanded(
@@ -3387,7 +6167,13 @@ impl<'state, 'input> Recorder<'state, 'input> {
         .enumerate()
         .any(|(section_idx, section)| {
           match section {
-            Section::Unchanged { .. } | Section::FileMode { .. } | Section::Binary { .. } => {
+            Section::Unchanged { .. }
+            | Section::Omitted { .. }
+            | Section::FileMode { .. }
+            | Section::Rename { .. }
+            | Section::Binary { .. }
+            | Section::Symlink { .. }
+            | Section::Submodule { .. } => {
               // Not collapsible/expandable.
               false
             }
@@ -3442,7 +6228,22 @@ impl<'state, 'input> Recorder<'state, 'input> {
     section_key: SectionKey,
   ) -> Result<Tristate, RecordError> {
     let section = self.section(section_key)?;
-    Ok(section.tristate())
+    if self.state.commits.len() <= 2 {
+      Ok(section.tristate())
+    } else {
+      let SectionKey {
+        commit_idx,
+        file_idx,
+        section_idx,
+      } = section_key;
+      let file = self.file(FileKey {
+        commit_idx,
+        file_idx,
+      })?;
+      Ok(Self::commit_tristate(file, commit_idx, |idx| {
+        idx == section_idx
+      }))
+    }
   }
 
   #[cfg(feature = "tree-sitter")]
@@ -3478,6 +6279,19 @@ impl<'state, 'input> Recorder<'state, 'input> {
     container_key: ContainerKey,
   ) -> Result<Tristate, RecordError> {
     let container = self.container(container_key)?;
+    if self.state.commits.len() > 2 {
+      // The stored flags only track membership in the first commit.
+      let section_indices = container.section_indices();
+      let file = self.file(FileKey {
+        commit_idx: container_key.commit_idx,
+        file_idx: container_key.file_idx,
+      })?;
+      return Ok(Self::commit_tristate(
+        file,
+        container_key.commit_idx,
+        |section_idx| section_indices.contains(&section_idx),
+      ));
+    }
     Ok(match container {
       crate::SemanticContainer::Struct {
         is_checked,
@@ -3594,6 +6408,19 @@ impl<'state, 'input> Recorder<'state, 'input> {
     member_key: MemberKey,
   ) -> Result<Tristate, RecordError> {
     let member = self.member(member_key)?;
+    if self.state.commits.len() > 2 {
+      // The stored flags only track membership in the first commit.
+      let section_indices = member.section_indices();
+      let file = self.file(FileKey {
+        commit_idx: member_key.commit_idx,
+        file_idx: member_key.file_idx,
+      })?;
+      return Ok(Self::commit_tristate(
+        file,
+        member_key.commit_idx,
+        |section_idx| section_indices.contains(&section_idx),
+      ));
+    }
     Ok(match member {
       crate::SemanticContainer::Field {
         is_checked,
@@ -3640,7 +6467,13 @@ impl<'state, 'input> Recorder<'state, 'input> {
         let line = &mut lines[line_idx];
         Ok(f(line))
       }
-      Section::Unchanged { .. } | Section::FileMode { .. } | Section::Binary { .. } => {
+      Section::Unchanged { .. }
+      | Section::Omitted { .. }
+      | Section::FileMode { .. }
+      | Section::Rename { .. }
+      | Section::Binary { .. }
+      | Section::Symlink { .. }
+      | Section::Submodule { .. } => {
         // Do nothing.
         Ok(None)
       }
@@ -3658,10 +6491,19 @@ enum ComponentId {
   MenuItem(usize),
   CommitMessageView,
   CommitEditMessageButton(usize),
+  Summary,
+  SummaryFile(FileKey),
+  SearchPrompt,
+  PickerDialog,
+  DirectoryTree,
+  DirectoryNode(usize),
+  DirectoryNodeToggleBox(usize),
   FileViewHeader(FileKey),
   SelectableItem(SelectionKey),
   ToggleBox(SelectionKey),
   ExpandBox(SelectionKey),
+  ContextGap(SectionKey),
+  ContextButton(SectionKey, ContextAction),
   QuitDialog,
   QuitDialogButton(QuitDialogButtonId),
   HelpDialog,
@@ -3711,19 +6553,241 @@ impl<Id> TristateBox<Id> {
       (TristateIconStyle::Check, Tristate::True, false) => "*",
       (TristateIconStyle::Check, Tristate::Partial, false) => "~",
 
-      (TristateIconStyle::Check, Tristate::False, true) => " ",
-      (TristateIconStyle::Check, Tristate::True, true) => "●",
-      (TristateIconStyle::Check, Tristate::Partial, true) => "◐",
-    };
-    format!("{l}{inner}{r}")
+      (TristateIconStyle::Check, Tristate::False, true) => " ",
+      (TristateIconStyle::Check, Tristate::True, true) => "●",
+      (TristateIconStyle::Check, Tristate::Partial, true) => "◐",
+    };
+    format!("{l}{inner}{r}")
+  }
+}
+
+impl<Id: Clone + Debug + Eq + Hash> Component for TristateBox<Id> {
+  type Id = Id;
+
+  fn id(&self) -> Self::Id {
+    self.id.clone()
+  }
+
+  fn draw(
+    &self,
+    viewport: &mut Viewport<Self::Id>,
+    x: isize,
+    y: isize,
+  ) {
+    let style = if self.is_read_only {
+      Style::default().fg(Color::Gray).add_modifier(Modifier::DIM)
+    } else {
+      Style::default().add_modifier(Modifier::BOLD)
+    };
+    let span = Span::styled(self.text(), style);
+    viewport.draw_span(x, y, &span);
+  }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+struct AppDebugInfo {
+  term_height: usize,
+  scroll_offset_y: isize,
+  selection_key: SelectionKey,
+  selection_key_y: Option<isize>,
+  drawn_rects: BTreeMap<ComponentId, DrawnRect>, // sorted for determinism
+}
+
+#[derive(Clone, Debug)]
+struct AppView<'a> {
+  debug_info: Option<AppDebugInfo>,
+  menu_bar: MenuBar<'a>,
+  summary_view: Option<SummaryView<'a>>,
+  directory_tree: Option<DirectoryTreeView<'a>>,
+  commit_view_mode: CommitViewMode,
+  commit_views: Vec<CommitView<'a>>,
+  search_prompt: Option<SearchPrompt<'a>>,
+  picker_dialog: Option<PickerDialog<'a>>,
+  quit_dialog: Option<QuitDialog>,
+  help_dialog: Option<HelpDialog>,
+}
+
+impl Component for AppView<'_> {
+  type Id = ComponentId;
+
+  fn id(&self) -> Self::Id {
+    ComponentId::App
+  }
+
+  fn draw(
+    &self,
+    viewport: &mut Viewport<Self::Id>,
+    x: isize,
+    _y: isize,
+  ) {
+    let Self {
+      debug_info,
+      menu_bar,
+      summary_view,
+      directory_tree,
+      commit_view_mode,
+      commit_views,
+      search_prompt,
+      picker_dialog,
+      quit_dialog,
+      help_dialog,
+    } = self;
+
+    if let Some(debug_info) = debug_info {
+      viewport.debug(format!("app debug info: {debug_info:#?}"));
+    }
+
+    let viewport_rect = viewport.mask_rect();
+
+    let menu_bar_height = 1usize;
+    let directory_tree_width = match directory_tree {
+      Some(_) => DirectoryTreeView::width(viewport_rect.width),
+      None => 0,
+    };
+    let main_pane_width = viewport_rect.width - directory_tree_width;
+    let commit_view_width = match commit_view_mode {
+      CommitViewMode::Inline => main_pane_width,
+      CommitViewMode::Adjacent => {
+        const MAX_COMMIT_VIEW_WIDTH: usize = 120;
+        MAX_COMMIT_VIEW_WIDTH.min(main_pane_width.saturating_sub(CommitView::MARGIN) / 2)
+      }
+    };
+    let main_pane_x = x + directory_tree_width.unwrap_isize();
+    let commit_views_mask = Mask {
+      x: viewport_rect.x + directory_tree_width.unwrap_isize(),
+      y: viewport_rect.y + menu_bar_height.unwrap_isize(),
+      width: Some(main_pane_width),
+      height: None,
+    };
+    viewport.with_mask(commit_views_mask, |viewport| {
+      let mut commit_views_y = menu_bar_height.unwrap_isize();
+      if let Some(summary_view) = summary_view {
+        let summary_view_rect = viewport.draw_component(main_pane_x, commit_views_y, summary_view);
+        commit_views_y += summary_view_rect.height.unwrap_isize();
+      }
+
+      let mut commit_view_x = main_pane_x;
+      for commit_view in commit_views {
+        let commit_view_mask = Mask {
+          x: commit_view_x,
+          y: commit_views_mask.y,
+          width: Some(commit_view_width),
+          height: None,
+        };
+        let commit_view_rect = viewport.with_mask(commit_view_mask, |viewport| {
+          viewport.draw_component(commit_view_x, commit_views_y, commit_view)
+        });
+        commit_view_x +=
+          (CommitView::MARGIN + commit_view_mask.apply(commit_view_rect).width).unwrap_isize();
+      }
+    });
+
+    if let Some(directory_tree) = directory_tree {
+      // The tree stays in place while the main pane scrolls.
+      let directory_tree_y = viewport_rect.y + menu_bar_height.unwrap_isize();
+      let directory_tree_mask = Mask {
+        x: viewport_rect.x,
+        y: directory_tree_y,
+        width: Some(directory_tree_width),
+        height: Some(viewport_rect.height.saturating_sub(menu_bar_height)),
+      };
+      viewport.with_mask(directory_tree_mask, |viewport| {
+        viewport.draw_component(x, directory_tree_y, directory_tree)
+      });
+    }
+
+    viewport.draw_component(x, viewport_rect.y, menu_bar);
+
+    if let Some(search_prompt) = search_prompt {
+      let search_prompt_y = viewport_rect.y + viewport_rect.height.unwrap_isize() - 1;
+      viewport.draw_component(x, search_prompt_y, search_prompt);
+    }
+
+    if let Some(picker_dialog) = picker_dialog {
+      viewport.draw_component(0, 0, picker_dialog);
+    }
+
+    if let Some(quit_dialog) = quit_dialog {
+      viewport.draw_component(0, 0, quit_dialog);
+    }
+    if let Some(help_dialog) = help_dialog {
+      viewport.draw_component(0, 0, help_dialog);
+    }
+  }
+}
+
+/// The line at the bottom of the screen where the search query is typed.
+#[derive(Clone, Debug)]
+struct SearchPrompt<'a> {
+  query: &'a str,
+  is_backward: bool,
+  /// Whether the query could be compiled (see [`SearchPattern::new`]).
+  is_valid: bool,
+}
+
+impl Component for SearchPrompt<'_> {
+  type Id = ComponentId;
+
+  fn id(&self) -> Self::Id {
+    ComponentId::SearchPrompt
+  }
+
+  fn draw(
+    &self,
+    viewport: &mut Viewport<Self::Id>,
+    x: isize,
+    y: isize,
+  ) {
+    let Self {
+      query,
+      is_backward,
+      is_valid,
+    } = self;
+    viewport.draw_blank(Rect {
+      x: viewport.mask_rect().x,
+      y,
+      width: viewport.mask_rect().width,
+      height: 1,
+    });
+    let prompt = if *is_backward { "?" } else { "/" };
+    let mut spans = vec![Span::raw(prompt)];
+    push_spans_from_line(query, &mut spans);
+    if !is_valid {
+      spans.push(Span::styled(
+        " (invalid regex)",
+        Style::default().fg(Color::Red),
+      ));
+    }
+    viewport.draw_text(x, y, Line::from(spans));
+  }
+}
+
+/// The sidebar opened by [`Event::ToggleDirectoryTree`]. It's drawn in a mask
+/// covering the left of the screen below the menu bar.
+#[derive(Clone, Debug)]
+struct DirectoryTreeView<'a> {
+  use_unicode: bool,
+  node_views: Vec<DirectoryNodeView<'a>>,
+  focused_idx: usize,
+}
+
+impl DirectoryTreeView<'_> {
+  /// The maximum width of the sidebar, including the line separating it from
+  /// the main pane.
+  const MAX_WIDTH: usize = 32;
+
+  /// The width of the sidebar in a terminal which is `term_width` wide.
+  fn width(term_width: usize) -> usize {
+    Self::MAX_WIDTH.min(term_width / 3)
   }
 }
 
-impl<Id: Clone + Debug + Eq + Hash> Component for TristateBox<Id> {
-  type Id = Id;
+impl Component for DirectoryTreeView<'_> {
+  type Id = ComponentId;
 
   fn id(&self) -> Self::Id {
-    self.id.clone()
+    ComponentId::DirectoryTree
   }
 
   fn draw(
@@ -3732,109 +6796,205 @@ impl<Id: Clone + Debug + Eq + Hash> Component for TristateBox<Id> {
     x: isize,
     y: isize,
   ) {
-    let style = if self.is_read_only {
-      Style::default().fg(Color::Gray).add_modifier(Modifier::DIM)
-    } else {
-      Style::default().add_modifier(Modifier::BOLD)
+    let Self {
+      use_unicode,
+      node_views,
+      focused_idx,
+    } = self;
+    let mask_rect = viewport.mask_rect();
+    let tree_width = mask_rect.width.saturating_sub(1);
+    viewport.draw_blank(Rect {
+      width: tree_width,
+      ..mask_rect
+    });
+
+    // Scroll the tree so that the focused node is visible.
+    let first_idx = (focused_idx + 1).saturating_sub(mask_rect.height);
+    let tree_mask = Mask {
+      width: Some(tree_width),
+      ..viewport.mask()
     };
-    let span = Span::styled(self.text(), style);
-    viewport.draw_span(x, y, &span);
-  }
-}
+    viewport.with_mask(tree_mask, |viewport| {
+      for (node_view, node_y) in node_views.iter().skip(first_idx).zip(y..) {
+        viewport.draw_component(x, node_y, node_view);
+      }
+    });
 
-#[allow(dead_code)]
-#[derive(Clone, Debug)]
-struct AppDebugInfo {
-  term_height: usize,
-  scroll_offset_y: isize,
-  selection_key: SelectionKey,
-  selection_key_y: Option<isize>,
-  drawn_rects: BTreeMap<ComponentId, DrawnRect>, // sorted for determinism
+    let separator = if *use_unicode { "\u{2502}" } else { "|" };
+    for separator_y in mask_rect.iter_ys() {
+      viewport.draw_span(
+        mask_rect.end_x() - 1,
+        separator_y,
+        &Span::styled(separator, Style::default().add_modifier(Modifier::DIM)),
+      );
+    }
+  }
 }
 
+/// A directory or file in the [`DirectoryTreeView`]. Clicking it focuses it,
+/// and clicking its toggle box selects or unselects every file below it.
 #[derive(Clone, Debug)]
-struct AppView<'a> {
-  debug_info: Option<AppDebugInfo>,
-  menu_bar: MenuBar<'a>,
-  commit_view_mode: CommitViewMode,
-  commit_views: Vec<CommitView<'a>>,
-  quit_dialog: Option<QuitDialog>,
-  help_dialog: Option<HelpDialog>,
+struct DirectoryNodeView<'a> {
+  node_idx: usize,
+  node: &'a DirectoryNode,
+  toggle_box: TristateBox<ComponentId>,
+  is_focused: bool,
 }
 
-impl Component for AppView<'_> {
+impl Component for DirectoryNodeView<'_> {
   type Id = ComponentId;
 
   fn id(&self) -> Self::Id {
-    ComponentId::App
+    ComponentId::DirectoryNode(self.node_idx)
   }
 
   fn draw(
     &self,
     viewport: &mut Viewport<Self::Id>,
     x: isize,
-    _y: isize,
+    y: isize,
   ) {
     let Self {
-      debug_info,
-      menu_bar,
-      commit_view_mode,
-      commit_views,
-      quit_dialog,
-      help_dialog,
+      node_idx: _,
+      node,
+      toggle_box,
+      is_focused,
     } = self;
-
-    if let Some(debug_info) = debug_info {
-      viewport.debug(format!("app debug info: {debug_info:#?}"));
+    let DirectoryNode {
+      depth,
+      name,
+      is_directory,
+      parent_idx: _,
+      file_idxs: _,
+    } = node;
+    let indent = (2 * depth).unwrap_isize();
+    let toggle_box_rect = viewport.draw_component(x + indent, y, toggle_box);
+    let name_rect = if *is_directory {
+      viewport.draw_span(
+        toggle_box_rect.end_x() + 1,
+        y,
+        &Span::styled(
+          format!("{name}/"),
+          Style::default()
+            .fg(Color::Blue)
+            .add_modifier(Modifier::BOLD),
+        ),
+      )
+    } else {
+      viewport.draw_span(toggle_box_rect.end_x() + 1, y, &Span::raw(name.as_str()))
+    };
+    if *is_focused {
+      highlight_rect(
+        viewport,
+        Rect {
+          x: viewport.mask_rect().x,
+          y,
+          width: (name_rect.end_x() - viewport.mask_rect().x).clamp_into_usize(),
+          height: 1,
+        },
+      );
     }
+  }
+}
 
-    let viewport_rect = viewport.mask_rect();
+#[derive(Clone, Debug)]
+struct PickerDialog<'a> {
+  picker: &'a Picker,
+}
 
-    let menu_bar_height = 1usize;
-    let commit_view_width = match commit_view_mode {
-      CommitViewMode::Inline => viewport.rect().width,
-      CommitViewMode::Adjacent => {
-        const MAX_COMMIT_VIEW_WIDTH: usize = 120;
-        MAX_COMMIT_VIEW_WIDTH.min(viewport.rect().width.saturating_sub(CommitView::MARGIN) / 2)
-      }
-    };
-    let commit_views_mask = Mask {
-      x: viewport_rect.x,
-      y: viewport_rect.y + menu_bar_height.unwrap_isize(),
-      width: Some(viewport_rect.width),
-      height: None,
-    };
-    viewport.with_mask(commit_views_mask, |viewport| {
-      let mut commit_view_x = 0;
-      for commit_view in commit_views {
-        let commit_view_mask = Mask {
-          x: commit_views_mask.x + commit_view_x,
-          y: commit_views_mask.y,
-          width: Some(commit_view_width),
-          height: None,
-        };
-        let commit_view_rect = viewport.with_mask(commit_view_mask, |viewport| {
-          viewport.draw_component(commit_view_x, menu_bar_height.unwrap_isize(), commit_view)
-        });
-        commit_view_x +=
-          (CommitView::MARGIN + commit_view_mask.apply(commit_view_rect).width).unwrap_isize();
-      }
-    });
+impl PickerDialog<'_> {
+  /// The number of matches which are shown at once.
+  const MAX_NUM_MATCHES: usize = 10;
 
-    viewport.draw_component(x, viewport_rect.y, menu_bar);
+  /// The minimum width of the dialog's contents, so that it doesn't change
+  /// size as the query is typed.
+  const MIN_WIDTH: usize = 50;
+}
 
-    if let Some(quit_dialog) = quit_dialog {
-      viewport.draw_component(0, 0, quit_dialog);
+impl Component for PickerDialog<'_> {
+  type Id = ComponentId;
+
+  fn id(&self) -> Self::Id {
+    ComponentId::PickerDialog
+  }
+
+  fn draw(
+    &self,
+    viewport: &mut Viewport<Self::Id>,
+    _x: isize,
+    _y: isize,
+  ) {
+    let Picker {
+      query,
+      items,
+      matches,
+      selected_idx,
+    } = self.picker;
+    const UI_PICKER_MATCH_STYLE: Style =
+      Style::new().fg(Color::Yellow).add_modifier(Modifier::BOLD);
+    const UI_PICKER_PATH_STYLE: Style = Style::new().add_modifier(Modifier::DIM);
+
+    let mut lines = vec![Line::from(vec![
+      Span::styled("> ", Style::new().bold()),
+      Span::raw(format!("{query:width$}", width = Self::MIN_WIDTH - 2)),
+    ])];
+    let first_idx = (selected_idx + 1).saturating_sub(Self::MAX_NUM_MATCHES);
+    for (match_idx, (item_idx, fuzzy_match)) in matches
+      .iter()
+      .enumerate()
+      .skip(first_idx)
+      .take(Self::MAX_NUM_MATCHES)
+    {
+      let PickerItem {
+        selection_key: _,
+        label,
+        path,
+      } = &items[*item_idx];
+      let FuzzyMatch { score: _, indices } = fuzzy_match;
+      let matched_ranges: Vec<Range<usize>> = indices
+        .iter()
+        .map(|idx| *idx..idx + label[*idx..].chars().next().map_or(0, char::len_utf8))
+        .collect();
+      let mut spans = vec![Span::raw("  ")];
+      push_styled_spans_from_line(
+        label,
+        &[(&matched_ranges, UI_PICKER_MATCH_STYLE)],
+        &mut spans,
+      );
+      if let Some(path) = path {
+        spans.push(Span::styled(format!("  {path}"), UI_PICKER_PATH_STYLE));
+      }
+      let line = Line::from(spans);
+      lines.push(if match_idx == *selected_idx {
+        line.style(Style::new().add_modifier(Modifier::REVERSED))
+      } else {
+        line
+      });
     }
-    if let Some(help_dialog) = help_dialog {
-      viewport.draw_component(0, 0, help_dialog);
+    if matches.is_empty() {
+      lines.push(Line::styled("  No matches", UI_PICKER_PATH_STYLE));
     }
+    // Keep the height of the dialog the same as the query is typed.
+    lines.resize(1 + Self::MAX_NUM_MATCHES, Line::default());
+
+    let dialog = Dialog {
+      id: self.id(),
+      title: Cow::Owned(format!(
+        "Go to file or symbol ({}/{})",
+        matches.len(),
+        items.len()
+      )),
+      body: Cow::Owned(Text::from(lines)),
+      buttons: &[],
+    };
+    viewport.draw_component(0, 0, &dialog);
   }
 }
 
 #[derive(Clone, Debug)]
 struct CommitMessageView<'a> {
   commit_idx: usize,
+  num_commits: usize,
   commit: &'a Commit,
 }
 
@@ -3851,23 +7011,51 @@ impl Component for CommitMessageView<'_> {
     x: isize,
     y: isize,
   ) {
-    let Self { commit_idx, commit } = self;
+    let Self {
+      commit_idx,
+      num_commits,
+      commit,
+    } = self;
+    // The commit label is only useful when the changes are being split into
+    // more than two commits, since otherwise there is only one commit to view.
+    let commit_label = if *num_commits > 2 {
+      Some(format!("Commit {}/{num_commits}", commit_idx + 1))
+    } else {
+      None
+    };
+    if commit_label.is_none() && commit.message.is_none() {
+      return;
+    }
+
+    viewport.draw_blank(Rect {
+      x,
+      y,
+      width: viewport.mask_rect().width,
+      height: 1,
+    });
+    let y = y + 1;
+
+    let style = Style::default();
+    let mut message_x = x;
+    if let Some(commit_label) = commit_label {
+      let label_rect = viewport.draw_span(
+        message_x,
+        y,
+        &Span::styled(commit_label, style.add_modifier(Modifier::BOLD)),
+      );
+      message_x = label_rect.end_x() + 1;
+      if commit.message.is_some() {
+        let divider_rect = viewport.draw_span(message_x, y, &Span::raw("• "));
+        message_x = divider_rect.end_x() + 1;
+      }
+    }
     match commit {
       Commit { message: None } => {}
       Commit {
         message: Some(message),
       } => {
-        viewport.draw_blank(Rect {
-          x,
-          y,
-          width: viewport.mask_rect().width,
-          height: 1,
-        });
-        let y = y + 1;
-
-        let style = Style::default();
         let button_rect = viewport.draw_component(
-          x,
+          message_x,
           y,
           &Button {
             id: ComponentId::CommitEditMessageButton(*commit_idx),
@@ -3896,16 +7084,16 @@ impl Component for CommitMessageView<'_> {
             style.add_modifier(Modifier::UNDERLINED),
           ),
         );
-        let y = y + 1;
-
-        viewport.draw_blank(Rect {
-          x,
-          y,
-          width: viewport.mask_rect().width,
-          height: 1,
-        });
       }
     }
+    let y = y + 1;
+
+    viewport.draw_blank(Rect {
+      x,
+      y,
+      width: viewport.mask_rect().width,
+      height: 1,
+    });
   }
 }
 
@@ -3975,44 +7163,230 @@ impl Component for CommitView<'_> {
         })
       };
 
-      // Render a sticky header if necessary.
-      let mask = viewport.mask();
-      if file_view_rect.y < mask.y
-        && mask.y < file_view_rect.y + file_view_rect.height.unwrap_isize()
-      {
-        viewport.with_mask(
-          Mask {
-            x,
-            y: mask.y,
-            width: Some(viewport.mask_rect().width),
-            height: Some(1),
-          },
-          |viewport| {
-            viewport.draw_component(
-              x,
-              mask.y,
-              &FileViewHeader {
-                file_key: file_view.file_key,
-                path: file_view.path,
-                old_path: file_view.old_path,
-                is_selected: file_view.is_header_selected,
-                toggle_box: file_view.toggle_box.clone(),
-                expand_box: file_view.expand_box.clone(),
-              },
-            );
-          },
-        );
-      }
+      // Render a sticky header if necessary.
+      let mask = viewport.mask();
+      if file_view_rect.y < mask.y
+        && mask.y < file_view_rect.y + file_view_rect.height.unwrap_isize()
+      {
+        viewport.with_mask(
+          Mask {
+            x,
+            y: mask.y,
+            width: Some(viewport.mask_rect().width),
+            height: Some(1),
+          },
+          |viewport| {
+            viewport.draw_component(
+              x,
+              mask.y,
+              &FileViewHeader {
+                file_key: file_view.file_key,
+                path: file_view.path,
+                old_path: file_view.old_path,
+                similarity: file_view.similarity,
+                unloaded: file_view.unloaded,
+                is_selected: file_view.is_header_selected,
+                toggle_box: file_view.toggle_box.clone(),
+                expand_box: file_view.expand_box.clone(),
+              },
+            );
+          },
+        );
+      }
+
+      y += file_view_rect.height.unwrap_isize();
+
+      if debug_info.is_some() {
+        viewport.debug(format!(
+          "file {} dims: {file_view_rect:?}",
+          file_view.path.to_string_lossy()
+        ));
+      }
+    }
+  }
+}
+
+/// A summary of the lines changed in each file, like `git diff --stat`.
+#[derive(Clone, Debug)]
+struct SummaryView<'a> {
+  /// Describes which changes are counted as selected.
+  selected_label: Cow<'a, str>,
+  diff_stat: DiffStat,
+  file_views: Vec<SummaryFileView<'a>>,
+}
+
+impl Component for SummaryView<'_> {
+  type Id = ComponentId;
+
+  fn id(&self) -> Self::Id {
+    ComponentId::Summary
+  }
+
+  fn draw(
+    &self,
+    viewport: &mut Viewport<Self::Id>,
+    x: isize,
+    y: isize,
+  ) {
+    let Self {
+      selected_label,
+      diff_stat,
+      file_views,
+    } = self;
+    let DiffStat {
+      total,
+      selected,
+      unselected: _,
+    } = diff_stat;
+    let describe_lines = |line_counts: &LineCounts| {
+      let LineCounts {
+        num_added,
+        num_removed,
+      } = line_counts;
+      format!(
+        "{num_added} {}(+), {num_removed} {}(-)",
+        if *num_added == 1 {
+          "insertion"
+        } else {
+          "insertions"
+        },
+        if *num_removed == 1 {
+          "deletion"
+        } else {
+          "deletions"
+        },
+      )
+    };
+    let num_files = file_views.len();
+    viewport.draw_text(
+      x,
+      y,
+      Span::styled(
+        format!(
+          "{num_files} {} changed, {}; {selected_label}: +{} -{}",
+          if num_files == 1 { "file" } else { "files" },
+          describe_lines(total),
+          selected.num_added,
+          selected.num_removed,
+        ),
+        Style::default().add_modifier(Modifier::BOLD),
+      ),
+    );
+    for (file_view, file_view_y) in file_views.iter().zip(y + 1..) {
+      viewport.draw_component(x, file_view_y, file_view);
+    }
+  }
+}
+
+/// A file's line in the [`SummaryView`]. Clicking it focuses the file.
+#[derive(Clone, Debug)]
+struct SummaryFileView<'a> {
+  file_key: FileKey,
+  path: &'a Path,
+  toggle_box: TristateBox<ComponentId>,
+  diff_stat: DiffStat,
+  /// The width of the widest path in the summary.
+  path_width: usize,
+  /// The width of the largest number of changed lines in the summary.
+  count_width: usize,
+  /// The largest number of changed lines in any file in the summary, which
+  /// determines how the bars are scaled.
+  max_changed: usize,
+}
+
+impl SummaryFileView<'_> {
+  /// Paths wider than this are truncated so that the bars have space.
+  const MAX_PATH_WIDTH: usize = 50;
+}
+
+impl Component for SummaryFileView<'_> {
+  type Id = ComponentId;
 
-      y += file_view_rect.height.unwrap_isize();
+  fn id(&self) -> Self::Id {
+    ComponentId::SummaryFile(self.file_key)
+  }
 
-      if debug_info.is_some() {
-        viewport.debug(format!(
-          "file {} dims: {file_view_rect:?}",
-          file_view.path.to_string_lossy()
-        ));
+  fn draw(
+    &self,
+    viewport: &mut Viewport<Self::Id>,
+    x: isize,
+    y: isize,
+  ) {
+    let Self {
+      file_key: _,
+      path,
+      toggle_box,
+      diff_stat,
+      path_width,
+      count_width,
+      max_changed,
+    } = self;
+    let DiffStat {
+      total,
+      selected,
+      unselected: _,
+    } = diff_stat;
+
+    let toggle_box_rect = viewport.draw_span(x, y, &Span::raw(toggle_box.text()));
+    let path_x = toggle_box_rect.end_x() + 1;
+    viewport.with_mask(
+      Mask {
+        x: path_x,
+        y,
+        width: Some(*path_width),
+        height: Some(1),
+      },
+      |viewport| viewport.draw_text(path_x, y, Span::raw(path.to_string_lossy())),
+    );
+    let count_rect = viewport.draw_span(
+      path_x + path_width.unwrap_isize(),
+      y,
+      &Span::raw(format!(" | {:>count_width$} ", total.num_changed())),
+    );
+
+    // Scale the bars to fit, like `git diff --stat`. The selected lines are
+    // drawn first, and the unselected lines are dimmed.
+    let bar_width = (viewport.mask_rect().end_x() - count_rect.end_x()).clamp_into_usize();
+    let num_chars = if *max_changed <= bar_width {
+      total.num_changed()
+    } else {
+      (total.num_changed() * bar_width).div_ceil(*max_changed)
+    };
+    let scale = |num_lines: usize, num_total_lines: usize, num_total_chars: usize| {
+      if num_total_lines == 0 {
+        0
+      } else {
+        (num_total_chars * num_lines).div_ceil(num_total_lines)
       }
-    }
+    };
+    let num_added_chars = scale(total.num_added, total.num_changed(), num_chars);
+    let num_removed_chars = num_chars - num_added_chars;
+    let num_selected_added_chars = scale(selected.num_added, total.num_added, num_added_chars);
+    let num_selected_removed_chars =
+      scale(selected.num_removed, total.num_removed, num_removed_chars);
+    let dim = Style::default().add_modifier(Modifier::DIM);
+    viewport.draw_text(
+      count_rect.end_x(),
+      y,
+      Line::from(vec![
+        Span::styled(
+          "+".repeat(num_selected_added_chars),
+          Style::default().fg(Color::Green),
+        ),
+        Span::styled(
+          "+".repeat(num_added_chars - num_selected_added_chars),
+          dim.fg(Color::Green),
+        ),
+        Span::styled(
+          "-".repeat(num_selected_removed_chars),
+          Style::default().fg(Color::Red),
+        ),
+        Span::styled(
+          "-".repeat(num_removed_chars - num_selected_removed_chars),
+          dim.fg(Color::Red),
+        ),
+      ]),
+    );
   }
 }
 
@@ -4126,6 +7500,8 @@ struct FileView<'a> {
   is_header_selected: bool,
   old_path: Option<&'a Path>,
   path: &'a Path,
+  similarity: Option<u8>,
+  unloaded: Option<FileStats>,
   #[cfg(feature = "tree-sitter")]
   container_views: Option<Vec<ContainerView<'a>>>,
   section_views: Vec<SectionView<'a>>,
@@ -4160,6 +7536,8 @@ impl Component for FileView<'_> {
       expand_box,
       old_path,
       path,
+      similarity,
+      unloaded,
       #[cfg(feature = "tree-sitter")]
       container_views,
       section_views,
@@ -4173,6 +7551,8 @@ impl Component for FileView<'_> {
         file_key: *file_key,
         path,
         old_path: *old_path,
+        similarity: *similarity,
+        unloaded: *unloaded,
         is_selected: *is_header_selected,
         toggle_box: toggle_box.clone(),
         expand_box: expand_box.clone(),
@@ -4495,6 +7875,8 @@ struct FileViewHeader<'a> {
   file_key: FileKey,
   path: &'a Path,
   old_path: Option<&'a Path>,
+  similarity: Option<u8>,
+  unloaded: Option<FileStats>,
   is_selected: bool,
   toggle_box: TristateBox<ComponentId>,
   expand_box: TristateBox<ComponentId>,
@@ -4508,6 +7890,8 @@ impl Component for FileViewHeader<'_> {
       file_key,
       path: _,
       old_path: _,
+      similarity: _,
+      unloaded: _,
       is_selected: _,
       toggle_box: _,
       expand_box: _,
@@ -4525,6 +7909,8 @@ impl Component for FileViewHeader<'_> {
       file_key: _,
       path,
       old_path,
+      similarity,
+      unloaded,
       is_selected,
       toggle_box,
       expand_box,
@@ -4553,17 +7939,25 @@ impl Component for FileViewHeader<'_> {
           height: 1,
         });
         let toggle_box_rect = viewport.draw_component(x, y, toggle_box);
-        viewport.draw_text(
+        let path_rect = viewport.draw_text(
           x + toggle_box_rect.width.unwrap_isize() + 1,
           y,
           Span::styled(
             format!(
-              "{}{}",
+              "{}{}{}",
               match old_path {
-                Some(old_path) => format!("{} => ", old_path.to_string_lossy()),
+                Some(old_path) => format!(
+                  "{} {} ",
+                  old_path.to_string_lossy(),
+                  if toggle_box.use_unicode { "→" } else { "=>" }
+                ),
                 None => String::new(),
               },
               path.to_string_lossy(),
+              match similarity {
+                Some(similarity) => format!(" ({similarity}%)"),
+                None => String::new(),
+              },
             ),
             if *is_selected {
               Style::default().fg(Color::Blue)
@@ -4572,6 +7966,28 @@ impl Component for FileViewHeader<'_> {
             },
           ),
         );
+        if let Some(FileStats {
+          num_added,
+          num_removed,
+        }) = unloaded
+        {
+          let format_count = |count: &Option<usize>| match count {
+            Some(count) => count.to_string(),
+            None => "?".to_string(),
+          };
+          viewport.draw_span(
+            path_rect.end_x() + 1,
+            y,
+            &Span::styled(
+              format!(
+                "(+{} -{}, not loaded)",
+                format_count(num_added),
+                format_count(num_removed)
+              ),
+              Style::default().add_modifier(Modifier::DIM),
+            ),
+          );
+        }
       },
     );
 
@@ -4606,8 +8022,26 @@ struct SectionView<'a> {
   total_num_sections: usize,
   editable_section_num: usize,
   total_num_editable_sections: usize,
+  /// Whether checked changes are those assigned to this section's commit
+  /// (see [`File::commit_idx`]) rather than those with `is_checked` set.
+  is_multi_commit: bool,
+  moved_lines: &'a MovedLines,
+  syntax_highlights: &'a SyntaxHighlights,
+  /// The search query whose matches are highlighted, if any.
+  search_pattern: Option<&'a SearchPattern>,
+  file: &'a File<'a>,
   section: &'a Section<'a>,
-  line_start_num: usize,
+  diff_layout: DiffLayout,
+  /// The line numbers of each line in the section (see
+  /// [`File::line_numbers`]).
+  line_numbers: Vec<LineNumbers>,
+  /// Whether to show the old and new line numbers of every line in a gutter.
+  is_line_number_gutter_visible: bool,
+  /// The number of unchanged lines shown before and after each change.
+  num_context_lines: usize,
+  /// How many more unchanged lines are shown, if this is an unchanged
+  /// section.
+  context_expansion: ContextExpansion,
 }
 
 impl SectionView<'_> {
@@ -4646,9 +8080,29 @@ impl Component for SectionView<'_> {
       total_num_sections,
       editable_section_num,
       total_num_editable_sections,
+      is_multi_commit,
+      moved_lines,
+      syntax_highlights,
+      search_pattern,
+      file,
       section,
-      line_start_num,
+      diff_layout,
+      line_numbers,
+      is_line_number_gutter_visible,
+      num_context_lines,
+      context_expansion,
     } = self;
+    let find_search_matches = |line: &str| match search_pattern {
+      Some(search_pattern) => search_pattern.find_matches(line),
+      None => Vec::new(),
+    };
+    let is_in_commit = |section_idx: usize, line_idx: usize, is_checked: bool| {
+      if *is_multi_commit {
+        file.commit_idx(section_idx, line_idx) == section_key.commit_idx
+      } else {
+        is_checked
+      }
+    };
     viewport.draw_blank(Rect {
       x,
       y,
@@ -4661,114 +8115,141 @@ impl Component for SectionView<'_> {
       file_idx,
       section_idx,
     } = *section_key;
+    let line_key = |line_idx: usize| LineKey {
+      commit_idx,
+      file_idx,
+      section_idx,
+      line_idx,
+    };
+    let line_syntax_highlights = |line_idx: usize| {
+      syntax_highlights.is_highlighted(file_idx).then(|| {
+        syntax_highlights.line(LineLocation {
+          file_idx,
+          section_idx,
+          line_idx,
+        })
+      })
+    };
+    let line_numbers = |line_idx: usize| line_numbers.get(line_idx).copied().unwrap_or_default();
+    let gutter_line_numbers =
+      |line_idx: usize| is_line_number_gutter_visible.then(|| line_numbers(line_idx));
+    let line_x = x + 2;
+    let draw_unchanged_line =
+      |viewport: &mut Viewport<ComponentId>, line_idx: usize, line: &str, y: isize| {
+        let line_view = |line_num: Option<usize>| SectionLineView {
+          line_key: line_key(line_idx),
+          inner: SectionLineViewInner::Unchanged {
+            line,
+            line_num: line_num.unwrap_or_default(),
+            line_numbers: gutter_line_numbers(line_idx),
+            syntax_highlights: line_syntax_highlights(line_idx),
+            search_matches: find_search_matches(line),
+          },
+        };
+        let LineNumbers { old, new } = line_numbers(line_idx);
+        match diff_layout {
+          DiffLayout::Unified => {
+            viewport.draw_component(line_x, y, &line_view(old));
+          }
+          DiffLayout::SideBySide => {
+            let (left_mask, right_mask) =
+              draw_side_by_side_separator(viewport, *use_unicode, line_x, y);
+            viewport.with_mask(left_mask, |viewport| {
+              viewport.draw_component(line_x, y, &line_view(old))
+            });
+            viewport.with_mask(right_mask, |viewport| {
+              viewport.draw_component(right_mask.x, y, &line_view(new))
+            });
+          }
+        }
+      };
     match section {
       Section::Unchanged { lines } => {
         if lines.is_empty() {
           return;
         }
 
-        let lines: Vec<_> = lines.iter().enumerate().collect();
+        // There's no change above the first section or below the last one to
+        // show context for, so only the lines revealed by the user are shown
+        // there.
         let is_first_section = section_idx == 0;
         let is_last_section = section_idx + 1 == *total_num_sections;
-        let before_ellipsis_lines = &lines[..min(NUM_CONTEXT_LINES, lines.len())];
-        let after_ellipsis_lines = &lines[lines.len().saturating_sub(NUM_CONTEXT_LINES)..];
-
-        match (before_ellipsis_lines, after_ellipsis_lines) {
-          ([.., (last_before_idx, _)], [(first_after_idx, _), ..])
-            if *last_before_idx + 1 >= *first_after_idx
-              && !is_first_section
-              && !is_last_section =>
-          {
-            let first_before_idx = before_ellipsis_lines.first().unwrap().0;
-            let last_after_idx = after_ellipsis_lines.last().unwrap().0;
-            let overlapped_lines = &lines[first_before_idx..=last_after_idx];
-            let overlapped_lines = if is_first_section {
-              &overlapped_lines[overlapped_lines.len().saturating_sub(NUM_CONTEXT_LINES)..]
-            } else if is_last_section {
-              &overlapped_lines[..lines.len().min(NUM_CONTEXT_LINES)]
-            } else {
-              overlapped_lines
-            };
-            for (dy, (line_idx, line)) in overlapped_lines.iter().enumerate() {
-              let line_view = SectionLineView {
-                line_key: LineKey {
-                  commit_idx,
-                  file_idx,
-                  section_idx,
-                  line_idx: *line_idx,
-                },
-                inner: SectionLineViewInner::Unchanged {
-                  line: line.as_ref(),
-                  line_num: line_start_num + line_idx,
-                },
-              };
-              viewport.draw_component(x + 2, y + dy.unwrap_isize(), &line_view);
-            }
-            return;
+        let ContextExpansion {
+          num_start_lines,
+          num_end_lines,
+        } = *context_expansion;
+        let num_start_lines = if is_first_section {
+          0
+        } else {
+          *num_context_lines
+        }
+        .saturating_add(num_start_lines);
+        let num_end_lines = if is_last_section {
+          0
+        } else {
+          *num_context_lines
+        }
+        .saturating_add(num_end_lines);
+        if num_start_lines.saturating_add(num_end_lines) >= lines.len() {
+          for (line_idx, line) in lines.iter().enumerate() {
+            draw_unchanged_line(viewport, line_idx, line, y + line_idx.unwrap_isize());
           }
-          _ => {}
-        };
+          return;
+        }
 
+        let end_lines_idx = lines.len() - num_end_lines;
         let mut dy = 0;
-        if !is_first_section {
-          for (line_idx, line) in before_ellipsis_lines {
-            let line_view = SectionLineView {
-              line_key: LineKey {
-                commit_idx,
-                file_idx,
-                section_idx,
-                line_idx: *line_idx,
-              },
-              inner: SectionLineViewInner::Unchanged {
-                line: line.as_ref(),
-                line_num: line_start_num + line_idx,
-              },
-            };
-            viewport.draw_component(x + 2, y + dy, &line_view);
-            dy += 1;
-          }
+        for (line_idx, line) in lines.iter().enumerate().take(num_start_lines) {
+          draw_unchanged_line(viewport, line_idx, line, y + dy);
+          dy += 1;
         }
-
-        let should_render_ellipsis = lines.len() > NUM_CONTEXT_LINES;
-        if should_render_ellipsis {
-          let ellipsis = if *use_unicode {
-            "\u{22EE}" // Vertical Ellipsis
-          } else {
-            ":"
-          };
-          viewport.draw_span(
-            x + 6, // align with line numbering
-            y + dy,
-            &Span::styled(ellipsis, Style::default().add_modifier(Modifier::DIM)),
-          );
+        viewport.draw_component(
+          x,
+          y + dy,
+          &ContextGapView {
+            use_unicode: *use_unicode,
+            section_key: *section_key,
+            num_hidden_lines: end_lines_idx - num_start_lines,
+          },
+        );
+        dy += 1;
+        for (line_idx, line) in lines.iter().enumerate().skip(end_lines_idx) {
+          draw_unchanged_line(viewport, line_idx, line, y + dy);
           dy += 1;
         }
+      }
 
-        if !is_last_section {
-          for (line_idx, line) in after_ellipsis_lines {
-            let line_view = SectionLineView {
-              line_key: LineKey {
-                commit_idx,
-                file_idx,
-                section_idx,
-                line_idx: *line_idx,
-              },
-              inner: SectionLineViewInner::Unchanged {
-                line: line.as_ref(),
-                line_num: line_start_num + line_idx,
-              },
-            };
-            viewport.draw_component(x + 2, y + dy, &line_view);
-            dy += 1;
-          }
-        }
+      Section::Omitted { num_lines } => {
+        let description = match num_lines {
+          Some(0) => return,
+          Some(1) => "1 unchanged line not shown".to_owned(),
+          Some(num_lines) => format!("{num_lines} unchanged lines not shown"),
+          None => "Remaining lines not shown".to_owned(),
+        };
+
+        let ellipsis = if *use_unicode {
+          "\u{22EE}" // Vertical Ellipsis
+        } else {
+          ":"
+        };
+        let style = Style::default().add_modifier(Modifier::DIM);
+        let ellipsis_rect = viewport.draw_span(
+          x + 6, // align with line numbering
+          y,
+          &Span::styled(ellipsis, style),
+        );
+        viewport.draw_span(
+          ellipsis_rect.end_x() + 1,
+          y,
+          &Span::styled(description, style),
+        );
       }
 
       Section::Changed { lines } => {
         // Draw expand box at end of line.
         let expand_box_width = expand_box.text().width().unwrap_isize();
         let expand_box_rect = viewport.draw_component(
-          viewport.mask_rect().width.unwrap_isize() - expand_box_width,
+          viewport.mask_rect().end_x() - expand_box_width,
           y,
           expand_box,
         );
@@ -4812,29 +8293,25 @@ impl Component for SectionView<'_> {
         if self.is_expanded() {
           // Draw changed lines.
           let y = y + 1;
-          for (line_idx, line) in lines.iter().enumerate() {
+          let inline_changes = inline_diff::inline_changes(lines);
+          let changed_line_view = |line_idx: usize| {
             let SectionChangedLine {
               is_checked,
               change_type,
               line,
-            } = line;
+            } = &lines[line_idx];
             let is_focused = match selection {
               Some(SectionSelection::ChangedLine(selected_line_idx)) => {
                 line_idx == *selected_line_idx
               }
               Some(SectionSelection::SectionHeader) | None => false,
             };
-            let line_key = LineKey {
-              commit_idx,
-              file_idx,
-              section_idx,
-              line_idx,
-            };
+            let line_key = line_key(line_idx);
             let toggle_box = TristateBox {
               use_unicode: *use_unicode,
               id: ComponentId::ToggleBox(SelectionKey::Line(line_key)),
               icon_style: TristateIconStyle::Check,
-              tristate: Tristate::from(*is_checked),
+              tristate: Tristate::from(is_in_commit(section_idx, line_idx, *is_checked)),
               is_focused,
               is_read_only: *is_read_only,
             };
@@ -4844,20 +8321,61 @@ impl Component for SectionView<'_> {
                 toggle_box,
                 change_type: *change_type,
                 line: line.as_ref(),
+                emphasized_ranges: &inline_changes[line_idx],
+                is_moved: moved_lines.is_moved(LineLocation {
+                  file_idx,
+                  section_idx,
+                  line_idx,
+                }),
+                line_numbers: gutter_line_numbers(line_idx),
+                syntax_highlights: line_syntax_highlights(line_idx),
+                search_matches: find_search_matches(line),
               },
             };
-            let y = y + line_idx.unwrap_isize();
-            viewport.draw_component(x + 2, y, &line_view);
-            if is_focused {
-              highlight_rect(
-                viewport,
-                Rect {
-                  x: viewport.mask_rect().x,
-                  y,
-                  width: viewport.mask_rect().width,
-                  height: 1,
-                },
-              );
+            (line_view, is_focused)
+          };
+          match diff_layout {
+            DiffLayout::Unified => {
+              for line_idx in 0..lines.len() {
+                let (line_view, is_focused) = changed_line_view(line_idx);
+                let y = y + line_idx.unwrap_isize();
+                viewport.draw_component(line_x, y, &line_view);
+                if is_focused {
+                  highlight_rect(
+                    viewport,
+                    Rect {
+                      x: viewport.mask_rect().x,
+                      y,
+                      width: viewport.mask_rect().width,
+                      height: 1,
+                    },
+                  );
+                }
+              }
+            }
+            DiffLayout::SideBySide => {
+              let rows = inline_diff::align_changed_lines(lines);
+              for (row, (removed_line_idx, added_line_idx)) in rows.into_iter().enumerate() {
+                let y = y + row.unwrap_isize();
+                let (left_mask, right_mask) =
+                  draw_side_by_side_separator(viewport, *use_unicode, line_x, y);
+                for (line_idx, mask) in
+                  [(removed_line_idx, left_mask), (added_line_idx, right_mask)]
+                {
+                  let line_idx = match line_idx {
+                    Some(line_idx) => line_idx,
+                    None => continue,
+                  };
+                  let (line_view, is_focused) = changed_line_view(line_idx);
+                  let line_x = line_x.max(mask.x);
+                  viewport.with_mask(mask, |viewport| {
+                    viewport.draw_component(line_x, y, &line_view);
+                    if is_focused {
+                      highlight_rect(viewport, viewport.mask_rect());
+                    }
+                  });
+                }
+              }
             }
           }
         }
@@ -4878,7 +8396,7 @@ impl Component for SectionView<'_> {
           use_unicode: *use_unicode,
           id: ComponentId::ToggleBox(selection_key),
           icon_style: TristateIconStyle::Check,
-          tristate: Tristate::from(*is_checked),
+          tristate: Tristate::from(is_in_commit(section_idx, 0, *is_checked)),
           is_focused,
           is_read_only: *is_read_only,
         };
@@ -4891,8 +8409,117 @@ impl Component for SectionView<'_> {
           FileMode::Unix(mode) => format!("File mode set to {mode:o}"),
           FileMode::Absent => "File deleted".to_owned(),
         };
-
+
+        viewport.draw_text(x, y, Span::styled(text, Style::default().fg(Color::Blue)));
+        if is_focused {
+          highlight_rect(
+            viewport,
+            Rect {
+              x: viewport.mask_rect().x,
+              y,
+              width: viewport.mask_rect().width,
+              height: 1,
+            },
+          );
+        }
+      }
+
+      Section::Rename {
+        is_checked,
+        is_copy,
+      } => {
+        let is_focused = match selection {
+          Some(SectionSelection::SectionHeader) => true,
+          Some(SectionSelection::ChangedLine(_)) | None => false,
+        };
+        let section_key = SectionKey {
+          commit_idx,
+          file_idx,
+          section_idx,
+        };
+        let toggle_box = TristateBox {
+          use_unicode: *use_unicode,
+          id: ComponentId::ToggleBox(SelectionKey::Section(section_key)),
+          icon_style: TristateIconStyle::Check,
+          tristate: Tristate::from(is_in_commit(section_idx, 0, *is_checked)),
+          is_focused,
+          is_read_only: *is_read_only,
+        };
+        let toggle_box_rect = viewport.draw_component(x, y, &toggle_box);
+        let x = x + toggle_box_rect.width.unwrap_isize() + 1;
+
+        let old_path = file.old_path.as_deref().unwrap_or(&file.path);
+        let text = format!(
+          "File {} from {}",
+          if *is_copy { "copied" } else { "renamed" },
+          old_path.to_string_lossy(),
+        );
+        viewport.draw_text(x, y, Span::styled(text, Style::default().fg(Color::Blue)));
+        if is_focused {
+          highlight_rect(
+            viewport,
+            Rect {
+              x: viewport.mask_rect().x,
+              y,
+              width: viewport.mask_rect().width,
+              height: 1,
+            },
+          );
+        }
+      }
+
+      Section::Binary {
+        is_checked,
+        old_description,
+        new_description,
+        contents,
+      } => {
+        let is_focused = match selection {
+          Some(SectionSelection::SectionHeader) => true,
+          Some(SectionSelection::ChangedLine(_)) | None => false,
+        };
+        let section_key = SectionKey {
+          commit_idx,
+          file_idx,
+          section_idx,
+        };
+        let toggle_box = TristateBox {
+          use_unicode: *use_unicode,
+          id: ComponentId::ToggleBox(SelectionKey::Section(section_key)),
+          icon_style: TristateIconStyle::Check,
+          tristate: Tristate::from(is_in_commit(section_idx, 0, *is_checked)),
+          is_focused,
+          is_read_only: *is_read_only,
+        };
+        let toggle_box_rect = viewport.draw_component(x, y, &toggle_box);
+        let x = x + toggle_box_rect.width.unwrap_isize() + 1;
+
+        let text = {
+          let mut result = vec![if old_description.is_some() || new_description.is_some() {
+            "binary contents:"
+          } else {
+            "binary contents"
+          }
+          .to_string()];
+          let description: Vec<_> = [old_description, new_description]
+            .iter()
+            .copied()
+            .flatten()
+            .map(|s| s.as_ref())
+            .collect();
+          result.push(description.join(" -> "));
+          format!("({})", result.join(" "))
+        };
         viewport.draw_text(x, y, Span::styled(text, Style::default().fg(Color::Blue)));
+        if contents.is_some() {
+          let expand_box_width = expand_box.text().width().unwrap_isize();
+          viewport.draw_component(
+            viewport.mask_rect().end_x() - expand_box_width,
+            y,
+            expand_box,
+          );
+        }
+
         if is_focused {
           highlight_rect(
             viewport,
@@ -4904,12 +8531,29 @@ impl Component for SectionView<'_> {
             },
           );
         }
+
+        if let Some(contents) = contents {
+          if self.is_expanded() {
+            draw_hex_dump(
+              viewport,
+              toggle_box_rect.x + 2,
+              y + 1,
+              *use_unicode,
+              contents,
+            );
+          }
+        }
       }
 
-      Section::Binary {
+      Section::Symlink {
         is_checked,
-        old_description,
-        new_description,
+        old_target: old,
+        new_target: new,
+      }
+      | Section::Submodule {
+        is_checked,
+        old_commit: old,
+        new_commit: new,
       } => {
         let is_focused = match selection {
           Some(SectionSelection::SectionHeader) => true,
@@ -4924,29 +8568,23 @@ impl Component for SectionView<'_> {
           use_unicode: *use_unicode,
           id: ComponentId::ToggleBox(SelectionKey::Section(section_key)),
           icon_style: TristateIconStyle::Check,
-          tristate: Tristate::from(*is_checked),
+          tristate: Tristate::from(is_in_commit(section_idx, 0, *is_checked)),
           is_focused,
           is_read_only: *is_read_only,
         };
         let toggle_box_rect = viewport.draw_component(x, y, &toggle_box);
         let x = x + toggle_box_rect.width.unwrap_isize() + 1;
 
-        let text = {
-          let mut result = vec![if old_description.is_some() || new_description.is_some() {
-            "binary contents:"
-          } else {
-            "binary contents"
-          }
-          .to_string()];
-          let description: Vec<_> = [old_description, new_description]
-            .iter()
-            .copied()
-            .flatten()
-            .map(|s| s.as_ref())
-            .collect();
-          result.push(description.join(" -> "));
-          format!("({})", result.join(" "))
+        let label = if matches!(section, Section::Symlink { .. }) {
+          "Symlink target"
+        } else {
+          "Submodule commit"
         };
+        let describe = |value: &Option<Cow<str>>| match value {
+          Some(value) => value.to_string(),
+          None => "(none)".to_string(),
+        };
+        let text = format!("{label}: {} -> {}", describe(old), describe(new));
         viewport.draw_text(x, y, Span::styled(text, Style::default().fg(Color::Blue)));
 
         if is_focused {
@@ -4965,16 +8603,123 @@ impl Component for SectionView<'_> {
   }
 }
 
+/// Draw a side-by-side hex dump of the rows of `contents` which changed. See
+/// [`hex_dump::hex_dump_lines`].
+fn draw_hex_dump<Id: Clone + Debug + Eq + Hash>(
+  viewport: &mut Viewport<Id>,
+  x: isize,
+  y: isize,
+  use_unicode: bool,
+  contents: &BinaryContents,
+) {
+  let BinaryContents { old, new } = contents;
+  let offset_width = format!("{:x}", old.len().max(new.len())).len().max(4);
+  const UI_OMITTED_STYLE: Style = Style::new().add_modifier(Modifier::DIM);
+  let separator = if use_unicode { " \u{2502} " } else { " | " };
+  let push_row_spans = |spans: &mut Vec<Span>, bytes: &[u8], other_bytes: &[u8], style: Style| {
+    let style_for = |idx: usize| {
+      if bytes.get(idx) == other_bytes.get(idx) {
+        Style::default()
+      } else {
+        style
+      }
+    };
+    for idx in 0..hex_dump::BYTES_PER_ROW {
+      match bytes.get(idx) {
+        Some(byte) => spans.push(Span::styled(format!("{byte:02x} "), style_for(idx))),
+        None => spans.push(Span::raw("   ")),
+      }
+    }
+    for idx in 0..hex_dump::BYTES_PER_ROW {
+      match bytes.get(idx) {
+        Some(byte) => spans.push(Span::styled(
+          hex_dump::ascii_char(*byte).to_string(),
+          style_for(idx),
+        )),
+        None => spans.push(Span::raw(" ")),
+      }
+    }
+  };
+
+  for (dy, line) in hex_dump::hex_dump_lines(old, new).into_iter().enumerate() {
+    let y = y + dy.unwrap_isize();
+    match line {
+      HexDumpLine::Row { offset } => {
+        let old_bytes = hex_dump::row_bytes(old, offset);
+        let new_bytes = hex_dump::row_bytes(new, offset);
+        let mut spans = vec![Span::raw(format!("{offset:0offset_width$x}  "))];
+        push_row_spans(
+          &mut spans,
+          old_bytes,
+          new_bytes,
+          Style::default().fg(Color::Red),
+        );
+        spans.push(Span::raw(separator));
+        push_row_spans(
+          &mut spans,
+          new_bytes,
+          old_bytes,
+          Style::default().fg(Color::Green),
+        );
+        viewport.draw_text(x, y, Line::from(spans));
+      }
+      HexDumpLine::Omitted { num_rows } => {
+        let ellipsis = if use_unicode { "\u{22EE}" } else { ":" };
+        let description = if num_rows == 1 {
+          "1 identical row not shown".to_owned()
+        } else {
+          format!("{num_rows} identical rows not shown")
+        };
+        viewport.draw_span(
+          x,
+          y,
+          &Span::styled(format!("{ellipsis} {description}"), UI_OMITTED_STYLE),
+        );
+      }
+      HexDumpLine::Truncated { num_rows } => {
+        viewport.draw_span(
+          x,
+          y,
+          &Span::styled(
+            format!("({num_rows} more rows not shown)"),
+            UI_OMITTED_STYLE,
+          ),
+        );
+      }
+    }
+  }
+}
+
 #[derive(Clone, Debug)]
 enum SectionLineViewInner<'a> {
   Unchanged {
     line: &'a str,
     line_num: usize,
+    /// The old and new line numbers to show in the gutter, if it's visible.
+    /// They replace `line_num`.
+    line_numbers: Option<LineNumbers>,
+    /// The syntax highlights of `line`, or `None` if its file isn't
+    /// highlighted (see [`SyntaxHighlights`]).
+    syntax_highlights: Option<&'a [Highlight]>,
+    /// The byte ranges of `line` which match the search query.
+    search_matches: Vec<Range<usize>>,
   },
   Changed {
     toggle_box: TristateBox<ComponentId>,
     change_type: ChangeType,
     line: &'a str,
+    /// The byte ranges of `line` which differ from its paired line, as
+    /// computed by [`inline_diff::inline_changes`].
+    emphasized_ranges: &'a [Range<usize>],
+    /// Whether the line was moved from or to elsewhere (see [`MovedLines`]).
+    is_moved: bool,
+    /// The old and new line numbers to show in the gutter, if it's visible.
+    line_numbers: Option<LineNumbers>,
+    /// The syntax highlights of `line`, or `None` if its file isn't
+    /// highlighted (see [`SyntaxHighlights`]).
+    syntax_highlights: Option<&'a [Highlight]>,
+    /// The byte ranges of `line` which match the search query.
+    search_matches: Vec<Range<usize>>,
   },
 }
 
@@ -5054,6 +8799,78 @@ fn push_spans_from_line<'line>(
   }
 }
 
+/// Like [`push_spans_from_line`], but the style of each of `styled_ranges` is
+/// applied to the parts of `line` in its byte ranges.
+fn push_styled_spans_from_line<'line>(
+  line: &'line str,
+  styled_ranges: &[(&[Range<usize>], Style)],
+  spans: &mut Vec<Span<'line>>,
+) {
+  let mut boundaries: Vec<usize> = styled_ranges
+    .iter()
+    .flat_map(|(ranges, _)| ranges.iter())
+    .flat_map(|range| [range.start, range.end])
+    .chain([0, line.len()])
+    .collect();
+  boundaries.sort_unstable();
+  boundaries.dedup();
+  for window in boundaries.windows(2) {
+    let (start, end) = (window[0], window[1]);
+    let num_spans = spans.len();
+    push_spans_from_line(&line[start..end], spans);
+    for (ranges, style) in styled_ranges {
+      if ranges.iter().any(|range| range.contains(&start)) {
+        for span in &mut spans[num_spans..] {
+          span.style = span.style.patch(*style);
+        }
+      }
+    }
+  }
+}
+
+/// The text of the gutter which shows a line's old and new line numbers. The
+/// number for a version which the line isn't in is left blank.
+fn line_number_gutter(line_numbers: LineNumbers) -> String {
+  let LineNumbers { old, new } = line_numbers;
+  let format_line_num = |line_num: Option<usize>| match line_num {
+    Some(line_num) => line_num.to_string(),
+    None => String::new(),
+  };
+  format!("{:>5} {:>5} ", format_line_num(old), format_line_num(new))
+}
+
+/// The byte ranges of each kind of syntax in a line, and the color to show
+/// them in. Only the foreground is set, so that the background color of
+/// changed lines shows through.
+fn syntax_highlight_ranges(highlights: &[Highlight]) -> Vec<(Vec<Range<usize>>, Style)> {
+  let mut result: Vec<(HighlightKind, Vec<Range<usize>>)> = Vec::new();
+  for Highlight { range, kind } in highlights {
+    match result.iter_mut().find(|(other_kind, _)| other_kind == kind) {
+      Some((_, ranges)) => ranges.push(range.clone()),
+      None => result.push((*kind, vec![range.clone()])),
+    }
+  }
+  result
+    .into_iter()
+    .map(|(kind, ranges)| {
+      let color = match kind {
+        HighlightKind::Attribute => Color::LightYellow,
+        HighlightKind::Comment => Color::DarkGray,
+        HighlightKind::Constant | HighlightKind::Number => Color::LightCyan,
+        HighlightKind::Function => Color::LightBlue,
+        HighlightKind::Keyword => Color::LightMagenta,
+        HighlightKind::Property => Color::Cyan,
+        HighlightKind::String => Color::LightGreen,
+        HighlightKind::Type => Color::Yellow,
+      };
+      (ranges, Style::default().fg(color))
+    })
+    .collect()
+}
+
+/// The style of the parts of lines which match the search query.
+const UI_SEARCH_MATCH_STYLE: Style = Style::new().add_modifier(Modifier::REVERSED);
+
 #[derive(Clone, Debug)]
 struct SectionLineView<'a> {
   line_key: LineKey,
@@ -5081,13 +8898,29 @@ impl Component for SectionLineView<'_> {
     });
 
     match &self.inner {
-      SectionLineViewInner::Unchanged { line, line_num } => {
+      SectionLineViewInner::Unchanged {
+        line,
+        line_num,
+        line_numbers,
+        syntax_highlights,
+        search_matches,
+      } => {
         // Pad the number in 5 columns because that will align the
         // beginning of the actual text with the `+`/`-` of the changed
-        // lines.
-        let line_number = Span::raw(format!("{line_num:5} "));
+        // lines. The gutter is aligned with the gutter of changed lines, after
+        // their checkbox.
+        let line_number = match line_numbers {
+          Some(line_numbers) => Span::raw(format!("    {}  ", line_number_gutter(*line_numbers))),
+          None => Span::raw(format!("{line_num:5} ")),
+        };
         let mut spans = vec![line_number];
-        push_spans_from_line(line, &mut spans);
+        let syntax_ranges = syntax_highlight_ranges(syntax_highlights.unwrap_or_default());
+        let mut styled_ranges = syntax_ranges
+          .iter()
+          .map(|(ranges, style)| (ranges.as_slice(), *style))
+          .collect::<Vec<_>>();
+        styled_ranges.push((search_matches, UI_SEARCH_MATCH_STYLE));
+        push_styled_spans_from_line(line, &styled_ranges, &mut spans);
 
         const UI_UNCHANGED_STYLE: Style = Style::new().add_modifier(Modifier::DIM);
         viewport.draw_text(x, y, Line::from(spans).style(UI_UNCHANGED_STYLE));
@@ -5097,17 +8930,54 @@ impl Component for SectionLineView<'_> {
         toggle_box,
         change_type,
         line,
+        emphasized_ranges,
+        is_moved,
+        line_numbers,
+        syntax_highlights,
+        search_matches,
       } => {
         let toggle_box_rect = viewport.draw_component(x, y, toggle_box);
         let x = toggle_box_rect.end_x() + 1;
 
-        let (change_type_text, changed_line_style) = match change_type {
-          ChangeType::Added => ("+ ", Style::default().fg(Color::Green)),
-          ChangeType::Removed => ("- ", Style::default().fg(Color::Red)),
+        // Moved lines are colored like `git diff --color-moved`.
+        let (change_type_text, change_type_color, background_color) = match (change_type, is_moved)
+        {
+          (ChangeType::Added, false) => ("+ ", Color::Green, Color::Indexed(22)),
+          (ChangeType::Removed, false) => ("- ", Color::Red, Color::Indexed(52)),
+          (ChangeType::Added, true) => ("+ ", Color::Cyan, Color::Indexed(23)),
+          (ChangeType::Removed, true) => ("- ", Color::Magenta, Color::Indexed(53)),
+        };
+        // Highlighted lines are shown in a background color instead, so that
+        // the syntax colors are shown over it.
+        let (change_type_style, changed_line_style) = match syntax_highlights {
+          Some(_) => (
+            Style::default().fg(change_type_color),
+            Style::default().bg(background_color),
+          ),
+          None => (Style::default(), Style::default().fg(change_type_color)),
         };
 
-        let mut spans = vec![Span::raw(change_type_text)];
-        push_spans_from_line(line, &mut spans);
+        // Emphasize the parts of the line which differ from its paired line,
+        // like `git diff --word-diff`.
+        const UI_EMPHASIZED_STYLE: Style = Style::new()
+          .add_modifier(Modifier::BOLD)
+          .add_modifier(Modifier::UNDERLINED);
+        let mut spans = Vec::new();
+        if let Some(line_numbers) = line_numbers {
+          spans.push(Span::styled(
+            line_number_gutter(*line_numbers),
+            Style::default().add_modifier(Modifier::DIM),
+          ));
+        }
+        spans.push(Span::styled(change_type_text, change_type_style));
+        let syntax_ranges = syntax_highlight_ranges(syntax_highlights.unwrap_or_default());
+        let mut styled_ranges = syntax_ranges
+          .iter()
+          .map(|(ranges, style)| (ranges.as_slice(), *style))
+          .collect::<Vec<_>>();
+        styled_ranges.push((emphasized_ranges, UI_EMPHASIZED_STYLE));
+        styled_ranges.push((search_matches, UI_SEARCH_MATCH_STYLE));
+        push_styled_spans_from_line(line, &styled_ranges, &mut spans);
 
         viewport.draw_text(x, y, Line::from(spans).style(changed_line_style));
       }
@@ -5232,9 +9102,7 @@ impl Component for HelpDialog {
       Line::from("    Quit/Cancel             q           Next/Prev               j/k or ↓/↑"),
       Line::from("    Confirm changes         c           Next/Prev of same type  PgDn/PgUp"),
       Line::from("    Force quit              ^c          Move out & fold         h or ←"),
-      Line::from(
-        "                                        Move out & don't fold   H or Shift-←    ",
-      ),
+      Line::from("    Show help               F1          Move out & don't fold   H or Shift-←"),
       Line::from(vec![
         Span::raw("    "),
         Span::styled("View controls", Style::new().bold().underlined()),
@@ -5246,7 +9114,14 @@ impl Component for HelpDialog {
         Span::styled("Scrolling", Style::new().bold().underlined()),
       ]),
       Line::from("    Edit commit message     e           Scroll up/down          ^y/^e"),
-      Line::from("                                                             or ^↑/^↓"),
+      Line::from("    Split/Unsplit section   s/S                              or ^↑/^↓"),
+      Line::from("    Show/hide summary       d"),
+      Line::from("    Show/hide dir tree      t"),
+      Line::from("    Unified/side-by-side    v"),
+      Line::from("    Show/hide line numbers  #"),
+      Line::from("    More context above      {"),
+      Line::from("    More context below      }"),
+      Line::from("    Show all context        ="),
       Line::from(vec![
         Span::raw("    "),
         Span::styled("Selection", Style::new().bold().underlined()),
@@ -5254,8 +9129,15 @@ impl Component for HelpDialog {
       ]),
       Line::from("    Toggle current          Space                            or ^PgUp/^PgDn"),
       Line::from("    Toggle and advance      Enter       Previous/Next page      ^u/^d"),
-      Line::from("    Invert all              a"),
+      Line::from("    Invert all              a           Previous/Next commit    [/]"),
       Line::from("    Invert all uniformly    A"),
+      Line::from(vec![
+        Span::raw("    Move to commit          1-9         "),
+        Span::styled("Search", Style::new().bold().underlined()),
+      ]),
+      Line::from("    Undo/Redo               u/^r        Search forward/backward / or ?"),
+      Line::from("                                        Next/Prev match         n/N"),
+      Line::from("                                        Go to file or symbol    ^p"),
     ]);
 
     let quit_button = Button {
@@ -5276,6 +9158,87 @@ impl Component for HelpDialog {
   }
 }
 
+/// The row standing in for the hidden lines of an unchanged section, with
+/// buttons to show some or all of them.
+struct ContextGapView {
+  use_unicode: bool,
+  section_key: SectionKey,
+  num_hidden_lines: usize,
+}
+
+impl Component for ContextGapView {
+  type Id = ComponentId;
+
+  fn id(&self) -> Self::Id {
+    ComponentId::ContextGap(self.section_key)
+  }
+
+  fn draw(
+    &self,
+    viewport: &mut Viewport<Self::Id>,
+    x: isize,
+    y: isize,
+  ) {
+    let Self {
+      use_unicode,
+      section_key,
+      num_hidden_lines,
+    } = self;
+    let (ellipsis, down_arrow, up_arrow) = if *use_unicode {
+      (
+        "\u{22EE}", // Vertical Ellipsis
+        "\u{2193}", // Downwards Arrow
+        "\u{2191}", // Upwards Arrow
+      )
+    } else {
+      (":", "v", "^")
+    };
+    let style = Style::default().add_modifier(Modifier::DIM);
+    let ellipsis_rect = viewport.draw_span(
+      x + 6, // align with line numbering
+      y,
+      &Span::styled(ellipsis, style),
+    );
+    let description = if *num_hidden_lines == 1 {
+      "1 unchanged line not shown".to_owned()
+    } else {
+      format!("{num_hidden_lines} unchanged lines not shown")
+    };
+    let description_rect = viewport.draw_span(
+      ellipsis_rect.end_x() + 1,
+      y,
+      &Span::styled(description, style),
+    );
+
+    // Showing more lines would show all of them anyways if there aren't many.
+    let mut buttons = Vec::new();
+    if *num_hidden_lines > NUM_CONTEXT_LINES_PER_EXPANSION {
+      buttons.extend([
+        (
+          ContextAction::MoreDown,
+          Cow::Owned(format!("{down_arrow} {NUM_CONTEXT_LINES_PER_EXPANSION}")),
+        ),
+        (
+          ContextAction::MoreUp,
+          Cow::Owned(format!("{up_arrow} {NUM_CONTEXT_LINES_PER_EXPANSION}")),
+        ),
+      ]);
+    }
+    buttons.push((ContextAction::All, Cow::Borrowed("all")));
+    let mut button_x = description_rect.end_x() + 1;
+    for (context_action, label) in buttons {
+      let button = Button {
+        id: ComponentId::ContextButton(*section_key, context_action),
+        label,
+        style: Style::default(),
+        is_focused: false,
+      };
+      viewport.draw_component(button_x, y, &button);
+      button_x += button.width().unwrap_isize() + 1;
+    }
+  }
+}
+
 struct Button<'a, Id> {
   id: Id,
   label: Cow<'a, str>,
@@ -5384,6 +9347,41 @@ impl<Id: Clone + Debug + Eq + Hash> Component for Dialog<'_, Id> {
   }
 }
 
+/// The number of lines in the old and new versions of the file which are
+/// covered by `section`.
+/// Draw the line between the old and new lines of the side-by-side layout on
+/// the row at `y`, where the lines start at `x`, and return the masks of the
+/// two halves of the row.
+fn draw_side_by_side_separator(
+  viewport: &mut Viewport<ComponentId>,
+  use_unicode: bool,
+  x: isize,
+  y: isize,
+) -> (Mask, Mask) {
+  let mask_rect = viewport.mask_rect();
+  let right_x = x + (mask_rect.end_x() - x) / 2;
+  let separator_x = right_x - 1;
+  let separator = if use_unicode { "\u{2502}" } else { "|" };
+  viewport.draw_span(
+    separator_x,
+    y,
+    &Span::styled(separator, Style::default().add_modifier(Modifier::DIM)),
+  );
+  let left_mask = Mask {
+    x: mask_rect.x,
+    y,
+    width: Some((separator_x - mask_rect.x).clamp_into_usize()),
+    height: Some(1),
+  };
+  let right_mask = Mask {
+    x: right_x,
+    y,
+    width: Some((mask_rect.end_x() - right_x).clamp_into_usize()),
+    height: Some(1),
+  };
+  (left_mask, right_mask)
+}
+
 fn highlight_rect<Id: Clone + Debug + Eq + Hash>(
   viewport: &mut Viewport<Id>,
   rect: Rect,
@@ -5429,6 +9427,9 @@ mod tests {
         path: Cow::Borrowed(Path::new("foo/bar")),
         file_mode: FileMode::FILE_DEFAULT,
         sections: Default::default(),
+        commit_assignments: Default::default(),
+        similarity: None,
+        unloaded: None,
         #[cfg(feature = "tree-sitter")]
         containers: None,
       }],
@@ -5450,6 +9451,280 @@ mod tests {
       }
   }
 
+  #[test]
+  fn test_changed_line_emphasized_ranges() {
+    let line_key = LineKey {
+      commit_idx: 0,
+      file_idx: 0,
+      section_idx: 0,
+      line_idx: 0,
+    };
+    let line_view = SectionLineView {
+      line_key,
+      inner: SectionLineViewInner::Changed {
+        toggle_box: TristateBox {
+          use_unicode: false,
+          id: ComponentId::ToggleBox(SelectionKey::Line(line_key)),
+          icon_style: TristateIconStyle::Check,
+          tristate: Tristate::False,
+          is_focused: false,
+          is_read_only: false,
+        },
+        change_type: ChangeType::Added,
+        line: "foo(1, 3);\n",
+        emphasized_ranges: &[0..3, 7..8],
+        is_moved: false,
+        line_numbers: None,
+        syntax_highlights: None,
+        search_matches: Vec::new(),
+      },
+    };
+    let area = ratatui::layout::Rect::new(0, 0, 20, 1);
+    let mut buffer = Buffer::empty(area);
+    let mut viewport = Viewport::<ComponentId>::new(&mut buffer, area.into());
+    viewport.draw_component(0, 0, &line_view);
+
+    let emphasized_cells: String = buffer
+      .content
+      .iter()
+      .filter(|cell| cell.modifier.contains(Modifier::UNDERLINED))
+      .map(|cell| cell.symbol())
+      .collect();
+    assert_eq!(buffer_view(&buffer), "\"[ ] + foo(1, 3);⏎   \"\n");
+    assert_eq!(emphasized_cells, "foo3");
+  }
+
+  #[test]
+  fn test_changed_line_search_matches() {
+    let line_key = LineKey {
+      commit_idx: 0,
+      file_idx: 0,
+      section_idx: 0,
+      line_idx: 0,
+    };
+    let line_view = SectionLineView {
+      line_key,
+      inner: SectionLineViewInner::Changed {
+        toggle_box: TristateBox {
+          use_unicode: false,
+          id: ComponentId::ToggleBox(SelectionKey::Line(line_key)),
+          icon_style: TristateIconStyle::Check,
+          tristate: Tristate::False,
+          is_focused: false,
+          is_read_only: false,
+        },
+        change_type: ChangeType::Added,
+        line: "foo(1, 3);\n",
+        emphasized_ranges: &[0..3, 7..8],
+        is_moved: false,
+        line_numbers: None,
+        syntax_highlights: None,
+        search_matches: vec![2..5, 9..10],
+      },
+    };
+    let area = ratatui::layout::Rect::new(0, 0, 20, 1);
+    let mut buffer = Buffer::empty(area);
+    let mut viewport = Viewport::<ComponentId>::new(&mut buffer, area.into());
+    viewport.draw_component(0, 0, &line_view);
+
+    let cells_with_modifier = |modifier: Modifier| -> String {
+      buffer
+        .content
+        .iter()
+        .filter(|cell| cell.modifier.contains(modifier))
+        .map(|cell| cell.symbol())
+        .collect()
+    };
+    assert_eq!(buffer_view(&buffer), "\"[ ] + foo(1, 3);⏎   \"\n");
+    assert_eq!(cells_with_modifier(Modifier::UNDERLINED), "foo3");
+    assert_eq!(cells_with_modifier(Modifier::REVERSED), "o(1;");
+  }
+
+  #[test]
+  fn test_changed_line_syntax_highlights() {
+    let line_key = LineKey {
+      commit_idx: 0,
+      file_idx: 0,
+      section_idx: 0,
+      line_idx: 0,
+    };
+    let line_view = |syntax_highlights| SectionLineView {
+      line_key,
+      inner: SectionLineViewInner::Changed {
+        toggle_box: TristateBox {
+          use_unicode: false,
+          id: ComponentId::ToggleBox(SelectionKey::Line(line_key)),
+          icon_style: TristateIconStyle::Check,
+          tristate: Tristate::False,
+          is_focused: false,
+          is_read_only: false,
+        },
+        change_type: ChangeType::Removed,
+        line: "let x = 1;\n",
+        emphasized_ranges: &[],
+        is_moved: false,
+        line_numbers: None,
+        syntax_highlights,
+        search_matches: Vec::new(),
+      },
+    };
+    let cell_colors = |line_view: &SectionLineView| {
+      let area = ratatui::layout::Rect::new(0, 0, 20, 1);
+      let mut buffer = Buffer::empty(area);
+      let mut viewport = Viewport::<ComponentId>::new(&mut buffer, area.into());
+      viewport.draw_component(0, 0, line_view);
+      buffer
+        .content
+        .iter()
+        .skip(4)
+        .take(11)
+        .map(|cell| format!("{:?} {:?} {:?}", cell.symbol(), cell.fg, cell.bg))
+        .collect::<Vec<_>>()
+    };
+
+    let highlights = [
+      Highlight {
+        range: 0..3,
+        kind: HighlightKind::Keyword,
+      },
+      Highlight {
+        range: 8..9,
+        kind: HighlightKind::Number,
+      },
+    ];
+    insta::assert_debug_snapshot!(cell_colors(&line_view(Some(&highlights))), @r#"
+    [
+        "\"-\" Red Indexed(52)",
+        "\" \" Red Indexed(52)",
+        "\"l\" LightMagenta Indexed(52)",
+        "\"e\" LightMagenta Indexed(52)",
+        "\"t\" LightMagenta Indexed(52)",
+        "\" \" Reset Indexed(52)",
+        "\"x\" Reset Indexed(52)",
+        "\" \" Reset Indexed(52)",
+        "\"=\" Reset Indexed(52)",
+        "\" \" Reset Indexed(52)",
+        "\"1\" LightCyan Indexed(52)",
+    ]
+    "#);
+
+    // Lines in files which aren't highlighted keep their usual colors.
+    insta::assert_debug_snapshot!(cell_colors(&line_view(None)), @r#"
+    [
+        "\"-\" Red Reset",
+        "\" \" Red Reset",
+        "\"l\" Red Reset",
+        "\"e\" Red Reset",
+        "\"t\" Red Reset",
+        "\" \" Red Reset",
+        "\"x\" Red Reset",
+        "\" \" Red Reset",
+        "\"=\" Red Reset",
+        "\" \" Red Reset",
+        "\"1\" Red Reset",
+    ]
+    "#);
+  }
+
+  #[test]
+  #[cfg(feature = "tree-sitter")]
+  fn test_undo_updates_container_checked_state() {
+    use crate::{ChangeType, SectionChangedLine, SemanticContainer};
+
+    let file = File {
+      old_path: None,
+      path: Cow::Borrowed(Path::new("test.rs")),
+      file_mode: FileMode::FILE_DEFAULT,
+      sections: vec![Section::Changed {
+        lines: vec![SectionChangedLine {
+          is_checked: false,
+          change_type: ChangeType::Added,
+          line: Cow::Borrowed("line 1"),
+        }],
+      }],
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
+      containers: Some(vec![SemanticContainer::Function {
+        name: "my_fn".to_string(),
+        section_indices: vec![0],
+        is_checked: false,
+        is_partial: false,
+      }]),
+    };
+    let state = RecordState {
+      is_read_only: false,
+      commits: Default::default(),
+      files: vec![file],
+    };
+    let mut input = TestingInput::new(80, 24, []);
+    let mut recorder = Recorder::new(state, &mut input);
+    let container_key = ContainerKey {
+      commit_idx: 0,
+      file_idx: 0,
+      container_idx: 0,
+    };
+
+    let undo_entry = recorder.make_undo_entry();
+    recorder
+      .toggle_item(SelectionKey::Container(container_key))
+      .unwrap();
+    recorder.push_undo_entry(undo_entry);
+    assert_eq!(
+      recorder.container_tristate(container_key).unwrap(),
+      Tristate::True
+    );
+
+    recorder.undo();
+    assert_eq!(
+      recorder.container_tristate(container_key).unwrap(),
+      Tristate::False
+    );
+    recorder.redo();
+    assert_eq!(
+      recorder.container_tristate(container_key).unwrap(),
+      Tristate::True
+    );
+  }
+
+  #[test]
+  fn test_undo_depth_is_limited() {
+    let state = RecordState {
+      is_read_only: false,
+      commits: Default::default(),
+      files: vec![File {
+        old_path: None,
+        path: Cow::Borrowed(Path::new("foo")),
+        file_mode: FileMode::FILE_DEFAULT,
+        sections: vec![Section::Changed {
+          lines: vec![SectionChangedLine {
+            is_checked: false,
+            change_type: ChangeType::Added,
+            line: Cow::Borrowed("line 1\n"),
+          }],
+        }],
+        commit_assignments: Default::default(),
+        similarity: None,
+        unloaded: None,
+        #[cfg(feature = "tree-sitter")]
+        containers: None,
+      }],
+    };
+    let mut input = TestingInput::new(80, 24, []);
+    let mut recorder = Recorder::new(state, &mut input);
+    for _ in 0..MAX_UNDO_DEPTH + 5 {
+      let undo_entry = recorder.make_undo_entry();
+      recorder.toggle_all();
+      recorder.push_undo_entry(undo_entry);
+    }
+    assert_eq!(recorder.undo_stack.len(), MAX_UNDO_DEPTH);
+
+    while !recorder.undo_stack.is_empty() {
+      recorder.undo();
+    }
+    assert_eq!(recorder.redo_stack.len(), MAX_UNDO_DEPTH);
+  }
+
   #[test]
   #[cfg(feature = "tree-sitter")]
   fn test_function_expanded_items_contains_sections() {
@@ -5467,6 +9742,9 @@ mod tests {
           line: Cow::Borrowed("line 1"),
         }],
       }],
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       containers: Some(vec![SemanticContainer::Function {
         name: "my_fn".to_string(),
         section_indices: vec![0],
@@ -5602,6 +9880,9 @@ mod tests {
           },
         ],
       }],
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       containers: Some(vec![SemanticContainer::Function {
         name: "test_function".to_string(),
         section_indices: vec![0],
@@ -5736,6 +10017,9 @@ mod tests {
           line: Cow::Borrowed("line 1"),
         }],
       }],
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       containers: Some(vec![SemanticContainer::Function {
         name: "my_function".to_string(),
         section_indices: vec![0],
@@ -5824,6 +10108,9 @@ mod tests {
           line: Cow::Borrowed("field_value"),
         }],
       }],
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       containers: Some(vec![SemanticContainer::Struct {
         name: "TestStruct".to_string(),
         children: vec![SemanticContainer::Field {
@@ -5935,6 +10222,9 @@ mod tests {
           line: Cow::Borrowed("new line"),
         }],
       }],
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       containers: Some(vec![SemanticContainer::Function {
         name: "test_fn".to_string(),
         section_indices: vec![0],
@@ -6044,6 +10334,9 @@ mod tests {
           ],
         },
       ],
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       containers: Some(vec![
         SemanticContainer::Function {
           name: "function1".to_string(),
@@ -4,9 +4,10 @@ use std::{borrow::Cow, iter};
 use assert_matches::assert_matches;
 use insta::{assert_debug_snapshot, assert_snapshot};
 use scm_record::helpers::{make_binary_description, TestingInput};
+use scm_record::search::SearchOptions;
 use scm_record::{
-  ChangeType, Commit, Event, File, FileMode, RecordError, RecordState, Recorder, Section,
-  SectionChangedLine, TestingScreenshot,
+  BinaryContents, ChangeType, Commit, Event, File, FileMode, FileStats, LineNumbers, RecordError,
+  RecordState, Recorder, Section, SectionChangedLine, SectionProvider, TestingScreenshot, Tristate,
 };
 
 type TestResult = Result<(), scm_record::RecordError>;
@@ -20,6 +21,9 @@ fn example_contents() -> RecordState<'static> {
         old_path: None,
         path: Cow::Borrowed(Path::new("foo/bar")),
         file_mode: FileMode::FILE_DEFAULT,
+        commit_assignments: Default::default(),
+        similarity: None,
+        unloaded: None,
         #[cfg(feature = "tree-sitter")]
         containers: None,
         sections: vec![
@@ -61,6 +65,9 @@ fn example_contents() -> RecordState<'static> {
         old_path: None,
         path: Cow::Borrowed(Path::new("baz")),
         file_mode: FileMode::FILE_DEFAULT,
+        commit_assignments: Default::default(),
+        similarity: None,
+        unloaded: None,
         #[cfg(feature = "tree-sitter")]
         containers: None,
         sections: vec![
@@ -135,7 +142,7 @@ fn test_select_scroll_into_view() -> TestResult {
   insta::assert_snapshot!(initial, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "(◐) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -181,7 +188,7 @@ fn test_toggle_all() -> TestResult {
   insta::assert_snapshot!(before, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "(◐) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -203,7 +210,7 @@ fn test_toggle_all() -> TestResult {
   insta::assert_snapshot!(after, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "(◐) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -253,7 +260,7 @@ fn test_toggle_all_uniform() -> TestResult {
   insta::assert_snapshot!(initial, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "(◐) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -265,7 +272,7 @@ fn test_toggle_all_uniform() -> TestResult {
   insta::assert_snapshot!(first_toggle, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "(●) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -277,7 +284,7 @@ fn test_toggle_all_uniform() -> TestResult {
   insta::assert_snapshot!(second_toggle, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "( ) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -289,7 +296,7 @@ fn test_toggle_all_uniform() -> TestResult {
   insta::assert_snapshot!(third_toggle, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "(●) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -322,7 +329,7 @@ fn test_quit_dialog_size() -> TestResult {
   insta::assert_snapshot!(expect_quit_dialog_to_be_centered, @r###"
     "[File] [Edit] [Select] [View]                                                                       "
     "(◐) foo/bar                                                                                      (-)"
-    "        ⋮                                                                                           "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                                          "
     "       18 this is some text⏎                                                                        "
     "       19 this is some text⏎                                                                        "
     "       20 this is some text⏎                                                                        "
@@ -415,7 +422,7 @@ fn test_quit_dialog_keyboard_navigation() -> TestResult {
   insta::assert_snapshot!(expect_q_closes_quit_dialog, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "(◐) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -508,7 +515,7 @@ fn test_quit_dialog_buttons() -> TestResult {
   insta::assert_snapshot!(expect_back_button_closes_quit_dialog, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "(◐) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -551,6 +558,9 @@ fn test_enter_next() -> TestResult {
         old_path: None,
         path: Cow::Borrowed(Path::new("foo")),
         file_mode: FileMode::FILE_DEFAULT,
+        commit_assignments: Default::default(),
+        similarity: None,
+        unloaded: None,
         #[cfg(feature = "tree-sitter")]
         containers: None,
         sections: vec![Section::Changed {
@@ -572,6 +582,9 @@ fn test_enter_next() -> TestResult {
         old_path: None,
         path: Cow::Borrowed(Path::new("bar")),
         file_mode: FileMode::FILE_DEFAULT,
+        commit_assignments: Default::default(),
+        similarity: None,
+        unloaded: None,
         #[cfg(feature = "tree-sitter")]
         containers: None,
         sections: vec![Section::Changed {
@@ -640,6 +653,9 @@ fn test_file_mode_change() -> TestResult {
         old_path: None,
         path: Cow::Borrowed(Path::new("foo")),
         file_mode: FileMode::FILE_DEFAULT,
+        commit_assignments: Default::default(),
+        similarity: None,
+        unloaded: None,
         #[cfg(feature = "tree-sitter")]
         containers: None,
         sections: vec![],
@@ -648,6 +664,9 @@ fn test_file_mode_change() -> TestResult {
         old_path: None,
         path: Cow::Borrowed(Path::new("bar")),
         file_mode: FileMode::FILE_DEFAULT,
+        commit_assignments: Default::default(),
+        similarity: None,
+        unloaded: None,
         #[cfg(feature = "tree-sitter")]
         containers: None,
         sections: vec![Section::FileMode {
@@ -659,6 +678,9 @@ fn test_file_mode_change() -> TestResult {
         old_path: None,
         path: Cow::Borrowed(Path::new("qux")),
         file_mode: FileMode::FILE_DEFAULT,
+        commit_assignments: Default::default(),
+        similarity: None,
+        unloaded: None,
         #[cfg(feature = "tree-sitter")]
         containers: None,
         sections: vec![],
@@ -704,6 +726,9 @@ fn test_file_mode_change() -> TestResult {
                     33188,
                 ),
                 sections: [],
+                commit_assignments: [],
+                similarity: None,
+                unloaded: None,
                 containers: None,
             },
             File {
@@ -720,6 +745,9 @@ fn test_file_mode_change() -> TestResult {
                         ),
                     },
                 ],
+                commit_assignments: [],
+                similarity: None,
+                unloaded: None,
                 containers: None,
             },
             File {
@@ -729,6 +757,9 @@ fn test_file_mode_change() -> TestResult {
                     33188,
                 ),
                 sections: [],
+                commit_assignments: [],
+                similarity: None,
+                unloaded: None,
                 containers: None,
             },
         ],
@@ -773,6 +804,9 @@ fn test_abbreviate_unchanged_sections() -> TestResult {
       old_path: None,
       path: Cow::Borrowed(Path::new("foo")),
       file_mode: FileMode::FILE_DEFAULT,
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       #[cfg(feature = "tree-sitter")]
       containers: None,
       sections: vec![
@@ -839,7 +873,7 @@ fn test_abbreviate_unchanged_sections() -> TestResult {
   insta::assert_snapshot!(initial, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "( ) foo                                                                      (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 3 unchanged lines not shown [all]                                     "
     "        4 start line 4/6⏎                                                       "
     "        5 start line 5/6⏎                                                       "
     "        6 start line 6/6⏎                                                       "
@@ -848,7 +882,7 @@ fn test_abbreviate_unchanged_sections() -> TestResult {
     "        7 middle line 1/7⏎                                                      "
     "        8 middle line 2/7⏎                                                      "
     "        9 middle line 3/7⏎                                                      "
-    "        ⋮                                                                       "
+    "        ⋮ 1 unchanged line not shown [all]                                      "
     "       11 middle line 5/7⏎                                                      "
     "       12 middle line 6/7⏎                                                      "
     "       13 middle line 7/7⏎                                                      "
@@ -857,7 +891,7 @@ fn test_abbreviate_unchanged_sections() -> TestResult {
     "       14 end line 1/6⏎                                                         "
     "       15 end line 2/6⏎                                                         "
     "       16 end line 3/6⏎                                                         "
-    "        ⋮                                                                       "
+    "        ⋮ 3 unchanged lines not shown [all]                                     "
     "                                                                                "
     "                                                                                "
     "                                                                                "
@@ -867,7 +901,7 @@ fn test_abbreviate_unchanged_sections() -> TestResult {
   insta::assert_snapshot!(collapse_bottom, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "[ ] foo                                                                      [±]"
-    "        ⋮                                                                       "
+    "        ⋮ 3 unchanged lines not shown [all]                                     "
     "        4 start line 4/6⏎                                                       "
     "        5 start line 5/6⏎                                                       "
     "        6 start line 6/6⏎                                                       "
@@ -876,7 +910,7 @@ fn test_abbreviate_unchanged_sections() -> TestResult {
     "        7 middle line 1/7⏎                                                      "
     "        8 middle line 2/7⏎                                                      "
     "        9 middle line 3/7⏎                                                      "
-    "        ⋮                                                                       "
+    "        ⋮ 1 unchanged line not shown [all]                                      "
     "       11 middle line 5/7⏎                                                      "
     "       12 middle line 6/7⏎                                                      "
     "       13 middle line 7/7⏎                                                      "
@@ -923,7 +957,7 @@ fn test_abbreviate_unchanged_sections() -> TestResult {
     "        7 middle line 1/7⏎                                                      "
     "        8 middle line 2/7⏎                                                      "
     "        9 middle line 3/7⏎                                                      "
-    "        ⋮                                                                       "
+    "        ⋮ 1 unchanged line not shown [all]                                      "
     "       11 middle line 5/7⏎                                                      "
     "       12 middle line 6/7⏎                                                      "
     "       13 middle line 7/7⏎                                                      "
@@ -932,7 +966,7 @@ fn test_abbreviate_unchanged_sections() -> TestResult {
     "       14 end line 1/6⏎                                                         "
     "       15 end line 2/6⏎                                                         "
     "       16 end line 3/6⏎                                                         "
-    "        ⋮                                                                       "
+    "        ⋮ 3 unchanged lines not shown [all]                                     "
     "                                                                                "
     "                                                                                "
     "                                                                                "
@@ -958,6 +992,9 @@ fn test_no_abbreviate_short_unchanged_sections() -> TestResult {
       old_path: None,
       path: Cow::Borrowed(Path::new("foo")),
       file_mode: FileMode::FILE_DEFAULT,
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       #[cfg(feature = "tree-sitter")]
       containers: None,
       sections: vec![
@@ -1037,12 +1074,16 @@ fn test_record_binary_file() -> TestResult {
       old_path: None,
       path: Cow::Borrowed(Path::new("foo")),
       file_mode: FileMode::FILE_DEFAULT,
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       #[cfg(feature = "tree-sitter")]
       containers: None,
       sections: vec![Section::Binary {
         is_checked: false,
         old_description: Some(Cow::Owned(make_binary_description("abc123", 123))),
         new_description: Some(Cow::Owned(make_binary_description("def456", 456))),
+        contents: None,
       }],
     }],
   };
@@ -1071,64 +1112,155 @@ fn test_record_binary_file() -> TestResult {
     "###);
 
   assert_debug_snapshot!(state, @r#"
-    RecordState {
-        is_read_only: false,
-        commits: [
-            Commit {
-                message: None,
-            },
-            Commit {
-                message: None,
-            },
-        ],
-        files: [
-            File {
-                old_path: None,
-                path: "foo",
-                file_mode: Unix(
-                    33188,
-                ),
-                sections: [
-                    Binary {
-                        is_checked: true,
-                        old_description: Some(
-                            "abc123 (123 bytes)",
-                        ),
-                        new_description: Some(
-                            "def456 (456 bytes)",
-                        ),
-                    },
-                ],
-                containers: None,
-            },
-        ],
-    }
-    "#);
+  RecordState {
+      is_read_only: false,
+      commits: [
+          Commit {
+              message: None,
+          },
+          Commit {
+              message: None,
+          },
+      ],
+      files: [
+          File {
+              old_path: None,
+              path: "foo",
+              file_mode: Unix(
+                  33188,
+              ),
+              sections: [
+                  Binary {
+                      is_checked: true,
+                      old_description: Some(
+                          "abc123 (123 bytes)",
+                      ),
+                      new_description: Some(
+                          "def456 (456 bytes)",
+                      ),
+                      contents: None,
+                  },
+              ],
+              commit_assignments: [],
+              similarity: None,
+              unloaded: None,
+              containers: None,
+          },
+      ],
+  }
+  "#);
 
   let (selected, unselected) = state.files[0].get_selected_contents();
-  assert_debug_snapshot!(selected, @r###"
-    SelectedChanges {
-        file_mode: Unix(
-            33188,
-        ),
-        contents: Binary {
-            old_description: Some(
-                "abc123 (123 bytes)",
-            ),
-            new_description: Some(
-                "def456 (456 bytes)",
-            ),
-        },
-    }
-    "###);
-  assert_debug_snapshot!(unselected, @r"
-    SelectedChanges {
-        file_mode: Unix(
-            33188,
-        ),
-        contents: Unchanged,
-    }
-    ");
+  assert_debug_snapshot!(selected, @r#"
+  SelectedChanges {
+      path: "foo",
+      file_mode: Unix(
+          33188,
+      ),
+      contents: Binary {
+          old_description: Some(
+              "abc123 (123 bytes)",
+          ),
+          new_description: Some(
+              "def456 (456 bytes)",
+          ),
+      },
+  }
+  "#);
+  assert_debug_snapshot!(unselected, @r#"
+  SelectedChanges {
+      path: "foo",
+      file_mode: Unix(
+          33188,
+      ),
+      contents: Unchanged,
+  }
+  "#);
+
+  Ok(())
+}
+
+#[test]
+fn test_binary_hex_dump() -> TestResult {
+  let state = RecordState {
+    is_read_only: false,
+    commits: Default::default(),
+    files: vec![File {
+      old_path: None,
+      path: Cow::Borrowed(Path::new("foo")),
+      file_mode: FileMode::FILE_DEFAULT,
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
+      #[cfg(feature = "tree-sitter")]
+      containers: None,
+      sections: vec![Section::Binary {
+        is_checked: false,
+        old_description: Some(Cow::Owned(make_binary_description("abc123", 43))),
+        new_description: Some(Cow::Owned(make_binary_description("def456", 45))),
+        contents: Some(BinaryContents {
+          old: Cow::Borrowed(b"The quick brown fox jumps over the lazy dog"),
+          new: Cow::Borrowed(b"The quick brown cat jumps over the lazy dog\x00\xff"),
+        }),
+      }],
+    }],
+  };
+
+  let expanded = TestingScreenshot::default();
+  let collapsed = TestingScreenshot::default();
+  let mut input = TestingInput::new(
+    80,
+    10,
+    [
+      Event::ExpandAll,
+      Event::FocusNext,
+      expanded.event(),
+      Event::ExpandItem,
+      collapsed.event(),
+      Event::ToggleItem,
+      Event::QuitAccept,
+    ],
+  );
+  let recorder = Recorder::new(state, &mut input);
+  let state = recorder.run()?;
+
+  insta::assert_snapshot!(expanded, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[ ] foo                                                                      [-]"
+  "  ( ) (binary contents: abc123 (43 bytes) -> def456 (45 bytes))              (-)"
+  "    ⋮ 1 identical row not shown                                                 "
+  "    0008  6b 20 62 72 6f 77 6e 20 k brown  │ 6b 20 62 72 6f 77 6e 20 k brown    "
+  "    0010  66 6f 78 20 6a 75 6d 70 fox jump │ 63 61 74 20 6a 75 6d 70 cat jump   "
+  "    0018  73 20 6f 76 65 72 20 74 s over t │ 73 20 6f 76 65 72 20 74 s over t   "
+  "    0020  68 65 20 6c 61 7a 79 20 he lazy  │ 68 65 20 6c 61 7a 79 20 he lazy    "
+  "    0028  64 6f 67                dog      │ 64 6f 67 00 ff          dog..      "
+  "                                                                                "
+  "#);
+  insta::assert_snapshot!(collapsed, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[ ] foo                                                                      [-]"
+  "  ( ) (binary contents: abc123 (43 bytes) -> def456 (45 bytes))              (+)"
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "#);
+
+  // The hex dump doesn't change what's selected.
+  let (selected, _unselected) = state.files[0].get_selected_contents();
+  assert_debug_snapshot!(selected.contents, @r#"
+  Binary {
+      old_description: Some(
+          "abc123 (43 bytes)",
+      ),
+      new_description: Some(
+          "def456 (45 bytes)",
+      ),
+  }
+  "#);
 
   Ok(())
 }
@@ -1142,12 +1274,16 @@ fn test_record_binary_file_noop() -> TestResult {
       old_path: None,
       path: Cow::Borrowed(Path::new("foo")),
       file_mode: FileMode::FILE_DEFAULT,
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       #[cfg(feature = "tree-sitter")]
       containers: None,
       sections: vec![Section::Binary {
         is_checked: false,
         old_description: Some(Cow::Owned(make_binary_description("abc123", 123))),
         new_description: Some(Cow::Owned(make_binary_description("def456", 456))),
+        contents: None,
       }],
     }],
   };
@@ -1171,64 +1307,70 @@ fn test_record_binary_file_noop() -> TestResult {
     "###);
 
   assert_debug_snapshot!(state, @r#"
-    RecordState {
-        is_read_only: false,
-        commits: [
-            Commit {
-                message: None,
-            },
-            Commit {
-                message: None,
-            },
-        ],
-        files: [
-            File {
-                old_path: None,
-                path: "foo",
-                file_mode: Unix(
-                    33188,
-                ),
-                sections: [
-                    Binary {
-                        is_checked: false,
-                        old_description: Some(
-                            "abc123 (123 bytes)",
-                        ),
-                        new_description: Some(
-                            "def456 (456 bytes)",
-                        ),
-                    },
-                ],
-                containers: None,
-            },
-        ],
-    }
-    "#);
+  RecordState {
+      is_read_only: false,
+      commits: [
+          Commit {
+              message: None,
+          },
+          Commit {
+              message: None,
+          },
+      ],
+      files: [
+          File {
+              old_path: None,
+              path: "foo",
+              file_mode: Unix(
+                  33188,
+              ),
+              sections: [
+                  Binary {
+                      is_checked: false,
+                      old_description: Some(
+                          "abc123 (123 bytes)",
+                      ),
+                      new_description: Some(
+                          "def456 (456 bytes)",
+                      ),
+                      contents: None,
+                  },
+              ],
+              commit_assignments: [],
+              similarity: None,
+              unloaded: None,
+              containers: None,
+          },
+      ],
+  }
+  "#);
 
   let (selected, unselected) = state.files[0].get_selected_contents();
-  assert_debug_snapshot!(selected, @r"
-    SelectedChanges {
-        file_mode: Unix(
-            33188,
-        ),
-        contents: Unchanged,
-    }
-    ");
-  assert_debug_snapshot!(unselected, @r###"
-    SelectedChanges {
-        file_mode: Unix(
-            33188,
-        ),
-        contents: Binary {
-            old_description: Some(
-                "abc123 (123 bytes)",
-            ),
-            new_description: Some(
-                "def456 (456 bytes)",
-            ),
-        },
-    }
-    "###);
+  assert_debug_snapshot!(selected, @r#"
+  SelectedChanges {
+      path: "foo",
+      file_mode: Unix(
+          33188,
+      ),
+      contents: Unchanged,
+  }
+  "#);
+  assert_debug_snapshot!(unselected, @r#"
+  SelectedChanges {
+      path: "foo",
+      file_mode: Unix(
+          33188,
+      ),
+      contents: Binary {
+          old_description: Some(
+              "abc123 (123 bytes)",
+          ),
+          new_description: Some(
+              "def456 (456 bytes)",
+          ),
+      },
+  }
+  "#);
 
   Ok(())
 }
@@ -1240,6 +1382,9 @@ fn test_state_binary_selected_contents() -> TestResult {
       old_path: None,
       path: Cow::Borrowed(Path::new("foo")),
       file_mode: FileMode::FILE_DEFAULT,
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       #[cfg(feature = "tree-sitter")]
       containers: None,
       sections: vec![
@@ -1254,6 +1399,7 @@ fn test_state_binary_selected_contents() -> TestResult {
           is_checked: binary,
           old_description: Some(Cow::Owned(make_binary_description("abc123", 123))),
           new_description: Some(Cow::Owned(make_binary_description("def456", 456))),
+          contents: None,
         },
       ],
     };
@@ -1261,19 +1407,19 @@ fn test_state_binary_selected_contents() -> TestResult {
     format!("{selection:?}")
   };
 
-  assert_snapshot!(test(false, false), @r###"(SelectedChanges { file_mode: Unix(33188), contents: Unchanged }, SelectedChanges { file_mode: Unix(33188), contents: Binary { old_description: Some("abc123 (123 bytes)"), new_description: Some("def456 (456 bytes)") } })"###);
+  assert_snapshot!(test(false, false), @r#"(SelectedChanges { path: "foo", file_mode: Unix(33188), contents: Unchanged }, SelectedChanges { path: "foo", file_mode: Unix(33188), contents: Binary { old_description: Some("abc123 (123 bytes)"), new_description: Some("def456 (456 bytes)") } })"#);
 
   // FIXME: should the selected contents be `Present { contents: "" }`? (Or
   // possibly `Absent`?)
-  assert_snapshot!(test(true, false), @r###"(SelectedChanges { file_mode: Unix(33188), contents: Unchanged }, SelectedChanges { file_mode: Unix(33188), contents: Binary { old_description: Some("abc123 (123 bytes)"), new_description: Some("def456 (456 bytes)") } })"###);
+  assert_snapshot!(test(true, false), @r#"(SelectedChanges { path: "foo", file_mode: Unix(33188), contents: Unchanged }, SelectedChanges { path: "foo", file_mode: Unix(33188), contents: Binary { old_description: Some("abc123 (123 bytes)"), new_description: Some("def456 (456 bytes)") } })"#);
 
   // NB: The result for this situation, where we've selected both a text and
   // binary segment for inclusion, is arbitrary. The caller should avoid
   // generating both kinds of sections in the same file (or we should improve
   // the UI to never allow selecting both).
-  assert_snapshot!(test(false, true), @r###"(SelectedChanges { file_mode: Unix(33188), contents: Binary { old_description: Some("abc123 (123 bytes)"), new_description: Some("def456 (456 bytes)") } }, SelectedChanges { file_mode: Unix(33188), contents: Unchanged })"###);
+  assert_snapshot!(test(false, true), @r#"(SelectedChanges { path: "foo", file_mode: Unix(33188), contents: Binary { old_description: Some("abc123 (123 bytes)"), new_description: Some("def456 (456 bytes)") } }, SelectedChanges { path: "foo", file_mode: Unix(33188), contents: Unchanged })"#);
 
-  assert_snapshot!(test(true, true), @r###"(SelectedChanges { file_mode: Unix(33188), contents: Binary { old_description: Some("abc123 (123 bytes)"), new_description: Some("def456 (456 bytes)") } }, SelectedChanges { file_mode: Unix(33188), contents: Unchanged })"###);
+  assert_snapshot!(test(true, true), @r#"(SelectedChanges { path: "foo", file_mode: Unix(33188), contents: Binary { old_description: Some("abc123 (123 bytes)"), new_description: Some("def456 (456 bytes)") } }, SelectedChanges { path: "foo", file_mode: Unix(33188), contents: Unchanged })"#);
 
   Ok(())
 }
@@ -1306,7 +1452,7 @@ fn test_mouse_support() -> TestResult {
   insta::assert_snapshot!(initial, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "(◐) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -1343,6 +1489,9 @@ fn test_mouse_click_checkbox() -> TestResult {
         old_path: None,
         path: Cow::Borrowed(Path::new("foo")),
         file_mode: FileMode::FILE_DEFAULT,
+        commit_assignments: Default::default(),
+        similarity: None,
+        unloaded: None,
         #[cfg(feature = "tree-sitter")]
         containers: None,
         sections: vec![],
@@ -1351,6 +1500,9 @@ fn test_mouse_click_checkbox() -> TestResult {
         old_path: None,
         path: Cow::Borrowed(Path::new("bar")),
         file_mode: FileMode::Absent,
+        commit_assignments: Default::default(),
+        similarity: None,
+        unloaded: None,
         #[cfg(feature = "tree-sitter")]
         containers: None,
         sections: vec![Section::FileMode {
@@ -1411,6 +1563,9 @@ fn test_mouse_click_wide_line() -> TestResult {
       old_path: None,
       path: Cow::Borrowed(Path::new("foo")),
       file_mode: FileMode::Absent,
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       #[cfg(feature = "tree-sitter")]
       containers: None,
       sections: vec![
@@ -1502,6 +1657,9 @@ fn test_mouse_click_dialog_buttons() -> TestResult {
       old_path: None,
       path: Cow::Borrowed(Path::new("foo")),
       file_mode: FileMode::FILE_DEFAULT,
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       #[cfg(feature = "tree-sitter")]
       containers: None,
       sections: vec![Section::Changed {
@@ -1556,6 +1714,9 @@ fn test_render_old_path() -> TestResult {
       old_path: Some(Cow::Borrowed(Path::new("foo"))),
       path: Cow::Borrowed(Path::new("bar")),
       file_mode: FileMode::FILE_DEFAULT,
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       #[cfg(feature = "tree-sitter")]
       containers: None,
       sections: vec![],
@@ -1570,14 +1731,219 @@ fn test_render_old_path() -> TestResult {
   let recorder = Recorder::new(state, &mut input);
   recorder.run()?;
 
-  insta::assert_snapshot!(screenshot, @r###"
-    "[File] [Edit] [Select] [View]                                                   "
-    "( ) foo => bar                                                               (-)"
-    "                                                                                "
-    "                                                                                "
-    "                                                                                "
-    "                                                                                "
-    "###);
+  insta::assert_snapshot!(screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "( ) foo → bar                                                                (-)"
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "#);
+
+  Ok(())
+}
+
+#[test]
+fn test_rename_section() -> TestResult {
+  let state = RecordState {
+    is_read_only: false,
+    commits: Default::default(),
+    files: vec![File {
+      old_path: Some(Cow::Borrowed(Path::new("foo"))),
+      path: Cow::Borrowed(Path::new("bar")),
+      file_mode: FileMode::FILE_DEFAULT,
+      sections: vec![
+        Section::Rename {
+          is_checked: false,
+          is_copy: false,
+        },
+        Section::Unchanged {
+          lines: vec![Cow::Borrowed("unchanged\n")],
+        },
+        Section::Changed {
+          lines: vec![
+            SectionChangedLine {
+              is_checked: false,
+              change_type: ChangeType::Removed,
+              line: Cow::Borrowed("before\n"),
+            },
+            SectionChangedLine {
+              is_checked: false,
+              change_type: ChangeType::Added,
+              line: Cow::Borrowed("after\n"),
+            },
+          ],
+        },
+      ],
+      commit_assignments: Default::default(),
+      similarity: Some(92),
+      unloaded: None,
+      #[cfg(feature = "tree-sitter")]
+      containers: None,
+    }],
+  };
+  let screenshot = TestingScreenshot::default();
+  let mut input = TestingInput::new(
+    80,
+    8,
+    [
+      Event::ExpandAll,
+      Event::FocusNext,
+      Event::ToggleItem,
+      screenshot.event(),
+      Event::QuitAccept,
+    ],
+  );
+  let recorder = Recorder::new(state, &mut input);
+  let state = recorder.run()?;
+
+  insta::assert_snapshot!(screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[◐] foo → bar (92%)                                                          [-]"
+  "  (●) File renamed from foo                                                     "
+  "        1 unchanged⏎                                                            "
+  "  [ ] Section 2/2                                                            [-]"
+  "    [ ] - before⏎                                                               "
+  "    [ ] + after⏎                                                                "
+  "                                                                                "
+  "#);
+
+  // Only the rename is selected, not the changes to the contents.
+  let (selected, unselected) = state.files[0].get_selected_contents();
+  assert_debug_snapshot!((selected, unselected), @r#"
+  (
+      SelectedChanges {
+          path: "bar",
+          file_mode: Unix(
+              33188,
+          ),
+          contents: Text {
+              contents: "unchanged\nbefore\n",
+          },
+      },
+      SelectedChanges {
+          path: "foo",
+          file_mode: Unix(
+              33188,
+          ),
+          contents: Text {
+              contents: "unchanged\nafter\n",
+          },
+      },
+  )
+  "#);
+
+  Ok(())
+}
+
+#[test]
+fn test_symlink_and_submodule_sections() -> TestResult {
+  let state = RecordState {
+    is_read_only: false,
+    commits: Default::default(),
+    files: vec![
+      File {
+        old_path: None,
+        path: Cow::Borrowed(Path::new("link")),
+        file_mode: FileMode::SYMLINK,
+        sections: vec![Section::Symlink {
+          is_checked: false,
+          old_target: Some(Cow::Borrowed("old-target")),
+          new_target: Some(Cow::Borrowed("new-target")),
+        }],
+        commit_assignments: Default::default(),
+        similarity: None,
+        unloaded: None,
+        #[cfg(feature = "tree-sitter")]
+        containers: None,
+      },
+      File {
+        old_path: None,
+        path: Cow::Borrowed(Path::new("sub")),
+        file_mode: FileMode::GITLINK,
+        sections: vec![Section::Submodule {
+          is_checked: false,
+          old_commit: Some(Cow::Borrowed("abc123")),
+          new_commit: Some(Cow::Borrowed("def456")),
+        }],
+        commit_assignments: Default::default(),
+        similarity: None,
+        unloaded: None,
+        #[cfg(feature = "tree-sitter")]
+        containers: None,
+      },
+    ],
+  };
+  let screenshot = TestingScreenshot::default();
+  let mut input = TestingInput::new(
+    80,
+    6,
+    [
+      Event::ExpandAll,
+      Event::FocusNext,
+      Event::ToggleItem,
+      screenshot.event(),
+      Event::QuitAccept,
+    ],
+  );
+  let recorder = Recorder::new(state, &mut input);
+  let state = recorder.run()?;
+
+  insta::assert_snapshot!(screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[●] link                                                                     [-]"
+  "  (●) Symlink target: old-target -> new-target                                  "
+  "[ ] sub                                                                      [-]"
+  "  [ ] Submodule commit: abc123 -> def456                                        "
+  "                                                                                "
+  "#);
+
+  let (selected, unselected) = state.files[0].get_selected_contents();
+  assert_debug_snapshot!((selected, unselected), @r#"
+  (
+      SelectedChanges {
+          path: "link",
+          file_mode: Unix(
+              40960,
+          ),
+          contents: Symlink {
+              target: "new-target",
+          },
+      },
+      SelectedChanges {
+          path: "link",
+          file_mode: Unix(
+              40960,
+          ),
+          contents: Symlink {
+              target: "old-target",
+          },
+      },
+  )
+  "#);
+  let (selected, unselected) = state.files[1].get_selected_contents();
+  assert_debug_snapshot!((selected, unselected), @r#"
+  (
+      SelectedChanges {
+          path: "sub",
+          file_mode: Unix(
+              57344,
+          ),
+          contents: Submodule {
+              commit: "abc123",
+          },
+      },
+      SelectedChanges {
+          path: "sub",
+          file_mode: Unix(
+              57344,
+          ),
+          contents: Submodule {
+              commit: "def456",
+          },
+      },
+  )
+  "#);
 
   Ok(())
 }
@@ -1619,7 +1985,7 @@ fn test_expand() -> TestResult {
   insta::assert_snapshot!(after_expand, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "(◐) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -2053,7 +2419,7 @@ fn test_sticky_header_scroll() -> TestResult {
   insta::assert_snapshot!(initial, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "(◐) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -2196,7 +2562,7 @@ fn test_scroll_click_no_jump() -> TestResult {
   insta::assert_snapshot!(initial, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "(◐) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -2205,7 +2571,7 @@ fn test_scroll_click_no_jump() -> TestResult {
   insta::assert_snapshot!(after_click, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "[◐] foo/bar                                                                  [-]"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -2301,11 +2667,11 @@ fn test_expand_menu() -> TestResult {
     "###);
   insta::assert_snapshot!(after_click, @r###"
     "[File] [Edit] [Select] [View]                                                   "
-    "(◐) foo[Edit message (e)]                                                    (+)"
-    "[●] baz[Toggle current (space)]                                              [+]"
+    "(◐) foo[Undo (u)]                                                            (+)"
+    "[●] baz[Redo (ctrl-r)]                                                       [+]"
+    "       [Edit message (e)]                                                       "
+    "       [Toggle current (space)]                                                 "
     "       [Toggle current and advance (enter)]                                     "
-    "       [Invert all items (a)]                                                   "
-    "       [Invert all items uniformly (A)]                                         "
     "###);
   insta::assert_snapshot!(after_click_different, @r###"
     "[File] [Edit] [Select] [View]                                                   "
@@ -2363,7 +2729,7 @@ fn test_read_only() -> TestResult {
   insta::assert_snapshot!(initial, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "<◐> foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -2388,7 +2754,7 @@ fn test_read_only() -> TestResult {
   insta::assert_snapshot!(after_toggle_all_ignored, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "<◐> foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -2413,7 +2779,7 @@ fn test_read_only() -> TestResult {
   insta::assert_snapshot!(after_toggle_all_uniform_ignored, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "<◐> foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -2509,6 +2875,9 @@ fn test_read_only() -> TestResult {
                         ],
                     },
                 ],
+                commit_assignments: [],
+                similarity: None,
+                unloaded: None,
                 containers: None,
             },
             File {
@@ -2554,6 +2923,9 @@ fn test_read_only() -> TestResult {
                         ],
                     },
                 ],
+                commit_assignments: [],
+                similarity: None,
+                unloaded: None,
                 containers: None,
             },
         ],
@@ -2586,7 +2958,7 @@ fn test_toggle_unchanged_line() -> TestResult {
   insta::assert_snapshot!(initial, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "(◐) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -2665,6 +3037,9 @@ fn test_toggle_unchanged_line() -> TestResult {
                         ],
                     },
                 ],
+                commit_assignments: [],
+                similarity: None,
+                unloaded: None,
                 containers: None,
             },
             File {
@@ -2710,6 +3085,9 @@ fn test_toggle_unchanged_line() -> TestResult {
                         ],
                     },
                 ],
+                commit_assignments: [],
+                similarity: None,
+                unloaded: None,
                 containers: None,
             },
         ],
@@ -2728,6 +3106,9 @@ fn test_max_file_view_width() -> TestResult {
       old_path: None,
       path: Cow::Owned("very/".repeat(100).into()),
       file_mode: FileMode::FILE_DEFAULT,
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       #[cfg(feature = "tree-sitter")]
       containers: None,
       sections: vec![
@@ -2829,7 +3210,7 @@ fn test_commit_message_view() -> TestResult {
     "[Edit message]  •  (no message)                                                 "
     "                                                                                "
     "(◐) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -2855,7 +3236,7 @@ fn test_commit_message_view() -> TestResult {
     "[Edit message]  •  Hello, world!                                                "
     "                                                                                "
     "(◐) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -2880,7 +3261,7 @@ fn test_commit_message_view() -> TestResult {
     "[Edit message]  •  Hello, world!                                                "
     "                                                                                "
     "(◐) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -3054,7 +3435,7 @@ fn test_next_prev() -> TestResult {
   insta::assert_snapshot!(initial, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "(◐) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -3186,7 +3567,7 @@ fn test_prev_same_kind() -> TestResult {
   insta::assert_snapshot!(initial, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "(◐) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -3208,7 +3589,7 @@ fn test_prev_same_kind() -> TestResult {
   insta::assert_snapshot!(still_initial, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "(◐) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -3377,7 +3758,7 @@ fn test_next_same_kind() -> TestResult {
   insta::assert_snapshot!(initial, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "(◐) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
     "       18 this is some text⏎                                                    "
     "       19 this is some text⏎                                                    "
     "       20 this is some text⏎                                                    "
@@ -3625,6 +4006,9 @@ fn test_tabs_in_files() -> TestResult {
       old_path: None,
       path: Cow::Borrowed(Path::new("foo/bar")),
       file_mode: FileMode::FILE_DEFAULT,
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       #[cfg(feature = "tree-sitter")]
       containers: None,
       sections: vec![
@@ -3705,7 +4089,7 @@ fn test_tabs_in_files() -> TestResult {
   insta::assert_snapshot!(initial, @r###"
     "[File] [Edit] [Select] [View]                                                   "
     "(●) foo/bar                                                                  (-)"
-    "        ⋮                                                                       "
+    "        ⋮ 7 unchanged lines not shown [all]                                     "
     "        8 →   this is some indented text⏎                                       "
     "        9 →   this is some indented text⏎                                       "
     "       10 →   this is some indented text⏎                                       "
@@ -3735,6 +4119,9 @@ fn test_carriage_return() -> TestResult {
       old_path: None,
       path: Cow::Borrowed(Path::new("foo")),
       file_mode: FileMode::FILE_DEFAULT,
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       #[cfg(feature = "tree-sitter")]
       containers: None,
       sections: vec![Section::Changed {
@@ -3820,6 +4207,9 @@ fn test_some_control_characters() -> TestResult {
       old_path: None,
       path: Cow::Borrowed(Path::new("foo")),
       file_mode: FileMode::FILE_DEFAULT,
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       #[cfg(feature = "tree-sitter")]
       containers: None,
       sections: vec![Section::Changed {
@@ -3864,6 +4254,9 @@ fn test_non_printing_characters() -> TestResult {
       old_path: None,
       path: Cow::Borrowed(Path::new("foo")),
       file_mode: FileMode::FILE_DEFAULT,
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
       #[cfg(feature = "tree-sitter")]
       containers: None,
       sections: vec![Section::Changed {
@@ -3898,3 +4291,1793 @@ fn test_non_printing_characters() -> TestResult {
 
   Ok(())
 }
+
+#[test]
+fn test_split_into_more_than_two_commits() -> TestResult {
+  let state = RecordState {
+    is_read_only: false,
+    commits: vec![Commit::default(), Commit::default(), Commit::default()],
+    files: vec![File {
+      old_path: None,
+      path: Cow::Borrowed(Path::new("foo")),
+      file_mode: FileMode::FILE_DEFAULT,
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
+      #[cfg(feature = "tree-sitter")]
+      containers: None,
+      sections: vec![
+        Section::Unchanged {
+          lines: vec![Cow::Borrowed("unchanged\n")],
+        },
+        Section::Changed {
+          lines: vec![
+            SectionChangedLine {
+              is_checked: true,
+              change_type: ChangeType::Added,
+              line: Cow::Borrowed("line 1\n"),
+            },
+            SectionChangedLine {
+              is_checked: true,
+              change_type: ChangeType::Added,
+              line: Cow::Borrowed("line 2\n"),
+            },
+            SectionChangedLine {
+              is_checked: true,
+              change_type: ChangeType::Added,
+              line: Cow::Borrowed("line 3\n"),
+            },
+          ],
+        },
+      ],
+    }],
+  };
+
+  let initial = TestingScreenshot::default();
+  let assigned = TestingScreenshot::default();
+  let second_commit = TestingScreenshot::default();
+  let third_commit = TestingScreenshot::default();
+  let third_commit_toggled = TestingScreenshot::default();
+  let second_commit_toggled = TestingScreenshot::default();
+  let first_commit_toggled = TestingScreenshot::default();
+  let mut input = TestingInput::new(
+    80,
+    10,
+    [
+      Event::ExpandAll,
+      initial.event(),
+      Event::FocusNext,
+      Event::FocusNext,
+      Event::AssignToCommit(1),
+      Event::FocusNext,
+      Event::AssignToCommit(2),
+      // Out-of-bounds commit; should do nothing.
+      Event::AssignToCommit(3),
+      assigned.event(),
+      Event::FocusNextCommit,
+      second_commit.event(),
+      Event::FocusNextCommit,
+      // Already at the last commit; should do nothing.
+      Event::FocusNextCommit,
+      third_commit.event(),
+      // Unchecking a change moves it to the second commit, like changes which
+      // were never assigned.
+      Event::ToggleItem,
+      third_commit_toggled.event(),
+      Event::FocusPrevCommit,
+      second_commit_toggled.event(),
+      // Unchecking a change in the second commit moves it to the first commit.
+      Event::FocusPrev,
+      Event::ToggleItem,
+      Event::FocusPrevCommit,
+      first_commit_toggled.event(),
+      Event::QuitAccept,
+    ],
+  );
+  let recorder = Recorder::new(state, &mut input);
+  let state = recorder.run()?;
+
+  insta::assert_snapshot!(initial, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "                                                                                "
+  "Commit 1/3                                                                      "
+  "                                                                                "
+  "(●) foo                                                                      (-)"
+  "        1 unchanged⏎                                                            "
+  "  [●] Section 1/1                                                            [-]"
+  "    [●] + line 1⏎                                                               "
+  "    [●] + line 2⏎                                                               "
+  "    [●] + line 3⏎                                                               "
+  "#);
+  insta::assert_snapshot!(assigned, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "                                                                                "
+  "Commit 1/3                                                                      "
+  "                                                                                "
+  "[◐] foo                                                                      [-]"
+  "        1 unchanged⏎                                                            "
+  "  [◐] Section 1/1                                                            [-]"
+  "    [ ] + line 1⏎                                                               "
+  "    ( ) + line 2⏎                                                               "
+  "    [●] + line 3⏎                                                               "
+  "#);
+  insta::assert_snapshot!(second_commit, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "                                                                                "
+  "Commit 2/3                                                                      "
+  "                                                                                "
+  "[◐] foo                                                                      [-]"
+  "        1 unchanged⏎                                                            "
+  "  [◐] Section 1/1                                                            [-]"
+  "    [●] + line 1⏎                                                               "
+  "    ( ) + line 2⏎                                                               "
+  "    [ ] + line 3⏎                                                               "
+  "#);
+  insta::assert_snapshot!(third_commit, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "                                                                                "
+  "Commit 3/3                                                                      "
+  "                                                                                "
+  "[◐] foo                                                                      [-]"
+  "        1 unchanged⏎                                                            "
+  "  [◐] Section 1/1                                                            [-]"
+  "    [ ] + line 1⏎                                                               "
+  "    (●) + line 2⏎                                                               "
+  "    [ ] + line 3⏎                                                               "
+  "#);
+  insta::assert_snapshot!(third_commit_toggled, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "                                                                                "
+  "Commit 3/3                                                                      "
+  "                                                                                "
+  "[ ] foo                                                                      [-]"
+  "        1 unchanged⏎                                                            "
+  "  [ ] Section 1/1                                                            [-]"
+  "    [ ] + line 1⏎                                                               "
+  "    ( ) + line 2⏎                                                               "
+  "    [ ] + line 3⏎                                                               "
+  "#);
+  insta::assert_snapshot!(second_commit_toggled, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "                                                                                "
+  "Commit 2/3                                                                      "
+  "                                                                                "
+  "[◐] foo                                                                      [-]"
+  "        1 unchanged⏎                                                            "
+  "  [◐] Section 1/1                                                            [-]"
+  "    [●] + line 1⏎                                                               "
+  "    (●) + line 2⏎                                                               "
+  "    [ ] + line 3⏎                                                               "
+  "#);
+  insta::assert_snapshot!(first_commit_toggled, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "                                                                                "
+  "Commit 1/3                                                                      "
+  "                                                                                "
+  "[◐] foo                                                                      [-]"
+  "        1 unchanged⏎                                                            "
+  "  [◐] Section 1/1                                                            [-]"
+  "    (●) + line 1⏎                                                               "
+  "    [ ] + line 2⏎                                                               "
+  "    [●] + line 3⏎                                                               "
+  "#);
+
+  let file = &state.files[0];
+  assert_debug_snapshot!(file.commit_assignments, @"[]");
+  assert_debug_snapshot!(file.get_commit_contents(0), @r#"
+  SelectedChanges {
+      path: "foo",
+      file_mode: Unix(
+          33188,
+      ),
+      contents: Text {
+          contents: "unchanged\nline 1\nline 3\n",
+      },
+  }
+  "#);
+  assert_debug_snapshot!(file.get_commit_contents(1), @r#"
+  SelectedChanges {
+      path: "foo",
+      file_mode: Unix(
+          33188,
+      ),
+      contents: Text {
+          contents: "unchanged\nline 2\n",
+      },
+  }
+  "#);
+  assert_debug_snapshot!(file.get_commit_contents(2), @r#"
+  SelectedChanges {
+      path: "foo",
+      file_mode: Unix(
+          33188,
+      ),
+      contents: Text {
+          contents: "unchanged\n",
+      },
+  }
+  "#);
+
+  Ok(())
+}
+
+#[test]
+fn test_omitted_lines_from_patch() -> TestResult {
+  let patch = "\
+--- a/foo
++++ b/foo
+@@ -1,2 +1,2 @@
+ line 1
+-line 2
++line 2 changed
+@@ -10,2 +10,2 @@
+-line 10
++line 10 changed
+ line 11
+";
+  let files = scm_record::patch::parse_patch(patch).expect("parsing patch");
+  let state = RecordState {
+    is_read_only: false,
+    commits: Default::default(),
+    files,
+  };
+
+  let initial = TestingScreenshot::default();
+  let mut input = TestingInput::new(
+    80,
+    12,
+    [Event::ExpandAll, initial.event(), Event::QuitAccept],
+  );
+  let recorder = Recorder::new(state, &mut input);
+  recorder.run()?;
+
+  insta::assert_snapshot!(initial, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "( ) foo                                                                      (-)"
+  "        1 line 1⏎                                                               "
+  "  [ ] Section 1/2                                                            [-]"
+  "    [ ] - line 2⏎                                                               "
+  "    [ ] + line 2 changed⏎                                                       "
+  "        ⋮ 7 unchanged lines not shown                                           "
+  "  [ ] Section 2/2                                                            [-]"
+  "    [ ] - line 10⏎                                                              "
+  "    [ ] + line 10 changed⏎                                                      "
+  "       11 line 11⏎                                                              "
+  "                                                                                "
+  "#);
+
+  Ok(())
+}
+
+#[test]
+fn test_undo_redo() -> TestResult {
+  let initial = TestingScreenshot::default();
+  let after_toggle_all = TestingScreenshot::default();
+  let after_undo = TestingScreenshot::default();
+  let after_redo = TestingScreenshot::default();
+  let after_toggle = TestingScreenshot::default();
+  let mut input = TestingInput::new(
+    80,
+    8,
+    [
+      initial.event(),
+      Event::ToggleAll,
+      after_toggle_all.event(),
+      Event::Undo,
+      after_undo.event(),
+      // Nothing more to undo.
+      Event::Undo,
+      Event::Redo,
+      after_redo.event(),
+      Event::Undo,
+      Event::FocusNext,
+      Event::ToggleItem,
+      after_toggle.event(),
+      // Making a new change discards the changes which could be redone.
+      Event::Redo,
+      Event::QuitAccept,
+    ],
+  );
+  let state = example_contents();
+  let recorder = Recorder::new(state, &mut input);
+  let state = recorder.run()?;
+
+  insta::assert_snapshot!(initial, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "(◐) foo/bar                                                                  (+)"
+  "[●] baz                                                                      [+]"
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "#);
+  insta::assert_snapshot!(after_toggle_all, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "(◐) foo/bar                                                                  (+)"
+  "[ ] baz                                                                      [+]"
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "#);
+  assert_eq!(after_undo.to_string(), initial.to_string());
+  assert_eq!(after_redo.to_string(), after_toggle_all.to_string());
+  insta::assert_snapshot!(after_toggle, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[◐] foo/bar                                                                  [+]"
+  "( ) baz                                                                      (+)"
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "#);
+  assert_eq!(state.files[0], example_contents().files[0]);
+  assert_ne!(state.files[1], example_contents().files[1]);
+
+  Ok(())
+}
+
+#[test]
+fn test_undo_commit_message_and_expand() -> TestResult {
+  let mut state = example_contents();
+  state.commits = vec![Commit {
+    message: Some("".to_string()),
+  }];
+
+  let initial = TestingScreenshot::default();
+  let after_edit = TestingScreenshot::default();
+  let after_expand = TestingScreenshot::default();
+  let after_undo_expand = TestingScreenshot::default();
+  let after_undo_edit = TestingScreenshot::default();
+  let mut input = TestingInput {
+    width: 80,
+    height: 6,
+    events: Box::new(
+      [
+        initial.event(),
+        Event::EditCommitMessage,
+        after_edit.event(),
+        Event::ExpandItem,
+        after_expand.event(),
+        Event::Undo,
+        after_undo_expand.event(),
+        Event::Undo,
+        after_undo_edit.event(),
+        Event::QuitAccept,
+      ]
+      .into_iter(),
+    ),
+    commit_messages: ["Hello, world!".to_string()].into_iter().collect(),
+  };
+  let recorder = Recorder::new(state, &mut input);
+  let state = recorder.run()?;
+
+  insta::assert_snapshot!(initial, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "                                                                                "
+  "[Edit message]  •  (no message)                                                 "
+  "                                                                                "
+  "(◐) foo/bar                                                                  (+)"
+  "[●] baz                                                                      [+]"
+  "#);
+  insta::assert_snapshot!(after_edit, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "                                                                                "
+  "[Edit message]  •  Hello, world!                                                "
+  "                                                                                "
+  "(◐) foo/bar                                                                  (+)"
+  "[●] baz                                                                      [+]"
+  "#);
+  insta::assert_snapshot!(after_expand, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "(◐) foo/bar                                                                  (-)"
+  "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
+  "       18 this is some text⏎                                                    "
+  "       19 this is some text⏎                                                    "
+  "       20 this is some text⏎                                                    "
+  "#);
+  insta::assert_snapshot!(after_undo_expand, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "(◐) foo/bar                                                                  (+)"
+  "[●] baz                                                                      [+]"
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "#);
+  insta::assert_snapshot!(after_undo_edit, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "(◐) foo/bar                                                                  (+)"
+  "[●] baz                                                                      [+]"
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "#);
+  assert_eq!(state.commits[0].message.as_deref(), Some(""));
+
+  Ok(())
+}
+
+#[test]
+fn test_run_with_cancelled_state() -> TestResult {
+  let mut input = TestingInput::new(
+    80,
+    6,
+    [
+      Event::ToggleAll,
+      Event::QuitCancel,
+      // Confirm quitting in the dialog.
+      Event::QuitInterrupt,
+    ],
+  );
+  let recorder = Recorder::new(example_contents(), &mut input);
+  let mut cancelled_state = None;
+  let result = recorder.run_with_cancelled_state(&mut cancelled_state);
+  assert_matches!(result, Err(RecordError::Cancelled));
+  let cancelled_state = cancelled_state.expect("state should be set after cancelling");
+  assert_ne!(cancelled_state.files, example_contents().files);
+  assert!(!cancelled_state.files[1]
+    .get_selected_patches(&Default::default())
+    .1
+    .is_empty());
+
+  Ok(())
+}
+
+#[test]
+fn test_section_provider() -> TestResult {
+  struct TestSectionProvider {
+    loaded_paths: Vec<String>,
+  }
+
+  impl SectionProvider for TestSectionProvider {
+    fn load_file(
+      &mut self,
+      file: &File,
+    ) -> Result<File<'static>, RecordError> {
+      let path = file.path.to_string_lossy().into_owned();
+      self.loaded_paths.push(path.clone());
+      Ok(File {
+        old_path: None,
+        path: Cow::Owned(file.path.to_path_buf()),
+        file_mode: FileMode::FILE_DEFAULT,
+        sections: vec![Section::Changed {
+          lines: vec![
+            SectionChangedLine {
+              is_checked: false,
+              change_type: ChangeType::Removed,
+              line: Cow::Owned(format!("old {path}\n")),
+            },
+            SectionChangedLine {
+              is_checked: false,
+              change_type: ChangeType::Added,
+              line: Cow::Owned(format!("new {path}\n")),
+            },
+          ],
+        }],
+        commit_assignments: Default::default(),
+        similarity: None,
+        unloaded: None,
+        #[cfg(feature = "tree-sitter")]
+        containers: None,
+      })
+    }
+  }
+
+  let unloaded_file = |path: &'static str, unloaded: FileStats| File {
+    old_path: None,
+    path: Cow::Borrowed(Path::new(path)),
+    file_mode: FileMode::FILE_DEFAULT,
+    sections: Vec::new(),
+    commit_assignments: Default::default(),
+    similarity: None,
+    unloaded: Some(unloaded),
+    #[cfg(feature = "tree-sitter")]
+    containers: None,
+  };
+  let state = RecordState {
+    is_read_only: false,
+    commits: Default::default(),
+    files: vec![
+      unloaded_file(
+        "foo",
+        FileStats {
+          num_added: Some(1),
+          num_removed: Some(1),
+        },
+      ),
+      unloaded_file("bar", FileStats::default()),
+      unloaded_file(
+        "baz",
+        FileStats {
+          num_added: Some(3),
+          num_removed: Some(0),
+        },
+      ),
+    ],
+  };
+
+  let initial = TestingScreenshot::default();
+  let searched = TestingScreenshot::default();
+  let expanded = TestingScreenshot::default();
+  let mut input = TestingInput::new(
+    80,
+    8,
+    [
+      initial.event(),
+      // Searching only loads the file whose path matches.
+      Event::StartSearch { is_backward: false },
+      Event::TextInput('b'),
+      Event::TextInput('a'),
+      Event::TextInput('z'),
+      Event::TextSubmit,
+      searched.event(),
+      Event::FocusPrev,
+      Event::ExpandItem,
+      expanded.event(),
+      Event::QuitAccept,
+    ],
+  );
+  let mut section_provider = TestSectionProvider {
+    loaded_paths: Vec::new(),
+  };
+  let recorder = Recorder::new(state, &mut input).with_section_provider(&mut section_provider);
+  let state = recorder.run()?;
+
+  insta::assert_snapshot!(initial, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "( ) foo                                                                      (+)"
+  "[ ] bar (+? -?, not loaded)                                                  [+]"
+  "[ ] baz (+3 -0, not loaded)                                                  [+]"
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "#);
+  insta::assert_snapshot!(searched, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[ ] foo                                                                      [+]"
+  "[ ] bar (+? -?, not loaded)                                                  [+]"
+  "( ) baz                                                                      (+)"
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "#);
+  insta::assert_snapshot!(expanded, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[ ] foo                                                                      [+]"
+  "( ) bar                                                                      (-)"
+  "  [ ] Section 1/1                                                            [-]"
+  "    [ ] - old bar⏎                                                              "
+  "    [ ] + new bar⏎                                                              "
+  "[ ] baz                                                                      [+]"
+  "                                                                                "
+  "#);
+  assert_eq!(section_provider.loaded_paths, ["foo", "baz", "bar"]);
+  assert!(state.files.iter().all(|file| file.unloaded.is_none()));
+
+  Ok(())
+}
+
+#[test]
+fn test_moved_lines_are_toggled_together() -> TestResult {
+  let changed_file = |path: &'static str, change_type: ChangeType| File {
+    old_path: None,
+    path: Cow::Borrowed(Path::new(path)),
+    file_mode: FileMode::FILE_DEFAULT,
+    sections: vec![Section::Changed {
+      lines: ["fn helper() {\n", "    compute_something();\n", "}\n"]
+        .into_iter()
+        .map(|line| SectionChangedLine {
+          is_checked: false,
+          change_type,
+          line: Cow::Borrowed(line),
+        })
+        .collect(),
+    }],
+    commit_assignments: Default::default(),
+    similarity: None,
+    unloaded: None,
+    #[cfg(feature = "tree-sitter")]
+    containers: None,
+  };
+  let state = RecordState {
+    is_read_only: false,
+    commits: Default::default(),
+    files: vec![
+      changed_file("old.rs", ChangeType::Removed),
+      changed_file("new.rs", ChangeType::Added),
+    ],
+  };
+  let screenshot = TestingScreenshot::default();
+  let mut input = TestingInput::new(
+    80,
+    11,
+    [
+      Event::ExpandAll,
+      Event::FocusNext,
+      Event::FocusNext,
+      Event::ToggleItem,
+      screenshot.event(),
+      Event::QuitAccept,
+    ],
+  );
+  let recorder = Recorder::new(state, &mut input);
+  let state = recorder.run()?;
+
+  // Selecting the removal of a moved line also selects its addition.
+  insta::assert_snapshot!(screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[◐] old.rs                                                                   [-]"
+  "  [◐] Section 1/1                                                            [-]"
+  "    (●) - fn helper() {⏎                                                        "
+  "    [ ] -     compute_something();⏎                                             "
+  "    [ ] - }⏎                                                                    "
+  "[◐] new.rs                                                                   [-]"
+  "  [◐] Section 1/1                                                            [-]"
+  "    [●] + fn helper() {⏎                                                        "
+  "    [ ] +     compute_something();⏎                                             "
+  "    [ ] + }⏎                                                                    "
+  "#);
+  assert_debug_snapshot!(state.files[1].get_selected_contents().0.contents, @r#"
+  Text {
+      contents: "fn helper() {\n",
+  }
+  "#);
+
+  Ok(())
+}
+
+#[test]
+fn test_split_and_merge_section() -> TestResult {
+  let changed_line = |change_type, line| SectionChangedLine {
+    is_checked: false,
+    change_type,
+    line: Cow::Borrowed(line),
+  };
+  let state = RecordState {
+    is_read_only: false,
+    commits: Default::default(),
+    files: vec![File {
+      old_path: None,
+      path: Cow::Borrowed(Path::new("foo")),
+      file_mode: FileMode::FILE_DEFAULT,
+      sections: vec![
+        Section::Unchanged {
+          lines: vec![Cow::Borrowed("start\n")],
+        },
+        Section::Changed {
+          lines: vec![
+            changed_line(ChangeType::Removed, "foo\n"),
+            changed_line(ChangeType::Added, "FOO\n"),
+            changed_line(ChangeType::Removed, "bar\n"),
+            changed_line(ChangeType::Removed, "baz\n"),
+            changed_line(ChangeType::Added, "BAZ\n"),
+          ],
+        },
+        Section::Unchanged {
+          lines: vec![Cow::Borrowed("end\n")],
+        },
+      ],
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
+      #[cfg(feature = "tree-sitter")]
+      containers: None,
+    }],
+  };
+  let split_screenshot = TestingScreenshot::default();
+  let toggled_screenshot = TestingScreenshot::default();
+  let merged_screenshot = TestingScreenshot::default();
+  let undo_screenshot = TestingScreenshot::default();
+  let mut input = TestingInput::new(
+    80,
+    12,
+    [
+      Event::ExpandItem,
+      Event::FocusNext,
+      Event::SplitSection,
+      split_screenshot.event(),
+      Event::FocusNextSameKind,
+      Event::ToggleItem,
+      toggled_screenshot.event(),
+      Event::MergeSections,
+      merged_screenshot.event(),
+      Event::Undo,
+      undo_screenshot.event(),
+      Event::Redo,
+      Event::QuitAccept,
+    ],
+  );
+  let recorder = Recorder::new(state, &mut input);
+  let state = recorder.run()?;
+
+  insta::assert_snapshot!(split_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[ ] foo                                                                      [-]"
+  "        1 start⏎                                                                "
+  "  ( ) Section 1/2                                                            (-)"
+  "    [ ] - foo⏎                                                                  "
+  "    [ ] + FOO⏎                                                                  "
+  "  [ ] Section 2/2                                                            [-]"
+  "    [ ] - bar⏎                                                                  "
+  "    [ ] - baz⏎                                                                  "
+  "    [ ] + BAZ⏎                                                                  "
+  "        5 end⏎                                                                  "
+  "                                                                                "
+  "#);
+  insta::assert_snapshot!(toggled_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[◐] foo                                                                      [-]"
+  "        1 start⏎                                                                "
+  "  [ ] Section 1/2                                                            [-]"
+  "    [ ] - foo⏎                                                                  "
+  "    [ ] + FOO⏎                                                                  "
+  "  (●) Section 2/2                                                            (-)"
+  "    [●] - bar⏎                                                                  "
+  "    [●] - baz⏎                                                                  "
+  "    [●] + BAZ⏎                                                                  "
+  "        5 end⏎                                                                  "
+  "                                                                                "
+  "#);
+  insta::assert_snapshot!(merged_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[◐] foo                                                                      [-]"
+  "        1 start⏎                                                                "
+  "  (◐) Section 1/1                                                            (-)"
+  "    [ ] - foo⏎                                                                  "
+  "    [ ] + FOO⏎                                                                  "
+  "    [●] - bar⏎                                                                  "
+  "    [●] - baz⏎                                                                  "
+  "    [●] + BAZ⏎                                                                  "
+  "        5 end⏎                                                                  "
+  "                                                                                "
+  "                                                                                "
+  "#);
+  insta::assert_snapshot!(undo_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[ ] foo                                                                      [-]"
+  "        1 start⏎                                                                "
+  "  ( ) Section 1/1                                                            (-)"
+  "    [ ] - foo⏎                                                                  "
+  "    [ ] + FOO⏎                                                                  "
+  "    [ ] - bar⏎                                                                  "
+  "    [ ] - baz⏎                                                                  "
+  "    [ ] + BAZ⏎                                                                  "
+  "        5 end⏎                                                                  "
+  "                                                                                "
+  "                                                                                "
+  "#);
+  assert_debug_snapshot!(state.files[0].get_selected_contents().0.contents, @r#"
+  Text {
+      contents: "start\nfoo\nBAZ\nend\n",
+  }
+  "#);
+
+  Ok(())
+}
+
+#[test]
+fn test_summary() -> TestResult {
+  let changed_file = |path: &'static str, lines: &[(ChangeType, &'static str)]| File {
+    old_path: None,
+    path: Cow::Borrowed(Path::new(path)),
+    file_mode: FileMode::FILE_DEFAULT,
+    sections: vec![Section::Changed {
+      lines: lines
+        .iter()
+        .map(|(change_type, line)| SectionChangedLine {
+          is_checked: false,
+          change_type: *change_type,
+          line: Cow::Borrowed(line),
+        })
+        .collect(),
+    }],
+    commit_assignments: Default::default(),
+    similarity: None,
+    unloaded: None,
+    #[cfg(feature = "tree-sitter")]
+    containers: None,
+  };
+  let state = RecordState {
+    is_read_only: false,
+    commits: Default::default(),
+    files: vec![
+      changed_file(
+        "foo",
+        &[
+          (ChangeType::Removed, "foo\n"),
+          (ChangeType::Added, "FOO\n"),
+          (ChangeType::Added, "FOO2\n"),
+        ],
+      ),
+      changed_file(
+        "bar/baz",
+        &[
+          (ChangeType::Removed, "baz\n"),
+          (ChangeType::Removed, "baz2\n"),
+        ],
+      ),
+      File {
+        sections: Vec::new(),
+        unloaded: Some(FileStats {
+          num_added: Some(4),
+          num_removed: None,
+        }),
+        ..changed_file("qux", &[])
+      },
+    ],
+  };
+  assert_debug_snapshot!(state.diff_stat(), @"
+  DiffStat {
+      total: LineCounts {
+          num_added: 6,
+          num_removed: 3,
+      },
+      selected: LineCounts {
+          num_added: 0,
+          num_removed: 0,
+      },
+      unselected: LineCounts {
+          num_added: 6,
+          num_removed: 3,
+      },
+  }
+  ");
+  assert_debug_snapshot!(state.file_counts(), @"
+  FileCounts {
+      num_files: 3,
+      num_selected: 0,
+      num_partially_selected: 0,
+  }
+  ");
+
+  let summary_screenshot = TestingScreenshot::default();
+  let toggled_screenshot = TestingScreenshot::default();
+  let clicked_screenshot = TestingScreenshot::default();
+  let mut input = TestingInput::new(
+    80,
+    8,
+    [
+      Event::ToggleSummary,
+      summary_screenshot.event(),
+      Event::ToggleItem,
+      toggled_screenshot.event(),
+      Event::Click { row: 3, column: 6 },
+      clicked_screenshot.event(),
+      Event::QuitAccept,
+    ],
+  );
+  let recorder = Recorder::new(state, &mut input);
+  let state = recorder.run()?;
+
+  insta::assert_snapshot!(summary_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "3 files changed, 6 insertions(+), 3 deletions(-); selected: +0 -0               "
+  "[ ] foo     | 3 ++-                                                             "
+  "[ ] bar/baz | 2 --                                                              "
+  "[ ] qux     | 4 ++++                                                            "
+  "( ) foo                                                                      (+)"
+  "[ ] bar/baz                                                                  [+]"
+  "[ ] qux (+4 -?, not loaded)                                                  [+]"
+  "#);
+  insta::assert_snapshot!(toggled_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "3 files changed, 6 insertions(+), 3 deletions(-); selected: +2 -1               "
+  "[●] foo     | 3 ++-                                                             "
+  "[ ] bar/baz | 2 --                                                              "
+  "[ ] qux     | 4 ++++                                                            "
+  "(●) foo                                                                      (+)"
+  "[ ] bar/baz                                                                  [+]"
+  "[ ] qux (+4 -?, not loaded)                                                  [+]"
+  "#);
+  insta::assert_snapshot!(clicked_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "3 files changed, 6 insertions(+), 3 deletions(-); selected: +2 -1               "
+  "[●] foo     | 3 ++-                                                             "
+  "[ ] bar/baz | 2 --                                                              "
+  "[ ] qux     | 4 ++++                                                            "
+  "[●] foo                                                                      [+]"
+  "( ) bar/baz                                                                  (+)"
+  "[ ] qux (+4 -?, not loaded)                                                  [+]"
+  "#);
+  assert_debug_snapshot!(state.files[0].diff_stat(), @"
+  DiffStat {
+      total: LineCounts {
+          num_added: 2,
+          num_removed: 1,
+      },
+      selected: LineCounts {
+          num_added: 2,
+          num_removed: 1,
+      },
+      unselected: LineCounts {
+          num_added: 0,
+          num_removed: 0,
+      },
+  }
+  ");
+  assert_debug_snapshot!(state.file_counts(), @"
+  FileCounts {
+      num_files: 3,
+      num_selected: 1,
+      num_partially_selected: 0,
+  }
+  ");
+
+  Ok(())
+}
+
+#[test]
+fn test_search() -> TestResult {
+  let type_chars = |query: &str| query.chars().map(Event::TextInput).collect::<Vec<_>>();
+  let typing_screenshot = TestingScreenshot::default();
+  let next_screenshot = TestingScreenshot::default();
+  let prev_screenshot = TestingScreenshot::default();
+  let backward_screenshot = TestingScreenshot::default();
+  let invalid_screenshot = TestingScreenshot::default();
+  let cancelled_screenshot = TestingScreenshot::default();
+  let mut input = TestingInput::new(
+    80,
+    12,
+    [
+      vec![Event::StartSearch { is_backward: false }],
+      type_chars("after.*2"),
+      vec![
+        typing_screenshot.event(),
+        Event::TextSubmit,
+        Event::SearchNext,
+        next_screenshot.event(),
+        Event::SearchPrev,
+        prev_screenshot.event(),
+        Event::StartSearch { is_backward: true },
+      ],
+      type_chars("baz"),
+      vec![
+        Event::TextSubmit,
+        backward_screenshot.event(),
+        Event::StartSearch { is_backward: false },
+      ],
+      type_chars("(te"),
+      vec![
+        invalid_screenshot.event(),
+        Event::TextCancel,
+        cancelled_screenshot.event(),
+        Event::QuitAccept,
+      ],
+    ]
+    .concat(),
+  );
+  let state = example_contents();
+  let recorder = Recorder::new(state, &mut input).with_search_options(SearchOptions {
+    is_regex: true,
+    ..Default::default()
+  });
+  recorder.run()?;
+
+  insta::assert_snapshot!(typing_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[◐] foo/bar                                                                  [-]"
+  "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
+  "       18 this is some text⏎                                                    "
+  "       19 this is some text⏎                                                    "
+  "       20 this is some text⏎                                                    "
+  "  [◐] Section 1/1                                                            [-]"
+  "    [●] - before text 1⏎                                                        "
+  "    [●] - before text 2⏎                                                        "
+  "    [●] + after text 1⏎                                                         "
+  "    ( ) + after text 2⏎                                                         "
+  "/after.*2                                                                       "
+  "#);
+  insta::assert_snapshot!(next_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[◐] foo/bar                                                                  [-]"
+  "    [ ] + after text 2⏎                                                         "
+  "       23 this is some trailing text⏎                                           "
+  "[●] baz                                                                      [-]"
+  "        1 Some leading text 1⏎                                                  "
+  "        2 Some leading text 2⏎                                                  "
+  "  [●] Section 1/1                                                            [-]"
+  "    [●] - before text 1⏎                                                        "
+  "    [●] - before text 2⏎                                                        "
+  "    [●] + after text 1⏎                                                         "
+  "    (●) + after text 2⏎                                                         "
+  "#);
+  insta::assert_snapshot!(prev_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[◐] foo/bar                                                                  [-]"
+  "    ( ) + after text 2⏎                                                         "
+  "       23 this is some trailing text⏎                                           "
+  "[●] baz                                                                      [-]"
+  "        1 Some leading text 1⏎                                                  "
+  "        2 Some leading text 2⏎                                                  "
+  "  [●] Section 1/1                                                            [-]"
+  "    [●] - before text 1⏎                                                        "
+  "    [●] - before text 2⏎                                                        "
+  "    [●] + after text 1⏎                                                         "
+  "    [●] + after text 2⏎                                                         "
+  "#);
+  insta::assert_snapshot!(backward_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[◐] foo/bar                                                                  [-]"
+  "       23 this is some trailing text⏎                                           "
+  "(●) baz                                                                      (-)"
+  "        1 Some leading text 1⏎                                                  "
+  "        2 Some leading text 2⏎                                                  "
+  "  [●] Section 1/1                                                            [-]"
+  "    [●] - before text 1⏎                                                        "
+  "    [●] - before text 2⏎                                                        "
+  "    [●] + after text 1⏎                                                         "
+  "    [●] + after text 2⏎                                                         "
+  "        5 this is some trailing text⏎                                           "
+  "#);
+  insta::assert_snapshot!(invalid_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[◐] foo/bar                                                                  [-]"
+  "       23 this is some trailing text⏎                                           "
+  "(●) baz                                                                      (-)"
+  "        1 Some leading text 1⏎                                                  "
+  "        2 Some leading text 2⏎                                                  "
+  "  [●] Section 1/1                                                            [-]"
+  "    [●] - before text 1⏎                                                        "
+  "    [●] - before text 2⏎                                                        "
+  "    [●] + after text 1⏎                                                         "
+  "    [●] + after text 2⏎                                                         "
+  "/(te (invalid regex)                                                            "
+  "#);
+  insta::assert_snapshot!(cancelled_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[◐] foo/bar                                                                  [-]"
+  "       23 this is some trailing text⏎                                           "
+  "(●) baz                                                                      (-)"
+  "        1 Some leading text 1⏎                                                  "
+  "        2 Some leading text 2⏎                                                  "
+  "  [●] Section 1/1                                                            [-]"
+  "    [●] - before text 1⏎                                                        "
+  "    [●] - before text 2⏎                                                        "
+  "    [●] + after text 1⏎                                                         "
+  "    [●] + after text 2⏎                                                         "
+  "        5 this is some trailing text⏎                                           "
+  "#);
+
+  Ok(())
+}
+
+#[test]
+fn test_picker() -> TestResult {
+  let changed_file = |path: &'static str| File {
+    old_path: None,
+    path: Cow::Borrowed(Path::new(path)),
+    file_mode: FileMode::FILE_DEFAULT,
+    sections: vec![Section::Changed {
+      lines: vec![SectionChangedLine {
+        is_checked: false,
+        change_type: ChangeType::Added,
+        line: Cow::Borrowed("foo\n"),
+      }],
+    }],
+    commit_assignments: Default::default(),
+    similarity: None,
+    unloaded: None,
+    #[cfg(feature = "tree-sitter")]
+    containers: None,
+  };
+  let state = RecordState {
+    is_read_only: false,
+    commits: Default::default(),
+    files: vec![
+      changed_file("README.md"),
+      changed_file("src/list/bin.rs"),
+      changed_file("src/lib.rs"),
+      changed_file("tests/test_lib.rs"),
+    ],
+  };
+
+  let type_chars = |query: &str| query.chars().map(Event::TextInput).collect::<Vec<_>>();
+  let opened_screenshot = TestingScreenshot::default();
+  let typed_screenshot = TestingScreenshot::default();
+  let chosen_screenshot = TestingScreenshot::default();
+  let mut input = TestingInput::new(
+    80,
+    18,
+    [
+      vec![Event::OpenPicker, opened_screenshot.event()],
+      type_chars("lib"),
+      vec![
+        Event::FocusNext,
+        typed_screenshot.event(),
+        Event::TextSubmit,
+        chosen_screenshot.event(),
+        Event::QuitAccept,
+      ],
+    ]
+    .concat(),
+  );
+  let recorder = Recorder::new(state, &mut input);
+  recorder.run()?;
+
+  insta::assert_snapshot!(opened_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "( ) README.md                                                                (+)"
+  "[ ] src/list/b┌Go to file or symbol (4/4)────────────────────────┐           [+]"
+  "[ ] src/lib.rs│>                                                 │           [+]"
+  "[ ] tests/test│  README.md                                       │           [+]"
+  "              │  src/list/bin.rs                                 │              "
+  "              │  src/lib.rs                                      │              "
+  "              │  tests/test_lib.rs                               │              "
+  "              │                                                  │              "
+  "              │                                                  │              "
+  "              │                                                  │              "
+  "              │                                                  │              "
+  "              │                                                  │              "
+  "              │                                                  │              "
+  "              └──────────────────────────────────────────────────┘              "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "#);
+  insta::assert_snapshot!(typed_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "( ) README.md                                                                (+)"
+  "[ ] src/list/b┌Go to file or symbol (3/4)────────────────────────┐           [+]"
+  "[ ] src/lib.rs│> lib                                             │           [+]"
+  "[ ] tests/test│  src/lib.rs                                      │           [+]"
+  "              │  tests/test_lib.rs                               │              "
+  "              │  src/list/bin.rs                                 │              "
+  "              │                                                  │              "
+  "              │                                                  │              "
+  "              │                                                  │              "
+  "              │                                                  │              "
+  "              │                                                  │              "
+  "              │                                                  │              "
+  "              │                                                  │              "
+  "              └──────────────────────────────────────────────────┘              "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "#);
+  insta::assert_snapshot!(chosen_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[ ] README.md                                                                [+]"
+  "[ ] src/list/bin.rs                                                          [+]"
+  "[ ] src/lib.rs                                                               [+]"
+  "( ) tests/test_lib.rs                                                        (+)"
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "#);
+
+  Ok(())
+}
+
+#[cfg(feature = "tree-sitter")]
+#[test]
+fn test_picker_semantic_container() -> TestResult {
+  use scm_record::SemanticContainer;
+
+  let state = RecordState {
+    is_read_only: false,
+    commits: Default::default(),
+    files: vec![File {
+      old_path: None,
+      path: Cow::Borrowed(Path::new("src/lib.rs")),
+      file_mode: FileMode::FILE_DEFAULT,
+      sections: vec![
+        Section::Changed {
+          lines: vec![SectionChangedLine {
+            is_checked: false,
+            change_type: ChangeType::Added,
+            line: Cow::Borrowed("fn build_list() {}\n"),
+          }],
+        },
+        Section::Changed {
+          lines: vec![SectionChangedLine {
+            is_checked: false,
+            change_type: ChangeType::Added,
+            line: Cow::Borrowed("fn parse() {}\n"),
+          }],
+        },
+      ],
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
+      containers: Some(vec![
+        SemanticContainer::Function {
+          name: "build_list".to_owned(),
+          section_indices: vec![0],
+          is_checked: false,
+          is_partial: false,
+        },
+        SemanticContainer::Function {
+          name: "parse".to_owned(),
+          section_indices: vec![1],
+          is_checked: false,
+          is_partial: false,
+        },
+      ]),
+    }],
+  };
+
+  let typed_screenshot = TestingScreenshot::default();
+  let chosen_screenshot = TestingScreenshot::default();
+  let mut input = TestingInput::new(
+    80,
+    15,
+    [
+      Event::OpenPicker,
+      Event::TextInput('p'),
+      Event::TextInput('r'),
+      typed_screenshot.event(),
+      Event::TextSubmit,
+      chosen_screenshot.event(),
+      Event::QuitAccept,
+    ],
+  );
+  let recorder = Recorder::new(state, &mut input);
+  recorder.run()?;
+
+  insta::assert_snapshot!(typed_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "( ) src/lib.rs┌Go to file or symbol (1/3)────────────────────────┐           (+)"
+  "              │> pr                                              │              "
+  "              │  parse  src/lib.rs                               │              "
+  "              │                                                  │              "
+  "              │                                                  │              "
+  "              │                                                  │              "
+  "              │                                                  │              "
+  "              │                                                  │              "
+  "              │                                                  │              "
+  "              │                                                  │              "
+  "              │                                                  │              "
+  "              │                                                  │              "
+  "              └──────────────────────────────────────────────────┘              "
+  "                                                                                "
+  "#);
+  insta::assert_snapshot!(chosen_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[ ] src/lib.rs                                                               [-]"
+  "  [ ] 󰡱 fn build_list                                                        [+]"
+  "  ( ) 󰡱 fn parse                                                             (+)"
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "#);
+
+  Ok(())
+}
+
+#[test]
+fn test_directory_tree() -> TestResult {
+  let changed_file = |path: &'static str| File {
+    old_path: None,
+    path: Cow::Borrowed(Path::new(path)),
+    file_mode: FileMode::FILE_DEFAULT,
+    sections: vec![Section::Changed {
+      lines: vec![SectionChangedLine {
+        is_checked: false,
+        change_type: ChangeType::Added,
+        line: Cow::Borrowed("foo\n"),
+      }],
+    }],
+    commit_assignments: Default::default(),
+    similarity: None,
+    unloaded: None,
+    #[cfg(feature = "tree-sitter")]
+    containers: None,
+  };
+  let state = RecordState {
+    is_read_only: false,
+    commits: Default::default(),
+    files: vec![
+      changed_file("README.md"),
+      changed_file("src/legacy/foo.rs"),
+      changed_file("src/legacy/bar.rs"),
+      changed_file("src/lib.rs"),
+    ],
+  };
+
+  let opened_screenshot = TestingScreenshot::default();
+  let focused_screenshot = TestingScreenshot::default();
+  let toggled_screenshot = TestingScreenshot::default();
+  let partial_toggled_screenshot = TestingScreenshot::default();
+  let unfocused_screenshot = TestingScreenshot::default();
+  let hidden_screenshot = TestingScreenshot::default();
+  let mut input = TestingInput::new(
+    80,
+    8,
+    [
+      Event::ToggleDirectoryTree,
+      opened_screenshot.event(),
+      Event::FocusNext,
+      Event::FocusNext,
+      focused_screenshot.event(),
+      Event::ToggleItem,
+      toggled_screenshot.event(),
+      Event::FocusOuter { fold_section: true },
+      Event::ToggleItem,
+      partial_toggled_screenshot.event(),
+      Event::ToggleItem,
+      Event::ToggleItemAndAdvance,
+      Event::FocusNext,
+      unfocused_screenshot.event(),
+      Event::ToggleDirectoryTree,
+      Event::ToggleDirectoryTree,
+      hidden_screenshot.event(),
+      Event::QuitAccept,
+    ],
+  );
+  let recorder = Recorder::new(state, &mut input);
+  let state = recorder.run()?;
+
+  insta::assert_snapshot!(opened_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[ ] README.md            │( ) README.md                                      (+)"
+  "[ ] src/                 │[ ] src/legacy/foo.rs                              [+]"
+  "  [ ] legacy/            │[ ] src/legacy/bar.rs                              [+]"
+  "    [ ] foo.rs           │[ ] src/lib.rs                                     [+]"
+  "    [ ] bar.rs           │                                                      "
+  "  [ ] lib.rs             │                                                      "
+  "                         │                                                      "
+  "#);
+  insta::assert_snapshot!(focused_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[ ] README.md            │[ ] README.md                                      [+]"
+  "[ ] src/                 │( ) src/legacy/foo.rs                              (+)"
+  "  [ ] legacy/            │[ ] src/legacy/bar.rs                              [+]"
+  "    [ ] foo.rs           │[ ] src/lib.rs                                     [+]"
+  "    [ ] bar.rs           │                                                      "
+  "  [ ] lib.rs             │                                                      "
+  "                         │                                                      "
+  "#);
+  insta::assert_snapshot!(toggled_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[ ] README.md            │[ ] README.md                                      [+]"
+  "[◐] src/                 │(●) src/legacy/foo.rs                              (+)"
+  "  [●] legacy/            │[●] src/legacy/bar.rs                              [+]"
+  "    [●] foo.rs           │[ ] src/lib.rs                                     [+]"
+  "    [●] bar.rs           │                                                      "
+  "  [ ] lib.rs             │                                                      "
+  "                         │                                                      "
+  "#);
+  insta::assert_snapshot!(partial_toggled_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[ ] README.md            │[ ] README.md                                      [+]"
+  "[ ] src/                 │( ) src/legacy/foo.rs                              (+)"
+  "  [ ] legacy/            │[ ] src/legacy/bar.rs                              [+]"
+  "    [ ] foo.rs           │[ ] src/lib.rs                                     [+]"
+  "    [ ] bar.rs           │                                                      "
+  "  [ ] lib.rs             │                                                      "
+  "                         │                                                      "
+  "#);
+  insta::assert_snapshot!(unfocused_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[ ] README.md            │[ ] README.md                                      [+]"
+  "[●] src/                 │[●] src/legacy/foo.rs                              [+]"
+  "  [●] legacy/            │(●) src/legacy/bar.rs                              (+)"
+  "    [●] foo.rs           │[●] src/lib.rs                                     [+]"
+  "    [●] bar.rs           │                                                      "
+  "  [●] lib.rs             │                                                      "
+  "                         │                                                      "
+  "#);
+  insta::assert_snapshot!(hidden_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[ ] README.md                                                                [+]"
+  "[●] src/legacy/foo.rs                                                        [+]"
+  "(●) src/legacy/bar.rs                                                        (+)"
+  "[●] src/lib.rs                                                               [+]"
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "#);
+  let tristates: Vec<Tristate> = state.files.iter().map(File::tristate).collect();
+  assert_eq!(
+    tristates,
+    vec![
+      Tristate::False,
+      Tristate::True,
+      Tristate::True,
+      Tristate::True
+    ]
+  );
+  Ok(())
+}
+
+#[test]
+fn test_side_by_side_layout() -> TestResult {
+  let state = example_contents();
+  let split_screenshot = TestingScreenshot::default();
+  let toggled_screenshot = TestingScreenshot::default();
+  let unified_screenshot = TestingScreenshot::default();
+  let mut input = TestingInput::new(
+    80,
+    12,
+    [
+      Event::ToggleDiffLayout,
+      Event::ExpandAll,
+      split_screenshot.event(),
+      Event::FocusNext,
+      Event::FocusNext,
+      Event::FocusNext,
+      Event::ToggleItem,
+      Event::Click { row: 8, column: 43 },
+      Event::Click { row: 8, column: 43 },
+      toggled_screenshot.event(),
+      Event::ToggleDiffLayout,
+      unified_screenshot.event(),
+      Event::QuitAccept,
+    ],
+  );
+  let recorder = Recorder::new(state, &mut input);
+  recorder.run()?;
+
+  insta::assert_snapshot!(split_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "(◐) foo/bar                                                                  (-)"
+  "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
+  "       18 this is some text⏎             │   18 this is some text⏎              "
+  "       19 this is some text⏎             │   19 this is some text⏎              "
+  "       20 this is some text⏎             │   20 this is some text⏎              "
+  "  [◐] Section 1/1                                                            [-]"
+  "    [●] - before text 1⏎                 │[●] + after text 1⏎                   "
+  "    [●] - before text 2⏎                 │[ ] + after text 2⏎                   "
+  "       23 this is some trailing text⏎    │   23 this is some trailing text⏎     "
+  "[●] baz                                                                      [-]"
+  "        1 Some leading text 1⏎           │    1 Some leading text 1⏎            "
+  "#);
+  insta::assert_snapshot!(toggled_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[◐] foo/bar                                                                  [-]"
+  "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
+  "       18 this is some text⏎             │   18 this is some text⏎              "
+  "       19 this is some text⏎             │   19 this is some text⏎              "
+  "       20 this is some text⏎             │   20 this is some text⏎              "
+  "  [◐] Section 1/1                                                            [-]"
+  "    [●] - before text 1⏎                 │[●] + after text 1⏎                   "
+  "    [ ] - before text 2⏎                 │(●) + after text 2⏎                   "
+  "       23 this is some trailing text⏎    │   23 this is some trailing text⏎     "
+  "[●] baz                                                                      [-]"
+  "        1 Some leading text 1⏎           │    1 Some leading text 1⏎            "
+  "#);
+  insta::assert_snapshot!(unified_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[◐] foo/bar                                                                  [-]"
+  "        ⋮ 17 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
+  "       18 this is some text⏎                                                    "
+  "       19 this is some text⏎                                                    "
+  "       20 this is some text⏎                                                    "
+  "  [◐] Section 1/1                                                            [-]"
+  "    [●] - before text 1⏎                                                        "
+  "    [ ] - before text 2⏎                                                        "
+  "    [●] + after text 1⏎                                                         "
+  "    (●) + after text 2⏎                                                         "
+  "       23 this is some trailing text⏎                                           "
+  "#);
+  Ok(())
+}
+
+#[test]
+fn test_line_numbers() -> TestResult {
+  let changed_line = |change_type: ChangeType, line: &'static str| SectionChangedLine {
+    is_checked: false,
+    change_type,
+    line: Cow::Borrowed(line),
+  };
+  let file = File {
+    old_path: None,
+    path: Cow::Borrowed(Path::new("foo")),
+    file_mode: FileMode::FILE_DEFAULT,
+    sections: vec![
+      Section::Unchanged {
+        lines: vec![Cow::Borrowed("one\n"), Cow::Borrowed("two\n")],
+      },
+      Section::Changed {
+        lines: vec![
+          changed_line(ChangeType::Removed, "three\n"),
+          changed_line(ChangeType::Added, "THREE\n"),
+          changed_line(ChangeType::Added, "three and a half\n"),
+        ],
+      },
+      Section::Omitted {
+        num_lines: Some(10),
+      },
+      Section::Changed {
+        lines: vec![changed_line(ChangeType::Removed, "fourteen\n")],
+      },
+      Section::Unchanged {
+        lines: vec![Cow::Borrowed("fifteen\n")],
+      },
+    ],
+    commit_assignments: Default::default(),
+    similarity: None,
+    unloaded: None,
+    #[cfg(feature = "tree-sitter")]
+    containers: None,
+  };
+  let line_numbers: Vec<Vec<String>> = file
+    .line_numbers()
+    .into_iter()
+    .map(|section_line_numbers| {
+      section_line_numbers
+        .into_iter()
+        .map(|LineNumbers { old, new }| format!("{old:?} {new:?}"))
+        .collect()
+    })
+    .collect();
+  assert_debug_snapshot!(line_numbers, @r#"
+  [
+      [
+          "Some(1) Some(1)",
+          "Some(2) Some(2)",
+      ],
+      [
+          "Some(3) None",
+          "None Some(3)",
+          "None Some(4)",
+      ],
+      [],
+      [
+          "Some(14) None",
+      ],
+      [
+          "Some(15) Some(15)",
+      ],
+  ]
+  "#);
+  assert_eq!(
+    file.line_numbers_at(3, 0),
+    Some(LineNumbers {
+      old: Some(14),
+      new: None,
+    })
+  );
+  assert_eq!(file.line_numbers_at(2, 0), None);
+
+  let state = RecordState {
+    is_read_only: false,
+    commits: Default::default(),
+    files: vec![file],
+  };
+  let gutter_screenshot = TestingScreenshot::default();
+  let side_by_side_screenshot = TestingScreenshot::default();
+  let hidden_screenshot = TestingScreenshot::default();
+  let mut input = TestingInput::new(
+    80,
+    9,
+    [
+      Event::ExpandAll,
+      Event::ToggleLineNumbers,
+      gutter_screenshot.event(),
+      Event::ToggleDiffLayout,
+      side_by_side_screenshot.event(),
+      Event::ToggleDiffLayout,
+      Event::ToggleLineNumbers,
+      hidden_screenshot.event(),
+      Event::QuitAccept,
+    ],
+  );
+  let recorder = Recorder::new(state, &mut input);
+  recorder.run()?;
+
+  insta::assert_snapshot!(gutter_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "( ) foo                                                                      (-)"
+  "            1     1   one⏎                                                      "
+  "            2     2   two⏎                                                      "
+  "  [ ] Section 1/2                                                            [-]"
+  "    [ ]     3       - three⏎                                                    "
+  "    [ ]           3 + THREE⏎                                                    "
+  "    [ ]           4 + three and a half⏎                                         "
+  "        ⋮ 10 unchanged lines not shown                                          "
+  "#);
+  insta::assert_snapshot!(side_by_side_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "( ) foo                                                                      (-)"
+  "            1     1   one⏎               │        1     1   one⏎                "
+  "            2     2   two⏎               │        2     2   two⏎                "
+  "  [ ] Section 1/2                                                            [-]"
+  "    [ ]     3       - three⏎             │[ ]           3 + THREE⏎              "
+  "                                         │[ ]           4 + three and a half⏎   "
+  "        ⋮ 10 unchanged lines not shown                                          "
+  "  [ ] Section 2/2                                                            [-]"
+  "#);
+  insta::assert_snapshot!(hidden_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "( ) foo                                                                      (-)"
+  "        1 one⏎                                                                  "
+  "        2 two⏎                                                                  "
+  "  [ ] Section 1/2                                                            [-]"
+  "    [ ] - three⏎                                                                "
+  "    [ ] + THREE⏎                                                                "
+  "    [ ] + three and a half⏎                                                     "
+  "        ⋮ 10 unchanged lines not shown                                          "
+  "#);
+  Ok(())
+}
+
+#[test]
+fn test_expand_context() -> TestResult {
+  let changed_line = |change_type: ChangeType, line: &'static str| SectionChangedLine {
+    is_checked: false,
+    change_type,
+    line: Cow::Borrowed(line),
+  };
+  let state = RecordState {
+    is_read_only: false,
+    commits: Default::default(),
+    files: vec![File {
+      old_path: None,
+      path: Cow::Borrowed(Path::new("foo")),
+      file_mode: FileMode::FILE_DEFAULT,
+      sections: vec![
+        Section::Changed {
+          lines: vec![
+            changed_line(ChangeType::Removed, "one\n"),
+            changed_line(ChangeType::Added, "ONE\n"),
+          ],
+        },
+        Section::Unchanged {
+          lines: (2..=19)
+            .map(|line_num| Cow::Owned(format!("line {line_num}\n")))
+            .collect(),
+        },
+        Section::Changed {
+          lines: vec![
+            changed_line(ChangeType::Removed, "twenty\n"),
+            changed_line(ChangeType::Added, "TWENTY\n"),
+          ],
+        },
+      ],
+      commit_assignments: Default::default(),
+      similarity: None,
+      unloaded: None,
+      #[cfg(feature = "tree-sitter")]
+      containers: None,
+    }],
+  };
+  let initial_screenshot = TestingScreenshot::default();
+  let more_below_screenshot = TestingScreenshot::default();
+  let undo_screenshot = TestingScreenshot::default();
+  let more_above_screenshot = TestingScreenshot::default();
+  let all_screenshot = TestingScreenshot::default();
+  let mut input = TestingInput::new(
+    80,
+    22,
+    [
+      Event::ExpandAll,
+      Event::FocusNext,
+      initial_screenshot.event(),
+      Event::ShowMoreContextBelow,
+      more_below_screenshot.event(),
+      Event::Undo,
+      undo_screenshot.event(),
+      Event::Click { row: 9, column: 4 },
+      Event::ShowMoreContextAbove,
+      more_above_screenshot.event(),
+      Event::Click { row: 6, column: 40 },
+      all_screenshot.event(),
+      Event::QuitAccept,
+    ],
+  );
+  let recorder = Recorder::new(state, &mut input).with_num_context_lines(1);
+  recorder.run()?;
+
+  insta::assert_snapshot!(initial_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[ ] foo                                                                      [-]"
+  "  ( ) Section 1/2                                                            (-)"
+  "    [ ] - one⏎                                                                  "
+  "    [ ] + ONE⏎                                                                  "
+  "        2 line 2⏎                                                               "
+  "        ⋮ 16 unchanged lines not shown [↓ 10] [↑ 10] [all]                      "
+  "       19 line 19⏎                                                              "
+  "  [ ] Section 2/2                                                            [-]"
+  "    [ ] - twenty⏎                                                               "
+  "    [ ] + TWENTY⏎                                                               "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "                                                                                "
+  "#);
+  insta::assert_snapshot!(more_below_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[ ] foo                                                                      [-]"
+  "  ( ) Section 1/2                                                            (-)"
+  "    [ ] - one⏎                                                                  "
+  "    [ ] + ONE⏎                                                                  "
+  "        2 line 2⏎                                                               "
+  "        3 line 3⏎                                                               "
+  "        4 line 4⏎                                                               "
+  "        5 line 5⏎                                                               "
+  "        6 line 6⏎                                                               "
+  "        7 line 7⏎                                                               "
+  "        8 line 8⏎                                                               "
+  "        9 line 9⏎                                                               "
+  "       10 line 10⏎                                                              "
+  "       11 line 11⏎                                                              "
+  "       12 line 12⏎                                                              "
+  "        ⋮ 6 unchanged lines not shown [all]                                     "
+  "       19 line 19⏎                                                              "
+  "  [ ] Section 2/2                                                            [-]"
+  "    [ ] - twenty⏎                                                               "
+  "    [ ] + TWENTY⏎                                                               "
+  "                                                                                "
+  "#);
+  assert_eq!(undo_screenshot.to_string(), initial_screenshot.to_string());
+  insta::assert_snapshot!(more_above_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[ ] foo                                                                      [-]"
+  "  [ ] Section 1/2                                                            [-]"
+  "    [ ] - one⏎                                                                  "
+  "    [ ] + ONE⏎                                                                  "
+  "        2 line 2⏎                                                               "
+  "        ⋮ 6 unchanged lines not shown [all]                                     "
+  "        9 line 9⏎                                                               "
+  "       10 line 10⏎                                                              "
+  "       11 line 11⏎                                                              "
+  "       12 line 12⏎                                                              "
+  "       13 line 13⏎                                                              "
+  "       14 line 14⏎                                                              "
+  "       15 line 15⏎                                                              "
+  "       16 line 16⏎                                                              "
+  "       17 line 17⏎                                                              "
+  "       18 line 18⏎                                                              "
+  "       19 line 19⏎                                                              "
+  "  [ ] Section 2/2                                                            [-]"
+  "    ( ) - twenty⏎                                                               "
+  "    [ ] + TWENTY⏎                                                               "
+  "                                                                                "
+  "#);
+  insta::assert_snapshot!(all_screenshot, @r#"
+  "[File] [Edit] [Select] [View]                                                   "
+  "[ ] foo                                                                      [-]"
+  "        2 line 2⏎                                                               "
+  "        3 line 3⏎                                                               "
+  "        4 line 4⏎                                                               "
+  "        5 line 5⏎                                                               "
+  "        6 line 6⏎                                                               "
+  "        7 line 7⏎                                                               "
+  "        8 line 8⏎                                                               "
+  "        9 line 9⏎                                                               "
+  "       10 line 10⏎                                                              "
+  "       11 line 11⏎                                                              "
+  "       12 line 12⏎                                                              "
+  "       13 line 13⏎                                                              "
+  "       14 line 14⏎                                                              "
+  "       15 line 15⏎                                                              "
+  "       16 line 16⏎                                                              "
+  "       17 line 17⏎                                                              "
+  "       18 line 18⏎                                                              "
+  "       19 line 19⏎                                                              "
+  "  [ ] Section 2/2                                                            [-]"
+  "    ( ) - twenty⏎                                                               "
+  "#);
+  Ok(())
+}
@@ -0,0 +1,215 @@
+//! Decoding and re-encoding text files which aren't UTF-8.
+
+use std::borrow::Cow;
+use std::fmt;
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+/// The encoding of a text file, so that its selected contents can be written
+/// back the same way.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub struct TextEncoding {
+  /// The character encoding.
+  pub encoding: &'static Encoding,
+
+  /// Whether the file starts with a byte order mark.
+  pub has_bom: bool,
+}
+
+impl TextEncoding {
+  /// UTF-8 without a byte order mark.
+  pub const UTF8: Self = Self {
+    encoding: UTF_8,
+    has_bom: false,
+  };
+
+  /// Decode `bytes` as text. A byte order mark determines the encoding if
+  /// present. Otherwise, the bytes are decoded as UTF-8 if possible, and then
+  /// as `fallback`, if provided. Returns `None` if the bytes can't be decoded
+  /// or look like binary contents.
+  pub fn decode(
+    bytes: &[u8],
+    fallback: Option<&'static Encoding>,
+  ) -> Option<(String, Self)> {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+      let contents =
+        encoding.decode_without_bom_handling_and_without_replacement(&bytes[bom_len..])?;
+      let encoding = Self {
+        encoding,
+        has_bom: true,
+      };
+      return Some((contents.into_owned(), encoding));
+    }
+
+    // Only UTF-16 text contains NUL bytes, and it's detected by its byte order
+    // mark above.
+    if bytes.contains(&0) {
+      return None;
+    }
+    if let Ok(contents) = std::str::from_utf8(bytes) {
+      return Some((contents.to_owned(), Self::UTF8));
+    }
+    let encoding = fallback?;
+    let contents = encoding.decode_without_bom_handling_and_without_replacement(bytes)?;
+    let encoding = Self {
+      encoding,
+      has_bom: false,
+    };
+    Some((contents.into_owned(), encoding))
+  }
+
+  /// Encode `contents` in this encoding. Returns `None` if `contents` contains
+  /// characters which can't be represented in this encoding.
+  pub fn encode<'a>(
+    &self,
+    contents: &'a str,
+  ) -> Option<Cow<'a, [u8]>> {
+    let Self { encoding, has_bom } = *self;
+    // `Encoding::encode` writes UTF-16 as UTF-8, as the Encoding Standard
+    // requires, so encode it here instead.
+    let to_bytes: Option<fn(u16) -> [u8; 2]> = if encoding == UTF_16LE {
+      Some(u16::to_le_bytes)
+    } else if encoding == UTF_16BE {
+      Some(u16::to_be_bytes)
+    } else {
+      None
+    };
+    let bytes = match to_bytes {
+      Some(to_bytes) => Cow::Owned(
+        contents
+          .encode_utf16()
+          .flat_map(to_bytes)
+          .collect::<Vec<u8>>(),
+      ),
+      None => {
+        let (bytes, _, has_unmappable_chars) = encoding.encode(contents);
+        if has_unmappable_chars {
+          return None;
+        }
+        bytes
+      }
+    };
+    if !has_bom {
+      return Some(bytes);
+    }
+    let bom: &[u8] = if encoding == UTF_16LE {
+      b"\xff\xfe"
+    } else if encoding == UTF_16BE {
+      b"\xfe\xff"
+    } else {
+      b"\xef\xbb\xbf"
+    };
+    Some(Cow::Owned([bom, &bytes].concat()))
+  }
+}
+
+impl fmt::Debug for TextEncoding {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    let Self { encoding, has_bom } = self;
+    f.debug_struct("TextEncoding")
+      .field("encoding", &encoding.name())
+      .field("has_bom", has_bom)
+      .finish()
+  }
+}
+
+impl fmt::Display for TextEncoding {
+  fn fmt(
+    &self,
+    f: &mut fmt::Formatter<'_>,
+  ) -> fmt::Result {
+    let Self { encoding, has_bom } = self;
+    write!(f, "{}", encoding.name())?;
+    if *has_bom {
+      write!(f, " with BOM")?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use encoding_rs::WINDOWS_1252;
+
+  use super::*;
+
+  #[test]
+  fn test_decode() {
+    let decode = |bytes: &[u8]| TextEncoding::decode(bytes, Some(WINDOWS_1252));
+    insta::assert_debug_snapshot!(decode(b"caf\xc3\xa9\n"), @r#"
+    Some(
+        (
+            "café\n",
+            TextEncoding {
+                encoding: "UTF-8",
+                has_bom: false,
+            },
+        ),
+    )
+    "#);
+    insta::assert_debug_snapshot!(decode(b"caf\xe9\n"), @r#"
+    Some(
+        (
+            "café\n",
+            TextEncoding {
+                encoding: "windows-1252",
+                has_bom: false,
+            },
+        ),
+    )
+    "#);
+    insta::assert_debug_snapshot!(decode(b"\xff\xfec\x00a\x00f\x00\xe9\x00\n\x00"), @r#"
+    Some(
+        (
+            "café\n",
+            TextEncoding {
+                encoding: "UTF-16LE",
+                has_bom: true,
+            },
+        ),
+    )
+    "#);
+    insta::assert_debug_snapshot!(decode(b"\xef\xbb\xbfcaf\xc3\xa9\n"), @r#"
+    Some(
+        (
+            "café\n",
+            TextEncoding {
+                encoding: "UTF-8",
+                has_bom: true,
+            },
+        ),
+    )
+    "#);
+    // Without a fallback encoding, only UTF-8 is decoded.
+    assert_eq!(TextEncoding::decode(b"caf\xe9\n", None), None);
+    // Text which isn't valid in the encoding indicated by its BOM is binary.
+    assert_eq!(
+      TextEncoding::decode(b"\xef\xbb\xbf\xe9", Some(WINDOWS_1252)),
+      None
+    );
+    assert_eq!(TextEncoding::decode(b"\x00\x01", Some(WINDOWS_1252)), None);
+  }
+
+  #[test]
+  fn test_encode_round_trip() {
+    for bytes in [
+      &b"caf\xc3\xa9\n"[..],
+      b"caf\xe9\n",
+      b"\xff\xfec\x00a\x00f\x00\xe9\x00\n\x00",
+      b"\xfe\xff\x00c\x00a\x00f\x00\xe9\x00\n",
+      b"\xef\xbb\xbfcaf\xc3\xa9\n",
+    ] {
+      let (contents, encoding) = TextEncoding::decode(bytes, Some(WINDOWS_1252)).unwrap();
+      assert_eq!(encoding.encode(&contents).as_deref(), Some(bytes));
+    }
+
+    let latin1 = TextEncoding {
+      encoding: WINDOWS_1252,
+      has_bom: false,
+    };
+    assert_eq!(latin1.encode("\u{1f600}"), None);
+  }
+}
@@ -9,11 +9,14 @@
 )]
 #![allow(clippy::too_many_arguments)]
 
+mod encoding;
+mod renames;
 mod render;
 pub mod testing;
+mod whitespace;
 
 use std::borrow::Cow;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf, StripPrefixError};
@@ -23,10 +26,17 @@ use sha1::Digest;
 use thiserror::Error;
 use walkdir::WalkDir;
 
+pub use encoding::TextEncoding;
+use renames::Rename;
+pub use render::DiffAlgorithm;
 use scm_record::helpers::CrosstermInput;
+use scm_record::query::SelectionQuery;
+use scm_record::selection::{ApplySelectionStats, SelectionDocument};
 use scm_record::{
-  File, FileMode, RecordError, RecordState, Recorder, SelectedChanges, SelectedContents,
+  File, FileMode, FileStats, RecordError, RecordState, Recorder, Section, SectionProvider,
+  SelectedChanges, SelectedContents,
 };
+pub use whitespace::WhitespaceOptions;
 
 /// Render a partial commit selector for use as a difftool or mergetool.
 ///
@@ -75,6 +85,65 @@ pub struct Opts {
   /// Enable verbose debug logging.
   #[clap(short = 'v', long = "verbose")]
   pub verbose: bool,
+
+  /// When quitting or confirming, save which changes were selected to this
+  /// file, so that they can be restored with `--load-selection`.
+  #[clap(long = "save-selection")]
+  pub save_selection: Option<PathBuf>,
+
+  /// Restore the changes selected in a previous session, as saved with
+  /// `--save-selection`. Changes are matched by their contents, so the
+  /// selection can be restored even if the files have changed slightly.
+  #[clap(long = "load-selection")]
+  pub load_selection: Option<PathBuf>,
+
+  /// Select the changes matching this query instead of showing the
+  /// interface. May be passed multiple times. For example, `--select
+  /// 'path:src/** line:"fn parse"'` selects the changed lines under `src/`
+  /// containing `fn parse`, and `--select 'hunk:"fn parse"'` selects every
+  /// hunk which touches `fn parse`. See `scm_record::query` for the full
+  /// syntax.
+  #[clap(long = "select", value_name = "QUERY")]
+  pub select: Vec<SelectionQuery>,
+
+  /// Unselect the changes matching this query instead of showing the
+  /// interface. May be passed multiple times, and is applied after all
+  /// `--select` queries.
+  #[clap(long = "deselect", value_name = "QUERY")]
+  pub deselect: Vec<SelectionQuery>,
+
+  /// When comparing directories, don't detect renamed files. By default, a
+  /// file which only exists in the right-hand directory is shown as renamed
+  /// from a file which only exists in the left-hand directory if their
+  /// contents are at least 50% similar.
+  #[clap(long = "no-renames")]
+  pub no_renames: bool,
+
+  /// When detecting renamed files, also show a file as copied from a file
+  /// which was modified. This reads every file in both directories.
+  #[clap(long = "find-copies", conflicts_with = "no_renames")]
+  pub find_copies: bool,
+
+  /// The encoding of text files which aren't valid UTF-8 and don't start with
+  /// a byte order mark, such as `latin1` or `shift_jis`. Files are written
+  /// back in the encoding they were read in. Without this option, or if the
+  /// file isn't valid in this encoding either, the file is treated as binary.
+  #[clap(long = "encoding", value_name = "LABEL")]
+  pub encoding: Option<String>,
+
+  /// Which differences in whitespace to ignore when diffing text files.
+  #[clap(flatten)]
+  pub whitespace: WhitespaceOptions,
+
+  /// The algorithm used to diff text files. `patience` and `histogram` often
+  /// align changes to code better than the default `myers`.
+  #[clap(
+    long = "diff-algorithm",
+    value_enum,
+    default_value_t,
+    env = "SYNTAX_DIFF_EDITOR_DIFF_ALGORITHM"
+  )]
+  pub diff_algorithm: DiffAlgorithm,
 }
 
 #[derive(Debug, Error)]
@@ -115,12 +184,27 @@ pub enum Error {
   #[error("writing file {path}: {source}")]
   WriteFile { path: PathBuf, source: io::Error },
 
+  #[error("writing file {path}: the contents can't be encoded as {encoding}")]
+  EncodeFile {
+    path: PathBuf,
+    encoding: TextEncoding,
+  },
+
+  #[error("unknown encoding: {label}")]
+  UnknownEncoding { label: String },
+
   #[error("file did not exist: {path}")]
   MissingMergeFile { path: PathBuf },
 
   #[error("file was not text: {path}")]
   BinaryMergeFile { path: PathBuf },
 
+  #[error("selection file did not exist: {path}")]
+  MissingSelectionFile { path: PathBuf },
+
+  #[error("loading selection from {path}: {source}")]
+  LoadSelection { path: PathBuf, source: RecordError },
+
   #[error("mixed path types: {left} is a {left_type} but {right} is a {right_type}")]
   MixedPathTypes {
     left: PathBuf,
@@ -131,6 +215,12 @@ pub enum Error {
 
   #[error("recording changes: {source}")]
   Record { source: RecordError },
+
+  #[error("can't write {path}: some of its lines aren't available")]
+  IncompleteFile { path: PathBuf },
+
+  #[error("can't write {path}: its symbolic link target isn't valid UTF-8")]
+  NonUtf8SymlinkTarget { path: PathBuf },
 }
 
 /// Result type alias.
@@ -147,6 +237,10 @@ pub struct FileInfo {
   pub contents: FileContents,
 }
 
+/// Binary files larger than this aren't kept in memory to be shown as a hex
+/// dump.
+pub const MAX_HEX_DUMP_BYTES: u64 = 1 << 20;
+
 /// Representation of a file's contents.
 #[derive(Clone, Debug)]
 pub enum FileContents {
@@ -161,8 +255,11 @@ pub enum FileContents {
     /// The hash of `contents`.
     hash: String,
 
-    /// The size of `contents`, in bytes.
+    /// The size of the file, in bytes.
     num_bytes: u64,
+
+    /// The encoding `contents` was decoded from.
+    encoding: TextEncoding,
   },
 
   /// The file is a binary file (not able to be displayed directly in the UI).
@@ -172,6 +269,24 @@ pub enum FileContents {
 
     /// The size of the file's contents, in bytes.
     num_bytes: u64,
+
+    /// The file's contents, if it's no larger than [`MAX_HEX_DUMP_BYTES`],
+    /// for showing a hex dump of the changes.
+    contents: Option<Vec<u8>>,
+  },
+
+  /// The file is a symbolic link.
+  Symlink {
+    /// The path the symbolic link points to. A target which isn't valid UTF-8
+    /// is read lossily, so it can only be kept, not written (see
+    /// [`Error::NonUtf8SymlinkTarget`]).
+    target: String,
+  },
+
+  /// The file is a Git submodule (a gitlink).
+  Submodule {
+    /// The hash of the commit the submodule points to.
+    commit: String,
   },
 }
 
@@ -197,6 +312,21 @@ pub trait Filesystem {
     contents: &str,
   ) -> Result<()>;
 
+  /// Write new file contents to `path`, encoded in `encoding`.
+  fn write_encoded_file(
+    &mut self,
+    path: &Path,
+    contents: &str,
+    encoding: TextEncoding,
+  ) -> Result<()>;
+
+  /// Replace the file at `path` with a symbolic link pointing to `target`.
+  fn write_symlink(
+    &mut self,
+    path: &Path,
+    target: &str,
+  ) -> Result<()>;
+
   /// Copy the file at `old_path` to `new_path`. (This can be more efficient
   /// than reading and writing the entire contents, particularly for large
   /// binary files.)
@@ -219,7 +349,10 @@ pub trait Filesystem {
   ) -> Result<()>;
 }
 
-struct RealFilesystem;
+struct RealFilesystem {
+  /// The encoding of text files which aren't UTF-8. See [`Opts::encoding`].
+  fallback_encoding: Option<&'static encoding_rs::Encoding>,
+}
 
 impl Filesystem for RealFilesystem {
   fn read_dir_diff_paths(
@@ -229,9 +362,18 @@ impl Filesystem for RealFilesystem {
   ) -> Result<BTreeSet<PathBuf>> {
     fn walk_dir(dir: &Path) -> Result<BTreeSet<PathBuf>> {
       let mut files = BTreeSet::new();
-      for entry in WalkDir::new(dir) {
+      let mut entries = WalkDir::new(dir).into_iter();
+      while let Some(entry) = entries.next() {
         let entry = entry.map_err(|err| Error::WalkDir { source: err })?;
-        if entry.file_type().is_file() || entry.file_type().is_symlink() {
+        // A submodule is compared as a single file, rather than by its
+        // contents.
+        let is_submodule = entry.depth() > 0
+          && entry.file_type().is_dir()
+          && read_submodule_commit(entry.path()).is_some();
+        if is_submodule {
+          entries.skip_current_dir();
+        }
+        if entry.file_type().is_file() || entry.file_type().is_symlink() || is_submodule {
           let relative_path = match entry.path().strip_prefix(dir) {
             Ok(path) => path.to_owned(),
             Err(err) => {
@@ -260,11 +402,27 @@ impl Filesystem for RealFilesystem {
     &self,
     path: &Path,
   ) -> Result<FileInfo> {
-    let file_mode = match fs::metadata(path) {
+    let file_mode = match fs::symlink_metadata(path) {
       Ok(metadata) => {
-        // TODO: no support for gitlinks (submodules).
         if metadata.is_symlink() {
-          FileMode::Unix(0o120000)
+          let target = fs::read_link(path).map_err(|err| Error::ReadFile {
+            path: path.to_owned(),
+            source: err,
+          })?;
+          return Ok(FileInfo {
+            file_mode: FileMode::SYMLINK,
+            contents: FileContents::Symlink {
+              target: target.to_string_lossy().into_owned(),
+            },
+          });
+        } else if metadata.is_dir() {
+          if let Some(commit) = read_submodule_commit(path) {
+            return Ok(FileInfo {
+              file_mode: FileMode::GITLINK,
+              contents: FileContents::Submodule { commit },
+            });
+          }
+          FileMode::FILE_DEFAULT
         } else {
           let permissions = metadata.permissions();
           #[cfg(unix)]
@@ -297,17 +455,19 @@ impl Filesystem for RealFilesystem {
           format!("{:x}", hasher.finalize())
         };
         let num_bytes: u64 = contents.len().try_into().unwrap();
-        if contents.contains(&0) {
-          FileContents::Binary { hash, num_bytes }
-        } else {
-          match String::from_utf8(contents) {
-            Ok(contents) => FileContents::Text {
-              contents,
-              hash,
-              num_bytes,
-            },
-            Err(_) => FileContents::Binary { hash, num_bytes },
-          }
+        let binary = |contents: Vec<u8>| FileContents::Binary {
+          hash: hash.clone(),
+          num_bytes,
+          contents: (num_bytes <= MAX_HEX_DUMP_BYTES).then_some(contents),
+        };
+        match TextEncoding::decode(&contents, self.fallback_encoding) {
+          Some((contents, encoding)) => FileContents::Text {
+            contents,
+            hash,
+            num_bytes,
+            encoding,
+          },
+          None => binary(contents),
         }
       }
       Err(err) if err.kind() == io::ErrorKind::NotFound => FileContents::Absent,
@@ -335,6 +495,40 @@ impl Filesystem for RealFilesystem {
     })
   }
 
+  fn write_encoded_file(
+    &mut self,
+    path: &Path,
+    contents: &str,
+    encoding: TextEncoding,
+  ) -> Result<()> {
+    let bytes = encoding.encode(contents).ok_or_else(|| Error::EncodeFile {
+      path: path.to_owned(),
+      encoding,
+    })?;
+    fs::write(path, bytes).map_err(|err| Error::WriteFile {
+      path: path.to_owned(),
+      source: err,
+    })
+  }
+
+  fn write_symlink(
+    &mut self,
+    path: &Path,
+    target: &str,
+  ) -> Result<()> {
+    self.remove_file(path)?;
+    #[cfg(unix)]
+    let result = std::os::unix::fs::symlink(target, path);
+    // Like Git with `core.symlinks = false`, write the target as the file's
+    // contents where symbolic links aren't supported.
+    #[cfg(not(unix))]
+    let result = fs::write(path, target);
+    result.map_err(|err| Error::WriteFile {
+      path: path.to_owned(),
+      source: err,
+    })
+  }
+
   fn copy_file(
     &mut self,
     old_path: &Path,
@@ -374,6 +568,32 @@ impl Filesystem for RealFilesystem {
   }
 }
 
+/// If `dir` is the working copy of a Git submodule, read the commit it has
+/// checked out.
+fn read_submodule_commit(dir: &Path) -> Option<String> {
+  let dot_git = dir.join(".git");
+  // In a superproject, `.git` is a file pointing to the submodule's Git
+  // directory.
+  let git_dir = match fs::read_to_string(&dot_git) {
+    Ok(contents) => dir.join(contents.strip_prefix("gitdir:")?.trim()),
+    Err(_) if dot_git.is_dir() => dot_git,
+    Err(_) => return None,
+  };
+  let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+  let head = head.trim();
+  let Some(ref_name) = head.strip_prefix("ref:").map(str::trim) else {
+    return Some(head.to_owned());
+  };
+  if let Ok(commit) = fs::read_to_string(git_dir.join(ref_name)) {
+    return Some(commit.trim().to_owned());
+  }
+  let packed_refs = fs::read_to_string(git_dir.join("packed-refs")).ok()?;
+  packed_refs.lines().find_map(|line| {
+    let (commit, name) = line.split_once(' ')?;
+    (name == ref_name).then(|| commit.to_owned())
+  })
+}
+
 /// Information about the files to display/diff in the UI.
 #[derive(Debug)]
 pub struct DiffContext {
@@ -392,6 +612,24 @@ pub struct DiffContext {
 pub fn process_opts(
   filesystem: &dyn Filesystem,
   opts: &Opts,
+) -> Result<DiffContext> {
+  process_opts_inner(filesystem, opts, false)
+}
+
+/// Like [`process_opts`], but when diffing directories, the files aren't read
+/// or diffed. Instead, each file's [`File::unloaded`] is set, and its sections
+/// should be loaded on demand with a [`DirDiffSectionProvider`].
+pub fn process_opts_lazy(
+  filesystem: &dyn Filesystem,
+  opts: &Opts,
+) -> Result<DiffContext> {
+  process_opts_inner(filesystem, opts, true)
+}
+
+fn process_opts_inner(
+  filesystem: &dyn Filesystem,
+  opts: &Opts,
+  lazy: bool,
 ) -> Result<DiffContext> {
   use tracing::debug;
 
@@ -439,6 +677,9 @@ pub fn process_opts(
         left.clone(),
         right.clone(),
         right.clone(),
+        None,
+        opts.whitespace,
+        opts.diff_algorithm,
       )?];
       DiffContext {
         files,
@@ -457,15 +698,56 @@ pub fn process_opts(
       ..
     } if effective_dir_diff => {
       let display_paths = filesystem.read_dir_diff_paths(left, right)?;
+      // Detecting renames only reads the files which exist on one side, so
+      // it's done even when the other files are loaded lazily.
+      let renames = if opts.no_renames {
+        Vec::new()
+      } else {
+        renames::detect_renames(filesystem, left, right, opts.find_copies)?
+      };
+      // A renamed file is shown at its new path only.
+      let renamed_paths: BTreeSet<&Path> = renames
+        .iter()
+        .filter(|rename| !rename.is_copy)
+        .map(|rename| rename.old_path.as_path())
+        .collect();
+      let renames_by_new_path: BTreeMap<&Path, &Rename> = renames
+        .iter()
+        .map(|rename| (rename.new_path.as_path(), rename))
+        .collect();
+
       let mut files = Vec::new();
       for display_path in display_paths {
-        files.push(render::create_file(
-          filesystem,
-          left.join(&display_path),
-          display_path.clone(),
-          right.join(&display_path),
-          display_path.clone(),
-        )?);
+        if renamed_paths.contains(display_path.as_path()) {
+          continue;
+        }
+        let rename = renames_by_new_path.get(display_path.as_path()).copied();
+        if lazy {
+          files.push(File {
+            old_path: rename.map(|rename| Cow::Owned(rename.old_path.clone())),
+            path: Cow::Owned(display_path),
+            // Replaced with the actual file mode once the file is loaded.
+            file_mode: FileMode::FILE_DEFAULT,
+            sections: Vec::new(),
+            commit_assignments: Default::default(),
+            similarity: rename.map(|rename| rename.similarity),
+            unloaded: Some(FileStats::default()),
+            #[cfg(feature = "tree-sitter")]
+            containers: None,
+          });
+        } else {
+          let old_display_path = rename.map_or(&display_path, |rename| &rename.old_path);
+          files.push(render::create_file(
+            filesystem,
+            left.join(old_display_path),
+            old_display_path.clone(),
+            right.join(&display_path),
+            display_path.clone(),
+            rename,
+            opts.whitespace,
+            opts.diff_algorithm,
+          )?);
+        }
       }
       DiffContext {
         files,
@@ -550,10 +832,21 @@ fn print_dry_run(
     } = file;
 
     let SelectedChanges {
+      path,
       contents,
       file_mode,
     } = selected_contents;
 
+    let selected_path = write_root.join(path);
+    if selected_path != file_path {
+      println!(
+        "Would move file {} back to {}",
+        file_path.display(),
+        selected_path.display()
+      );
+    }
+    let file_path = selected_path;
+
     if file_mode == FileMode::Absent {
       println!("Would delete file: {}", file_path.display());
       continue;
@@ -591,6 +884,22 @@ fn print_dry_run(
           println!("  {line}");
         }
       }
+      SelectedContents::Symlink { target } => {
+        println!("Would update symlink: {}", file_path.display());
+        println!("  Target: {target}");
+      }
+      SelectedContents::Submodule { commit } => {
+        println!("Would update submodule: {}", file_path.display());
+        println!("  Commit: {commit}");
+      }
+      SelectedContents::Incomplete {
+        num_omitted_lines: _,
+      } => {
+        println!(
+          "Can't update file with unavailable lines: {}",
+          file_path.display()
+        );
+      }
     }
   }
 }
@@ -610,15 +919,52 @@ pub fn apply_changes(
   if is_read_only {
     return Ok(());
   }
+
+  // Check before writing anything, so that no files are left half-written.
+  for file in &files {
+    let (selected_changes, _unselected_changes) = file.get_selected_contents();
+    if let SelectedContents::Incomplete {
+      num_omitted_lines: _,
+    } = selected_changes.contents
+    {
+      return Err(Error::IncompleteFile {
+        path: write_root.join(selected_changes.path),
+      });
+    }
+    if let SelectedContents::Symlink { target } = &selected_changes.contents {
+      let path = write_root.join(selected_changes.path);
+      if target.contains(char::REPLACEMENT_CHARACTER) && !is_symlink_to(filesystem, &path, target)?
+      {
+        return Err(Error::NonUtf8SymlinkTarget { path });
+      }
+    }
+  }
+
   for file in files {
     let file_path = write_root.join(file.path.clone());
     let (selected_changes, _unselected_changes) = file.get_selected_contents();
 
     let SelectedChanges {
+      path,
       contents,
       file_mode,
     } = selected_changes;
 
+    // If the rename wasn't selected, move the file back to its old path.
+    let is_renamed = file
+      .sections
+      .iter()
+      .any(|section| matches!(section, Section::Rename { .. }));
+    let selected_path = write_root.join(path);
+    if selected_path != file_path {
+      if let Some(parent_dir) = selected_path.parent() {
+        filesystem.create_dir_all(parent_dir)?;
+      }
+      filesystem.copy_file(&file_path, &selected_path)?;
+      filesystem.remove_file(&file_path)?;
+    }
+    let file_path = selected_path;
+
     if file_mode == FileMode::Absent {
       filesystem.remove_file(&file_path)?;
     }
@@ -633,8 +979,9 @@ pub fn apply_changes(
       } => {
         let new_path = file_path;
         let old_path = match &file.old_path {
-          Some(old_path) => old_path.clone(),
-          None => Cow::Borrowed(new_path.as_path()),
+          // The new contents of a renamed file are already at its path.
+          Some(old_path) if !is_renamed => old_path.clone(),
+          Some(_) | None => Cow::Borrowed(new_path.as_path()),
         };
         filesystem.copy_file(&old_path, &new_path)?;
       }
@@ -644,14 +991,263 @@ pub fn apply_changes(
         }
 
         // TODO: Respect executable bit
-        filesystem.write_file(&file_path, &contents)?;
+        let encoding = match read_for_overwrite(filesystem, &file_path)? {
+          // Write the file back in the encoding it was read in.
+          FileContents::Text { encoding, .. } => encoding,
+          FileContents::Absent
+          | FileContents::Binary { .. }
+          | FileContents::Symlink { .. }
+          | FileContents::Submodule { .. } => TextEncoding::UTF8,
+        };
+        filesystem.write_encoded_file(&file_path, &contents, encoding)?;
+      }
+      SelectedContents::Symlink { target } => {
+        if !is_symlink_to(filesystem, &file_path, &target)? {
+          if let Some(parent_dir) = file_path.parent() {
+            filesystem.create_dir_all(parent_dir)?;
+          }
+          filesystem.write_symlink(&file_path, &target)?;
+        }
+      }
+      SelectedContents::Submodule { commit } => {
+        let is_checked_out = match read_for_overwrite(filesystem, &file_path)? {
+          FileContents::Submodule {
+            commit: current_commit,
+          } => current_commit == commit,
+          FileContents::Absent
+          | FileContents::Text { .. }
+          | FileContents::Binary { .. }
+          | FileContents::Symlink { .. } => false,
+        };
+        if !is_checked_out {
+          // We can't check out a different commit in the submodule, so write
+          // the commit the same way `git difftool --dir-diff` does.
+          filesystem.write_file(&file_path, &format!("Subproject commit {commit}\n"))?;
+        }
+      }
+      SelectedContents::Incomplete {
+        num_omitted_lines: _,
+      } => {
+        return Err(Error::IncompleteFile { path: file_path });
       }
     }
   }
   Ok(())
 }
 
-/// Select changes interactively and apply them to disk.
+/// Whether the file at `path` is already a symbolic link to `target`, so that
+/// it doesn't need to be written. This keeps a target which isn't valid UTF-8,
+/// which [`FileContents::Symlink`] only has a lossy copy of.
+fn is_symlink_to(
+  filesystem: &dyn Filesystem,
+  path: &Path,
+  target: &str,
+) -> Result<bool> {
+  let FileInfo {
+    file_mode: _,
+    contents,
+  } = filesystem.read_file_info(path)?;
+  Ok(match contents {
+    FileContents::Symlink {
+      target: current_target,
+    } => current_target == target,
+    FileContents::Absent
+    | FileContents::Text { .. }
+    | FileContents::Binary { .. }
+    | FileContents::Submodule { .. } => false,
+  })
+}
+
+/// Read the current contents of the file at `path` before it's overwritten.
+/// If it's a symbolic link, it's removed, so that writing to it doesn't write
+/// to its target instead.
+fn read_for_overwrite(
+  filesystem: &mut dyn Filesystem,
+  path: &Path,
+) -> Result<FileContents> {
+  let FileInfo {
+    file_mode,
+    contents,
+  } = filesystem.read_file_info(path)?;
+  if file_mode == FileMode::SYMLINK {
+    filesystem.remove_file(path)?;
+  }
+  Ok(contents)
+}
+
+/// Restore the selection saved to `path` by [`save_selection`] into `state`.
+pub fn load_selection(
+  filesystem: &dyn Filesystem,
+  path: &Path,
+  state: &mut RecordState,
+) -> Result<ApplySelectionStats> {
+  let FileInfo {
+    file_mode: _,
+    contents,
+  } = filesystem.read_file_info(path)?;
+  let json = match contents {
+    FileContents::Text {
+      contents,
+      hash: _,
+      num_bytes: _,
+      encoding: _,
+    } => contents,
+    FileContents::Absent => {
+      return Err(Error::MissingSelectionFile {
+        path: path.to_owned(),
+      })
+    }
+    FileContents::Binary {
+      hash: _,
+      num_bytes: _,
+      contents: _,
+    }
+    | FileContents::Symlink { target: _ }
+    | FileContents::Submodule { commit: _ } => {
+      return Err(Error::BinaryMergeFile {
+        path: path.to_owned(),
+      })
+    }
+  };
+  let document = SelectionDocument::from_json(&json).map_err(|err| Error::LoadSelection {
+    path: path.to_owned(),
+    source: err,
+  })?;
+  Ok(document.apply(state))
+}
+
+/// Save which changes are selected in `state` to `path`, so that they can be
+/// restored by [`load_selection`].
+pub fn save_selection(
+  filesystem: &mut dyn Filesystem,
+  path: &Path,
+  state: &RecordState,
+) -> Result<()> {
+  let json = SelectionDocument::from_state(state)
+    .to_json()
+    .map_err(|err| Error::Record { source: err })?;
+  filesystem.write_file(path, &json)
+}
+
+/// Loads the files of a directory diff created by [`process_opts_lazy`] on
+/// demand, so that the interface can be shown before every file has been read
+/// and diffed.
+pub struct DirDiffSectionProvider<'a> {
+  filesystem: &'a dyn Filesystem,
+  left: PathBuf,
+  right: PathBuf,
+  whitespace: WhitespaceOptions,
+  algorithm: DiffAlgorithm,
+}
+
+impl<'a> DirDiffSectionProvider<'a> {
+  /// Constructor. `left` and `right` are the directories being compared.
+  pub fn new(
+    filesystem: &'a dyn Filesystem,
+    left: PathBuf,
+    right: PathBuf,
+  ) -> Self {
+    Self {
+      filesystem,
+      left,
+      right,
+      whitespace: Default::default(),
+      algorithm: Default::default(),
+    }
+  }
+
+  /// Ignore differences in whitespace when diffing files, as configured by
+  /// `whitespace`.
+  pub fn with_whitespace_options(
+    mut self,
+    whitespace: WhitespaceOptions,
+  ) -> Self {
+    self.whitespace = whitespace;
+    self
+  }
+
+  /// Diff text files with `algorithm`.
+  pub fn with_diff_algorithm(
+    mut self,
+    algorithm: DiffAlgorithm,
+  ) -> Self {
+    self.algorithm = algorithm;
+    self
+  }
+}
+
+impl SectionProvider for DirDiffSectionProvider<'_> {
+  fn load_file(
+    &mut self,
+    file: &File,
+  ) -> std::result::Result<File<'static>, RecordError> {
+    let Self {
+      filesystem,
+      left,
+      right,
+      whitespace,
+      algorithm,
+    } = self;
+    let display_path = file.path.to_path_buf();
+    let rename = match (&file.old_path, file.similarity) {
+      (Some(old_path), Some(similarity)) => {
+        // A copied file's source still exists.
+        let FileInfo {
+          file_mode,
+          contents: _,
+        } = filesystem
+          .read_file_info(&right.join(old_path))
+          .map_err(|err| RecordError::Other(err.to_string()))?;
+        Some(Rename {
+          old_path: old_path.to_path_buf(),
+          new_path: display_path.clone(),
+          similarity,
+          is_copy: file_mode != FileMode::Absent,
+        })
+      }
+      (Some(_), None) | (None, _) => None,
+    };
+    let old_display_path = rename
+      .as_ref()
+      .map_or(&display_path, |rename| &rename.old_path);
+    render::create_file(
+      *filesystem,
+      left.join(old_display_path),
+      old_display_path.clone(),
+      right.join(&display_path),
+      display_path.clone(),
+      rename.as_ref(),
+      *whitespace,
+      *algorithm,
+    )
+    .map_err(|err| RecordError::Other(err.to_string()))
+  }
+}
+
+/// Select the changes in `state` matching any of the `select` queries, and
+/// then unselect those matching any of the `deselect` queries.
+pub fn select_changes(
+  state: &mut RecordState,
+  select: &[SelectionQuery],
+  deselect: &[SelectionQuery],
+) {
+  use tracing::info;
+
+  for (queries, is_select) in [(select, true), (deselect, false)] {
+    for query in queries {
+      let num_matches = query.apply(state, is_select);
+      info!(
+        "{} {} change(s) matching {:?}",
+        if is_select { "Selected" } else { "Unselected" },
+        num_matches,
+        query
+      );
+    }
+  }
+}
+
+/// Select changes interactively (or with `--select`/`--deselect`) and apply
+/// them to disk.
 pub fn run(opts: Opts) -> Result<()> {
   use tracing::{debug, info};
 
@@ -666,25 +1262,87 @@ pub fn run(opts: Opts) -> Result<()> {
     is_stdin_tty, is_stdout_tty, is_stderr_tty
   );
 
-  let filesystem = RealFilesystem;
+  let fallback_encoding = match &opts.encoding {
+    Some(label) => Some(
+      encoding_rs::Encoding::for_label(label.as_bytes()).ok_or_else(|| Error::UnknownEncoding {
+        label: label.clone(),
+      })?,
+    ),
+    None => None,
+  };
+  let filesystem = RealFilesystem { fallback_encoding };
   debug!("Processing filesystem options...");
-  let DiffContext { files, write_root } = process_opts(&filesystem, &opts)?;
+  // Selections can only be applied to loaded files, so only load files on
+  // demand if the user will select changes interactively.
+  let is_interactive =
+    opts.select.is_empty() && opts.deselect.is_empty() && opts.load_selection.is_none();
+  let DiffContext { files, write_root } = if is_interactive {
+    process_opts_lazy(&filesystem, &opts)?
+  } else {
+    process_opts(&filesystem, &opts)?
+  };
   info!("Loaded {} file(s) for diffing", files.len());
 
-  let state = RecordState {
+  let mut state = RecordState {
     is_read_only: opts.read_only,
     commits: Default::default(),
     files,
   };
+  if let Some(path) = &opts.load_selection {
+    let ApplySelectionStats {
+      num_exact,
+      num_fuzzy,
+      num_unmatched,
+    } = load_selection(&filesystem, path, &mut state)?;
+    info!(
+      "Restored selection from {}: {} exact matches, {} fuzzy matches, {} unmatched",
+      path.display(),
+      num_exact,
+      num_fuzzy,
+      num_unmatched
+    );
+  }
 
-  debug!("Initializing crossterm input reader...");
-  let mut input = CrosstermInput;
-
-  debug!("Creating recorder...");
-  let recorder = Recorder::new(state, &mut input);
-
-  debug!("Starting recorder UI...");
-  match recorder.run() {
+  let mut cancelled_state = None;
+  let mut section_provider =
+    DirDiffSectionProvider::new(&filesystem, opts.left.clone(), opts.right.clone())
+      .with_whitespace_options(opts.whitespace)
+      .with_diff_algorithm(opts.diff_algorithm);
+  let result = if opts.select.is_empty() && opts.deselect.is_empty() {
+    debug!("Initializing crossterm input reader...");
+    let mut input = CrosstermInput;
+
+    debug!("Creating recorder...");
+    let recorder = Recorder::new(state, &mut input).with_section_provider(&mut section_provider);
+
+    debug!("Starting recorder UI...");
+    recorder.run_with_cancelled_state(&mut cancelled_state)
+  } else {
+    select_changes(&mut state, &opts.select, &opts.deselect);
+    Ok(state)
+  };
+  if let Some(path) = &opts.save_selection {
+    // Accepting the changes loads every file, but cancelling doesn't, so load
+    // the rest of the files to save the selections in them.
+    if let Some(state) = &mut cancelled_state {
+      for file in &mut state.files {
+        if file.unloaded.is_some() {
+          let loaded_file = section_provider
+            .load_file(file)
+            .map_err(|err| Error::Record { source: err })?;
+          *file = File {
+            unloaded: None,
+            ..loaded_file
+          };
+        }
+      }
+    }
+    if let Some(state) = result.as_ref().ok().or(cancelled_state.as_ref()) {
+      let mut filesystem = RealFilesystem { fallback_encoding };
+      save_selection(&mut filesystem, path, state)?;
+    }
+  }
+  match result {
     Ok(state) => {
       if opts.dry_run {
         print_dry_run(&write_root, state);
@@ -790,6 +1448,50 @@ mod tests {
       Ok(())
     }
 
+    fn write_encoded_file(
+      &mut self,
+      path: &Path,
+      contents: &str,
+      encoding: TextEncoding,
+    ) -> Result<()> {
+      self.assert_parent_dir_exists(path);
+      let bytes = encoding.encode(contents).ok_or_else(|| Error::EncodeFile {
+        path: path.to_owned(),
+        encoding,
+      })?;
+      self.files.insert(
+        path.to_owned(),
+        FileInfo {
+          file_mode: FileMode::Unix(0o100644),
+          contents: FileContents::Text {
+            contents: contents.to_owned(),
+            hash: "abc123".to_string(),
+            num_bytes: bytes.len().try_into().unwrap(),
+            encoding,
+          },
+        },
+      );
+      Ok(())
+    }
+
+    fn write_symlink(
+      &mut self,
+      path: &Path,
+      target: &str,
+    ) -> Result<()> {
+      self.assert_parent_dir_exists(path);
+      self.files.insert(
+        path.to_owned(),
+        FileInfo {
+          file_mode: FileMode::SYMLINK,
+          contents: FileContents::Symlink {
+            target: target.to_owned(),
+          },
+        },
+      );
+      Ok(())
+    }
+
     fn copy_file(
       &mut self,
       old_path: &Path,
@@ -827,6 +1529,7 @@ mod tests {
         contents,
         hash: "abc123".to_string(),
         num_bytes,
+        encoding: TextEncoding::UTF8,
       },
     }
   }
@@ -867,6 +1570,15 @@ qux2
         read_only: false,
         dry_run: false,
         verbose: false,
+        save_selection: None,
+        load_selection: None,
+        select: Vec::new(),
+        deselect: Vec::new(),
+        no_renames: false,
+        find_copies: false,
+        encoding: None,
+        whitespace: Default::default(),
+        diff_algorithm: Default::default(),
       },
     )?;
     assert_debug_snapshot!(files, @r#"
@@ -915,6 +1627,9 @@ qux2
                         ],
                     },
                 ],
+                commit_assignments: [],
+                similarity: None,
+                unloaded: None,
                 containers: None,
             },
         ]
@@ -930,35 +1645,43 @@ qux2
         files,
       },
     )?;
-    insta::assert_debug_snapshot!(filesystem, @r###"
-        TestFilesystem {
-            files: {
-                "left": FileInfo {
-                    file_mode: Unix(
-                        33188,
-                    ),
-                    contents: Text {
-                        contents: "foo\ncommon1\ncommon2\nbar\n",
-                        hash: "abc123",
-                        num_bytes: 24,
+    insta::assert_debug_snapshot!(filesystem, @r#"
+    TestFilesystem {
+        files: {
+            "left": FileInfo {
+                file_mode: Unix(
+                    33188,
+                ),
+                contents: Text {
+                    contents: "foo\ncommon1\ncommon2\nbar\n",
+                    hash: "abc123",
+                    num_bytes: 24,
+                    encoding: TextEncoding {
+                        encoding: "UTF-8",
+                        has_bom: false,
                     },
                 },
-                "right": FileInfo {
-                    file_mode: Unix(
-                        33188,
-                    ),
-                    contents: Text {
-                        contents: "qux1\ncommon1\ncommon2\nqux2\n",
-                        hash: "abc123",
-                        num_bytes: 26,
+            },
+            "right": FileInfo {
+                file_mode: Unix(
+                    33188,
+                ),
+                contents: Text {
+                    contents: "qux1\ncommon1\ncommon2\nqux2\n",
+                    hash: "abc123",
+                    num_bytes: 26,
+                    encoding: TextEncoding {
+                        encoding: "UTF-8",
+                        has_bom: false,
                     },
                 },
             },
-            dirs: {
-                "",
-            },
-        }
-        "###);
+        },
+        dirs: {
+            "",
+        },
+    }
+    "#);
 
     Ok(())
   }
@@ -990,6 +1713,15 @@ qux2
         read_only: false,
         dry_run: false,
         verbose: false,
+        save_selection: None,
+        load_selection: None,
+        select: Vec::new(),
+        deselect: Vec::new(),
+        no_renames: false,
+        find_copies: false,
+        encoding: None,
+        whitespace: Default::default(),
+        diff_algorithm: Default::default(),
       },
     )?;
 
@@ -1002,35 +1734,43 @@ qux2
         files,
       },
     )?;
-    insta::assert_debug_snapshot!(filesystem, @r###"
-        TestFilesystem {
-            files: {
-                "left": FileInfo {
-                    file_mode: Unix(
-                        33188,
-                    ),
-                    contents: Text {
-                        contents: "foo\ncommon1\ncommon2\nbar\n",
-                        hash: "abc123",
-                        num_bytes: 24,
+    insta::assert_debug_snapshot!(filesystem, @r#"
+    TestFilesystem {
+        files: {
+            "left": FileInfo {
+                file_mode: Unix(
+                    33188,
+                ),
+                contents: Text {
+                    contents: "foo\ncommon1\ncommon2\nbar\n",
+                    hash: "abc123",
+                    num_bytes: 24,
+                    encoding: TextEncoding {
+                        encoding: "UTF-8",
+                        has_bom: false,
                     },
                 },
-                "right": FileInfo {
-                    file_mode: Unix(
-                        33188,
-                    ),
-                    contents: Text {
-                        contents: "foo\ncommon1\ncommon2\nbar\n",
-                        hash: "abc123",
-                        num_bytes: 24,
+            },
+            "right": FileInfo {
+                file_mode: Unix(
+                    33188,
+                ),
+                contents: Text {
+                    contents: "foo\ncommon1\ncommon2\nbar\n",
+                    hash: "abc123",
+                    num_bytes: 24,
+                    encoding: TextEncoding {
+                        encoding: "UTF-8",
+                        has_bom: false,
                     },
                 },
             },
-            dirs: {
-                "",
-            },
-        }
-        "###);
+        },
+        dirs: {
+            "",
+        },
+    }
+    "#);
 
     Ok(())
   }
@@ -1054,6 +1794,15 @@ qux2
         read_only: false,
         dry_run: false,
         verbose: false,
+        save_selection: None,
+        load_selection: None,
+        select: Vec::new(),
+        deselect: Vec::new(),
+        no_renames: false,
+        find_copies: false,
+        encoding: None,
+        whitespace: Default::default(),
+        diff_algorithm: Default::default(),
       },
     )?;
     assert_debug_snapshot!(files, @r#"
@@ -1081,6 +1830,9 @@ qux2
                         ],
                     },
                 ],
+                commit_assignments: [],
+                similarity: None,
+                unloaded: None,
                 containers: None,
             },
         ]
@@ -1096,25 +1848,29 @@ qux2
         files,
       },
     )?;
-    insta::assert_debug_snapshot!(filesystem, @r###"
-        TestFilesystem {
-            files: {
-                "right": FileInfo {
-                    file_mode: Unix(
-                        33188,
-                    ),
-                    contents: Text {
-                        contents: "right\n",
-                        hash: "abc123",
-                        num_bytes: 6,
+    insta::assert_debug_snapshot!(filesystem, @r#"
+    TestFilesystem {
+        files: {
+            "right": FileInfo {
+                file_mode: Unix(
+                    33188,
+                ),
+                contents: Text {
+                    contents: "right\n",
+                    hash: "abc123",
+                    num_bytes: 6,
+                    encoding: TextEncoding {
+                        encoding: "UTF-8",
+                        has_bom: false,
                     },
                 },
             },
-            dirs: {
-                "",
-            },
-        }
-        "###);
+        },
+        dirs: {
+            "",
+        },
+    }
+    "#);
 
     Ok(())
   }
@@ -1138,6 +1894,15 @@ qux2
         read_only: false,
         dry_run: false,
         verbose: false,
+        save_selection: None,
+        load_selection: None,
+        select: Vec::new(),
+        deselect: Vec::new(),
+        no_renames: false,
+        find_copies: false,
+        encoding: None,
+        whitespace: Default::default(),
+        diff_algorithm: Default::default(),
       },
     )?;
     assert_debug_snapshot!(files, @r#"
@@ -1165,6 +1930,9 @@ qux2
                         ],
                     },
                 ],
+                commit_assignments: [],
+                similarity: None,
+                unloaded: None,
                 containers: None,
             },
         ]
@@ -1180,25 +1948,29 @@ qux2
         files,
       },
     )?;
-    insta::assert_debug_snapshot!(filesystem, @r###"
-        TestFilesystem {
-            files: {
-                "left": FileInfo {
-                    file_mode: Unix(
-                        33188,
-                    ),
-                    contents: Text {
-                        contents: "left\n",
-                        hash: "abc123",
-                        num_bytes: 5,
+    insta::assert_debug_snapshot!(filesystem, @r#"
+    TestFilesystem {
+        files: {
+            "left": FileInfo {
+                file_mode: Unix(
+                    33188,
+                ),
+                contents: Text {
+                    contents: "left\n",
+                    hash: "abc123",
+                    num_bytes: 5,
+                    encoding: TextEncoding {
+                        encoding: "UTF-8",
+                        has_bom: false,
                     },
                 },
             },
-            dirs: {
-                "",
-            },
-        }
-        "###);
+        },
+        dirs: {
+            "",
+        },
+    }
+    "#);
 
     Ok(())
   }
@@ -1220,6 +1992,15 @@ qux2
         read_only: false,
         dry_run: false,
         verbose: false,
+        save_selection: None,
+        load_selection: None,
+        select: Vec::new(),
+        deselect: Vec::new(),
+        no_renames: false,
+        find_copies: false,
+        encoding: None,
+        whitespace: Default::default(),
+        diff_algorithm: Default::default(),
       },
     );
     insta::assert_debug_snapshot!(result, @r###"
@@ -1255,6 +2036,15 @@ qux2
         read_only: false,
         dry_run: false,
         verbose: false,
+        save_selection: None,
+        load_selection: None,
+        select: Vec::new(),
+        deselect: Vec::new(),
+        no_renames: false,
+        find_copies: false,
+        encoding: None,
+        whitespace: Default::default(),
+        diff_algorithm: Default::default(),
       },
     )?;
 
@@ -1267,37 +2057,45 @@ qux2
         files,
       },
     )?;
-    assert_debug_snapshot!(filesystem, @r###"
-        TestFilesystem {
-            files: {
-                "left/foo": FileInfo {
-                    file_mode: Unix(
-                        33188,
-                    ),
-                    contents: Text {
-                        contents: "left contents\n",
-                        hash: "abc123",
-                        num_bytes: 14,
+    assert_debug_snapshot!(filesystem, @r#"
+    TestFilesystem {
+        files: {
+            "left/foo": FileInfo {
+                file_mode: Unix(
+                    33188,
+                ),
+                contents: Text {
+                    contents: "left contents\n",
+                    hash: "abc123",
+                    num_bytes: 14,
+                    encoding: TextEncoding {
+                        encoding: "UTF-8",
+                        has_bom: false,
                     },
                 },
-                "right/foo": FileInfo {
-                    file_mode: Unix(
-                        33188,
-                    ),
-                    contents: Text {
-                        contents: "left contents\n",
-                        hash: "abc123",
-                        num_bytes: 14,
+            },
+            "right/foo": FileInfo {
+                file_mode: Unix(
+                    33188,
+                ),
+                contents: Text {
+                    contents: "left contents\n",
+                    hash: "abc123",
+                    num_bytes: 14,
+                    encoding: TextEncoding {
+                        encoding: "UTF-8",
+                        has_bom: false,
                     },
                 },
             },
-            dirs: {
-                "",
-                "left",
-                "right",
-            },
-        }
-        "###);
+        },
+        dirs: {
+            "",
+            "left",
+            "right",
+        },
+    }
+    "#);
 
     Ok(())
   }
@@ -1320,6 +2118,15 @@ qux2
         read_only: false,
         dry_run: false,
         verbose: false,
+        save_selection: None,
+        load_selection: None,
+        select: Vec::new(),
+        deselect: Vec::new(),
+        no_renames: false,
+        find_copies: false,
+        encoding: None,
+        whitespace: Default::default(),
+        diff_algorithm: Default::default(),
       },
     )?;
 
@@ -1332,37 +2139,45 @@ qux2
         files,
       },
     )?;
-    assert_debug_snapshot!(filesystem, @r###"
-        TestFilesystem {
-            files: {
-                "left/foo": FileInfo {
-                    file_mode: Unix(
-                        33188,
-                    ),
-                    contents: Text {
-                        contents: "left contents\n",
-                        hash: "abc123",
-                        num_bytes: 14,
+    assert_debug_snapshot!(filesystem, @r#"
+    TestFilesystem {
+        files: {
+            "left/foo": FileInfo {
+                file_mode: Unix(
+                    33188,
+                ),
+                contents: Text {
+                    contents: "left contents\n",
+                    hash: "abc123",
+                    num_bytes: 14,
+                    encoding: TextEncoding {
+                        encoding: "UTF-8",
+                        has_bom: false,
                     },
                 },
-                "right/foo": FileInfo {
-                    file_mode: Unix(
-                        33188,
-                    ),
-                    contents: Text {
-                        contents: "left contents\n",
-                        hash: "abc123",
-                        num_bytes: 14,
+            },
+            "right/foo": FileInfo {
+                file_mode: Unix(
+                    33188,
+                ),
+                contents: Text {
+                    contents: "left contents\n",
+                    hash: "abc123",
+                    num_bytes: 14,
+                    encoding: TextEncoding {
+                        encoding: "UTF-8",
+                        has_bom: false,
                     },
                 },
             },
-            dirs: {
-                "",
-                "left",
-                "right",
-            },
-        }
-        "###);
+        },
+        dirs: {
+            "",
+            "left",
+            "right",
+        },
+    }
+    "#);
 
     Ok(())
   }
@@ -1407,6 +2222,15 @@ Hello world 4
         base: Some("base".into()),
         output: Some("output".into()),
         verbose: false,
+        save_selection: None,
+        load_selection: None,
+        select: Vec::new(),
+        deselect: Vec::new(),
+        no_renames: false,
+        find_copies: false,
+        encoding: None,
+        whitespace: Default::default(),
+        diff_algorithm: Default::default(),
       },
     )?;
     insta::assert_debug_snapshot!(files, @r#"
@@ -1451,6 +2275,9 @@ Hello world 4
                         ],
                     },
                 ],
+                commit_assignments: [],
+                similarity: None,
+                unloaded: None,
                 containers: None,
             },
         ]
@@ -1467,55 +2294,71 @@ Hello world 4
       },
     )?;
 
-    assert_debug_snapshot!(filesystem, @r###"
-        TestFilesystem {
-            files: {
-                "base": FileInfo {
-                    file_mode: Unix(
-                        33188,
-                    ),
-                    contents: Text {
-                        contents: "Hello world 1\nHello world 2\nHello world 3\nHello world 4\n",
-                        hash: "abc123",
-                        num_bytes: 56,
+    assert_debug_snapshot!(filesystem, @r#"
+    TestFilesystem {
+        files: {
+            "base": FileInfo {
+                file_mode: Unix(
+                    33188,
+                ),
+                contents: Text {
+                    contents: "Hello world 1\nHello world 2\nHello world 3\nHello world 4\n",
+                    hash: "abc123",
+                    num_bytes: 56,
+                    encoding: TextEncoding {
+                        encoding: "UTF-8",
+                        has_bom: false,
                     },
                 },
-                "left": FileInfo {
-                    file_mode: Unix(
-                        33188,
-                    ),
-                    contents: Text {
-                        contents: "Hello world 1\nHello world 2\nHello world L\nHello world 4\n",
-                        hash: "abc123",
-                        num_bytes: 56,
+            },
+            "left": FileInfo {
+                file_mode: Unix(
+                    33188,
+                ),
+                contents: Text {
+                    contents: "Hello world 1\nHello world 2\nHello world L\nHello world 4\n",
+                    hash: "abc123",
+                    num_bytes: 56,
+                    encoding: TextEncoding {
+                        encoding: "UTF-8",
+                        has_bom: false,
                     },
                 },
-                "output": FileInfo {
-                    file_mode: Unix(
-                        33188,
-                    ),
-                    contents: Text {
-                        contents: "Hello world 1\nHello world 2\nHello world L\nHello world R\nHello world 4\n",
-                        hash: "abc123",
-                        num_bytes: 70,
+            },
+            "output": FileInfo {
+                file_mode: Unix(
+                    33188,
+                ),
+                contents: Text {
+                    contents: "Hello world 1\nHello world 2\nHello world L\nHello world R\nHello world 4\n",
+                    hash: "abc123",
+                    num_bytes: 70,
+                    encoding: TextEncoding {
+                        encoding: "UTF-8",
+                        has_bom: false,
                     },
                 },
-                "right": FileInfo {
-                    file_mode: Unix(
-                        33188,
-                    ),
-                    contents: Text {
-                        contents: "Hello world 1\nHello world 2\nHello world R\nHello world 4\n",
-                        hash: "abc123",
-                        num_bytes: 56,
+            },
+            "right": FileInfo {
+                file_mode: Unix(
+                    33188,
+                ),
+                contents: Text {
+                    contents: "Hello world 1\nHello world 2\nHello world R\nHello world 4\n",
+                    hash: "abc123",
+                    num_bytes: 56,
+                    encoding: TextEncoding {
+                        encoding: "UTF-8",
+                        has_bom: false,
                     },
                 },
             },
-            dirs: {
-                "",
-            },
-        }
-        "###);
+        },
+        dirs: {
+            "",
+        },
+    }
+    "#);
 
     Ok(())
   }
@@ -1544,6 +2387,15 @@ Hello world 2
         base: None,
         output: None,
         verbose: false,
+        save_selection: None,
+        load_selection: None,
+        select: Vec::new(),
+        deselect: Vec::new(),
+        no_renames: false,
+        find_copies: false,
+        encoding: None,
+        whitespace: Default::default(),
+        diff_algorithm: Default::default(),
       },
     )?;
     insta::assert_debug_snapshot!(files, @r#"
@@ -1576,6 +2428,9 @@ Hello world 2
                         ],
                     },
                 ],
+                commit_assignments: [],
+                similarity: None,
+                unloaded: None,
                 containers: None,
             },
         ]
@@ -1611,25 +2466,29 @@ Hello world 2
         files: files.clone(),
       },
     )?;
-    insta::assert_debug_snapshot!(filesystem, @r###"
-        TestFilesystem {
-            files: {
-                "right": FileInfo {
-                    file_mode: Unix(
-                        33188,
-                    ),
-                    contents: Text {
-                        contents: "Hello world 1\nHello world 2\n",
-                        hash: "abc123",
-                        num_bytes: 28,
+    insta::assert_debug_snapshot!(filesystem, @r#"
+    TestFilesystem {
+        files: {
+            "right": FileInfo {
+                file_mode: Unix(
+                    33188,
+                ),
+                contents: Text {
+                    contents: "Hello world 1\nHello world 2\n",
+                    hash: "abc123",
+                    num_bytes: 28,
+                    encoding: TextEncoding {
+                        encoding: "UTF-8",
+                        has_bom: false,
                     },
                 },
             },
-            dirs: {
-                "",
-            },
-        }
-        "###);
+        },
+        dirs: {
+            "",
+        },
+    }
+    "#);
 
     // Select only some changes from new file.
     match files[0].sections.get_mut(1).unwrap() {
@@ -1645,26 +2504,380 @@ Hello world 2
         files: files.clone(),
       },
     )?;
-    insta::assert_debug_snapshot!(filesystem, @r###"
-        TestFilesystem {
-            files: {
-                "right": FileInfo {
-                    file_mode: Unix(
-                        33188,
-                    ),
-                    contents: Text {
-                        contents: "Hello world 2\n",
-                        hash: "abc123",
-                        num_bytes: 14,
+    insta::assert_debug_snapshot!(filesystem, @r#"
+    TestFilesystem {
+        files: {
+            "right": FileInfo {
+                file_mode: Unix(
+                    33188,
+                ),
+                contents: Text {
+                    contents: "Hello world 2\n",
+                    hash: "abc123",
+                    num_bytes: 14,
+                    encoding: TextEncoding {
+                        encoding: "UTF-8",
+                        has_bom: false,
                     },
                 },
             },
-            dirs: {
-                "",
+        },
+        dirs: {
+            "",
+        },
+    }
+    "#);
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_save_and_load_selection() -> Result<()> {
+    let mut filesystem = TestFilesystem::new(btreemap! {
+        PathBuf::from("left") => file_info("foo\ncommon\nbar\n"),
+        PathBuf::from("right") => file_info("qux1\ncommon\nqux2\n"),
+    });
+    let opts = Opts {
+      dir_diff: false,
+      left: PathBuf::from("left"),
+      right: PathBuf::from("right"),
+      base: None,
+      output: None,
+      read_only: false,
+      dry_run: false,
+      verbose: false,
+      save_selection: None,
+      load_selection: None,
+      select: Vec::new(),
+      deselect: Vec::new(),
+      no_renames: false,
+      find_copies: false,
+      encoding: None,
+      whitespace: Default::default(),
+      diff_algorithm: Default::default(),
+    };
+    let DiffContext {
+      mut files,
+      write_root: _,
+    } = process_opts(&filesystem, &opts)?;
+    match files[0].sections.get_mut(0).unwrap() {
+      Section::Changed { ref mut lines } => lines[1].is_checked = true,
+      _ => panic!("Expected changed section"),
+    }
+    let state = RecordState {
+      is_read_only: false,
+      commits: Default::default(),
+      files,
+    };
+    save_selection(&mut filesystem, Path::new("selection.json"), &state)?;
+
+    let DiffContext {
+      files,
+      write_root: _,
+    } = process_opts(&filesystem, &opts)?;
+    let mut new_state = RecordState {
+      is_read_only: false,
+      commits: Default::default(),
+      files,
+    };
+    let stats = load_selection(&filesystem, Path::new("selection.json"), &mut new_state)?;
+    assert_eq!(
+      stats,
+      ApplySelectionStats {
+        num_exact: 4,
+        num_fuzzy: 0,
+        num_unmatched: 0,
+      }
+    );
+    assert_eq!(new_state, state);
+
+    assert!(matches!(
+      load_selection(&filesystem, Path::new("missing.json"), &mut new_state),
+      Err(Error::MissingSelectionFile { .. })
+    ));
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_select_changes() -> Result<()> {
+    let mut filesystem = TestFilesystem::new(btreemap! {
+        PathBuf::from("left/src/lib.rs") => file_info("fn foo() {}\n"),
+        PathBuf::from("right/src/lib.rs") => file_info("fn foo() {}\ndbg!(foo());\nfn bar() {}\n"),
+        PathBuf::from("left/README") => file_info("old\n"),
+        PathBuf::from("right/README") => file_info("new\n"),
+    });
+    let opts = Opts::try_parse_from([
+      "syntax-diff-editor",
+      "--dir-diff",
+      "left",
+      "right",
+      "--select",
+      "path:src/**",
+      "--deselect",
+      r"line:^dbg!\(",
+    ])
+    .unwrap();
+    let DiffContext { files, write_root } = process_opts(&filesystem, &opts)?;
+    let mut state = RecordState {
+      is_read_only: false,
+      commits: Default::default(),
+      files,
+    };
+    select_changes(&mut state, &opts.select, &opts.deselect);
+    apply_changes(&mut filesystem, &write_root, state)?;
+
+    let contents = |path: &str| match filesystem.read_file_info(Path::new(path)) {
+      Ok(FileInfo {
+        file_mode: _,
+        contents: FileContents::Text { contents, .. },
+      }) => contents,
+      result => panic!("unexpected file info: {result:?}"),
+    };
+    assert_eq!(contents("right/src/lib.rs"), "fn foo() {}\nfn bar() {}\n");
+    assert_eq!(contents("right/README"), "old\n");
+
+    assert!(
+      Opts::try_parse_from(["syntax-diff-editor", "a", "b", "--select", "name:foo"]).is_err()
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_select_hunk() -> Result<()> {
+    let mut filesystem = TestFilesystem::new(btreemap! {
+        PathBuf::from("left") => file_info("fn parse() {\n    old();\n}\nfn other() {\n    a();\n}\n"),
+        PathBuf::from("right") => file_info("fn parse(input: &str) {\n    new();\n}\nfn other() {\n    b();\n}\n"),
+    });
+    let opts = Opts::try_parse_from([
+      "syntax-diff-editor",
+      "left",
+      "right",
+      "--select",
+      r#"hunk:"fn parse""#,
+    ])
+    .unwrap();
+    let DiffContext { files, write_root } = process_opts(&filesystem, &opts)?;
+    let mut state = RecordState {
+      is_read_only: false,
+      commits: Default::default(),
+      files,
+    };
+    select_changes(&mut state, &opts.select, &opts.deselect);
+    apply_changes(&mut filesystem, &write_root, state)?;
+
+    // The whole first hunk is selected, including `new();`, but not the
+    // second hunk.
+    match filesystem.read_file_info(Path::new("right"))? {
+      FileInfo {
+        file_mode: _,
+        contents: FileContents::Text { contents, .. },
+      } => assert_eq!(
+        contents,
+        "fn parse(input: &str) {\n    new();\n}\nfn other() {\n    a();\n}\n"
+      ),
+      file_info => panic!("unexpected file info: {file_info:?}"),
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn test_dir_diff_lazy() -> Result<()> {
+    let filesystem = TestFilesystem::new(btreemap! {
+        PathBuf::from("left/foo") => file_info("foo\n"),
+        PathBuf::from("right/foo") => file_info("foo changed\n"),
+        PathBuf::from("right/bar") => file_info("new file\n"),
+    });
+    let opts = Opts::try_parse_from(["syntax-diff-editor", "--dir-diff", "left", "right"]).unwrap();
+
+    let DiffContext {
+      files: lazy_files,
+      write_root: lazy_write_root,
+    } = process_opts_lazy(&filesystem, &opts)?;
+    let DiffContext { files, write_root } = process_opts(&filesystem, &opts)?;
+    assert_eq!(lazy_write_root, write_root);
+    assert!(lazy_files
+      .iter()
+      .all(|file| file.unloaded.is_some() && file.sections.is_empty()));
+
+    let mut section_provider =
+      DirDiffSectionProvider::new(&filesystem, opts.left.clone(), opts.right.clone());
+    let loaded_files = lazy_files
+      .iter()
+      .map(|file| section_provider.load_file(file))
+      .collect::<std::result::Result<Vec<_>, _>>()
+      .unwrap();
+    assert_eq!(loaded_files, files);
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_dir_diff_lazy_renames() -> Result<()> {
+    let filesystem = TestFilesystem::new(btreemap! {
+        PathBuf::from("left/foo") => file_info("foo\n"),
+        PathBuf::from("right/foo") => file_info("foo changed\n"),
+        PathBuf::from("left/old") => file_info("line 1\nline 2\nline 3\n"),
+        PathBuf::from("right/new") => file_info("line 1\nline 2\nline 3\nline 4\n"),
+    });
+    let opts = Opts::try_parse_from(["syntax-diff-editor", "--dir-diff", "left", "right"]).unwrap();
+
+    let DiffContext {
+      files: lazy_files,
+      write_root: _,
+    } = process_opts_lazy(&filesystem, &opts)?;
+    let DiffContext {
+      files,
+      write_root: _,
+    } = process_opts(&filesystem, &opts)?;
+    assert_eq!(
+      lazy_files
+        .iter()
+        .map(|file| (
+          file.old_path.as_deref(),
+          file.path.as_ref(),
+          file.similarity
+        ))
+        .collect::<Vec<_>>(),
+      [
+        (None, Path::new("foo"), None),
+        (Some(Path::new("old")), Path::new("new"), Some(75)),
+      ]
+    );
+
+    let mut section_provider =
+      DirDiffSectionProvider::new(&filesystem, opts.left.clone(), opts.right.clone());
+    let loaded_files = lazy_files
+      .iter()
+      .map(|file| section_provider.load_file(file))
+      .collect::<std::result::Result<Vec<_>, _>>()
+      .unwrap();
+    assert_eq!(loaded_files, files);
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_read_file_info_submodule() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!(
+      "syntax-diff-editor-test-submodule-{}",
+      std::process::id()
+    ));
+    let commit = "1111111111111111111111111111111111111111";
+    fs::create_dir_all(dir.join("sub/.git")).unwrap();
+    fs::write(dir.join("sub/.git/HEAD"), format!("{commit}\n")).unwrap();
+    // A regular file with the same contents as those written by `git difftool
+    // --dir-diff` for a submodule is still a regular file.
+    fs::write(dir.join("file"), format!("Subproject commit {commit}\n")).unwrap();
+
+    let filesystem = RealFilesystem {
+      fallback_encoding: None,
+    };
+    let submodule = filesystem.read_file_info(&dir.join("sub"));
+    let file = filesystem.read_file_info(&dir.join("file"));
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_debug_snapshot!(submodule?, @r#"
+    FileInfo {
+        file_mode: Unix(
+            57344,
+        ),
+        contents: Submodule {
+            commit: "1111111111111111111111111111111111111111",
+        },
+    }
+    "#);
+    assert_debug_snapshot!(file?, @r#"
+    FileInfo {
+        file_mode: Unix(
+            33188,
+        ),
+        contents: Text {
+            contents: "Subproject commit 1111111111111111111111111111111111111111\n",
+            hash: "bf9ad72f4157a3dd5c4bca4e37c9318a7f20a77c",
+            num_bytes: 59,
+            encoding: TextEncoding {
+                encoding: "UTF-8",
+                has_bom: false,
             },
-        }
-        "###);
+        },
+    }
+    "#);
+    Ok(())
+  }
 
+  #[cfg(unix)]
+  #[test]
+  fn test_apply_non_utf8_symlink_target() -> Result<()> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::symlink;
+
+    let dir = std::env::temp_dir().join(format!(
+      "syntax-diff-editor-test-non-utf8-symlink-{}",
+      std::process::id()
+    ));
+    let left = dir.join("left");
+    let right = dir.join("right");
+    fs::create_dir_all(&left).unwrap();
+    fs::create_dir_all(&right).unwrap();
+    let non_utf8_target = OsStr::from_bytes(b"target-\xff");
+    symlink("target", left.join("added")).unwrap();
+    symlink(non_utf8_target, right.join("added")).unwrap();
+    symlink(non_utf8_target, left.join("removed")).unwrap();
+    symlink("target", right.join("removed")).unwrap();
+
+    let mut filesystem = RealFilesystem {
+      fallback_encoding: None,
+    };
+    let opts = Opts::try_parse_from([
+      OsStr::new("syntax-diff-editor"),
+      left.as_os_str(),
+      right.as_os_str(),
+    ])
+    .unwrap();
+    let DiffContext {
+      mut files,
+      write_root,
+    } = process_opts(&filesystem, &opts)?;
+    // Keeping the new non-UTF-8 target leaves the link as it is, but
+    // restoring the old one would write the lossy copy of it.
+    files[0].set_checked(true);
+    let result = apply_changes(
+      &mut filesystem,
+      &write_root,
+      RecordState {
+        is_read_only: false,
+        commits: Default::default(),
+        files: files.clone(),
+      },
+    );
+    let removed_error = result.map_err(|err| err.to_string());
+    files[1].set_checked(true);
+    let result = apply_changes(
+      &mut filesystem,
+      &write_root,
+      RecordState {
+        is_read_only: false,
+        commits: Default::default(),
+        files,
+      },
+    );
+    let added_target = fs::read_link(right.join("added"));
+    let removed_target = fs::read_link(right.join("removed"));
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+      removed_error,
+      Err(format!(
+        "can't write {}: its symbolic link target isn't valid UTF-8",
+        right.join("removed").display()
+      ))
+    );
+    result?;
+    assert_eq!(added_target.unwrap(), non_utf8_target);
+    assert_eq!(removed_target.unwrap(), Path::new("target"));
     Ok(())
   }
 }
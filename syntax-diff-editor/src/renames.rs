@@ -0,0 +1,387 @@
+//! Detection of renamed and copied files in directory diffs.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use super::{FileContents, FileInfo, Filesystem, Result};
+
+/// The minimum similarity, as a percentage, for a file to be considered
+/// renamed or copied. This matches Git's default.
+pub const RENAME_THRESHOLD: u8 = 50;
+
+/// A file in the right-hand directory which was renamed or copied from a file
+/// in the left-hand directory.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Rename {
+  /// The path of the file in the left-hand directory.
+  pub old_path: PathBuf,
+
+  /// The path of the file in the right-hand directory.
+  pub new_path: PathBuf,
+
+  /// How similar the contents of the two files are, as a percentage.
+  pub similarity: u8,
+
+  /// Whether the file at `old_path` still exists in the right-hand directory.
+  pub is_copy: bool,
+}
+
+/// Find the files which only exist in `right` and are similar enough to a file
+/// in `left` to be considered renamed or copied from it.
+///
+/// Files which only exist in `left` are candidates for renames. If
+/// `find_copies` is set, files which exist in both directories are also
+/// candidates for copies, but like Git, only if they were modified. This reads
+/// every file in both directories, so it's off by default (as with Git's
+/// `-C`).
+pub fn detect_renames(
+  filesystem: &dyn Filesystem,
+  left: &Path,
+  right: &Path,
+  find_copies: bool,
+) -> Result<Vec<Rename>> {
+  let left_paths = filesystem.read_dir_diff_paths(left, left)?;
+  let right_paths = filesystem.read_dir_diff_paths(right, right)?;
+  let added_paths: Vec<&PathBuf> = right_paths.difference(&left_paths).collect();
+  let removed_paths: Vec<&PathBuf> = left_paths.difference(&right_paths).collect();
+  if added_paths.is_empty() || (removed_paths.is_empty() && !find_copies) {
+    return Ok(Vec::new());
+  }
+
+  let read_contents = |path: PathBuf| -> Result<FileContents> {
+    let FileInfo {
+      file_mode: _,
+      contents,
+    } = filesystem.read_file_info(&path)?;
+    Ok(contents)
+  };
+  let mut rename_sources = Vec::new();
+  for path in removed_paths {
+    rename_sources.push((path, read_contents(left.join(path))?));
+  }
+  let mut copy_sources = Vec::new();
+  if find_copies {
+    for path in left_paths.intersection(&right_paths) {
+      let contents = read_contents(left.join(path))?;
+      if !is_same_contents(&contents, &read_contents(right.join(path))?) {
+        copy_sources.push((path, contents));
+      }
+    }
+  }
+  let rename_sources = Sources::new(rename_sources);
+  let copy_sources = Sources::new(copy_sources);
+
+  let mut renames = Vec::new();
+  let mut renamed_paths = BTreeSet::new();
+  for new_path in added_paths {
+    let new_contents = read_contents(right.join(new_path))?;
+    let (old_path, similarity, is_copy) =
+      match rename_sources.find_most_similar(&renamed_paths, &new_contents) {
+        Some((old_path, similarity)) => {
+          renamed_paths.insert(old_path);
+          (old_path, similarity, false)
+        }
+        None => match copy_sources.find_most_similar(&BTreeSet::new(), &new_contents) {
+          Some((old_path, similarity)) => (old_path, similarity, true),
+          None => continue,
+        },
+      };
+    renames.push(Rename {
+      old_path: old_path.clone(),
+      new_path: new_path.clone(),
+      similarity,
+      is_copy,
+    });
+  }
+  Ok(renames)
+}
+
+/// The files which a new file may have been renamed or copied from.
+struct Sources<'a> {
+  sources: Vec<(&'a PathBuf, FileContents)>,
+
+  /// The indexes into `sources` of the files with each hash, so that identical
+  /// files are found without comparing the new file to every source.
+  indexes_by_hash: HashMap<String, Vec<usize>>,
+}
+
+impl<'a> Sources<'a> {
+  fn new(sources: Vec<(&'a PathBuf, FileContents)>) -> Self {
+    let mut indexes_by_hash: HashMap<String, Vec<usize>> = HashMap::new();
+    for (idx, (_, contents)) in sources.iter().enumerate() {
+      if let Some(hash) = non_empty_hash(contents) {
+        indexes_by_hash
+          .entry(hash.to_owned())
+          .or_default()
+          .push(idx);
+      }
+    }
+    Self {
+      sources,
+      indexes_by_hash,
+    }
+  }
+
+  /// Find the source most similar to `contents`, ignoring those in `excluded`
+  /// and those below [`RENAME_THRESHOLD`].
+  fn find_most_similar(
+    &self,
+    excluded: &BTreeSet<&PathBuf>,
+    contents: &FileContents,
+  ) -> Option<(&'a PathBuf, u8)> {
+    let Self {
+      sources,
+      indexes_by_hash,
+    } = self;
+    let is_candidate = |old_path: &PathBuf| !excluded.contains(old_path);
+
+    // An identical file is always the most similar.
+    let identical_path = non_empty_hash(contents)
+      .and_then(|hash| indexes_by_hash.get(hash))
+      .into_iter()
+      .flatten()
+      .map(|idx| &sources[*idx])
+      .find(|(old_path, old_contents)| {
+        is_candidate(old_path) && is_same_contents(old_contents, contents)
+      });
+    if let Some((old_path, _)) = identical_path {
+      return Some((old_path, 100));
+    }
+
+    sources
+      .iter()
+      .filter(|(old_path, old_contents)| {
+        is_candidate(old_path) && may_be_similar(old_contents, contents)
+      })
+      .map(|(old_path, old_contents)| (*old_path, similarity(old_contents, contents)))
+      .filter(|(_, similarity)| *similarity >= RENAME_THRESHOLD)
+      // Prefer the first of equally similar files.
+      .rev()
+      .max_by_key(|(_, similarity)| *similarity)
+  }
+}
+
+/// The hash of a non-empty text or binary file. Empty files are never
+/// considered similar, so they don't have one.
+fn non_empty_hash(contents: &FileContents) -> Option<&str> {
+  match contents {
+    FileContents::Text {
+      contents,
+      hash,
+      num_bytes: _,
+      encoding: _,
+    } => (!contents.is_empty()).then_some(hash.as_str()),
+    FileContents::Binary {
+      hash,
+      num_bytes,
+      contents: _,
+    } => (*num_bytes > 0).then_some(hash.as_str()),
+    FileContents::Absent | FileContents::Symlink { .. } | FileContents::Submodule { .. } => None,
+  }
+}
+
+/// Whether two files may be at least [`RENAME_THRESHOLD`] similar, checked
+/// without comparing their contents. The common lines of two text files can't
+/// be longer than the smaller file, so files of very different sizes aren't.
+fn may_be_similar(
+  old: &FileContents,
+  new: &FileContents,
+) -> bool {
+  match (old, new) {
+    (
+      FileContents::Text {
+        contents: old_contents,
+        ..
+      },
+      FileContents::Text {
+        contents: new_contents,
+        ..
+      },
+    ) => {
+      let min_len = old_contents.len().min(new_contents.len());
+      let max_len = old_contents.len().max(new_contents.len());
+      min_len * 100 >= max_len * usize::from(RENAME_THRESHOLD)
+    }
+    (
+      FileContents::Absent
+      | FileContents::Text { .. }
+      | FileContents::Binary { .. }
+      | FileContents::Symlink { .. }
+      | FileContents::Submodule { .. },
+      _,
+    ) => true,
+  }
+}
+
+fn is_same_contents(
+  left: &FileContents,
+  right: &FileContents,
+) -> bool {
+  match (left, right) {
+    (
+      FileContents::Text { contents: left, .. },
+      FileContents::Text {
+        contents: right, ..
+      },
+    ) => left == right,
+    (FileContents::Binary { hash: left, .. }, FileContents::Binary { hash: right, .. }) => {
+      left == right
+    }
+    (FileContents::Symlink { target: left }, FileContents::Symlink { target: right }) => {
+      left == right
+    }
+    (FileContents::Submodule { commit: left }, FileContents::Submodule { commit: right }) => {
+      left == right
+    }
+    (FileContents::Absent, FileContents::Absent) => true,
+    (
+      FileContents::Absent
+      | FileContents::Text { .. }
+      | FileContents::Binary { .. }
+      | FileContents::Symlink { .. }
+      | FileContents::Submodule { .. },
+      _,
+    ) => false,
+  }
+}
+
+/// Calculate how similar two files are, as a percentage. Like Git, this is
+/// the number of bytes in lines common to both files, compared to the size of
+/// the larger file. Binary files are only similar if they're identical, and
+/// empty files are never similar.
+fn similarity(
+  old: &FileContents,
+  new: &FileContents,
+) -> u8 {
+  match (old, new) {
+    (
+      FileContents::Text {
+        contents: old_contents,
+        hash: _,
+        num_bytes: _,
+        encoding: _,
+      },
+      FileContents::Text {
+        contents: new_contents,
+        hash: _,
+        num_bytes: _,
+        encoding: _,
+      },
+    ) => {
+      let max_len = old_contents.len().max(new_contents.len());
+      if max_len == 0 {
+        return 0;
+      }
+      let mut old_lines: HashMap<&str, usize> = HashMap::new();
+      for line in old_contents.split_inclusive('\n') {
+        *old_lines.entry(line).or_default() += 1;
+      }
+      let mut common_len = 0;
+      for line in new_contents.split_inclusive('\n') {
+        if let Some(count @ 1..) = old_lines.get_mut(line) {
+          *count -= 1;
+          common_len += line.len();
+        }
+      }
+      u8::try_from(common_len * 100 / max_len).unwrap_or(100)
+    }
+
+    (
+      FileContents::Binary {
+        hash: old_hash,
+        num_bytes: old_num_bytes,
+        contents: _,
+      },
+      FileContents::Binary {
+        hash: new_hash,
+        num_bytes: _,
+        contents: _,
+      },
+    ) => {
+      if old_hash == new_hash && *old_num_bytes > 0 {
+        100
+      } else {
+        0
+      }
+    }
+
+    // Like Git, only regular files are detected as renamed.
+    (FileContents::Absent, _)
+    | (_, FileContents::Absent)
+    | (FileContents::Symlink { .. } | FileContents::Submodule { .. }, _)
+    | (_, FileContents::Symlink { .. } | FileContents::Submodule { .. })
+    | (FileContents::Text { .. }, FileContents::Binary { .. })
+    | (FileContents::Binary { .. }, FileContents::Text { .. }) => 0,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use maplit::btreemap;
+
+  use crate::testing::{file_info, TestFilesystem};
+
+  use super::*;
+
+  #[test]
+  fn test_similarity() {
+    let text = |contents: &str| file_info(contents).contents;
+    assert_eq!(similarity(&text("foo\nbar\n"), &text("foo\nbar\n")), 100);
+    assert_eq!(similarity(&text("foo\nbar\n"), &text("foo\nbaz\n")), 50);
+    assert_eq!(similarity(&text("foo\n"), &text("bar\n")), 0);
+    assert_eq!(similarity(&text(""), &text("")), 0);
+    // Repeated lines only match once.
+    assert_eq!(similarity(&text("foo\n"), &text("foo\nfoo\n")), 50);
+
+    assert!(may_be_similar(&text("foo\n"), &text("foo\nbar\n")));
+    assert!(!may_be_similar(&text("foo\n"), &text("foo\nbar\nbaz\n")));
+  }
+
+  #[test]
+  fn test_detect_renames() -> Result<()> {
+    let filesystem = TestFilesystem::new(btreemap! {
+      PathBuf::from("left/moved") => file_info("1\n2\n3\n4\n"),
+      PathBuf::from("right/renamed") => file_info("1\n2\n3\nfour\n"),
+      PathBuf::from("left/deleted") => file_info("foo\n"),
+      PathBuf::from("right/added") => file_info("bar\n"),
+      PathBuf::from("left/source") => file_info("a\nb\nc\n"),
+      PathBuf::from("right/source") => file_info("a\nb\nc\nd\n"),
+      PathBuf::from("right/copied") => file_info("a\nb\nc\n"),
+    });
+    insta::assert_debug_snapshot!(
+      detect_renames(&filesystem, Path::new("left"), Path::new("right"), true)?,
+      @r#"
+    [
+        Rename {
+            old_path: "source",
+            new_path: "copied",
+            similarity: 100,
+            is_copy: true,
+        },
+        Rename {
+            old_path: "moved",
+            new_path: "renamed",
+            similarity: 54,
+            is_copy: false,
+        },
+    ]
+    "#
+    );
+
+    // Modified files are only read when finding copies.
+    insta::assert_debug_snapshot!(
+      detect_renames(&filesystem, Path::new("left"), Path::new("right"), false)?,
+      @r#"
+    [
+        Rename {
+            old_path: "moved",
+            new_path: "renamed",
+            similarity: 54,
+            is_copy: false,
+        },
+    ]
+    "#
+    );
+    Ok(())
+  }
+}
@@ -1,11 +1,16 @@
+mod algorithm;
+
 use std::borrow::Cow;
 use std::path::PathBuf;
 
 use scm_record::helpers::make_binary_description;
-use scm_record::{ChangeType, File, Section, SectionChangedLine};
+use scm_record::{BinaryContents, ChangeType, File, Section, SectionChangedLine};
 use tracing::warn;
 
-use super::{Error, FileContents, FileInfo, Filesystem};
+use super::renames::Rename;
+use super::{Error, FileContents, FileInfo, Filesystem, WhitespaceOptions};
+pub use algorithm::DiffAlgorithm;
+use algorithm::DiffOp;
 
 fn make_section_changed_lines(
   contents: &str,
@@ -21,12 +26,52 @@ fn make_section_changed_lines(
     .collect()
 }
 
+/// The bytes of both sides of a binary file, for showing a hex dump of the
+/// changes, if they were read.
+fn binary_contents(
+  left: &FileContents,
+  right: &FileContents,
+) -> Option<BinaryContents<'static>> {
+  let bytes = |contents: &FileContents| match contents {
+    FileContents::Absent | FileContents::Symlink { .. } | FileContents::Submodule { .. } => {
+      Some(Vec::new())
+    }
+    FileContents::Text {
+      contents,
+      hash: _,
+      num_bytes: _,
+      encoding: _,
+    } => Some(contents.as_bytes().to_vec()),
+    FileContents::Binary {
+      hash: _,
+      num_bytes: _,
+      contents,
+    } => contents.clone(),
+  };
+  Some(BinaryContents {
+    old: Cow::Owned(bytes(left)?),
+    new: Cow::Owned(bytes(right)?),
+  })
+}
+
+/// The symlink target and submodule commit of `contents`, if any.
+fn special_contents(contents: &FileContents) -> (Option<&str>, Option<&str>) {
+  match contents {
+    FileContents::Symlink { target } => (Some(target), None),
+    FileContents::Submodule { commit } => (None, Some(commit)),
+    FileContents::Absent | FileContents::Text { .. } | FileContents::Binary { .. } => (None, None),
+  }
+}
+
 pub fn create_file(
   filesystem: &dyn Filesystem,
   left_path: PathBuf,
   left_display_path: PathBuf,
   right_path: PathBuf,
   right_display_path: PathBuf,
+  rename: Option<&Rename>,
+  whitespace: WhitespaceOptions,
+  algorithm: DiffAlgorithm,
 ) -> Result<File<'static>, Error> {
   let FileInfo {
     file_mode: left_file_mode,
@@ -48,6 +93,19 @@ pub fn create_file(
     _ => (None, None),
   };
 
+  if let Some(Rename {
+    old_path: _,
+    new_path: _,
+    similarity: _,
+    is_copy,
+  }) = rename
+  {
+    sections.push(Section::Rename {
+      is_checked: false,
+      is_copy: *is_copy,
+    });
+  }
+
   if left_file_mode != right_file_mode {
     sections.push(Section::FileMode {
       is_checked: false,
@@ -55,34 +113,70 @@ pub fn create_file(
     });
   }
 
+  let (old_target, old_commit) = special_contents(&left_contents);
+  let (new_target, new_commit) = special_contents(&right_contents);
+  if old_target != new_target {
+    sections.push(Section::Symlink {
+      is_checked: false,
+      old_target: old_target.map(|target| Cow::Owned(target.to_owned())),
+      new_target: new_target.map(|target| Cow::Owned(target.to_owned())),
+    });
+  }
+  if old_commit != new_commit {
+    sections.push(Section::Submodule {
+      is_checked: false,
+      old_commit: old_commit.map(|commit| Cow::Owned(commit.to_owned())),
+      new_commit: new_commit.map(|commit| Cow::Owned(commit.to_owned())),
+    });
+  }
+
+  let is_binary = |contents: &FileContents| matches!(contents, FileContents::Binary { .. });
+  let binary_contents = if is_binary(&left_contents) || is_binary(&right_contents) {
+    binary_contents(&left_contents, &right_contents)
+  } else {
+    None
+  };
+
+  // Symlinks and submodules are handled above, and have no contents to diff.
   match (left_contents, right_contents) {
-    (FileContents::Absent, FileContents::Absent) => {}
     (
-      FileContents::Absent,
+      FileContents::Absent | FileContents::Symlink { .. } | FileContents::Submodule { .. },
+      FileContents::Absent | FileContents::Symlink { .. } | FileContents::Submodule { .. },
+    ) => {}
+    (
+      FileContents::Absent | FileContents::Symlink { .. } | FileContents::Submodule { .. },
       FileContents::Text {
         contents,
         hash: _,
         num_bytes: _,
+        encoding: _,
       },
     ) => sections.push(Section::Changed {
       lines: make_section_changed_lines(&contents, ChangeType::Added),
     }),
 
-    (FileContents::Absent, FileContents::Binary { hash, num_bytes }) => {
-      sections.push(Section::Binary {
-        is_checked: false,
-        old_description: None,
-        new_description: Some(Cow::Owned(make_binary_description(&hash, num_bytes))),
-      })
-    }
+    (
+      FileContents::Absent | FileContents::Symlink { .. } | FileContents::Submodule { .. },
+      FileContents::Binary {
+        hash,
+        num_bytes,
+        contents: _,
+      },
+    ) => sections.push(Section::Binary {
+      is_checked: false,
+      old_description: None,
+      new_description: Some(Cow::Owned(make_binary_description(&hash, num_bytes))),
+      contents: binary_contents,
+    }),
 
     (
       FileContents::Text {
         contents,
         hash: _,
         num_bytes: _,
+        encoding: _,
       },
-      FileContents::Absent,
+      FileContents::Absent | FileContents::Symlink { .. } | FileContents::Submodule { .. },
     ) => sections.push(Section::Changed {
       lines: make_section_changed_lines(&contents, ChangeType::Removed),
     }),
@@ -92,14 +186,21 @@ pub fn create_file(
         contents: old_contents,
         hash: _,
         num_bytes: _,
+        encoding: _,
       },
       FileContents::Text {
         contents: new_contents,
         hash: _,
         num_bytes: _,
+        encoding: _,
       },
     ) => {
-      sections.extend(create_diff(&old_contents, &new_contents));
+      sections.extend(create_diff(
+        &old_contents,
+        &new_contents,
+        whitespace,
+        algorithm,
+      ));
     }
 
     (
@@ -107,19 +208,23 @@ pub fn create_file(
         contents: _,
         hash: old_hash,
         num_bytes: old_num_bytes,
+        encoding: _,
       }
       | FileContents::Binary {
         hash: old_hash,
         num_bytes: old_num_bytes,
+        contents: _,
       },
       FileContents::Text {
         contents: _,
         hash: new_hash,
         num_bytes: new_num_bytes,
+        encoding: _,
       }
       | FileContents::Binary {
         hash: new_hash,
         num_bytes: new_num_bytes,
+        contents: _,
       },
     ) => sections.push(Section::Binary {
       is_checked: false,
@@ -131,15 +236,22 @@ pub fn create_file(
         &new_hash,
         new_num_bytes,
       ))),
+      contents: binary_contents,
     }),
 
-    (FileContents::Binary { hash, num_bytes }, FileContents::Absent) => {
-      sections.push(Section::Binary {
-        is_checked: false,
-        old_description: Some(Cow::Owned(make_binary_description(&hash, num_bytes))),
-        new_description: None,
-      })
-    }
+    (
+      FileContents::Binary {
+        hash,
+        num_bytes,
+        contents: _,
+      },
+      FileContents::Absent | FileContents::Symlink { .. } | FileContents::Submodule { .. },
+    ) => sections.push(Section::Binary {
+      is_checked: false,
+      old_description: Some(Cow::Owned(make_binary_description(&hash, num_bytes))),
+      new_description: None,
+      contents: binary_contents,
+    }),
   }
 
   #[cfg_attr(not(feature = "tree-sitter"), allow(unused_mut))]
@@ -152,6 +264,9 @@ pub fn create_file(
     path: Cow::Owned(right_display_path),
     file_mode: left_file_mode,
     sections,
+    commit_assignments: Default::default(),
+    similarity: rename.map(|rename| rename.similarity),
+    unloaded: None,
     #[cfg(feature = "tree-sitter")]
     containers: None,
   };
@@ -309,30 +424,39 @@ pub fn create_merge_file(
       (FileContents::Absent, _, _) => return Err(Error::MissingMergeFile { path: base_path }),
       (_, FileContents::Absent, _) => return Err(Error::MissingMergeFile { path: left_path }),
       (_, _, FileContents::Absent) => return Err(Error::MissingMergeFile { path: right_path }),
-      (FileContents::Binary { .. }, _, _) => {
-        return Err(Error::BinaryMergeFile { path: base_path })
-      }
-      (_, FileContents::Binary { .. }, _) => {
-        return Err(Error::BinaryMergeFile { path: left_path })
-      }
-      (_, _, FileContents::Binary { .. }) => {
-        return Err(Error::BinaryMergeFile { path: right_path })
-      }
+      (
+        FileContents::Binary { .. } | FileContents::Symlink { .. } | FileContents::Submodule { .. },
+        _,
+        _,
+      ) => return Err(Error::BinaryMergeFile { path: base_path }),
+      (
+        _,
+        FileContents::Binary { .. } | FileContents::Symlink { .. } | FileContents::Submodule { .. },
+        _,
+      ) => return Err(Error::BinaryMergeFile { path: left_path }),
+      (
+        _,
+        _,
+        FileContents::Binary { .. } | FileContents::Symlink { .. } | FileContents::Submodule { .. },
+      ) => return Err(Error::BinaryMergeFile { path: right_path }),
       (
         FileContents::Text {
           contents: base_contents,
           hash: _,
           num_bytes: _,
+          encoding: _,
         },
         FileContents::Text {
           contents: left_contents,
           hash: _,
           num_bytes: _,
+          encoding: _,
         },
         FileContents::Text {
           contents: right_contents,
           hash: _,
           num_bytes: _,
+          encoding: _,
         },
       ) => (base_contents, left_contents, right_contents),
     };
@@ -343,6 +467,9 @@ pub fn create_merge_file(
     path: Cow::Owned(output_path),
     file_mode: left_file_mode,
     sections,
+    commit_assignments: Default::default(),
+    similarity: None,
+    unloaded: None,
     #[cfg(feature = "tree-sitter")]
     containers: None,
   })
@@ -351,70 +478,129 @@ pub fn create_merge_file(
 fn create_diff(
   old_contents: &str,
   new_contents: &str,
+  whitespace: WhitespaceOptions,
+  algorithm: DiffAlgorithm,
 ) -> Vec<Section<'static>> {
-  let patch = {
-    // Set the context length to the maximum number of lines in either file,
-    // because we will handle abbreviating context ourselves.
-    let max_lines = old_contents
-      .lines()
-      .count()
-      .max(new_contents.lines().count());
-    let mut diff_options = diffy::DiffOptions::new();
-    diff_options.set_context_len(max_lines);
-    diff_options.create_patch(old_contents, new_contents)
-  };
+  if old_contents == new_contents {
+    return Vec::new();
+  }
+
+  let old_lines: Vec<&str> = old_contents.split_inclusive('\n').collect();
+  let new_lines: Vec<&str> = new_contents.split_inclusive('\n').collect();
+  // Lines are compared after normalizing their whitespace, but the original
+  // lines are shown.
+  let old_keys: Vec<Cow<str>> = old_lines
+    .iter()
+    .map(|line| whitespace.normalize_line(line))
+    .collect();
+  let new_keys: Vec<Cow<str>> = new_lines
+    .iter()
+    .map(|line| whitespace.normalize_line(line))
+    .collect();
+  let ops = algorithm.diff(
+    &old_keys.iter().map(AsRef::as_ref).collect::<Vec<&str>>(),
+    &new_keys.iter().map(AsRef::as_ref).collect::<Vec<&str>>(),
+  );
 
   let mut sections = Vec::new();
-  for hunk in patch.hunks() {
-    sections.extend(hunk.lines().iter().fold(Vec::new(), |mut acc, line| {
-      match line {
-        diffy::Line::Context(line) => match acc.last_mut() {
-          Some(Section::Unchanged { lines }) => {
-            lines.push(Cow::Owned((*line).to_owned()));
-          }
-          _ => {
-            acc.push(Section::Unchanged {
-              lines: vec![Cow::Owned((*line).to_owned())],
-            });
-          }
-        },
-        diffy::Line::Delete(line) => {
-          let line = SectionChangedLine {
-            is_checked: false,
-            change_type: ChangeType::Removed,
-            line: Cow::Owned((*line).to_owned()),
-          };
-          match acc.last_mut() {
-            Some(Section::Changed { lines }) => {
-              lines.push(line);
-            }
-            _ => {
-              acc.push(Section::Changed { lines: vec![line] });
-            }
-          }
+  let mut old_lines = old_lines.into_iter();
+  let mut new_lines = new_lines.into_iter();
+  for op in ops {
+    match op {
+      DiffOp::Equal => {
+        // Show the right-hand side's whitespace, which is what's written back.
+        old_lines.next();
+        if let Some(line) = new_lines.next() {
+          push_unchanged_line(&mut sections, line);
         }
-        diffy::Line::Insert(line) => {
-          let line = SectionChangedLine {
-            is_checked: false,
-            change_type: ChangeType::Added,
-            line: Cow::Owned((*line).to_owned()),
-          };
-          match acc.last_mut() {
-            Some(Section::Changed { lines }) => {
-              lines.push(line);
-            }
-            _ => {
-              acc.push(Section::Changed { lines: vec![line] });
-            }
-          }
+      }
+      DiffOp::Delete => {
+        if let Some(line) = old_lines.next() {
+          push_changed_line(&mut sections, ChangeType::Removed, line);
+        }
+      }
+      DiffOp::Insert => {
+        if let Some(line) = new_lines.next() {
+          push_changed_line(&mut sections, ChangeType::Added, line);
         }
       }
-      acc
-    }));
+    }
+  }
+  if whitespace.ignore_blank_lines {
+    sections = hide_blank_line_changes(sections);
   }
   sections
 }
 
+fn push_unchanged_line(
+  sections: &mut Vec<Section<'static>>,
+  line: &str,
+) {
+  let line = Cow::Owned(line.to_owned());
+  match sections.last_mut() {
+    Some(Section::Unchanged { lines }) => {
+      lines.push(line);
+    }
+    _ => {
+      sections.push(Section::Unchanged { lines: vec![line] });
+    }
+  }
+}
+
+fn push_changed_line(
+  sections: &mut Vec<Section<'static>>,
+  change_type: ChangeType,
+  line: &str,
+) {
+  let line = SectionChangedLine {
+    is_checked: false,
+    change_type,
+    line: Cow::Owned(line.to_owned()),
+  };
+  match sections.last_mut() {
+    Some(Section::Changed { lines }) => {
+      lines.push(line);
+    }
+    _ => {
+      sections.push(Section::Changed { lines: vec![line] });
+    }
+  }
+}
+
+/// Replace the changed sections which only add or remove blank lines with the
+/// right-hand side's lines, shown as unchanged.
+fn hide_blank_line_changes(sections: Vec<Section<'static>>) -> Vec<Section<'static>> {
+  let mut result = Vec::new();
+  for section in sections {
+    match section {
+      Section::Changed { lines }
+        if lines
+          .iter()
+          .all(|SectionChangedLine { line, .. }| line.trim().is_empty()) =>
+      {
+        for SectionChangedLine {
+          is_checked: _,
+          change_type,
+          line,
+        } in lines
+        {
+          match change_type {
+            ChangeType::Added => push_unchanged_line(&mut result, &line),
+            ChangeType::Removed => {}
+          }
+        }
+      }
+      Section::Unchanged { lines } => {
+        for line in lines {
+          push_unchanged_line(&mut result, &line);
+        }
+      }
+      section => result.push(section),
+    }
+  }
+  result
+}
+
 fn make_conflict_markers(
   base: &str,
   left: &str,
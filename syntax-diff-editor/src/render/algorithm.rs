@@ -0,0 +1,454 @@
+//! Line diff algorithms used to construct sections.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use clap::ValueEnum;
+
+/// The algorithm used to calculate which lines of a text file changed. All
+/// algorithms produce a minimal-or-close diff, but differ in which of several
+/// equally short diffs they choose.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum DiffAlgorithm {
+  /// The Myers algorithm, which finds a shortest diff, but may align the
+  /// changes on common lines like `}`.
+  #[default]
+  Myers,
+
+  /// The patience algorithm, which aligns the changes on lines which occur
+  /// exactly once in both files, and diffs the lines between them.
+  Patience,
+
+  /// The histogram algorithm, which extends the patience algorithm to align
+  /// the changes on the lines which occur least often in both files.
+  Histogram,
+}
+
+/// What happened to a line in a diff, as calculated by [`DiffAlgorithm::diff`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffOp {
+  /// The next old line is the same as the next new line.
+  Equal,
+
+  /// The next old line was removed.
+  Delete,
+
+  /// The next new line was added.
+  Insert,
+}
+
+/// Lines which occur more often than this in the old lines aren't used to
+/// align the changes in the histogram algorithm.
+const MAX_CHAIN_LEN: usize = 64;
+
+impl DiffAlgorithm {
+  /// Diff `old` and `new`, which are compared line by line. Returns one
+  /// [`DiffOp::Equal`] or [`DiffOp::Delete`] for each old line, and one
+  /// [`DiffOp::Equal`] or [`DiffOp::Insert`] for each new line.
+  pub fn diff(
+    self,
+    old: &[&str],
+    new: &[&str],
+  ) -> Vec<DiffOp> {
+    let mut ops = Vec::with_capacity(old.len().max(new.len()));
+    match self {
+      DiffAlgorithm::Myers => myers(old, new, &mut ops),
+      DiffAlgorithm::Patience => patience(old, new, &mut ops),
+      DiffAlgorithm::Histogram => histogram(old, new, &mut ops),
+    }
+    ops
+  }
+}
+
+fn myers<'a>(
+  old: &[&'a str],
+  new: &[&'a str],
+  ops: &mut Vec<DiffOp>,
+) {
+  // `diffy` diffs text rather than lines, so diff the lines' indexes into a
+  // table of distinct lines instead. This way, each line is diffed as a whole,
+  // even if it doesn't end with a newline.
+  let mut line_ids: HashMap<&str, usize> = HashMap::new();
+  let mut to_text = |lines: &[&'a str]| -> String {
+    let mut text = String::new();
+    for line in lines {
+      let next_id = line_ids.len();
+      let id = *line_ids.entry(line).or_insert(next_id);
+      writeln!(text, "{id}").unwrap();
+    }
+    text
+  };
+  let old_text = to_text(old);
+  let new_text = to_text(new);
+
+  // Set the context length to the maximum number of lines in either file, so
+  // that there's at most one hunk, which covers all lines.
+  let mut diff_options = diffy::DiffOptions::new();
+  diff_options.set_context_len(old.len().max(new.len()));
+  let patch = diff_options.create_patch(&old_text, &new_text);
+  match patch.hunks() {
+    [] => ops.extend(old.iter().map(|_| DiffOp::Equal)),
+    hunks => {
+      for hunk in hunks {
+        ops.extend(hunk.lines().iter().map(|line| match line {
+          diffy::Line::Context(_) => DiffOp::Equal,
+          diffy::Line::Delete(_) => DiffOp::Delete,
+          diffy::Line::Insert(_) => DiffOp::Insert,
+        }));
+      }
+    }
+  }
+}
+
+/// Push the ops for the lines at the start of `old` and `new` which are the
+/// same, and return the remaining lines.
+fn push_common_prefix<'a, 'b>(
+  old: &'a [&'b str],
+  new: &'a [&'b str],
+  ops: &mut Vec<DiffOp>,
+) -> (&'a [&'b str], &'a [&'b str]) {
+  let len = old
+    .iter()
+    .zip(new.iter())
+    .take_while(|(old_line, new_line)| old_line == new_line)
+    .count();
+  ops.extend((0..len).map(|_| DiffOp::Equal));
+  (&old[len..], &new[len..])
+}
+
+/// Push the ops for the lines of `old` and `new` when either is empty.
+/// Returns whether there were any lines left to diff.
+fn push_trivial_diff(
+  old: &[&str],
+  new: &[&str],
+  ops: &mut Vec<DiffOp>,
+) -> bool {
+  if old.is_empty() || new.is_empty() {
+    ops.extend(old.iter().map(|_| DiffOp::Delete));
+    ops.extend(new.iter().map(|_| DiffOp::Insert));
+    false
+  } else {
+    true
+  }
+}
+
+fn patience(
+  old: &[&str],
+  new: &[&str],
+  ops: &mut Vec<DiffOp>,
+) {
+  let (old, new) = push_common_prefix(old, new, ops);
+  if !push_trivial_diff(old, new, ops) {
+    return;
+  }
+
+  let anchors = unique_common_lines(old, new);
+  if anchors.is_empty() {
+    myers(old, new, ops);
+    return;
+  }
+  let mut old_start = 0;
+  let mut new_start = 0;
+  for (old_idx, new_idx) in anchors {
+    patience(&old[old_start..old_idx], &new[new_start..new_idx], ops);
+    ops.push(DiffOp::Equal);
+    old_start = old_idx + 1;
+    new_start = new_idx + 1;
+  }
+  patience(&old[old_start..], &new[new_start..], ops);
+}
+
+/// Find the longest sequence of lines which occur exactly once in both `old`
+/// and `new`, in the same order in both. Returns the indexes of the lines in
+/// `old` and `new`.
+fn unique_common_lines(
+  old: &[&str],
+  new: &[&str],
+) -> Vec<(usize, usize)> {
+  #[derive(Default)]
+  struct Occurrences {
+    num_old: usize,
+    num_new: usize,
+    old_idx: usize,
+    new_idx: usize,
+  }
+  let mut occurrences: HashMap<&str, Occurrences> = HashMap::new();
+  for (old_idx, line) in old.iter().enumerate() {
+    let occurrence = occurrences.entry(line).or_default();
+    occurrence.num_old += 1;
+    occurrence.old_idx = old_idx;
+  }
+  for (new_idx, line) in new.iter().enumerate() {
+    let occurrence = occurrences.entry(line).or_default();
+    occurrence.num_new += 1;
+    occurrence.new_idx = new_idx;
+  }
+  let mut candidates: Vec<(usize, usize)> = occurrences
+    .into_values()
+    .filter_map(
+      |Occurrences {
+         num_old,
+         num_new,
+         old_idx,
+         new_idx,
+       }| (num_old == 1 && num_new == 1).then_some((old_idx, new_idx)),
+    )
+    .collect();
+  candidates.sort_by_key(|(_old_idx, new_idx)| *new_idx);
+
+  // Patience sorting: find the longest increasing subsequence of old indexes.
+  let mut pile_tops: Vec<usize> = Vec::new();
+  let mut prev_candidates: Vec<Option<usize>> = vec![None; candidates.len()];
+  for (candidate_idx, (old_idx, _new_idx)) in candidates.iter().enumerate() {
+    let pile_idx = pile_tops.partition_point(|top| candidates[*top].0 < *old_idx);
+    if pile_idx > 0 {
+      prev_candidates[candidate_idx] = Some(pile_tops[pile_idx - 1]);
+    }
+    if pile_idx == pile_tops.len() {
+      pile_tops.push(candidate_idx);
+    } else {
+      pile_tops[pile_idx] = candidate_idx;
+    }
+  }
+  let mut result = Vec::new();
+  let mut candidate_idx = pile_tops.last().copied();
+  while let Some(idx) = candidate_idx {
+    result.push(candidates[idx]);
+    candidate_idx = prev_candidates[idx];
+  }
+  result.reverse();
+  result
+}
+
+fn histogram(
+  old: &[&str],
+  new: &[&str],
+  ops: &mut Vec<DiffOp>,
+) {
+  let mut old = old;
+  let mut new = new;
+  loop {
+    (old, new) = push_common_prefix(old, new, ops);
+    if !push_trivial_diff(old, new, ops) {
+      return;
+    }
+
+    let Some(Match {
+      old_start,
+      new_start,
+      len,
+      num_occurrences: _,
+    }) = find_rarest_match(old, new)
+    else {
+      myers(old, new, ops);
+      return;
+    };
+    histogram(&old[..old_start], &new[..new_start], ops);
+    ops.extend((0..len).map(|_| DiffOp::Equal));
+    old = &old[old_start + len..];
+    new = &new[new_start + len..];
+  }
+}
+
+/// A run of lines which are the same in the old and new lines.
+struct Match {
+  old_start: usize,
+  new_start: usize,
+  len: usize,
+
+  /// The fewest times any line of the match occurs in the old lines.
+  num_occurrences: usize,
+}
+
+/// Find the run of common lines whose lines occur least often in `old`,
+/// preferring longer runs if there are several.
+fn find_rarest_match(
+  old: &[&str],
+  new: &[&str],
+) -> Option<Match> {
+  let mut old_indexes: HashMap<&str, Vec<usize>> = HashMap::new();
+  for (old_idx, line) in old.iter().enumerate() {
+    old_indexes.entry(line).or_default().push(old_idx);
+  }
+  let num_occurrences = |line: &str| old_indexes.get(line).map_or(0, Vec::len);
+
+  let mut best_match: Option<Match> = None;
+  let mut new_idx = 0;
+  while new_idx < new.len() {
+    let mut next_new_idx = new_idx + 1;
+    let old_idxs = match old_indexes.get(new[new_idx]) {
+      Some(old_idxs) if old_idxs.len() <= MAX_CHAIN_LEN => old_idxs,
+      Some(_) | None => {
+        new_idx = next_new_idx;
+        continue;
+      }
+    };
+    for old_idx in old_idxs {
+      let mut old_start = *old_idx;
+      let mut new_start = new_idx;
+      while old_start > 0 && new_start > 0 && old[old_start - 1] == new[new_start - 1] {
+        old_start -= 1;
+        new_start -= 1;
+      }
+      let mut len = new_idx - new_start + 1;
+      while old_start + len < old.len()
+        && new_start + len < new.len()
+        && old[old_start + len] == new[new_start + len]
+      {
+        len += 1;
+      }
+      next_new_idx = next_new_idx.max(new_start + len);
+
+      let candidate = Match {
+        old_start,
+        new_start,
+        len,
+        num_occurrences: new[new_start..new_start + len]
+          .iter()
+          .map(|line| num_occurrences(line))
+          .min()
+          .unwrap_or_default(),
+      };
+      let is_better = match &best_match {
+        None => true,
+        Some(best_match) => {
+          (candidate.num_occurrences, std::cmp::Reverse(candidate.len))
+            < (
+              best_match.num_occurrences,
+              std::cmp::Reverse(best_match.len),
+            )
+        }
+      };
+      if is_better {
+        best_match = Some(candidate);
+      }
+    }
+    new_idx = next_new_idx;
+  }
+  best_match
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Render the diff of `old` and `new` with `algorithm` in unified diff
+  /// style.
+  fn render_diff(
+    algorithm: DiffAlgorithm,
+    old: &str,
+    new: &str,
+  ) -> String {
+    let old: Vec<&str> = old.split_inclusive('\n').collect();
+    let new: Vec<&str> = new.split_inclusive('\n').collect();
+    let mut old_lines = old.iter();
+    let mut new_lines = new.iter();
+    let mut result = String::new();
+    for op in algorithm.diff(&old, &new) {
+      let (prefix, line) = match op {
+        DiffOp::Equal => {
+          old_lines.next();
+          (' ', new_lines.next())
+        }
+        DiffOp::Delete => ('-', old_lines.next()),
+        DiffOp::Insert => ('+', new_lines.next()),
+      };
+      result.push(prefix);
+      result.push_str(line.unwrap());
+    }
+    assert_eq!(old_lines.next(), None);
+    assert_eq!(new_lines.next(), None);
+    result
+  }
+
+  /// Myers aligns the moved rules on their common lines, while patience and
+  /// histogram align them on their selectors.
+  const OLD: &str = "\
+.foo1 {
+    margin: 0;
+}
+
+.bar {
+    margin: 0;
+}
+";
+
+  const NEW: &str = "\
+.bar {
+    margin: 0;
+}
+
+.foo1 {
+    margin: 0;
+    color: green;
+}
+";
+
+  #[test]
+  fn test_myers() {
+    insta::assert_snapshot!(render_diff(DiffAlgorithm::Myers, OLD, NEW), @"
+    -.foo1 {
+    +.bar {
+         margin: 0;
+     }
+     
+    -.bar {
+    +.foo1 {
+         margin: 0;
+    +    color: green;
+     }
+    ");
+  }
+
+  #[test]
+  fn test_patience() {
+    insta::assert_snapshot!(render_diff(DiffAlgorithm::Patience, OLD, NEW), @"
+    +.bar {
+    +    margin: 0;
+    +}
+    +
+     .foo1 {
+         margin: 0;
+    +    color: green;
+     }
+    -
+    -.bar {
+    -    margin: 0;
+    -}
+    ");
+  }
+
+  #[test]
+  fn test_histogram() {
+    insta::assert_snapshot!(render_diff(DiffAlgorithm::Histogram, OLD, NEW), @"
+    -.foo1 {
+    -    margin: 0;
+    -}
+    -
+     .bar {
+         margin: 0;
+     }
+    +
+    +.foo1 {
+    +    margin: 0;
+    +    color: green;
+    +}
+    ");
+  }
+
+  #[test]
+  fn test_edge_cases() {
+    for algorithm in [
+      DiffAlgorithm::Myers,
+      DiffAlgorithm::Patience,
+      DiffAlgorithm::Histogram,
+    ] {
+      assert_eq!(render_diff(algorithm, "", ""), "");
+      assert_eq!(render_diff(algorithm, "a\n", ""), "-a\n");
+      assert_eq!(render_diff(algorithm, "", "a\n"), "+a\n");
+      assert_eq!(render_diff(algorithm, "a\nb", "a\nb\n"), " a\n-b+b\n");
+      // All lines are diffed, even if none of them are unique.
+      render_diff(algorithm, "x\ny\nx\ny\n", "y\nx\ny\nx\n");
+    }
+  }
+}
@@ -5,7 +5,7 @@ use std::path::{Path, PathBuf};
 
 use scm_record::{File, FileMode};
 
-use crate::{Error, FileContents, FileInfo, Filesystem, Result};
+use crate::{Error, FileContents, FileInfo, Filesystem, Result, TextEncoding};
 
 /// In-memory filesystem for testing purposes.
 #[derive(Debug)]
@@ -89,6 +89,50 @@ impl Filesystem for TestFilesystem {
     Ok(())
   }
 
+  fn write_encoded_file(
+    &mut self,
+    path: &Path,
+    contents: &str,
+    encoding: TextEncoding,
+  ) -> Result<()> {
+    self.assert_parent_dir_exists(path);
+    let bytes = encoding.encode(contents).ok_or_else(|| Error::EncodeFile {
+      path: path.to_owned(),
+      encoding,
+    })?;
+    self.files.insert(
+      path.to_owned(),
+      FileInfo {
+        file_mode: FileMode::Unix(0o100644),
+        contents: FileContents::Text {
+          contents: contents.to_owned(),
+          hash: "abc123".to_string(),
+          num_bytes: bytes.len().try_into().unwrap(),
+          encoding,
+        },
+      },
+    );
+    Ok(())
+  }
+
+  fn write_symlink(
+    &mut self,
+    path: &Path,
+    target: &str,
+  ) -> Result<()> {
+    self.assert_parent_dir_exists(path);
+    self.files.insert(
+      path.to_owned(),
+      FileInfo {
+        file_mode: FileMode::SYMLINK,
+        contents: FileContents::Symlink {
+          target: target.to_owned(),
+        },
+      },
+    );
+    Ok(())
+  }
+
   fn copy_file(
     &mut self,
     old_path: &Path,
@@ -128,6 +172,7 @@ pub fn file_info(contents: impl Into<String>) -> FileInfo {
       contents,
       hash: "abc123".to_string(),
       num_bytes,
+      encoding: TextEncoding::UTF8,
     },
   }
 }
@@ -0,0 +1,113 @@
+//! Ignoring whitespace when diffing text files.
+
+use std::borrow::Cow;
+
+use clap::Args;
+
+/// Which differences in whitespace to ignore when diffing text files. Lines
+/// which differ only in ignored whitespace are shown as unchanged, with the
+/// contents of the right-hand side, so that the right-hand side's whitespace
+/// is kept when the selected contents are written back.
+#[derive(Args, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WhitespaceOptions {
+  /// Ignore changes in the amount of whitespace, and whitespace at the end of
+  /// lines.
+  #[clap(long = "ignore-space-change")]
+  pub ignore_space_change: bool,
+
+  /// Ignore all whitespace when comparing lines.
+  #[clap(long = "ignore-all-space")]
+  pub ignore_all_space: bool,
+
+  /// Ignore changes whose lines are all blank.
+  #[clap(long = "ignore-blank-lines")]
+  pub ignore_blank_lines: bool,
+
+  /// Ignore carriage returns at the end of lines.
+  #[clap(long = "ignore-cr-at-eol")]
+  pub ignore_cr_at_eol: bool,
+}
+
+impl WhitespaceOptions {
+  /// Normalize `line`, including its trailing newline, if any, so that lines
+  /// which differ only in ignored whitespace are equal.
+  pub fn normalize_line<'a>(
+    &self,
+    line: &'a str,
+  ) -> Cow<'a, str> {
+    let Self {
+      ignore_space_change,
+      ignore_all_space,
+      ignore_blank_lines: _,
+      ignore_cr_at_eol,
+    } = *self;
+    // A missing newline at the end of the file is still a difference.
+    let (contents, newline) = match line.strip_suffix('\n') {
+      Some(contents) => (contents, "\n"),
+      None => (line, ""),
+    };
+    let contents: String = if ignore_all_space {
+      contents.chars().filter(|c| !c.is_whitespace()).collect()
+    } else if ignore_space_change {
+      let mut normalized = String::with_capacity(contents.len());
+      let mut is_in_whitespace = false;
+      for c in contents.trim_end().chars() {
+        if c.is_whitespace() {
+          if !is_in_whitespace {
+            normalized.push(' ');
+          }
+          is_in_whitespace = true;
+        } else {
+          normalized.push(c);
+          is_in_whitespace = false;
+        }
+      }
+      normalized
+    } else if ignore_cr_at_eol {
+      match contents.strip_suffix('\r') {
+        Some(contents) => contents.to_owned(),
+        None => return Cow::Borrowed(line),
+      }
+    } else {
+      return Cow::Borrowed(line);
+    };
+    Cow::Owned(contents + newline)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_normalize_line() {
+    let space_change = WhitespaceOptions {
+      ignore_space_change: true,
+      ..Default::default()
+    };
+    assert_eq!(
+      space_change.normalize_line("  foo \t bar  \r\n"),
+      " foo bar\n"
+    );
+    assert_eq!(space_change.normalize_line("\tfoo bar"), " foo bar");
+
+    let all_space = WhitespaceOptions {
+      ignore_all_space: true,
+      ..Default::default()
+    };
+    assert_eq!(all_space.normalize_line("  foo \t bar  \r\n"), "foobar\n");
+
+    let cr_at_eol = WhitespaceOptions {
+      ignore_cr_at_eol: true,
+      ..Default::default()
+    };
+    assert_eq!(cr_at_eol.normalize_line(" foo\r\n"), " foo\n");
+    assert_eq!(cr_at_eol.normalize_line(" foo\r"), " foo");
+    assert_eq!(cr_at_eol.normalize_line("foo\r\r\n"), "foo\r\n");
+
+    assert_eq!(
+      WhitespaceOptions::default().normalize_line(" foo\r\n"),
+      " foo\r\n"
+    );
+  }
+}
@@ -1,11 +1,14 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use insta::assert_debug_snapshot;
 use maplit::btreemap;
 
-use scm_record::{RecordState, Section};
+use scm_record::{FileMode, RecordState, Section};
 use syntax_diff_editor::testing::{file_info, select_all, TestFilesystem};
-use syntax_diff_editor::{apply_changes, process_opts, DiffContext, Opts, Result};
+use syntax_diff_editor::{
+  apply_changes, process_opts, DiffContext, FileContents, FileInfo, Filesystem, Opts, Result,
+  TextEncoding, WhitespaceOptions,
+};
 
 #[test]
 fn test_diff() -> Result<()> {
@@ -30,6 +33,15 @@ qux2
     &filesystem,
     &Opts {
       verbose: false,
+      save_selection: None,
+      load_selection: None,
+      select: Vec::new(),
+      deselect: Vec::new(),
+      no_renames: false,
+      find_copies: false,
+      encoding: None,
+      whitespace: Default::default(),
+      diff_algorithm: Default::default(),
       dir_diff: false,
       left: PathBuf::from("left"),
       right: PathBuf::from("right"),
@@ -85,6 +97,9 @@ qux2
                     ],
                 },
             ],
+            commit_assignments: [],
+            similarity: None,
+            unloaded: None,
             containers: None,
         },
     ]
@@ -100,35 +115,43 @@ qux2
       files,
     },
   )?;
-  insta::assert_debug_snapshot!(filesystem, @r###"
-    TestFilesystem {
-        files: {
-            "left": FileInfo {
-                file_mode: Unix(
-                    33188,
-                ),
-                contents: Text {
-                    contents: "foo\ncommon1\ncommon2\nbar\n",
-                    hash: "abc123",
-                    num_bytes: 24,
-                },
-            },
-            "right": FileInfo {
-                file_mode: Unix(
-                    33188,
-                ),
-                contents: Text {
-                    contents: "qux1\ncommon1\ncommon2\nqux2\n",
-                    hash: "abc123",
-                    num_bytes: 26,
-                },
-            },
-        },
-        dirs: {
-            "",
-        },
-    }
-    "###);
+  insta::assert_debug_snapshot!(filesystem, @r#"
+  TestFilesystem {
+      files: {
+          "left": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "foo\ncommon1\ncommon2\nbar\n",
+                  hash: "abc123",
+                  num_bytes: 24,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+          "right": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "qux1\ncommon1\ncommon2\nqux2\n",
+                  hash: "abc123",
+                  num_bytes: 26,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+      },
+      dirs: {
+          "",
+      },
+  }
+  "#);
 
   Ok(())
 }
@@ -153,6 +176,15 @@ qux2
     &filesystem,
     &Opts {
       verbose: false,
+      save_selection: None,
+      load_selection: None,
+      select: Vec::new(),
+      deselect: Vec::new(),
+      no_renames: false,
+      find_copies: false,
+      encoding: None,
+      whitespace: Default::default(),
+      diff_algorithm: Default::default(),
       dir_diff: false,
       left: PathBuf::from("left"),
       right: PathBuf::from("right"),
@@ -172,35 +204,43 @@ qux2
       files,
     },
   )?;
-  insta::assert_debug_snapshot!(filesystem, @r###"
-    TestFilesystem {
-        files: {
-            "left": FileInfo {
-                file_mode: Unix(
-                    33188,
-                ),
-                contents: Text {
-                    contents: "foo\ncommon1\ncommon2\nbar\n",
-                    hash: "abc123",
-                    num_bytes: 24,
-                },
-            },
-            "right": FileInfo {
-                file_mode: Unix(
-                    33188,
-                ),
-                contents: Text {
-                    contents: "foo\ncommon1\ncommon2\nbar\n",
-                    hash: "abc123",
-                    num_bytes: 24,
-                },
-            },
-        },
-        dirs: {
-            "",
-        },
-    }
-    "###);
+  insta::assert_debug_snapshot!(filesystem, @r#"
+  TestFilesystem {
+      files: {
+          "left": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "foo\ncommon1\ncommon2\nbar\n",
+                  hash: "abc123",
+                  num_bytes: 24,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+          "right": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "foo\ncommon1\ncommon2\nbar\n",
+                  hash: "abc123",
+                  num_bytes: 24,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+      },
+      dirs: {
+          "",
+      },
+  }
+  "#);
 
   Ok(())
 }
@@ -217,6 +257,15 @@ fn test_diff_absent_left() -> Result<()> {
     &filesystem,
     &Opts {
       verbose: false,
+      save_selection: None,
+      load_selection: None,
+      select: Vec::new(),
+      deselect: Vec::new(),
+      no_renames: false,
+      find_copies: false,
+      encoding: None,
+      whitespace: Default::default(),
+      diff_algorithm: Default::default(),
       dir_diff: false,
       left: PathBuf::from("left"),
       right: PathBuf::from("right"),
@@ -251,6 +300,9 @@ fn test_diff_absent_left() -> Result<()> {
                     ],
                 },
             ],
+            commit_assignments: [],
+            similarity: None,
+            unloaded: None,
             containers: None,
         },
     ]
@@ -266,25 +318,29 @@ fn test_diff_absent_left() -> Result<()> {
       files,
     },
   )?;
-  insta::assert_debug_snapshot!(filesystem, @r###"
-    TestFilesystem {
-        files: {
-            "right": FileInfo {
-                file_mode: Unix(
-                    33188,
-                ),
-                contents: Text {
-                    contents: "right\n",
-                    hash: "abc123",
-                    num_bytes: 6,
-                },
-            },
-        },
-        dirs: {
-            "",
-        },
-    }
-    "###);
+  insta::assert_debug_snapshot!(filesystem, @r#"
+  TestFilesystem {
+      files: {
+          "right": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "right\n",
+                  hash: "abc123",
+                  num_bytes: 6,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+      },
+      dirs: {
+          "",
+      },
+  }
+  "#);
 
   Ok(())
 }
@@ -301,6 +357,15 @@ fn test_diff_absent_right() -> Result<()> {
     &filesystem,
     &Opts {
       verbose: false,
+      save_selection: None,
+      load_selection: None,
+      select: Vec::new(),
+      deselect: Vec::new(),
+      no_renames: false,
+      find_copies: false,
+      encoding: None,
+      whitespace: Default::default(),
+      diff_algorithm: Default::default(),
       dir_diff: false,
       left: PathBuf::from("left"),
       right: PathBuf::from("right"),
@@ -335,6 +400,9 @@ fn test_diff_absent_right() -> Result<()> {
                     ],
                 },
             ],
+            commit_assignments: [],
+            similarity: None,
+            unloaded: None,
             containers: None,
         },
     ]
@@ -350,25 +418,29 @@ fn test_diff_absent_right() -> Result<()> {
       files,
     },
   )?;
-  insta::assert_debug_snapshot!(filesystem, @r###"
-    TestFilesystem {
-        files: {
-            "left": FileInfo {
-                file_mode: Unix(
-                    33188,
-                ),
-                contents: Text {
-                    contents: "left\n",
-                    hash: "abc123",
-                    num_bytes: 5,
-                },
-            },
-        },
-        dirs: {
-            "",
-        },
-    }
-    "###);
+  insta::assert_debug_snapshot!(filesystem, @r#"
+  TestFilesystem {
+      files: {
+          "left": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "left\n",
+                  hash: "abc123",
+                  num_bytes: 5,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+      },
+      dirs: {
+          "",
+      },
+  }
+  "#);
 
   Ok(())
 }
@@ -383,6 +455,15 @@ fn test_reject_diff_non_files() -> Result<()> {
     &filesystem,
     &Opts {
       verbose: false,
+      save_selection: None,
+      load_selection: None,
+      select: Vec::new(),
+      deselect: Vec::new(),
+      no_renames: false,
+      find_copies: false,
+      encoding: None,
+      whitespace: Default::default(),
+      diff_algorithm: Default::default(),
       dir_diff: false,
       left: PathBuf::from("left"),
       right: PathBuf::from("right"),
@@ -418,6 +499,15 @@ fn test_diff_files_in_subdirectories() -> Result<()> {
     &filesystem,
     &Opts {
       verbose: false,
+      save_selection: None,
+      load_selection: None,
+      select: Vec::new(),
+      deselect: Vec::new(),
+      no_renames: false,
+      find_copies: false,
+      encoding: None,
+      whitespace: Default::default(),
+      diff_algorithm: Default::default(),
       dir_diff: false,
       left: PathBuf::from("left/foo"),
       right: PathBuf::from("right/foo"),
@@ -437,37 +527,45 @@ fn test_diff_files_in_subdirectories() -> Result<()> {
       files,
     },
   )?;
-  assert_debug_snapshot!(filesystem, @r###"
-    TestFilesystem {
-        files: {
-            "left/foo": FileInfo {
-                file_mode: Unix(
-                    33188,
-                ),
-                contents: Text {
-                    contents: "left contents\n",
-                    hash: "abc123",
-                    num_bytes: 14,
-                },
-            },
-            "right/foo": FileInfo {
-                file_mode: Unix(
-                    33188,
-                ),
-                contents: Text {
-                    contents: "left contents\n",
-                    hash: "abc123",
-                    num_bytes: 14,
-                },
-            },
-        },
-        dirs: {
-            "",
-            "left",
-            "right",
-        },
-    }
-    "###);
+  assert_debug_snapshot!(filesystem, @r#"
+  TestFilesystem {
+      files: {
+          "left/foo": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "left contents\n",
+                  hash: "abc123",
+                  num_bytes: 14,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+          "right/foo": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "left contents\n",
+                  hash: "abc123",
+                  num_bytes: 14,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+      },
+      dirs: {
+          "",
+          "left",
+          "right",
+      },
+  }
+  "#);
 
   Ok(())
 }
@@ -483,6 +581,15 @@ fn test_dir_diff_no_changes() -> Result<()> {
     &filesystem,
     &Opts {
       verbose: false,
+      save_selection: None,
+      load_selection: None,
+      select: Vec::new(),
+      deselect: Vec::new(),
+      no_renames: false,
+      find_copies: false,
+      encoding: None,
+      whitespace: Default::default(),
+      diff_algorithm: Default::default(),
       dir_diff: false,
       left: PathBuf::from("left/foo"),
       right: PathBuf::from("right/foo"),
@@ -502,37 +609,45 @@ fn test_dir_diff_no_changes() -> Result<()> {
       files,
     },
   )?;
-  assert_debug_snapshot!(filesystem, @r###"
-    TestFilesystem {
-        files: {
-            "left/foo": FileInfo {
-                file_mode: Unix(
-                    33188,
-                ),
-                contents: Text {
-                    contents: "left contents\n",
-                    hash: "abc123",
-                    num_bytes: 14,
-                },
-            },
-            "right/foo": FileInfo {
-                file_mode: Unix(
-                    33188,
-                ),
-                contents: Text {
-                    contents: "left contents\n",
-                    hash: "abc123",
-                    num_bytes: 14,
-                },
-            },
-        },
-        dirs: {
-            "",
-            "left",
-            "right",
-        },
-    }
-    "###);
+  assert_debug_snapshot!(filesystem, @r#"
+  TestFilesystem {
+      files: {
+          "left/foo": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "left contents\n",
+                  hash: "abc123",
+                  num_bytes: 14,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+          "right/foo": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "left contents\n",
+                  hash: "abc123",
+                  num_bytes: 14,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+      },
+      dirs: {
+          "",
+          "left",
+          "right",
+      },
+  }
+  "#);
 
   Ok(())
 }
@@ -570,6 +685,15 @@ Hello world 4
     &filesystem,
     &Opts {
       verbose: false,
+      save_selection: None,
+      load_selection: None,
+      select: Vec::new(),
+      deselect: Vec::new(),
+      no_renames: false,
+      find_copies: false,
+      encoding: None,
+      whitespace: Default::default(),
+      diff_algorithm: Default::default(),
       dir_diff: false,
       left: "left".into(),
       right: "right".into(),
@@ -621,6 +745,9 @@ Hello world 4
                     ],
                 },
             ],
+            commit_assignments: [],
+            similarity: None,
+            unloaded: None,
             containers: None,
         },
     ]
@@ -637,55 +764,71 @@ Hello world 4
     },
   )?;
 
-  assert_debug_snapshot!(filesystem, @r###"
-    TestFilesystem {
-        files: {
-            "base": FileInfo {
-                file_mode: Unix(
-                    33188,
-                ),
-                contents: Text {
-                    contents: "Hello world 1\nHello world 2\nHello world 3\nHello world 4\n",
-                    hash: "abc123",
-                    num_bytes: 56,
-                },
-            },
-            "left": FileInfo {
-                file_mode: Unix(
-                    33188,
-                ),
-                contents: Text {
-                    contents: "Hello world 1\nHello world 2\nHello world L\nHello world 4\n",
-                    hash: "abc123",
-                    num_bytes: 56,
-                },
-            },
-            "output": FileInfo {
-                file_mode: Unix(
-                    33188,
-                ),
-                contents: Text {
-                    contents: "Hello world 1\nHello world 2\nHello world L\nHello world R\nHello world 4\n",
-                    hash: "abc123",
-                    num_bytes: 70,
-                },
-            },
-            "right": FileInfo {
-                file_mode: Unix(
-                    33188,
-                ),
-                contents: Text {
-                    contents: "Hello world 1\nHello world 2\nHello world R\nHello world 4\n",
-                    hash: "abc123",
-                    num_bytes: 56,
-                },
-            },
-        },
-        dirs: {
-            "",
-        },
-    }
-    "###);
+  assert_debug_snapshot!(filesystem, @r#"
+  TestFilesystem {
+      files: {
+          "base": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "Hello world 1\nHello world 2\nHello world 3\nHello world 4\n",
+                  hash: "abc123",
+                  num_bytes: 56,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+          "left": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "Hello world 1\nHello world 2\nHello world L\nHello world 4\n",
+                  hash: "abc123",
+                  num_bytes: 56,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+          "output": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "Hello world 1\nHello world 2\nHello world L\nHello world R\nHello world 4\n",
+                  hash: "abc123",
+                  num_bytes: 70,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+          "right": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "Hello world 1\nHello world 2\nHello world R\nHello world 4\n",
+                  hash: "abc123",
+                  num_bytes: 56,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+      },
+      dirs: {
+          "",
+      },
+  }
+  "#);
 
   Ok(())
 }
@@ -707,6 +850,15 @@ Hello world 2
     &filesystem,
     &Opts {
       verbose: false,
+      save_selection: None,
+      load_selection: None,
+      select: Vec::new(),
+      deselect: Vec::new(),
+      no_renames: false,
+      find_copies: false,
+      encoding: None,
+      whitespace: Default::default(),
+      diff_algorithm: Default::default(),
       dir_diff: false,
       left: "left".into(),
       right: "right".into(),
@@ -746,6 +898,9 @@ Hello world 2
                     ],
                 },
             ],
+            commit_assignments: [],
+            similarity: None,
+            unloaded: None,
             containers: None,
         },
     ]
@@ -781,25 +936,29 @@ Hello world 2
       files: files.clone(),
     },
   )?;
-  insta::assert_debug_snapshot!(filesystem, @r###"
-    TestFilesystem {
-        files: {
-            "right": FileInfo {
-                file_mode: Unix(
-                    33188,
-                ),
-                contents: Text {
-                    contents: "Hello world 1\nHello world 2\n",
-                    hash: "abc123",
-                    num_bytes: 28,
-                },
-            },
-        },
-        dirs: {
-            "",
-        },
-    }
-    "###);
+  insta::assert_debug_snapshot!(filesystem, @r#"
+  TestFilesystem {
+      files: {
+          "right": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "Hello world 1\nHello world 2\n",
+                  hash: "abc123",
+                  num_bytes: 28,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+      },
+      dirs: {
+          "",
+      },
+  }
+  "#);
 
   // Select only some changes from new file.
   match files[0].sections.get_mut(1).unwrap() {
@@ -815,25 +974,616 @@ Hello world 2
       files: files.clone(),
     },
   )?;
-  insta::assert_debug_snapshot!(filesystem, @r###"
-    TestFilesystem {
-        files: {
-            "right": FileInfo {
-                file_mode: Unix(
-                    33188,
-                ),
-                contents: Text {
-                    contents: "Hello world 2\n",
-                    hash: "abc123",
-                    num_bytes: 14,
-                },
-            },
-        },
-        dirs: {
-            "",
-        },
+  insta::assert_debug_snapshot!(filesystem, @r#"
+  TestFilesystem {
+      files: {
+          "right": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "Hello world 2\n",
+                  hash: "abc123",
+                  num_bytes: 14,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+      },
+      dirs: {
+          "",
+      },
+  }
+  "#);
+
+  Ok(())
+}
+
+#[test]
+fn test_dir_diff_rename() -> Result<()> {
+  let mut filesystem = TestFilesystem::new(btreemap! {
+      PathBuf::from("left/old") => file_info("1\n2\n3\n4\n"),
+      PathBuf::from("right/new") => file_info("1\n2\n3\nfour\n"),
+  });
+  let DiffContext {
+    mut files,
+    write_root,
+  } = process_opts(
+    &filesystem,
+    &Opts {
+      verbose: false,
+      save_selection: None,
+      load_selection: None,
+      select: Vec::new(),
+      deselect: Vec::new(),
+      no_renames: false,
+      find_copies: false,
+      encoding: None,
+      whitespace: Default::default(),
+      diff_algorithm: Default::default(),
+      dir_diff: true,
+      left: PathBuf::from("left"),
+      right: PathBuf::from("right"),
+      base: None,
+      output: None,
+      read_only: false,
+      dry_run: false,
+    },
+  )?;
+  assert_eq!(files.len(), 1);
+  insta::assert_debug_snapshot!(
+    (&files[0].old_path, &files[0].path, files[0].similarity, &files[0].sections[0]),
+    @r#"
+  (
+      Some(
+          "old",
+      ),
+      "new",
+      Some(
+          54,
+      ),
+      Rename {
+          is_checked: false,
+          is_copy: false,
+      },
+  )
+  "#
+  );
+
+  // Accept the edit, but not the rename.
+  for section in &mut files[0].sections {
+    if let Section::Changed { .. } = section {
+      section.set_checked(true);
     }
-    "###);
+  }
+  apply_changes(
+    &mut filesystem,
+    &write_root,
+    RecordState {
+      is_read_only: false,
+      commits: Default::default(),
+      files,
+    },
+  )?;
+  insta::assert_debug_snapshot!(filesystem, @r#"
+  TestFilesystem {
+      files: {
+          "left/old": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "1\n2\n3\n4\n",
+                  hash: "abc123",
+                  num_bytes: 8,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+          "right/old": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "1\n2\n3\nfour\n",
+                  hash: "abc123",
+                  num_bytes: 11,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+      },
+      dirs: {
+          "",
+          "left",
+          "right",
+      },
+  }
+  "#);
+
+  Ok(())
+}
+
+#[test]
+fn test_dir_diff_symlink_and_submodule() -> Result<()> {
+  let symlink = |target: &str| FileInfo {
+    file_mode: FileMode::SYMLINK,
+    contents: FileContents::Symlink {
+      target: target.to_owned(),
+    },
+  };
+  let submodule = |commit: &str| FileInfo {
+    file_mode: FileMode::GITLINK,
+    contents: FileContents::Submodule {
+      commit: commit.repeat(40),
+    },
+  };
+  let mut filesystem = TestFilesystem::new(btreemap! {
+      PathBuf::from("left/link") => symlink("old-target"),
+      PathBuf::from("right/link") => symlink("new-target"),
+      PathBuf::from("left/file") => file_info("foo\n"),
+      PathBuf::from("right/file") => symlink("foo"),
+      PathBuf::from("left/sub") => submodule("1"),
+      PathBuf::from("right/sub") => submodule("2"),
+  });
+  let DiffContext {
+    mut files,
+    write_root,
+  } = process_opts(
+    &filesystem,
+    &Opts {
+      verbose: false,
+      save_selection: None,
+      load_selection: None,
+      select: Vec::new(),
+      deselect: Vec::new(),
+      no_renames: false,
+      find_copies: false,
+      encoding: None,
+      whitespace: Default::default(),
+      diff_algorithm: Default::default(),
+      dir_diff: true,
+      left: PathBuf::from("left"),
+      right: PathBuf::from("right"),
+      base: None,
+      output: None,
+      read_only: false,
+      dry_run: false,
+    },
+  )?;
+  insta::assert_debug_snapshot!(
+    files.iter().map(|file| (&file.path, &file.sections)).collect::<Vec<_>>(),
+    @r#"
+  [
+      (
+          "file",
+          [
+              FileMode {
+                  is_checked: false,
+                  mode: Unix(
+                      40960,
+                  ),
+              },
+              Symlink {
+                  is_checked: false,
+                  old_target: None,
+                  new_target: Some(
+                      "foo",
+                  ),
+              },
+              Changed {
+                  lines: [
+                      SectionChangedLine {
+                          is_checked: false,
+                          change_type: Removed,
+                          line: "foo\n",
+                      },
+                  ],
+              },
+          ],
+      ),
+      (
+          "link",
+          [
+              Symlink {
+                  is_checked: false,
+                  old_target: Some(
+                      "old-target",
+                  ),
+                  new_target: Some(
+                      "new-target",
+                  ),
+              },
+          ],
+      ),
+      (
+          "sub",
+          [
+              Submodule {
+                  is_checked: false,
+                  old_commit: Some(
+                      "1111111111111111111111111111111111111111",
+                  ),
+                  new_commit: Some(
+                      "2222222222222222222222222222222222222222",
+                  ),
+              },
+          ],
+      ),
+  ]
+  "#
+  );
+
+  // Accept the new symlink target, but not the other changes.
+  files[1].set_checked(true);
+  apply_changes(
+    &mut filesystem,
+    &write_root,
+    RecordState {
+      is_read_only: false,
+      commits: Default::default(),
+      files,
+    },
+  )?;
+  insta::assert_debug_snapshot!(filesystem, @r#"
+  TestFilesystem {
+      files: {
+          "left/file": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "foo\n",
+                  hash: "abc123",
+                  num_bytes: 4,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+          "left/link": FileInfo {
+              file_mode: Unix(
+                  40960,
+              ),
+              contents: Symlink {
+                  target: "old-target",
+              },
+          },
+          "left/sub": FileInfo {
+              file_mode: Unix(
+                  57344,
+              ),
+              contents: Submodule {
+                  commit: "1111111111111111111111111111111111111111",
+              },
+          },
+          "right/file": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "foo\n",
+                  hash: "abc123",
+                  num_bytes: 4,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+          "right/link": FileInfo {
+              file_mode: Unix(
+                  40960,
+              ),
+              contents: Symlink {
+                  target: "new-target",
+              },
+          },
+          "right/sub": FileInfo {
+              file_mode: Unix(
+                  33188,
+              ),
+              contents: Text {
+                  contents: "Subproject commit 1111111111111111111111111111111111111111\n",
+                  hash: "abc123",
+                  num_bytes: 59,
+                  encoding: TextEncoding {
+                      encoding: "UTF-8",
+                      has_bom: false,
+                  },
+              },
+          },
+      },
+      dirs: {
+          "",
+          "left",
+          "right",
+      },
+  }
+  "#);
+
+  Ok(())
+}
+
+#[test]
+fn test_diff_binary_contents() -> Result<()> {
+  let binary = |contents: &[u8]| FileInfo {
+    file_mode: FileMode::FILE_DEFAULT,
+    contents: FileContents::Binary {
+      hash: "abc123".to_owned(),
+      num_bytes: contents.len().try_into().unwrap(),
+      contents: Some(contents.to_vec()),
+    },
+  };
+  let filesystem = TestFilesystem::new(btreemap! {
+      PathBuf::from("left") => binary(b"\x00old"),
+      PathBuf::from("right") => file_info("new\n"),
+  });
+  let DiffContext {
+    files,
+    write_root: _,
+  } = process_opts(
+    &filesystem,
+    &Opts {
+      verbose: false,
+      save_selection: None,
+      load_selection: None,
+      select: Vec::new(),
+      deselect: Vec::new(),
+      no_renames: false,
+      find_copies: false,
+      encoding: None,
+      whitespace: Default::default(),
+      diff_algorithm: Default::default(),
+      dir_diff: false,
+      left: PathBuf::from("left"),
+      right: PathBuf::from("right"),
+      base: None,
+      output: None,
+      read_only: false,
+      dry_run: false,
+    },
+  )?;
+  // The contents are provided so that a hex dump can be shown.
+  insta::assert_debug_snapshot!(files[0].sections, @r#"
+  [
+      Binary {
+          is_checked: false,
+          old_description: Some(
+              "abc123 (4 bytes)",
+          ),
+          new_description: Some(
+              "abc123 (4 bytes)",
+          ),
+          contents: Some(
+              BinaryContents {
+                  old: [
+                      0,
+                      111,
+                      108,
+                      100,
+                  ],
+                  new: [
+                      110,
+                      101,
+                      119,
+                      10,
+                  ],
+              },
+          ),
+      },
+  ]
+  "#);
+
+  Ok(())
+}
+
+#[test]
+fn test_diff_encoded_text() -> Result<()> {
+  let utf16 = TextEncoding {
+    encoding: encoding_rs::UTF_16LE,
+    has_bom: true,
+  };
+  let text = |contents: &str| FileInfo {
+    file_mode: FileMode::FILE_DEFAULT,
+    contents: FileContents::Text {
+      contents: contents.to_owned(),
+      hash: "abc123".to_owned(),
+      num_bytes: utf16.encode(contents).unwrap().len().try_into().unwrap(),
+      encoding: utf16,
+    },
+  };
+  let mut filesystem = TestFilesystem::new(btreemap! {
+      PathBuf::from("left") => text("café\nold\n"),
+      PathBuf::from("right") => text("café\nnew\n"),
+  });
+  let DiffContext { files, write_root } = process_opts(
+    &filesystem,
+    &Opts {
+      verbose: false,
+      save_selection: None,
+      load_selection: None,
+      select: Vec::new(),
+      deselect: Vec::new(),
+      no_renames: false,
+      find_copies: false,
+      encoding: None,
+      whitespace: Default::default(),
+      diff_algorithm: Default::default(),
+      dir_diff: false,
+      left: PathBuf::from("left"),
+      right: PathBuf::from("right"),
+      base: None,
+      output: None,
+      read_only: false,
+      dry_run: false,
+    },
+  )?;
+  assert_eq!(files.len(), 1);
+
+  // Nothing is selected, so the right side is written back with the left
+  // side's contents, in the right side's original encoding.
+  apply_changes(
+    &mut filesystem,
+    &write_root,
+    RecordState {
+      is_read_only: false,
+      commits: Default::default(),
+      files,
+    },
+  )?;
+  insta::assert_debug_snapshot!(filesystem.read_file_info(Path::new("right"))?, @r#"
+  FileInfo {
+      file_mode: Unix(
+          33188,
+      ),
+      contents: Text {
+          contents: "café\nold\n",
+          hash: "abc123",
+          num_bytes: 20,
+          encoding: TextEncoding {
+              encoding: "UTF-16LE",
+              has_bom: true,
+          },
+      },
+  }
+  "#);
+
+  Ok(())
+}
+
+#[test]
+fn test_diff_ignore_whitespace() -> Result<()> {
+  let mut filesystem = TestFilesystem::new(btreemap! {
+      PathBuf::from("left") => file_info("if x {\n  foo();\n}\n\nbar();\n"),
+      PathBuf::from("right") => file_info("if x {\r\n    foo();\r\n    qux();\r\n}\r\nbar();\r\n"),
+  });
+  let DiffContext { files, write_root } = process_opts(
+    &filesystem,
+    &Opts {
+      verbose: false,
+      save_selection: None,
+      load_selection: None,
+      select: Vec::new(),
+      deselect: Vec::new(),
+      no_renames: false,
+      find_copies: false,
+      encoding: None,
+      whitespace: WhitespaceOptions {
+        ignore_space_change: true,
+        ignore_blank_lines: true,
+        ..Default::default()
+      },
+      diff_algorithm: Default::default(),
+      dir_diff: false,
+      left: PathBuf::from("left"),
+      right: PathBuf::from("right"),
+      base: None,
+      output: None,
+      read_only: false,
+      dry_run: false,
+    },
+  )?;
+  insta::assert_debug_snapshot!(files[0].sections, @r#"
+  [
+      Unchanged {
+          lines: [
+              "if x {\r\n",
+              "    foo();\r\n",
+          ],
+      },
+      Changed {
+          lines: [
+              SectionChangedLine {
+                  is_checked: false,
+                  change_type: Added,
+                  line: "    qux();\r\n",
+              },
+          ],
+      },
+      Unchanged {
+          lines: [
+              "}\r\n",
+              "bar();\r\n",
+          ],
+      },
+  ]
+  "#);
 
+  // The unselected line is left out, but the other lines keep the right-hand
+  // side's whitespace.
+  apply_changes(
+    &mut filesystem,
+    &write_root,
+    RecordState {
+      is_read_only: false,
+      commits: Default::default(),
+      files,
+    },
+  )?;
+  insta::assert_debug_snapshot!(filesystem.read_file_info(Path::new("right"))?.contents, @r#"
+  Text {
+      contents: "if x {\r\n    foo();\r\n}\r\nbar();\r\n",
+      hash: "abc123",
+      num_bytes: 31,
+      encoding: TextEncoding {
+          encoding: "UTF-8",
+          has_bom: false,
+      },
+  }
+  "#);
+
+  Ok(())
+}
+
+#[test]
+fn test_apply_changes_with_omitted_lines() -> Result<()> {
+  let mut filesystem = TestFilesystem::new(btreemap! {
+      PathBuf::from("foo") => file_info("old\n"),
+  });
+  let mut files = scm_record::patch::parse_patch(
+    "\
+--- foo
++++ foo
+@@ -1 +1 @@
+-old
++new
+@@ -10 +10 @@
+-old
++new
+",
+  )
+  .unwrap();
+  select_all(&mut files);
+
+  // Writing the selected lines would drop the 8 lines between the hunks.
+  let result = apply_changes(
+    &mut filesystem,
+    Path::new(""),
+    RecordState {
+      is_read_only: false,
+      commits: Default::default(),
+      files,
+    },
+  );
+  insta::assert_snapshot!(result.unwrap_err(), @"can't write foo: some of its lines aren't available");
+  insta::assert_debug_snapshot!(filesystem.read_file_info(Path::new("foo"))?.contents, @r#"
+  Text {
+      contents: "old\n",
+      hash: "abc123",
+      num_bytes: 4,
+      encoding: TextEncoding {
+          encoding: "UTF-8",
+          has_bom: false,
+      },
+  }
+  "#);
   Ok(())
 }